@@ -0,0 +1,400 @@
+use std::{fs, path::Path, path::PathBuf};
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate};
+use ledger_rs_core::{core::DATE_FORMAT, state::ledgerstate::LedgerState};
+use ledger_rs_qfx::{qfx::QfxImportState, symbols::load_accounts};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+
+const CLOSING_BOOKED: &str = "CLBD";
+const DEBIT: &str = "DBIT";
+const BOOKED: &str = "BOOK";
+
+///
+/// camt.053 (ISO 20022 BankToCustomerStatement) document. Only the elements
+/// used by the importer are deserialized; names follow the XML tags.
+///
+#[derive(Debug, Deserialize)]
+pub struct Document {
+    #[serde(rename = "BkToCstmrStmt")]
+    pub bk_to_cstmr_stmt: BkToCstmrStmt,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BkToCstmrStmt {
+    #[serde(rename = "Stmt", default)]
+    pub stmt: Vec<Stmt>,
+}
+
+/// Statement of one account
+#[derive(Debug, Deserialize)]
+pub struct Stmt {
+    #[serde(rename = "Acct")]
+    pub acct: Acct,
+    #[serde(rename = "Bal", default)]
+    pub bal: Vec<Bal>,
+    #[serde(rename = "Ntry", default)]
+    pub ntry: Vec<Ntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Acct {
+    #[serde(rename = "Id")]
+    pub id: AcctId,
+    #[serde(rename = "Ccy")]
+    pub ccy: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcctId {
+    #[serde(rename = "IBAN")]
+    pub iban: Option<String>,
+    #[serde(rename = "Othr")]
+    pub othr: Option<Othr>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Othr {
+    #[serde(rename = "Id")]
+    pub id: String,
+}
+
+/// Amount with its currency attribute
+#[derive(Debug, Deserialize)]
+pub struct Amt {
+    #[serde(rename = "@Ccy")]
+    pub ccy: String,
+    #[serde(rename = "$text", deserialize_with = "from_camt_decimal")]
+    pub value: Decimal,
+}
+
+/// ISO date, either as Dt or as the date part of DtTm
+#[derive(Debug, Deserialize)]
+pub struct DateChoice {
+    #[serde(rename = "Dt")]
+    pub dt: Option<String>,
+    #[serde(rename = "DtTm")]
+    pub dt_tm: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Bal {
+    #[serde(rename = "Tp")]
+    pub tp: BalTp,
+    #[serde(rename = "Amt")]
+    pub amt: Amt,
+    #[serde(rename = "CdtDbtInd")]
+    pub cdt_dbt_ind: String,
+    #[serde(rename = "Dt")]
+    pub dt: DateChoice,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BalTp {
+    #[serde(rename = "CdOrPrtry")]
+    pub cd_or_prtry: CdOrPrtry,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CdOrPrtry {
+    #[serde(rename = "Cd")]
+    pub cd: Option<String>,
+}
+
+/// Statement entry
+#[derive(Debug, Deserialize)]
+pub struct Ntry {
+    #[serde(rename = "NtryRef")]
+    pub ntry_ref: Option<String>,
+    #[serde(rename = "Amt")]
+    pub amt: Amt,
+    #[serde(rename = "CdtDbtInd")]
+    pub cdt_dbt_ind: String,
+    #[serde(rename = "Sts")]
+    pub sts: Option<Sts>,
+    #[serde(rename = "BookgDt")]
+    pub bookg_dt: DateChoice,
+    #[serde(rename = "AcctSvcrRef")]
+    pub acct_svcr_ref: Option<String>,
+    #[serde(rename = "NtryDtls")]
+    pub ntry_dtls: Option<NtryDtls>,
+    #[serde(rename = "AddtlNtryInf")]
+    pub addtl_ntry_inf: Option<String>,
+}
+
+///
+/// Status of an entry, as text up to camt.053.001.04 and as a code since,
+/// `<Sts>BOOK</Sts>` or `<Sts><Cd>BOOK</Cd></Sts>`
+///
+#[derive(Debug, Deserialize)]
+pub struct Sts {
+    #[serde(rename = "$text")]
+    pub text: Option<String>,
+    #[serde(rename = "Cd")]
+    pub cd: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NtryDtls {
+    #[serde(rename = "TxDtls", default)]
+    pub tx_dtls: Vec<TxDtls>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TxDtls {
+    #[serde(rename = "RltdPties")]
+    pub rltd_pties: Option<RltdPties>,
+    #[serde(rename = "RmtInf")]
+    pub rmt_inf: Option<RmtInf>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RltdPties {
+    #[serde(rename = "Dbtr")]
+    pub dbtr: Option<Party>,
+    #[serde(rename = "Cdtr")]
+    pub cdtr: Option<Party>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Party {
+    #[serde(rename = "Nm")]
+    pub nm: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RmtInf {
+    #[serde(rename = "Ustrd", default)]
+    pub ustrd: Vec<String>,
+}
+
+impl DateChoice {
+    pub fn date(&self) -> Result<NaiveDate> {
+        let s = match (&self.dt, &self.dt_tm) {
+            (Some(d), _) => d,
+            (None, Some(d)) => d,
+            (None, None) => return Err(anyhow::anyhow!("Missing date")),
+        };
+        Ok(NaiveDate::parse_from_str(
+            s.get(0..10).unwrap_or(s),
+            DATE_FORMAT,
+        )?)
+    }
+}
+
+impl Acct {
+    pub fn get_acctid(&self) -> String {
+        match (&self.id.iban, &self.id.othr) {
+            (Some(iban), _) => iban.clone(),
+            (None, Some(o)) => o.id.clone(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+fn signed(amt: &Amt, cdt_dbt_ind: &str) -> Decimal {
+    if cdt_dbt_ind == DEBIT {
+        -amt.value
+    } else {
+        amt.value
+    }
+}
+
+impl Ntry {
+    /// Whether the entry is booked, taking an entry without a status as booked
+    pub fn is_booked(&self) -> bool {
+        match &self.sts {
+            Some(s) => s.cd.as_deref().or(s.text.as_deref()).map(str::trim) == Some(BOOKED),
+            None => true,
+        }
+    }
+
+    /// Counterparty name and unstructured remittance joined with ` / `,
+    /// falling back to the additional entry information
+    pub fn narration(&self) -> String {
+        let tx = self.ntry_dtls.as_ref().and_then(|d| d.tx_dtls.first());
+        let party = tx.and_then(|t| t.rltd_pties.as_ref()).and_then(|p| {
+            if self.cdt_dbt_ind == DEBIT {
+                p.cdtr.as_ref()
+            } else {
+                p.dbtr.as_ref()
+            }
+        });
+        let name = party.and_then(|p| p.nm.clone());
+        let memo = tx
+            .and_then(|t| t.rmt_inf.as_ref())
+            .map(|r| r.ustrd.join(" "))
+            .filter(|m| !m.is_empty())
+            .or(self.addtl_ntry_inf.clone());
+        match (name, memo) {
+            (Some(n), Some(m)) => format!("{n} / {m}"),
+            (Some(n), None) => n,
+            (None, Some(m)) => m,
+            (None, None) => "PROBLEM".to_string(),
+        }
+    }
+
+    /// Bank reference used like the QFX FITID
+    pub fn fitid(&self) -> String {
+        self.acct_svcr_ref
+            .clone()
+            .or(self.ntry_ref.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Document {
+    ///
+    /// Fills the QFX intermediates with the booked entries, leaving out the
+    /// pending and informational ones. Closing booked balances are end of
+    /// day, so they become assertions on the following day.
+    ///
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+        for s in self.bk_to_cstmr_stmt.stmt.iter() {
+            let acctid = s.acct.get_acctid();
+            for n in s.ntry.iter().filter(|n| n.is_booked()) {
+                state.append_transaction(
+                    n.fitid(),
+                    n.bookg_dt.date()?,
+                    n.narration(),
+                    acctid.clone(),
+                    signed(&n.amt, &n.cdt_dbt_ind),
+                    n.amt.ccy.clone(),
+                );
+            }
+            for b in s.bal.iter() {
+                if b.tp.cd_or_prtry.cd.as_deref() != Some(CLOSING_BOOKED) {
+                    continue;
+                }
+                state.append_balance(
+                    b.dt.date()? + Duration::days(1),
+                    acctid.clone(),
+                    signed(&b.amt, &b.cdt_dbt_ind),
+                    b.amt.ccy.clone(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn process_camt(filename: &PathBuf) -> Result<Document> {
+    let input = fs::read_to_string(filename)?;
+    let doc = quick_xml::de::from_str::<Document>(&input)?;
+    Ok(doc)
+}
+
+///
+/// Imports a camt.053 file into `state` through the same intermediates and
+/// accounts file as the QFX importer, see QfxImportState::insert_into.
+///
+pub fn parse_camt_file(
+    filename: PathBuf,
+    symbols_f: PathBuf,
+    fitids_f: Option<&Path>,
+    state: &mut LedgerState,
+) -> Result<usize> {
+    let symbols = load_accounts(String::from(symbols_f.to_str().unwrap()))?;
+    let mut import_state = QfxImportState::new();
+    let doc = process_camt(&filename)?;
+    doc.to_bk(&mut import_state)?;
+    import_state.insert_into(&symbols, fitids_f, state)
+}
+
+fn from_camt_decimal<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Decimal::from_str_exact(s.trim()).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Acct><Id><IBAN>DE89370400440532013000</IBAN></Id><Ccy>EUR</Ccy></Acct>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">100.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-02-29</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">40.50</Amt><CdtDbtInd>DBIT</CdtDbtInd><Dt><Dt>2024-03-01</Dt></Dt>
+      </Bal>
+      <Ntry>
+        <Amt Ccy="EUR">29.50</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-03-01</Dt></BookgDt><AcctSvcrRef>REF001</AcctSvcrRef>
+        <NtryDtls><TxDtls>
+          <RltdPties><Dbtr><Nm>Me</Nm></Dbtr><Cdtr><Nm>Stadtwerke</Nm></Cdtr></RltdPties>
+          <RmtInf><Ustrd>Rechnung 42</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">70.50</Amt><CdtDbtInd>CRDT</CdtDbtInd><Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><DtTm>2024-03-01T10:00:00</DtTm></BookgDt><NtryRef>REF002</NtryRef>
+        <AddtlNtryInf>Salary</AddtlNtryInf>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">5.00</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>PDNG</Sts>
+        <BookgDt><Dt>2024-03-01</Dt></BookgDt><AcctSvcrRef>REF003</AcctSvcrRef>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+
+    fn import() -> QfxImportState {
+        let doc = quick_xml::de::from_str::<Document>(STATEMENT).unwrap();
+        let mut state = QfxImportState::new();
+        doc.to_bk(&mut state).unwrap();
+        state
+    }
+
+    #[test]
+    fn only_booked_entries_are_imported() {
+        let fitids: Vec<String> = import().transactions.into_iter().map(|t| t.fitid).collect();
+        assert_eq!(fitids, ["REF001", "REF002"]);
+    }
+
+    #[test]
+    fn debits_are_negative() {
+        let state = import();
+        let amounts: Vec<String> = state
+            .transactions
+            .iter()
+            .map(|t| t.quantity.to_string())
+            .collect();
+        assert_eq!(amounts, ["-29.50", "70.50"]);
+        assert_eq!(state.balances[0].quantity.to_string(), "-40.50");
+    }
+
+    #[test]
+    fn narration_names_the_counterparty() {
+        let narrations: Vec<String> = import()
+            .transactions
+            .into_iter()
+            .map(|t| t.narration)
+            .collect();
+        assert_eq!(narrations, ["Stadtwerke / Rechnung 42", "Salary"]);
+    }
+
+    #[test]
+    fn closing_booked_balance_is_asserted_the_next_day() {
+        let state = import();
+        assert_eq!(state.balances.len(), 1);
+        assert_eq!(
+            state.balances[0].date,
+            NaiveDate::from_ymd_opt(2024, 3, 2).unwrap()
+        );
+        assert_eq!(
+            state.transactions[1].date,
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+        );
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
+use anyhow::Result;
+use anyhow::anyhow;
+use arrow::array::{Array, RecordBatch};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow_convert::{
+    deserialize::{ArrowDeserialize, TryIntoCollection},
+    field::ArrowField,
+    serialize::{ArrowSerialize, TryIntoArrow},
+};
+
+use crate::core::{
+    HeaderParams, IncludeParams, InfoParams, MetadataParams, OPTION_ACTION, PostingParams,
+    PriceParams, VerificationParams,
+};
+use crate::parse::{ParseError, parse_shallow_raw, state_include_files};
+use crate::roots::AccountRoots;
+use crate::state::ledgerstate::LedgerState;
+
+const CACHE_TRANSACTIONS: &str = "transactions.arrow";
+const CACHE_POSTINGS: &str = "postings.arrow";
+const CACHE_VERIFICATIONS: &str = "verifications.arrow";
+const CACHE_INCLUDES: &str = "includes.arrow";
+const CACHE_INFORMATIONALS: &str = "informationals.arrow";
+const CACHE_METADATA: &str = "metadata.arrow";
+const CACHE_PRICES: &str = "prices.arrow";
+
+/// Rows that carry their position in the file they were parsed from
+trait Located {
+    fn start(&self) -> u32;
+    /// Moves the row from file-local numbering to `base + local` in `file_no`
+    fn relocate(&mut self, base: u32, file_no: u32);
+}
+
+macro_rules! located {
+    ($t:ty) => {
+        impl Located for $t {
+            fn start(&self) -> u32 {
+                self.start
+            }
+            fn relocate(&mut self, base: u32, file_no: u32) {
+                self.statement_no += base;
+                self.file_no = file_no;
+            }
+        }
+    };
+    ($t:ty, transaction_no) => {
+        impl Located for $t {
+            fn start(&self) -> u32 {
+                self.start
+            }
+            fn relocate(&mut self, base: u32, file_no: u32) {
+                self.statement_no += base;
+                self.transaction_no += base;
+                self.file_no = file_no;
+            }
+        }
+    };
+}
+
+located!(HeaderParams);
+located!(PostingParams, transaction_no);
+located!(VerificationParams);
+located!(InfoParams);
+located!(MetadataParams, transaction_no);
+located!(PriceParams);
+
+/// The statements of a single file, numbered by their byte offset in it.
+/// `includes` are the include directives as written.
+#[derive(Debug, Default)]
+pub(crate) struct FileRows {
+    transactions: Vec<HeaderParams>,
+    postings: Vec<PostingParams>,
+    verifications: Vec<VerificationParams>,
+    includes: Vec<IncludeParams>,
+    informationals: Vec<InfoParams>,
+    metadata: Vec<MetadataParams>,
+    prices: Vec<PriceParams>,
+}
+
+/// How far each table of a FileRows has been copied into the LedgerState
+#[derive(Default)]
+pub(crate) struct Cursor {
+    transactions: usize,
+    postings: usize,
+    verifications: usize,
+    informationals: usize,
+    metadata: usize,
+    prices: usize,
+}
+
+fn copy_rows<T: Located + Clone>(
+    rows: &[T],
+    n: &mut usize,
+    before: u32,
+    base: u32,
+    file_no: u32,
+    out: &mut Vec<T>,
+) {
+    while *n < rows.len() && rows[*n].start() < before {
+        let mut r = rows[*n].clone();
+        r.relocate(base, file_no);
+        out.push(r);
+        *n += 1;
+    }
+}
+
+impl FileRows {
+    fn from_state(state: LedgerState) -> Self {
+        Self {
+            transactions: state.transactions,
+            postings: state.postings,
+            verifications: state.verifications,
+            includes: state.includes,
+            informationals: state.informationals,
+            metadata: state.metadata,
+            prices: state.prices,
+        }
+    }
+
+    /// Copies the rows starting before `before`, renumbered from `base`
+    pub(crate) fn copy_into(
+        &self,
+        c: &mut Cursor,
+        before: u32,
+        base: u32,
+        file_no: u32,
+        state: &mut LedgerState,
+    ) {
+        copy_rows(
+            &self.transactions,
+            &mut c.transactions,
+            before,
+            base,
+            file_no,
+            &mut state.transactions,
+        );
+        copy_rows(
+            &self.postings,
+            &mut c.postings,
+            before,
+            base,
+            file_no,
+            &mut state.postings,
+        );
+        copy_rows(
+            &self.verifications,
+            &mut c.verifications,
+            before,
+            base,
+            file_no,
+            &mut state.verifications,
+        );
+        copy_rows(
+            &self.informationals,
+            &mut c.informationals,
+            before,
+            base,
+            file_no,
+            &mut state.informationals,
+        );
+        copy_rows(
+            &self.metadata,
+            &mut c.metadata,
+            before,
+            base,
+            file_no,
+            &mut state.metadata,
+        );
+        copy_rows(
+            &self.prices,
+            &mut c.prices,
+            before,
+            base,
+            file_no,
+            &mut state.prices,
+        );
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        write_table(&dir.join(CACHE_TRANSACTIONS), &self.transactions)?;
+        write_table(&dir.join(CACHE_POSTINGS), &self.postings)?;
+        write_table(&dir.join(CACHE_VERIFICATIONS), &self.verifications)?;
+        write_table(&dir.join(CACHE_INCLUDES), &self.includes)?;
+        write_table(&dir.join(CACHE_INFORMATIONALS), &self.informationals)?;
+        write_table(&dir.join(CACHE_METADATA), &self.metadata)?;
+        write_table(&dir.join(CACHE_PRICES), &self.prices)?;
+        Ok(())
+    }
+
+    fn read(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            transactions: read_table(&dir.join(CACHE_TRANSACTIONS))?,
+            postings: read_table(&dir.join(CACHE_POSTINGS))?,
+            verifications: read_table(&dir.join(CACHE_VERIFICATIONS))?,
+            includes: read_table(&dir.join(CACHE_INCLUDES))?,
+            informationals: read_table(&dir.join(CACHE_INFORMATIONALS))?,
+            metadata: read_table(&dir.join(CACHE_METADATA))?,
+            prices: read_table(&dir.join(CACHE_PRICES))?,
+        })
+    }
+}
+
+fn write_table<T>(f: &Path, rows: &[T]) -> Result<()>
+where
+    T: ArrowSerialize + ArrowField<Type = T> + 'static,
+{
+    let batch: RecordBatch = rows.try_into_arrow()?;
+    let mut w = FileWriter::try_new(File::create(f)?, &batch.schema())?;
+    w.write(&batch)?;
+    w.finish()?;
+    Ok(())
+}
+
+fn read_table<T>(f: &Path) -> Result<Vec<T>>
+where
+    T: ArrowDeserialize + ArrowField<Type = T> + 'static,
+{
+    let mut rows = vec![];
+    for batch in FileReader::try_new(File::open(f)?, None)? {
+        // write_table writes the rows as the single struct column of the batch
+        let array: Arc<dyn Array> = batch?.column(0).clone();
+        let mut v: Vec<T> = array.try_into_collection()?;
+        rows.append(&mut v);
+    }
+    Ok(rows)
+}
+
+/// Removes the entries of `dir` named with `prefix` but `keep`, the rows of older contents
+fn evict(dir: &Path, prefix: &str, keep: &str) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for e in entries.flatten() {
+        let name = e.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(prefix) && name != keep {
+            let _ = fs::remove_dir_all(e.path());
+        }
+    }
+}
+
+/// A file as collect_files parses it, its rows numbered from 0
+pub(crate) struct ParsedFile {
+    pub(crate) file_no: u32,
+    pub(crate) rows: Rc<FileRows>,
+    /// The length of the file, without the files it includes
+    pub(crate) length: u32,
+    /// Its include directives, one per file included
+    pub(crate) includes: Vec<IncludeParams>,
+}
+
+/// The number of `f` in `state`, numbering it if new
+fn insert_file(f: &Path, state: &mut LedgerState) -> u32 {
+    let n = state.input_files.len() as u32;
+    *state.input_files.entry(f.to_path_buf()).or_insert(n)
+}
+
+/// The error of a file included by `inc` of `f`, at the include if it could not be read
+fn include_error(f: &Path, contents: &str, inc: &IncludeParams, e: anyhow::Error) -> anyhow::Error {
+    match e.is::<ParseError>() {
+        true => e,
+        false => ParseError::new(f, contents, inc.start, e.to_string()).into(),
+    }
+}
+
+///
+/// Parses ledgers file by file, keeping each file's statements keyed by its
+/// path and a hash of its contents. Parsing again, in the same process or
+/// from `dir` on disk, only re-parses the files whose contents changed; the
+/// rows of the others are reused and renumbered as if the whole include tree
+/// had been parsed, so the DataFrames built by `verify` are unchanged.
+///
+/// The key is a 64-bit hash from std's DefaultHasher: a collision, however
+/// unlikely, would reuse the rows of other contents, and a toolchain upgrade
+/// may invalidate the disk cache. Only the latest contents of each path are
+/// kept, in memory and in `dir`.
+///
+#[derive(Debug, Default)]
+pub struct ParseCache {
+    dir: Option<PathBuf>,
+    files: HashMap<u64, Rc<FileRows>>,
+    /// The key of the contents of each path last parsed
+    keys: HashMap<PathBuf, u64>,
+    pub parsed: usize,
+    pub reused: usize,
+}
+
+impl ParseCache {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Parses `f` and its includes into `state`, the same as parse_filename.
+    /// As with parse_filename, `f` must already be inserted into `state`.
+    ///
+    pub fn parse(&mut self, f: PathBuf, state: &mut LedgerState) -> Result<()> {
+        let file_no = state.input_files[&f];
+        self.parse_file(&f, file_no, 0, state)?;
+        Ok(())
+    }
+
+    /// Adds `f` numbered from `entry` and returns its length with its includes
+    fn parse_file(
+        &mut self,
+        f: &Path,
+        file_no: u32,
+        entry: u32,
+        state: &mut LedgerState,
+    ) -> Result<u32> {
+        let (contents, rows) = self.file_rows(f, state)?;
+        let mut c = Cursor::default();
+        let mut added = 0;
+        for inc in rows.includes.iter() {
+            rows.copy_into(&mut c, inc.start, entry + added, file_no, state);
+            let statement_no = entry + added + inc.statement_no;
+            let mut length = 0;
+            for (in_f, in_path) in state_include_files(state, f, &inc.path) {
+                let in_file_no = insert_file(&in_f, state);
+                length += self
+                    .parse_file(&in_f, in_file_no, statement_no + length, state)
+                    .map_err(|e| include_error(f, &contents, inc, e))?;
+                state.includes.push(IncludeParams {
+                    statement_no,
+                    file_no,
+                    start: inc.start,
+                    end: inc.end,
+                    path: in_path,
+                });
+            }
+            added += length;
+        }
+        rows.copy_into(&mut c, u32::MAX, entry + added, file_no, state);
+
+        Ok(contents.len() as u32 + added)
+    }
+
+    /// The contents of `f` and its rows, with its options set in `state`
+    fn file_rows(&mut self, f: &Path, state: &mut LedgerState) -> Result<(String, Rc<FileRows>)> {
+        let contents = fs::read_to_string(f).map_err(|e| anyhow!("{}: {}", f.display(), e))?;
+        let rows = self.rows(f, &contents, &state.roots, state.keep_raw)?;
+        for x in rows.informationals.iter() {
+            if x.action == OPTION_ACTION
+                && let Some(a) = x.attribute.as_deref()
+            {
+                state.roots.set_option(a, &x.value);
+                state.options.set(a, &x.value);
+            }
+        }
+        Ok((contents, rows))
+    }
+
+    ///
+    /// Adds `f` numbered `file_no` and the files it includes to `files` in
+    /// include order, each parsed on its own as by parse, without copying
+    /// their rows into `state`
+    ///
+    pub(crate) fn collect_files(
+        &mut self,
+        f: &Path,
+        file_no: u32,
+        state: &mut LedgerState,
+        files: &mut Vec<ParsedFile>,
+    ) -> Result<()> {
+        let (contents, rows) = self.file_rows(f, state)?;
+        let n = files.len();
+        files.push(ParsedFile {
+            file_no,
+            rows: rows.clone(),
+            length: contents.len() as u32,
+            includes: vec![],
+        });
+        for inc in rows.includes.iter() {
+            for (in_f, in_path) in state_include_files(state, f, &inc.path) {
+                let in_file_no = insert_file(&in_f, state);
+                self.collect_files(&in_f, in_file_no, state, files)
+                    .map_err(|e| include_error(f, &contents, inc, e))?;
+                files[n].includes.push(IncludeParams {
+                    path: in_path,
+                    ..inc.clone()
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn rows(
+        &mut self,
+        f: &Path,
+        contents: &str,
+        roots: &AccountRoots,
+        keep_raw: bool,
+    ) -> Result<Rc<FileRows>> {
+        let mut h = DefaultHasher::new();
+        f.hash(&mut h);
+        let path_key = h.finish();
+        contents.hash(&mut h);
+        roots.hash(&mut h);
+        keep_raw.hash(&mut h);
+        let key = h.finish();
+
+        if let Some(rows) = self.files.get(&key) {
+            self.reused += 1;
+            return Ok(rows.clone());
+        }
+        let prefix = format!("{:016x}-", path_key);
+        let name = format!("{}{:016x}", prefix, key);
+        let dir = self.dir.as_ref().map(|d| d.join(&name));
+        let rows = match dir.as_ref().map(|d| FileRows::read(d)) {
+            Some(Ok(rows)) => {
+                self.reused += 1;
+                rows
+            }
+            _ => {
+                self.parsed += 1;
+                let rows = FileRows::from_state(parse_shallow_raw(f, contents, roots, keep_raw)?);
+                if let Some(d) = self.dir.as_ref() {
+                    rows.write(&d.join(&name))?;
+                    evict(d, &prefix, &name);
+                }
+                rows
+            }
+        };
+        let rows = Rc::new(rows);
+        if let Some(old) = self.keys.insert(f.to_path_buf(), key) {
+            self.files.remove(&old);
+        }
+        self.files.insert(key, rows.clone());
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::util::pretty::pretty_format_batches;
+
+    use super::*;
+    use crate::parse::parse_filename;
+
+    fn table<T>(rows: &[T]) -> String
+    where
+        T: ArrowSerialize + ArrowField<Type = T> + 'static,
+    {
+        let batch: RecordBatch = rows.try_into_arrow().unwrap();
+        pretty_format_batches(&[batch]).unwrap().to_string()
+    }
+
+    /// The rows of the ledger `f` as tables, parsed as by `parse`
+    fn rows_of(f: &Path, parse: impl FnOnce(PathBuf, &mut LedgerState) -> Result<()>) -> String {
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        parse(f.to_path_buf(), &mut state).unwrap();
+        [
+            table(&state.transactions),
+            table(&state.postings),
+            table(&state.verifications),
+            table(&state.includes),
+            table(&state.informationals),
+            table(&state.metadata),
+            table(&state.prices),
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    fn cached_rows_are_those_of_a_fresh_parse() {
+        let dir = std::env::temp_dir().join(format!("ledger-rs-cache-{}", std::process::id()));
+        let cache_dir = dir.join("cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("inc")).unwrap();
+        let f = dir.join("top.bean");
+        fs::write(
+            &f,
+            "option \"title\" \"Test\"\n2024-01-01 open Assets:A\n\
+             include \"inc/a.bean\"\n\
+             2024-01-03 * \"after\"\n  Assets:A 1.00 CAD\n  Income:B\n",
+        )
+        .unwrap();
+        let inc = dir.join("inc/a.bean");
+        fs::write(
+            &inc,
+            "2024-01-02 * \"included\" #tag\n  key: \"value\"\n  Assets:A 2.00 CAD\n  Income:B\n\
+             2024-01-02 price XYZ 1.50 CAD\n2024-01-04 balance Assets:A 3.00 CAD\n",
+        )
+        .unwrap();
+
+        let fresh = rows_of(&f, parse_filename);
+        let mut cache = ParseCache::new(Some(cache_dir.clone()));
+        assert_eq!(rows_of(&f, |f, s| cache.parse(f, s)), fresh);
+        assert_eq!((cache.parsed, cache.reused), (2, 0));
+        assert_eq!(rows_of(&f, |f, s| cache.parse(f, s)), fresh);
+        assert_eq!((cache.parsed, cache.reused), (2, 2));
+        let mut from_disk = ParseCache::new(Some(cache_dir.clone()));
+        assert_eq!(rows_of(&f, |f, s| from_disk.parse(f, s)), fresh);
+        assert_eq!((from_disk.parsed, from_disk.reused), (0, 2));
+
+        fs::write(
+            &inc,
+            "2024-01-02 * \"changed\"\n  Assets:A 3.00 CAD\n  Income:B\n",
+        )
+        .unwrap();
+        let fresh = rows_of(&f, parse_filename);
+        assert_eq!(rows_of(&f, |f, s| from_disk.parse(f, s)), fresh);
+        assert_eq!((from_disk.parsed, from_disk.reused), (1, 3));
+        // The rows of the old contents of inc/a.bean are gone
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 2);
+        assert_eq!(from_disk.files.len(), 2);
+    }
+}
@@ -0,0 +1,1164 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use arrow::array::{Date32Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Date32Type};
+use chrono::{Days, Local, NaiveDate};
+use datafusion::functions_aggregate::expr_fn::{count, max, min, sum};
+use datafusion::functions_window::expr_fn::row_number;
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+use futures::StreamExt;
+use itertools::izip;
+use serde::Serialize;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_RIGHT, ACCOUNT_SEP, ACTION_COL, CLOSE_ACTION, CLOSE_DATE, COMMODITY,
+    COMMODITY_LIST_SEP, DATE, DATE_FORMAT, DATE_RANGE_FUTURE_DAYS, DATE_RANGE_MIN,
+    DISABLE_CHECK_OPTION, DiagnosticParams, ENABLE_CHECK_OPTION, ERROR_DOWNCAST, FILE_NO,
+    FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY, MAX_DATE_OPTION, MESSAGE,
+    MIN_DATE_OPTION, NUM, OPEN_ACTION, OPEN_DATE, QUANTITY, START, STATEMENT_NO,
+    STATEMENT_NO_RIGHT, TOTAL, TOTALS, TRANSACTION_NO,
+};
+use crate::custom::{Budget, ContributionLimit, CustomHandler, CustomRule, diagnostics_df};
+use crate::events::write_event;
+use crate::parse::ParseError;
+use crate::state::ledgerstate::LedgerState;
+
+pub const CHECK_BALANCED: &str = "balanced";
+pub const CHECK_BALANCE: &str = "balance";
+pub const CHECK_BALANCE_TOLERANCE: &str = "balance-tolerance";
+pub const CHECK_OPEN_CLOSE: &str = "open-close";
+pub const CHECK_COMMODITY: &str = "commodity";
+pub const CHECK_SIGN: &str = "sign";
+pub const CHECK_PARSE: &str = "parse";
+pub const CHECK_DATE_RANGE: &str = "date-range";
+pub const CHECK_DATE_ORDER: &str = "date-order";
+pub const CHECK_ZERO_AMOUNT: &str = "zero-amount";
+pub const CHECK_POSTING_SIGN: &str = "posting-sign";
+pub const CHECK_SINGLE_POSTING: &str = "single-posting";
+
+const ROW_NO: &str = "row_no";
+const COMMODITY_RIGHT: &str = "commodity_right";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub rule: String,
+    pub severity: Severity,
+    pub statement_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub date: Option<NaiveDate>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    ///
+    /// The error of the parse rule for a parse into `state` failing with `e`,
+    /// at the offset its ParseError gives in the file failing, else not tied
+    /// to a statement
+    ///
+    pub fn parse_failure(state: &LedgerState, e: &anyhow::Error) -> Self {
+        let located = e
+            .downcast_ref::<ParseError>()
+            .and_then(|x| Some((x, *state.input_files.get(&x.path)?)));
+        let (statement_no, file_no, start, message) = match located {
+            Some((x, file_no)) => (0, file_no, x.offset, x.message.clone()),
+            None => (u32::MAX, 0, 0, e.to_string()),
+        };
+        Self {
+            rule: CHECK_PARSE.to_string(),
+            severity: Severity::Error,
+            statement_no,
+            file_no,
+            start,
+            date: None,
+            message,
+        }
+    }
+}
+
+///
+/// A check run against a verified LedgerState. `check` returns its findings
+/// as a DataFrame with the columns STATEMENT_NO, FILE_NO, START, DATE and
+/// MESSAGE, one row per Diagnostic; the location columns may be null for
+/// findings not tied to a statement.
+///
+pub trait VerificationRule {
+    fn name(&self) -> &str;
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    /// Whether the findings are only hints, left as warnings when checking strictly
+    fn advisory(&self) -> bool {
+        false
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame>;
+}
+
+/// Transactions whose tc amounts do not sum to zero
+pub struct Balanced;
+
+impl VerificationRule for Balanced {
+    fn name(&self) -> &str {
+        CHECK_BALANCED
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let transactions_df = state
+            .transactions_df
+            .clone()
+            .context("No transactions df")?;
+        let df = state
+            .errors_df
+            .clone()
+            .context("No errors df")?
+            .join(
+                transactions_df.select(vec![
+                    col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                    col(FILE_NO),
+                    col(START),
+                    col(DATE),
+                ])?,
+                JoinType::Left,
+                &[TRANSACTION_NO],
+                &[STATEMENT_NO_RIGHT],
+                None,
+            )?
+            .select(vec![
+                col(TRANSACTION_NO).alias(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                // Left without a commodity, a posting without an amount was
+                // one of several that no residual could be told apart for
+                when(
+                    col(FINAL_TC_COMMODITY).is_null(),
+                    lit("no amount can be inferred for a posting without one"),
+                )
+                .otherwise(concat(vec![
+                    lit("transaction does not balance: "),
+                    coalesce(vec![cast(col(TOTALS), DataType::Utf8), lit("?")]),
+                    lit(" "),
+                    col(FINAL_TC_COMMODITY),
+                ]))?
+                .alias(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+/// Balance assertions that do not hold, as in balance_errors_df
+pub struct BalanceAssertions;
+
+impl VerificationRule for BalanceAssertions {
+    fn name(&self) -> &str {
+        CHECK_BALANCE
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let df = state.balance_errors_df()?.select(vec![
+            col(STATEMENT_NO),
+            col(FILE_NO),
+            col(START),
+            col(DATE),
+            concat(vec![
+                col(ACCOUNT),
+                lit(" expected "),
+                cast(col(QUANTITY), DataType::Utf8),
+                lit(" "),
+                col(COMMODITY),
+                lit(" but is "),
+                cast(col(TOTAL), DataType::Utf8),
+            ])
+            .alias(MESSAGE),
+        ])?;
+        Ok(df)
+    }
+}
+
+///
+/// Balance assertions off by no more than their tolerance. Warnings, so
+/// rounding differences from imports pass unless checking strictly.
+///
+pub struct BalanceTolerance;
+
+impl VerificationRule for BalanceTolerance {
+    fn name(&self) -> &str {
+        CHECK_BALANCE_TOLERANCE
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let df = state.balance_tolerated_df()?.select(vec![
+            col(STATEMENT_NO),
+            col(FILE_NO),
+            col(START),
+            col(DATE),
+            concat(vec![
+                col(ACCOUNT),
+                lit(" expected "),
+                cast(col(QUANTITY), DataType::Utf8),
+                lit(" "),
+                col(COMMODITY),
+                lit(" but is "),
+                cast(col(TOTAL), DataType::Utf8),
+                lit(", within its tolerance"),
+            ])
+            .alias(MESSAGE),
+        ])?;
+        Ok(df)
+    }
+}
+
+/// Postings to accounts never opened, before their open or after their close
+pub struct OpenClose;
+
+impl VerificationRule for OpenClose {
+    fn name(&self) -> &str {
+        CHECK_OPEN_CLOSE
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let verifications_df = state
+            .verifications_df
+            .clone()
+            .context("No verifications df")?;
+        let dates_df = verifications_df
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(OPEN_ACTION))
+                    .or(col(ACTION_COL).eq(lit(CLOSE_ACTION))),
+            )?
+            .aggregate(
+                vec![col(ACCOUNT).alias(ACCOUNT_RIGHT)],
+                vec![
+                    min(when(col(ACTION_COL).eq(lit(OPEN_ACTION)), col(DATE)).end()?)
+                        .alias(OPEN_DATE),
+                    max(when(col(ACTION_COL).eq(lit(CLOSE_ACTION)), col(DATE)).end()?)
+                        .alias(CLOSE_DATE),
+                ],
+            )?;
+
+        let rounding_account = state.rounding_account();
+        let df = state
+            .journal_df()?
+            .join(dates_df, JoinType::Left, &[ACCOUNT], &[ACCOUNT_RIGHT], None)?
+            .with_column(
+                MESSAGE,
+                when(
+                    col(OPEN_DATE)
+                        .is_null()
+                        .and(col(ACCOUNT).not_eq(lit(rounding_account))),
+                    concat(vec![col(ACCOUNT), lit(" is not opened")]),
+                )
+                .when(
+                    col(DATE).lt(col(OPEN_DATE)),
+                    concat(vec![
+                        col(ACCOUNT),
+                        lit(" is not open until "),
+                        cast(col(OPEN_DATE), DataType::Utf8),
+                    ]),
+                )
+                .when(
+                    col(DATE).gt(col(CLOSE_DATE)),
+                    concat(vec![
+                        col(ACCOUNT),
+                        lit(" is closed on "),
+                        cast(col(CLOSE_DATE), DataType::Utf8),
+                    ]),
+                )
+                .end()?,
+            )?
+            .filter(col(MESSAGE).is_not_null())?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                col(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+///
+/// Postings in a commodity the open directive of their account does not
+/// list, for the accounts opened with a list of commodities
+///
+pub struct Commodities;
+
+impl VerificationRule for Commodities {
+    fn name(&self) -> &str {
+        CHECK_COMMODITY
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let declared_df = state
+            .verifications_df
+            .clone()
+            .context("No verifications df")?
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(OPEN_ACTION))
+                    .and(col(COMMODITY).is_not_null()),
+            )?
+            .aggregate(
+                vec![col(ACCOUNT).alias(ACCOUNT_RIGHT)],
+                vec![max(col(COMMODITY)).alias(COMMODITY)],
+            )?;
+        let declared = string_to_array(
+            col(COMMODITY),
+            lit(COMMODITY_LIST_SEP),
+            lit(ScalarValue::Utf8(None)),
+        );
+        let df = state
+            .journal_df()?
+            .join(
+                declared_df,
+                JoinType::Inner,
+                &[ACCOUNT],
+                &[ACCOUNT_RIGHT],
+                None,
+            )?
+            .filter(not(array_has(declared, col(FINAL_CP_COMMODITY))))?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                concat(vec![
+                    col(FINAL_CP_COMMODITY),
+                    lit(" is not declared by the open of "),
+                    col(ACCOUNT),
+                ])
+                .alias(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+///
+/// Account totals with an unusual sign for their type: negative Assets or
+/// Expenses, positive Liabilities or Income, located at the first posting
+/// of the total. Advisory warnings, as refunds and overdrafts are
+/// legitimate.
+///
+pub struct Signs;
+
+impl VerificationRule for Signs {
+    fn name(&self) -> &str {
+        CHECK_SIGN
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn advisory(&self) -> bool {
+        true
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let prefix =
+            |base: &str| starts_with(col(ACCOUNT), lit(format!("{}{}", base, ACCOUNT_SEP)));
+        let negative = prefix(&state.roots.assets).or(prefix(&state.roots.expenses));
+        let positive = prefix(&state.roots.liabilities).or(prefix(&state.roots.income));
+        let totals_df = state.journal_df()?.aggregate(
+            vec![
+                col(ACCOUNT).alias(ACCOUNT_RIGHT),
+                col(FINAL_CP_COMMODITY).alias(COMMODITY_RIGHT),
+            ],
+            vec![sum(col(FINAL_CP_QUANTITY)).alias(TOTAL)],
+        )?;
+        let first = row_number()
+            .partition_by(vec![col(ACCOUNT), col(FINAL_CP_COMMODITY)])
+            .order_by(vec![
+                col(STATEMENT_NO).sort(true, false),
+                col(START).sort(true, false),
+            ])
+            .build()?;
+        let df = state
+            .journal_df()?
+            .with_column(ROW_NO, first)?
+            .filter(col(ROW_NO).eq(lit(1u64)))?
+            .join(
+                totals_df,
+                JoinType::Inner,
+                &[ACCOUNT, FINAL_CP_COMMODITY],
+                &[ACCOUNT_RIGHT, COMMODITY_RIGHT],
+                None,
+            )?
+            .filter(
+                negative
+                    .and(col(TOTAL).lt(lit(0)))
+                    .or(positive.and(col(TOTAL).gt(lit(0)))),
+            )?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                concat(vec![
+                    col(ACCOUNT),
+                    lit(" totals "),
+                    cast(col(TOTAL), DataType::Utf8),
+                    lit(" "),
+                    col(FINAL_CP_COMMODITY),
+                ])
+                .alias(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+/// Postings of a zero amount, as written or inferred, most likely a typo
+pub struct ZeroAmounts;
+
+impl VerificationRule for ZeroAmounts {
+    fn name(&self) -> &str {
+        CHECK_ZERO_AMOUNT
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let df = state
+            .journal_df()?
+            .filter(col(FINAL_CP_QUANTITY).eq(lit(0)))?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                concat(vec![lit("zero amount posted to "), col(ACCOUNT)]).alias(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+///
+/// Postings with the sign Signs warns about in a total, for the types where
+/// one posting of it is already unusual: negative Expenses, as refunds, and
+/// positive Income, as clawbacks. Assets and Liabilities go both ways.
+///
+pub struct PostingSigns;
+
+impl VerificationRule for PostingSigns {
+    fn name(&self) -> &str {
+        CHECK_POSTING_SIGN
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn advisory(&self) -> bool {
+        true
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let prefix =
+            |base: &str| starts_with(col(ACCOUNT), lit(format!("{}{}", base, ACCOUNT_SEP)));
+        let df = state
+            .journal_df()?
+            .filter(
+                prefix(&state.roots.expenses)
+                    .and(col(FINAL_CP_QUANTITY).lt(lit(0)))
+                    .or(prefix(&state.roots.income).and(col(FINAL_CP_QUANTITY).gt(lit(0)))),
+            )?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                concat(vec![
+                    col(ACCOUNT),
+                    lit(" receives "),
+                    cast(col(FINAL_CP_QUANTITY), DataType::Utf8),
+                    lit(" "),
+                    col(FINAL_CP_COMMODITY),
+                ])
+                .alias(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+/// Transactions of a single posting, which balance only by posting zero
+pub struct SinglePostings;
+
+impl VerificationRule for SinglePostings {
+    fn name(&self) -> &str {
+        CHECK_SINGLE_POSTING
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let transactions_df = state
+            .transactions_df
+            .clone()
+            .context("No transactions df")?;
+        let df = state
+            .journal_df()?
+            .aggregate(
+                vec![col(TRANSACTION_NO)],
+                vec![
+                    count(col(STATEMENT_NO)).alias(NUM),
+                    max(col(ACCOUNT)).alias(ACCOUNT),
+                ],
+            )?
+            .filter(col(NUM).eq(lit(1)))?
+            .join(
+                transactions_df.select(vec![
+                    col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                    col(FILE_NO),
+                    col(START),
+                    col(DATE),
+                ])?,
+                JoinType::Inner,
+                &[TRANSACTION_NO],
+                &[STATEMENT_NO_RIGHT],
+                None,
+            )?
+            .select(vec![
+                col(TRANSACTION_NO).alias(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                concat(vec![
+                    lit("transaction has a single posting, to "),
+                    col(ACCOUNT),
+                ])
+                .alias(MESSAGE),
+            ])?;
+        Ok(df)
+    }
+}
+
+///
+/// Statements dated before `min` or after `max`, by default 1970-01-01 and a
+/// year from today, which are most likely mistyped or misparsed dates. A
+/// ledger can move the bounds with `option "min_date" "<date>"` and
+/// `option "max_date" "<date>"`.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub min: NaiveDate,
+    pub max: NaiveDate,
+}
+
+impl Default for DateRange {
+    fn default() -> Self {
+        let today = Local::now().date_naive();
+        Self {
+            min: NaiveDate::parse_from_str(DATE_RANGE_MIN, DATE_FORMAT).unwrap_or(NaiveDate::MIN),
+            max: today + Days::new(DATE_RANGE_FUTURE_DAYS),
+        }
+    }
+}
+
+impl DateRange {
+    /// This range with the bounds set by the options of the ledger
+    pub fn with_options(&self, state: &LedgerState) -> Self {
+        let option = |name: &str| {
+            state
+                .options
+                .get(name)
+                .and_then(|v| NaiveDate::parse_from_str(v.trim(), DATE_FORMAT).ok())
+        };
+        Self {
+            min: option(MIN_DATE_OPTION).unwrap_or(self.min),
+            max: option(MAX_DATE_OPTION).unwrap_or(self.max),
+        }
+    }
+
+    pub fn contains(&self, d: NaiveDate) -> bool {
+        self.min <= d && d <= self.max
+    }
+
+    /// The transactions, directives and prices dated outside the range, in
+    /// statement order. Needs no verify, so importers can run it on what they read.
+    pub fn outside(&self, state: &LedgerState) -> Vec<DiagnosticParams> {
+        let mut res = vec![];
+        let mut push = |statement_no, file_no, start, date: NaiveDate, what: &str| {
+            if !self.contains(date) {
+                res.push(DiagnosticParams {
+                    statement_no,
+                    file_no,
+                    start,
+                    date: Some(date),
+                    message: format!("{} dated outside {} to {}", what, self.min, self.max),
+                });
+            }
+        };
+        for t in state.transactions.iter() {
+            push(t.statement_no, t.file_no, t.start, t.date, &t.narration);
+        }
+        for v in state.verifications.iter() {
+            push(v.statement_no, v.file_no, v.start, v.date, &v.account);
+        }
+        for p in state.prices.iter() {
+            push(p.statement_no, p.file_no, p.start, p.date, &p.commodity);
+        }
+        for x in state.informationals.iter() {
+            if let Some(d) = x.date {
+                push(x.statement_no, x.file_no, x.start, d, &x.value);
+            }
+        }
+        res.sort_by_key(|x| x.statement_no);
+        res
+    }
+
+    ///
+    /// Drops the transactions with their postings, directives and prices
+    /// dated outside the range, so that an importer keeps only one period of
+    /// what it read. Needs no verify. Returns the number of statements dropped.
+    ///
+    pub fn retain(&self, state: &mut LedgerState) -> usize {
+        let n = state.transactions.len()
+            + state.verifications.len()
+            + state.prices.len()
+            + state.informationals.len();
+        let dropped: HashSet<u32> = state
+            .transactions
+            .iter()
+            .filter(|t| !self.contains(t.date))
+            .map(|t| t.statement_no)
+            .collect();
+        state
+            .transactions
+            .retain(|t| !dropped.contains(&t.statement_no));
+        state
+            .postings
+            .retain(|p| !dropped.contains(&p.transaction_no));
+        state.verifications.retain(|v| self.contains(v.date));
+        state.prices.retain(|p| self.contains(p.date));
+        state
+            .informationals
+            .retain(|x| x.date.is_none_or(|d| self.contains(d)));
+        n - state.transactions.len()
+            - state.verifications.len()
+            - state.prices.len()
+            - state.informationals.len()
+    }
+}
+
+impl VerificationRule for DateRange {
+    fn name(&self) -> &str {
+        CHECK_DATE_RANGE
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        diagnostics_df(&self.with_options(state).outside(state))
+    }
+}
+
+///
+/// Transactions dated before a transaction above them in the same file,
+/// which usually means a typo in the date or an entry pasted in the wrong
+/// place. Needs no verify, so lint runs it too.
+///
+pub fn out_of_order(state: &LedgerState) -> Vec<DiagnosticParams> {
+    let mut transactions: Vec<_> = state.transactions.iter().collect();
+    transactions.sort_by_key(|x| (x.file_no, x.start));
+    let mut latest: HashMap<u32, NaiveDate> = HashMap::new();
+    let mut res = vec![];
+    for x in transactions {
+        let l = latest.entry(x.file_no).or_insert(x.date);
+        if x.date < *l {
+            res.push(DiagnosticParams {
+                statement_no: x.statement_no,
+                file_no: x.file_no,
+                start: x.start,
+                date: Some(x.date),
+                message: format!("{} is dated before {} above it", x.narration, l),
+            });
+        } else {
+            *l = x.date;
+        }
+    }
+    res.sort_by_key(|x| x.statement_no);
+    res
+}
+
+/// Transactions out of date order within their file, see out_of_order
+pub struct DateOrder;
+
+impl VerificationRule for DateOrder {
+    fn name(&self) -> &str {
+        CHECK_DATE_ORDER
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        diagnostics_df(&out_of_order(state))
+    }
+}
+
+///
+/// The verification rules to run: the built-ins, including the handlers of
+/// custom directives, plus any pushed by the caller. A rule is skipped when disabled here or by
+/// `option "disable_check" "<name>"` in the ledger. The optional rules, the
+/// zero-amount, posting-sign and single-posting warnings, are skipped unless
+/// enabled here or by `option "enable_check" "<name>"`. In strict mode the
+/// warnings of every rule are reported as errors.
+///
+pub struct Checks {
+    rules: Vec<Box<dyn VerificationRule>>,
+    disabled: HashSet<String>,
+    optional: HashSet<String>,
+    strict: bool,
+}
+
+impl Default for Checks {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Checks {
+    pub fn builtin() -> Self {
+        Self {
+            rules: vec![
+                Box::new(Balanced),
+                Box::new(BalanceAssertions),
+                Box::new(BalanceTolerance),
+                Box::new(OpenClose),
+                Box::new(Commodities),
+                Box::new(Signs),
+                Box::new(DateRange::default()),
+                Box::new(DateOrder),
+                Box::new(ZeroAmounts),
+                Box::new(PostingSigns),
+                Box::new(SinglePostings),
+                Box::new(CustomRule(Box::new(Budget))),
+                Box::new(CustomRule(Box::new(ContributionLimit))),
+            ],
+            disabled: HashSet::new(),
+            optional: [CHECK_ZERO_AMOUNT, CHECK_POSTING_SIGN, CHECK_SINGLE_POSTING]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            strict: false,
+        }
+    }
+
+    pub fn push(&mut self, rule: Box<dyn VerificationRule>) {
+        self.rules.push(rule);
+    }
+
+    /// Dispatches the custom directives named as `handler` to it
+    pub fn push_custom(&mut self, handler: Box<dyn CustomHandler>) {
+        self.rules.push(Box::new(CustomRule(handler)));
+    }
+
+    pub fn disable(&mut self, name: &str) {
+        self.disabled.insert(name.to_string());
+    }
+
+    pub fn enable(&mut self, name: &str) {
+        self.disabled.remove(name);
+        self.optional.remove(name);
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.rules.iter().map(|r| r.name()).collect()
+    }
+
+    /// Runs the enabled rules, returning their diagnostics in statement order
+    pub async fn run(&self, state: &LedgerState) -> Result<Vec<Diagnostic>> {
+        let disabled_by_option: HashSet<&str> = state
+            .options
+            .get_all(DISABLE_CHECK_OPTION)
+            .iter()
+            .map(|x| x.as_str())
+            .collect();
+        let enabled_by_option: HashSet<&str> = state
+            .options
+            .get_all(ENABLE_CHECK_OPTION)
+            .iter()
+            .map(|x| x.as_str())
+            .collect();
+
+        let mut diagnostics = vec![];
+        for rule in self.rules.iter() {
+            let name = rule.name();
+            if self.disabled.contains(name)
+                || disabled_by_option.contains(name)
+                || self.optional.contains(name) && !enabled_by_option.contains(name)
+            {
+                continue;
+            }
+            let severity = if self.strict && !rule.advisory() {
+                Severity::Error
+            } else {
+                rule.severity()
+            };
+            let df = rule.check(state)?;
+            let mut stream = df.execute_stream().await?;
+            while let Some(b) = stream.next().await.transpose()? {
+                let get_u32 = |name: &str| -> Result<&UInt32Array> {
+                    b.column_by_name(name)
+                        .context(format!("Unable to find {} col", name))?
+                        .as_any()
+                        .downcast_ref::<UInt32Array>()
+                        .context(ERROR_DOWNCAST)
+                };
+                let date = b
+                    .column_by_name(DATE)
+                    .context("Unable to find date col")?
+                    .as_any()
+                    .downcast_ref::<Date32Array>()
+                    .context(ERROR_DOWNCAST)?;
+                let message = b
+                    .column_by_name(MESSAGE)
+                    .context("Unable to find message col")?
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .context(ERROR_DOWNCAST)?;
+                for (no, f, st, d, m) in izip!(
+                    get_u32(STATEMENT_NO)?,
+                    get_u32(FILE_NO)?,
+                    get_u32(START)?,
+                    date,
+                    message
+                ) {
+                    diagnostics.push(Diagnostic {
+                        rule: name.to_string(),
+                        severity,
+                        statement_no: no.unwrap_or(u32::MAX),
+                        file_no: f.unwrap_or(0),
+                        start: st.unwrap_or(0),
+                        date: d.map(Date32Type::to_naive_date),
+                        message: m.unwrap_or_default().to_string(),
+                    });
+                }
+            }
+        }
+        diagnostics.sort_by_key(|x| x.statement_no);
+        Ok(diagnostics)
+    }
+}
+
+/// Line and column (both from 1) of the byte offset `start` in `contents`
+pub(crate) fn line_col(contents: &str, start: u32) -> (usize, usize) {
+    let end = (start as usize).min(contents.len());
+    let before = &contents.as_bytes()[..end];
+    let line = before.iter().filter(|c| **c == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|c| *c == b'\n')
+        .map(|n| n + 1)
+        .unwrap_or(0);
+    (line, contents[line_start..end].chars().count() + 1)
+}
+
+/// Resolves the file, line and column of diagnostics, reading each file once
+struct Locator<'a> {
+    state: &'a LedgerState,
+    files: HashMap<u32, &'a PathBuf>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(state: &'a LedgerState) -> Self {
+        Self {
+            state,
+            files: state.input_files.iter().map(|(f, n)| (*n, f)).collect(),
+        }
+    }
+
+    fn locate(&mut self, x: &Diagnostic) -> Option<(&'a PathBuf, usize, usize)> {
+        if x.statement_no == u32::MAX {
+            return None;
+        }
+        let f = *self.files.get(&x.file_no)?;
+        let c = self.state.source(x.file_no).unwrap_or_default();
+        let (line, column) = line_col(&c, x.start);
+        Some((f, line, column))
+    }
+}
+
+fn count_errors(diagnostics: &[Diagnostic]) -> usize {
+    diagnostics
+        .iter()
+        .filter(|x| x.severity == Severity::Error)
+        .count()
+}
+
+///
+/// Prints diagnostics as `file:line: severity [rule] date message`. Returns
+/// the number of errors, warnings excluded.
+///
+pub fn write_diagnostics(state: &LedgerState, diagnostics: &[Diagnostic]) -> usize {
+    let mut locator = Locator::new(state);
+    for x in diagnostics {
+        let location = match locator.locate(x) {
+            Some((f, line, _)) => format!("{}:{}", f.display(), line),
+            None => String::from("-"),
+        };
+        let date = x.date.map(|d| format!(" {}", d)).unwrap_or_default();
+        println!(
+            "{}: {} [{}]{} {}",
+            location,
+            x.severity.name(),
+            x.rule,
+            date,
+            x.message
+        );
+    }
+    count_errors(diagnostics)
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    file: Option<String>,
+    line: Option<usize>,
+    column: Option<usize>,
+    severity: &'a str,
+    rule: &'a str,
+    date: Option<String>,
+    message: &'a str,
+}
+
+/// The diagnostics with their locations as written by write_diagnostics_json
+fn json_rows<'a>(state: &LedgerState, diagnostics: &'a [Diagnostic]) -> Vec<JsonDiagnostic<'a>> {
+    let mut locator = Locator::new(state);
+    diagnostics
+        .iter()
+        .map(|x| {
+            let location = locator.locate(x);
+            JsonDiagnostic {
+                file: location.map(|(f, _, _)| f.display().to_string()),
+                line: location.map(|(_, l, _)| l),
+                column: location.map(|(_, _, c)| c),
+                severity: x.severity.name(),
+                rule: &x.rule,
+                date: x.date.map(|d| d.to_string()),
+                message: &x.message,
+            }
+        })
+        .collect()
+}
+
+///
+/// Prints diagnostics as a JSON array of objects with the keys file, line,
+/// column, severity, rule, date and message; the location keys are null for
+/// findings not tied to a statement. Returns the number of errors.
+///
+pub fn write_diagnostics_json(state: &LedgerState, diagnostics: &[Diagnostic]) -> Result<usize> {
+    let rows = json_rows(state, diagnostics);
+    println!("{}", serde_json::to_string_pretty(&rows)?);
+    Ok(count_errors(diagnostics))
+}
+
+///
+/// Prints diagnostics as the objects of write_diagnostics_json, one per
+/// line, with their severity as the event. Returns the number of errors.
+///
+pub fn write_diagnostics_ndjson(state: &LedgerState, diagnostics: &[Diagnostic]) -> Result<usize> {
+    let mut w = std::io::stdout().lock();
+    for x in json_rows(state, diagnostics) {
+        write_event(&mut w, x.severity, &x)?;
+    }
+    Ok(count_errors(diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::core::{BALANCE_ACTION, PENDING_FLAG};
+    use crate::parse::parse_contents;
+
+    #[test]
+    fn parse_failure_is_located_in_its_file() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-01 open Assets:A\n2024-01-05 * \"x\"\n  Assets:A 1.00\n";
+        let e = parse_contents(f, contents, &mut state).unwrap_err();
+        let diagnostics = [Diagnostic::parse_failure(&state, &e)];
+        let rows = json_rows(&state, &diagnostics);
+        assert_eq!(rows[0].file.as_deref(), Some("buffer.bean"));
+        assert_eq!((rows[0].line, rows[0].column), (Some(3), Some(16)));
+        assert_eq!(rows[0].rule, CHECK_PARSE);
+        assert_eq!(rows[0].message, "invalid amount, expected commodity");
+    }
+
+    #[test]
+    fn other_failures_are_not_tied_to_a_statement() {
+        let state = LedgerState::new();
+        let e = anyhow::anyhow!("nope.bean: No such file or directory");
+        let x = Diagnostic::parse_failure(&state, &e);
+        assert_eq!(x.statement_no, u32::MAX);
+        assert_eq!(x.message, "nope.bean: No such file or directory");
+    }
+
+    #[test]
+    fn retain_drops_what_is_outside_with_its_postings() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-01 open Assets:A\n\
+                        2024-01-05 * \"in\"\n  Assets:A 1.00 CAD\n  Income:A\n\
+                        2024-02-05 * \"out\"\n  Assets:A 2.00 CAD\n  Income:A\n\
+                        2024-01-31 balance Assets:A 1.00 CAD\n\
+                        2024-02-01 price XYZ 3.00 CAD\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        let range = DateRange {
+            min: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            max: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+        };
+        assert_eq!(range.retain(&mut state), 3);
+        assert_eq!(state.transactions.len(), 1);
+        assert_eq!(state.transactions[0].narration, "in");
+        assert_eq!(state.postings.len(), 2);
+        assert!(
+            state
+                .postings
+                .iter()
+                .all(|p| p.transaction_no == state.transactions[0].statement_no)
+        );
+        assert_eq!(state.verifications.len(), 1);
+        assert_eq!(state.verifications[0].action, BALANCE_ACTION);
+        assert!(state.prices.is_empty());
+    }
+
+    /// The messages of the optional rules for `contents`, with `enabled` enabled
+    async fn optional_messages(contents: &str, enabled: &[&str]) -> Vec<String> {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        let mut checks = Checks::builtin();
+        for name in enabled {
+            checks.enable(name);
+        }
+        let optional = [CHECK_ZERO_AMOUNT, CHECK_POSTING_SIGN, CHECK_SINGLE_POSTING];
+        checks
+            .run(&state)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|x| optional.contains(&x.rule.as_str()))
+            .map(|x| format!("{} {} {}", x.severity.name(), x.rule, x.message))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn optional_checks_warn_only_when_enabled() {
+        let contents = "2024-01-01 open Assets:A\n2024-01-01 open Expenses:B\n\
+                        2024-01-02 * \"refund\"\n  Expenses:B -5.00 CAD\n  Assets:A\n\
+                        2024-01-03 * \"nothing\"\n  Assets:A 0.00 CAD\n";
+        assert!(optional_messages(contents, &[]).await.is_empty());
+        assert_eq!(
+            optional_messages(contents, &[CHECK_POSTING_SIGN, CHECK_SINGLE_POSTING]).await,
+            [
+                "warning posting-sign Expenses:B receives -5.00 CAD",
+                "warning single-posting transaction has a single posting, to Assets:A",
+            ]
+        );
+        let by_option = format!("option \"enable_check\" \"zero-amount\"\n{}", contents);
+        assert_eq!(
+            optional_messages(&by_option, &[]).await,
+            ["warning zero-amount zero amount posted to Assets:A"]
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_see_the_whole_ledger_whatever_the_flag_filter() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-01 open Assets:A\n2024-01-01 open Income:A\n\
+                        2024-01-02 * \"cleared\"\n  Assets:A 5.00 CAD\n  Income:A\n\
+                        2024-01-03 ! \"pending\"\n  Assets:A 2.00 CAD\n  Income:A\n\
+                        2024-01-04 balance Assets:A 7.00 CAD\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        state.flag_filter = Some(PENDING_FLAG.to_string());
+        state.verify().await.unwrap();
+        let diagnostics = Checks::builtin().run(&state).await.unwrap();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let count = |df: DataFrame| async { df.count().await.unwrap() };
+        assert_eq!(count(state.journal_df().unwrap()).await, 4);
+        assert_eq!(count(state.report_journal_df().unwrap()).await, 2);
+    }
+
+    #[tokio::test]
+    async fn strict_leaves_the_advisory_checks_as_located_warnings() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-01 open Assets:A CAD\n2024-01-01 open Expenses:B\n\
+                        2024-01-02 * \"refund\"\n  Expenses:B -5.00 CAD\n  Assets:A\n\
+                        2024-01-03 * \"foreign\"\n  Assets:A 2.00 USD\n  Expenses:B\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        let mut checks = Checks::builtin();
+        checks.set_strict(true);
+        let messages: Vec<String> = checks
+            .run(&state)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| {
+                let date = x.date.map(|d| d.to_string()).unwrap_or_default();
+                format!("{} {} {} {}", x.severity.name(), x.rule, date, x.message)
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "warning sign 2024-01-02 Expenses:B totals -5.00 CAD",
+                "error commodity 2024-01-03 USD is not declared by the open of Assets:A",
+                "warning sign 2024-01-03 Expenses:B totals -2.00 USD",
+            ]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::RangeInclusive;
+
 use arrow_convert::{ArrowDeserialize, ArrowField, ArrowSerialize};
 
 use chrono::NaiveDate;
@@ -9,6 +11,11 @@ pub const LIABILITIES_BASE: &str = "Liabilities";
 pub const EQUITY_BASE: &str = "Equity";
 pub const INCOME_BASE: &str = "Income";
 pub const EXPENSES_BASE: &str = "Expenses";
+pub const NAME_ASSETS_OPTION: &str = "name_assets";
+pub const NAME_LIABILITIES_OPTION: &str = "name_liabilities";
+pub const NAME_EQUITY_OPTION: &str = "name_equity";
+pub const NAME_INCOME_OPTION: &str = "name_income";
+pub const NAME_EXPENSES_OPTION: &str = "name_expenses";
 
 pub const OPEN_SYMBOL: &str = "open";
 pub const CLOSE_SYMBOL: &str = "close";
@@ -16,7 +23,24 @@ pub const BALANCE_SYMBOL: &str = "balance";
 pub const EVENT_SYMBOL: &str = "event";
 pub const OPTION_SYMBOL: &str = "option";
 pub const INCLUDE_SYMBOL: &str = "include";
+pub const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+pub const BOM: char = '\u{feff}';
+pub const CRLF: &str = "\r\n";
 pub const CUSTOM_SYMBOL: &str = "custom";
+pub const NOTE_SYMBOL: &str = "note";
+pub const PRICE_SYMBOL: &str = "price";
+
+// The tables verify registers in the SessionContext of a ledger
+pub const TRANSACTIONS_TABLE: &str = "transactions";
+pub const POSTINGS_TABLE: &str = "postings";
+pub const VERIFICATIONS_TABLE: &str = "verifications";
+pub const INFORMATIONALS_TABLE: &str = "informationals";
+pub const METADATA_TABLE: &str = "metadata";
+pub const PRICES_TABLE: &str = "prices";
+pub const ERRORS_TABLE: &str = "errors";
+pub const ACCOUNTS_TABLE: &str = "accounts";
+pub const CP_COMMODITIES_TABLE: &str = "cp_commodities";
+pub const TC_COMMODITIES_TABLE: &str = "tc_commodities";
 
 pub const DATE_FORMAT: &str = "%Y-%m-%d";
 pub const ACCOUNT: &str = "account";
@@ -30,33 +54,145 @@ pub const CP_QUANTITY: &str = "cp_quantity";
 pub const FILE_NO: &str = "file_no";
 pub const LENGTH: &str = "length";
 pub const START: &str = "start";
+pub const START_RIGHT: &str = "start_right";
 pub const STATEMENT_NO: &str = "statement_no";
 pub const STATEMENT_NO_RIGHT: &str = "statement_no_right";
+pub const CMP_ACCOUNT: &str = "cmp_account";
 pub const TC_COMMODITY: &str = "tc_commodity";
 pub const TC_COMMODITY_RIGHT: &str = "tc_commodity_right";
 pub const TC_QUANTITY: &str = "tc_quantity";
+pub const PER_UNIT: &str = "per_unit";
+pub const UNIT_PRICE: &str = "unit_price";
 pub const COMMODITY: &str = "commodity";
+pub const DEFAULT_COMMODITY: &str = "default_commodity";
+pub const COMMODITY_LIST_SEP: &str = ",";
 pub const QUANTITY: &str = "quantity";
 pub const TOTALS: &str = "totals";
+pub const RAW: &str = "raw";
 pub const NUM: &str = "num";
+pub const SPLIT_NO: &str = "split_no";
 pub const TRANSACTION_NO: &str = "transaction_no";
 pub const TRANSACTION_NO_RIGHT: &str = "transaction_no_right";
 pub const ACCOUNT_SEP: &str = ":";
+/// The Unicode blocks of combining diacritical marks, allowed in account names
+pub const COMBINING_MARKS: [RangeInclusive<char>; 5] = [
+    '\u{0300}'..='\u{036f}',
+    '\u{1ab0}'..='\u{1aff}',
+    '\u{1dc0}'..='\u{1dff}',
+    '\u{20d0}'..='\u{20ff}',
+    '\u{fe20}'..='\u{fe2f}',
+];
+pub const COMMODITY_PUNCTUATION: &str = "'.-";
+pub const ACCOUNT_WILDCARD: char = '*';
+pub const TODO_ACCOUNT: &str = "TODO";
 pub const PRECISION: usize = 38;
 pub const SCALE: usize = 2;
+pub const PRICE_SCALE: usize = 6;
+pub const CONVERTED_SCALE: usize = SCALE + PRICE_SCALE;
 pub const ACTION_COL: &str = "action";
 
 pub const TOTAL: &str = "total";
+pub const TOLERANCE: &str = "tolerance";
 pub const ACCOUNT_RIGHT: &str = "account_right";
 pub const TOTALS_ACCOUNT: &str = "account_totals";
 pub const RIGHT_QUALIFIER: &str = "_right";
 pub const MATCH: &str = "match";
 pub const DATE: &str = "date";
+pub const POSTING_DATE: &str = "posting_date";
+pub const POSTING_ACCOUNT: &str = "posting_account";
+pub const POSTING_FLAG: &str = "posting_flag";
+pub const COMMENT: &str = "comment";
 pub const COST_SEP: &str = "@@";
+pub const PRICE_SEP: &str = "@";
 pub const TRANSACTION_FLAG: &str = "*";
+pub const PENDING_FLAG: &str = "!";
+pub const FLAG: &str = "flag";
+pub const SUBTREE_FLAG: &str = "*";
+pub const TOLERANCE_SYMBOL: &str = "~";
 pub const TAGS: &str = "tags";
+pub const OPENING: &str = "opening";
+pub const CHANGE: &str = "change";
+pub const CLOSING: &str = "closing";
+pub const EARNINGS_ACCOUNT: &str = "Equity:Earnings";
+pub const EARNINGS_NAME: &str = "Earnings";
+pub const INVESTMENTS_NAME: &str = "Investments";
+pub const ROUNDING_NAME: &str = "Rounding";
+pub const TAG: &str = "tag";
+pub const TAG_SEP: &str = " ";
+pub const UNTAGGED: &str = "untagged";
+pub const PNL_BY_ACCOUNT: &str = "account";
+pub const PNL_BY_TAG: &str = "tag";
+pub const META_PREFIX: &str = "meta:";
+pub const META_SEP: &str = ":";
+pub const NO_META: &str = "none";
+pub const KEY: &str = "key";
+pub const PRICE: &str = "price";
+pub const CURRENCY: &str = "currency";
+pub const CONVERTED: &str = "converted";
+pub const PRICE_COMMODITY: &str = "price_commodity";
+pub const PRICE_CURRENCY: &str = "price_currency";
+pub const UNITS: &str = "units";
+pub const BUY_UNITS: &str = "buy_units";
+pub const BUY_COST: &str = "buy_cost";
+pub const COST_BASIS: &str = "cost_basis";
+pub const AVERAGE_COST: &str = "average_cost";
+pub const MARKET_VALUE: &str = "market_value";
+pub const GAIN: &str = "gain";
+pub const PRICE_DATE: &str = "price_date";
+pub const ROUNDING: &str = "rounding";
+pub const MESSAGE: &str = "message";
+pub const OPEN_DATE: &str = "open_date";
+pub const CLOSE_DATE: &str = "close_date";
+pub const DISABLE_CHECK_OPTION: &str = "disable_check";
+pub const ENABLE_CHECK_OPTION: &str = "enable_check";
+pub const MIN_DATE_OPTION: &str = "min_date";
+pub const MAX_DATE_OPTION: &str = "max_date";
+pub const OPERATING_CURRENCY_OPTION: &str = "operating_currency";
+pub const ROUNDING_ACCOUNT_OPTION: &str = "rounding_account";
+pub const TITLE_OPTION: &str = "title";
+pub const TOLERANCE_OPTION: &str = "inferred_tolerance_default";
+pub const DATE_RANGE_MIN: &str = "1970-01-01";
+pub const DATE_RANGE_FUTURE_DAYS: u64 = 366;
+pub const PERIOD: &str = "period";
+pub const UNTIL: &str = "until";
+pub const CASHFLOW_NET: &str = "Net";
+pub const CASHFLOW_DEPTH: usize = 2;
+pub const COVERAGE_GAP_DAYS: u32 = 31;
+pub const RUNWAY_MONTHS: u32 = 6;
+pub const GEN_FILES: usize = 4;
+pub const GEN_ACCOUNTS: usize = 16;
+pub const SAMPLE_ACCOUNTS: usize = 8;
+pub const GEN_SEED: u64 = 42;
+pub const HASH_PREFIX: &str = "sha256";
+pub const HMAC_PREFIX: &str = "hmac-sha256";
+pub const HMAC_BLOCK_BYTES: usize = 64;
+pub const MEAN: &str = "mean";
+pub const STDDEV: &str = "stddev";
+pub const ANOMALY_STDDEVS: f64 = 3.0;
+pub const ANOMALY_MIN_POSTINGS: i64 = 5;
+pub const LINT_STALE_DAYS: i64 = 90;
+pub const RECUR_KEY: &str = "recur";
+pub const FORECAST_TAG: &str = "#forecast";
+pub const CONTRIBUTION_TAG: &str = "#contribution";
+pub const DOMESTIC_COUNTRY: &str = "CA";
+pub const DOMESTIC_CURRENCY: &str = "CAD";
+pub const YEAR: &str = "year";
+pub const ROOM: &str = "room";
+pub const CONVERT_LEDGER: &str = "ledger";
+pub const CONVERT_HLEDGER: &str = "hledger";
+pub const FMT_COLUMN: usize = 52;
+pub const FORMAT_TABLE: &str = "table";
+pub const FORMAT_NDJSON: &str = "ndjson";
+pub const EVENT_TRANSACTION: &str = "transaction";
+pub const EVENT_POSTING: &str = "posting";
+pub const EVENT_BALANCE: &str = "balance";
+pub const EVENT_ERROR: &str = "error";
+pub const STREAM_BATCH_BYTES: usize = 1 << 20;
+pub const STREAM_AHEAD_BATCHES: usize = 16;
 
 pub const NARRATION: &str = "narration";
+pub const ATTRIBUTE: &str = "attribute";
+pub const VALUE: &str = "value";
 
 pub const ERROR_NO_ACCOUNT_DF: &str = "No accounts dataframe";
 pub const ERROR_NO_POSTINGS_DF: &str = "No postings dataframe";
@@ -69,6 +205,8 @@ pub const CLOSE_ACTION: u32 = 2;
 pub const EVENT_ACTION: u32 = 3;
 pub const OPTION_ACTION: u32 = 4;
 pub const CUSTOM_ACTION: u32 = 5;
+pub const NOTE_ACTION: u32 = 6;
+pub const SUBTREE_BALANCE_ACTION: u32 = 7;
 
 #[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
 pub struct IncludeParams {
@@ -87,10 +225,14 @@ pub struct VerificationParams {
     pub start: u32,
     pub end: u32,
     pub date: NaiveDate,
-    pub action: u32, // Open, Balance, CLose
+    pub action: u32, // Open, Balance, CLose, Subtree Balance
     pub account: String,
     pub quantity: Option<Decimal>,
     pub commodity: Option<String>,
+    /// How far the balance may be off, `~ 0.01` after the amount
+    pub tolerance: Option<Decimal>,
+    /// The statement as written, when the parser keeps it
+    pub raw: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
@@ -101,8 +243,12 @@ pub struct HeaderParams {
     pub start: u32,
     pub end: u32,
     pub date: NaiveDate,
+    pub flag: String,
     pub narration: String,
     pub tags: Option<String>,
+    /// The transaction as written from its header to its last posting or
+    /// metadata line, when the parser keeps it
+    pub raw: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
@@ -116,8 +262,39 @@ pub struct PostingParams {
     pub account: String,
     pub cp_quantity: Option<Decimal>,
     pub cp_commodity: Option<String>,
+    /// The price as written after `@@`, or `@` when per_unit, else the
+    /// units; verify weighs the posting by it
     pub tc_quantity: Option<Decimal>,
     pub tc_commodity: Option<String>,
+    /// Whether tc_quantity is a price per unit rather than a total
+    pub per_unit: bool,
+    pub flag: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct PriceParams {
+    pub statement_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub end: u32,
+    pub date: NaiveDate,
+    pub commodity: String,
+    pub price: Decimal,
+    pub currency: String,
+    /// The statement as written, when the parser keeps it
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct MetadataParams {
+    pub statement_no: u32,
+    pub transaction_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub end: u32,
+    pub key: String,
+    pub value: String,
 }
 
 #[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
@@ -127,7 +304,68 @@ pub struct InfoParams {
     pub start: u32,
     pub end: u32,
     pub date: Option<NaiveDate>,
-    pub action: u32, // Event, Option, Custom
+    pub action: u32, // Event, Option, Custom, Note
     pub attribute: Option<String>,
     pub value: String,
+    /// The statement as written, when the parser keeps it
+    pub raw: Option<String>,
+}
+
+/// Price coverage of a commodity over the dates it was posted
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct CoverageParams {
+    pub commodity: String,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+    pub prices: u32,
+    pub largest_gap: u32,
+    pub gaps: u32,
+    pub gap_ranges: String,
+}
+
+/// A statement cycle of an account against the statement balance at its close
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct StatementCycleParams {
+    pub commodity: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub postings: u32,
+    pub change: Decimal,
+    pub closing: Decimal,
+    pub statement: Option<Decimal>,
+    pub difference: Option<Decimal>,
+    pub cycle_difference: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct DiagnosticParams {
+    pub statement_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub date: Option<NaiveDate>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct BudgetParams {
+    pub statement_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub date: NaiveDate,
+    pub account: String,
+    pub period: String,
+    pub quantity: Decimal,
+    pub commodity: String,
+    pub until: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, ArrowField, ArrowSerialize, ArrowDeserialize)]
+pub struct ContributionLimitParams {
+    pub statement_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub account: String,
+    pub year: i32,
+    pub quantity: Decimal,
+    pub commodity: String,
 }
@@ -0,0 +1,387 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use anyhow::Result;
+use arrow::array::{Array, RecordBatch, StructArray};
+use arrow::datatypes::DataType;
+use arrow_convert::{
+    field::ArrowField,
+    serialize::{ArrowSerialize, TryIntoArrow},
+};
+use chrono::{Datelike, NaiveDate};
+use datafusion::functions::datetime::expr_fn::{date_trunc, make_date};
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::check::{Severity, VerificationRule};
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, BudgetParams, COMMODITY, CUSTOM_ACTION, ContributionLimitParams, DATE,
+    DiagnosticParams, FILE_NO, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, MESSAGE, PERIOD,
+    POSTING_ACCOUNT, POSTING_DATE, PRECISION, QUANTITY, ROOM, SCALE, START, STATEMENT_NO, TOTAL,
+    UNTIL, YEAR,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::zero_lit;
+
+pub const CUSTOM_BUDGET: &str = "budget";
+pub const CUSTOM_CONTRIBUTION_LIMIT: &str = "contribution-limit";
+pub const CUSTOM_REPORT: &str = "report";
+
+pub const BUDGET_MONTHLY: &str = "monthly";
+pub const BUDGET_QUARTERLY: &str = "quarterly";
+pub const BUDGET_YEARLY: &str = "yearly";
+
+/// A `custom` directive split into its name, the first token, and arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomDirective {
+    pub statement_no: u32,
+    pub file_no: u32,
+    pub start: u32,
+    pub date: Option<NaiveDate>,
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// Splits a custom value on whitespace, keeping quoted strings whole and
+/// dropping a trailing comment
+fn tokens(value: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => res.push(chars.by_ref().take_while(|c| *c != '"').collect()),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut t = String::from(c);
+                while let Some(n) = chars.next_if(|n| !n.is_whitespace()) {
+                    t.push(n);
+                }
+                res.push(t);
+            }
+        }
+    }
+    res
+}
+
+/// The custom directives of the ledger, in statement order
+pub fn custom_directives(state: &LedgerState) -> Vec<CustomDirective> {
+    let mut res: Vec<CustomDirective> = state
+        .informationals
+        .iter()
+        .filter(|x| x.action == CUSTOM_ACTION)
+        .filter_map(|x| {
+            let mut args = tokens(&x.value);
+            if args.is_empty() {
+                return None;
+            }
+            let name = args.remove(0);
+            Some(CustomDirective {
+                statement_no: x.statement_no,
+                file_no: x.file_no,
+                start: x.start,
+                date: x.date,
+                name,
+                args,
+            })
+        })
+        .collect();
+    res.sort_by_key(|x| x.statement_no);
+    res
+}
+
+///
+/// The command line saved as the report `name`, by a directive such as
+/// `2024-01-01 custom "report" "food" "register" "--account" "Expenses:*:Food"`:
+/// the command then its arguments, the ledger left out. The last directive
+/// of a name overrides the earlier ones.
+///
+pub fn saved_report(state: &LedgerState, name: &str) -> Option<Vec<String>> {
+    custom_directives(state)
+        .into_iter()
+        .filter(|x| x.name == CUSTOM_REPORT && x.args.first().is_some_and(|n| n == name))
+        .map(|x| x.args[1..].to_vec())
+        .rfind(|x| !x.is_empty())
+}
+
+pub(crate) fn read_rows<T>(rows: &[T]) -> Result<DataFrame>
+where
+    T: ArrowSerialize + ArrowField<Type = T> + 'static,
+{
+    let ctx = SessionContext::new();
+    let array: Arc<dyn Array> = rows.try_into_arrow()?;
+    let struct_array = array
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .context("Unable to downcast rows")?;
+    let batch: RecordBatch = struct_array.into();
+    Ok(ctx.read_batch(batch)?)
+}
+
+/// DiagnosticParams, for handlers that build findings row by row, as a DataFrame shaped as VerificationRule::check returns
+pub fn diagnostics_df(rows: &[DiagnosticParams]) -> Result<DataFrame> {
+    read_rows(rows)
+}
+
+///
+/// Gives semantics to the `custom "<name>" ...` directives with its name.
+/// Registered with Checks::push_custom, a handler runs as a verification
+/// rule of the same name, so it can be disabled like any other.
+///
+pub trait CustomHandler {
+    fn name(&self) -> &str;
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    /// Findings for `directives`, shaped as VerificationRule::check returns
+    fn handle(&self, directives: &[CustomDirective], state: &LedgerState) -> Result<DataFrame>;
+}
+
+/// Runs a CustomHandler on the directives dispatched to it
+pub(crate) struct CustomRule(pub(crate) Box<dyn CustomHandler>);
+
+impl VerificationRule for CustomRule {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn severity(&self) -> Severity {
+        self.0.severity()
+    }
+
+    fn check(&self, state: &LedgerState) -> Result<DataFrame> {
+        let directives: Vec<CustomDirective> = custom_directives(state)
+            .into_iter()
+            .filter(|x| x.name == self.0.name())
+            .collect();
+        self.0.handle(&directives, state)
+    }
+}
+
+///
+/// `custom "budget" Account "monthly" 500.00 CAD` budgets the cp amounts
+/// posted to the account and its sub-accounts, per monthly, quarterly or
+/// yearly period, from the directive's date until the next budget of the
+/// account in the same commodity. Each period spending more is reported.
+///
+pub struct Budget;
+
+impl Budget {
+    fn params(d: &CustomDirective) -> Option<BudgetParams> {
+        let [account, period, quantity, commodity] = d.args.as_slice() else {
+            return None;
+        };
+        if ![BUDGET_MONTHLY, BUDGET_QUARTERLY, BUDGET_YEARLY].contains(&period.as_str()) {
+            return None;
+        }
+        Some(BudgetParams {
+            statement_no: d.statement_no,
+            file_no: d.file_no,
+            start: d.start,
+            date: d.date?,
+            account: account.clone(),
+            period: period.clone(),
+            quantity: Decimal::from_str_exact(quantity).ok()?,
+            commodity: commodity.clone(),
+            until: None,
+        })
+    }
+}
+
+impl CustomHandler for Budget {
+    fn name(&self) -> &str {
+        CUSTOM_BUDGET
+    }
+
+    fn handle(&self, directives: &[CustomDirective], state: &LedgerState) -> Result<DataFrame> {
+        let mut budgets = vec![];
+        let mut invalid = vec![];
+        for d in directives {
+            match Self::params(d) {
+                Some(b) => budgets.push(b),
+                None => invalid.push(DiagnosticParams {
+                    statement_no: d.statement_no,
+                    file_no: d.file_no,
+                    start: d.start,
+                    date: d.date,
+                    message: format!("invalid budget: {}", d.args.join(" ")),
+                }),
+            }
+        }
+
+        budgets.sort_by_key(|b| (b.date, b.statement_no));
+        for n in 0..budgets.len() {
+            budgets[n].until = budgets[n + 1..]
+                .iter()
+                .find(|b| b.account == budgets[n].account && b.commodity == budgets[n].commodity)
+                .map(|b| b.date);
+        }
+
+        let budgets_df = read_rows(&budgets)?.with_column(
+            QUANTITY,
+            cast(
+                col(QUANTITY),
+                DataType::Decimal128(PRECISION as u8, SCALE as i8),
+            ),
+        )?;
+
+        let postings_df = state.journal_df()?.select(vec![
+            col(DATE).alias(POSTING_DATE),
+            col(ACCOUNT).alias(POSTING_ACCOUNT),
+            col(FINAL_CP_COMMODITY),
+            col(FINAL_CP_QUANTITY),
+        ])?;
+
+        let mut df = diagnostics_df(&invalid)?;
+        for (period, granularity) in [
+            (BUDGET_MONTHLY, "month"),
+            (BUDGET_QUARTERLY, "quarter"),
+            (BUDGET_YEARLY, "year"),
+        ] {
+            let account_match = col(POSTING_ACCOUNT).eq(col(ACCOUNT)).or(starts_with(
+                col(POSTING_ACCOUNT),
+                concat(vec![col(ACCOUNT), lit(ACCOUNT_SEP)]),
+            ));
+            let period_df = budgets_df
+                .clone()
+                .filter(col(PERIOD).eq(lit(period)))?
+                .join_on(
+                    postings_df.clone(),
+                    JoinType::Inner,
+                    vec![
+                        col(COMMODITY).eq(col(FINAL_CP_COMMODITY)),
+                        col(POSTING_DATE).gt_eq(col(DATE)),
+                        col(UNTIL).is_null().or(col(POSTING_DATE).lt(col(UNTIL))),
+                        account_match,
+                    ],
+                )?
+                .with_column(
+                    PERIOD,
+                    cast(
+                        date_trunc(lit(granularity), col(POSTING_DATE)),
+                        DataType::Date32,
+                    ),
+                )?
+                .aggregate(
+                    vec![
+                        col(STATEMENT_NO),
+                        col(FILE_NO),
+                        col(START),
+                        col(PERIOD),
+                        col(ACCOUNT),
+                        col(QUANTITY),
+                        col(COMMODITY),
+                    ],
+                    vec![sum(col(FINAL_CP_QUANTITY)).alias(TOTAL)],
+                )?
+                .filter(col(TOTAL).gt(col(QUANTITY)))?
+                .select(vec![
+                    col(STATEMENT_NO),
+                    col(FILE_NO),
+                    col(START),
+                    col(PERIOD).alias(DATE),
+                    concat(vec![
+                        col(ACCOUNT),
+                        lit(" spent "),
+                        cast(col(TOTAL), DataType::Utf8),
+                        lit(" "),
+                        col(COMMODITY),
+                        lit(format!(" over a {} budget of ", period)),
+                        cast(col(QUANTITY), DataType::Utf8),
+                    ])
+                    .alias(MESSAGE),
+                ])?;
+            df = df.union(period_df)?;
+        }
+        Ok(df)
+    }
+}
+
+///
+/// `custom "contribution-limit" Assets:Investments:RRSP 31560.00 CAD`
+/// declares the contribution room added to a registered account, such as
+/// an RRSP or a TFSA, for the calendar year of the directive's date; a
+/// later directive for the same year replaces it. Room left unused carries
+/// forward, so the first directive may be the room available so far. Each
+/// year ending with contributions beyond the room, as reported by
+/// contribution_room_df, is an overcontribution.
+///
+pub struct ContributionLimit;
+
+impl ContributionLimit {
+    fn params(d: &CustomDirective) -> Option<ContributionLimitParams> {
+        let [account, quantity, commodity] = d.args.as_slice() else {
+            return None;
+        };
+        Some(ContributionLimitParams {
+            statement_no: d.statement_no,
+            file_no: d.file_no,
+            start: d.start,
+            account: account.clone(),
+            year: d.date?.year(),
+            quantity: Decimal::from_str_exact(quantity).ok()?,
+            commodity: commodity.clone(),
+        })
+    }
+
+    /// The valid contribution-limit directives of `directives`, and a finding for each other one
+    pub(crate) fn limits(
+        directives: &[CustomDirective],
+    ) -> (Vec<ContributionLimitParams>, Vec<DiagnosticParams>) {
+        let mut limits = vec![];
+        let mut invalid = vec![];
+        for d in directives
+            .iter()
+            .filter(|x| x.name == CUSTOM_CONTRIBUTION_LIMIT)
+        {
+            match Self::params(d) {
+                Some(x) => limits.push(x),
+                None => invalid.push(DiagnosticParams {
+                    statement_no: d.statement_no,
+                    file_no: d.file_no,
+                    start: d.start,
+                    date: d.date,
+                    message: format!("invalid contribution limit: {}", d.args.join(" ")),
+                }),
+            }
+        }
+        (limits, invalid)
+    }
+}
+
+impl CustomHandler for ContributionLimit {
+    fn name(&self) -> &str {
+        CUSTOM_CONTRIBUTION_LIMIT
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn handle(&self, directives: &[CustomDirective], state: &LedgerState) -> Result<DataFrame> {
+        let (_, invalid) = Self::limits(directives);
+        let over_df = state
+            .contribution_years_df()?
+            .filter(col(ROOM).lt(zero_lit()))?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                make_date(col(YEAR), lit(12), lit(31)).alias(DATE),
+                concat(vec![
+                    col(ACCOUNT),
+                    lit(" is overcontributed by "),
+                    cast(-col(ROOM), DataType::Utf8),
+                    lit(" "),
+                    col(COMMODITY),
+                    lit(" in "),
+                    cast(col(YEAR), DataType::Utf8),
+                ])
+                .alias(MESSAGE),
+            ])?;
+        Ok(diagnostics_df(&invalid)?.union(over_df)?)
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Write;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use arrow::array::RecordBatch;
+use arrow::json::writer::{LineDelimited, WriterBuilder};
+use datafusion::execution::SendableRecordBatchStream;
+use datafusion::physical_plan::stream::RecordBatchReceiverStream;
+use datafusion::prelude::*;
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
+
+use crate::core::{FORMAT_NDJSON, FORMAT_TABLE, STREAM_AHEAD_BATCHES};
+use crate::locale::Locale;
+
+/// How the commands print their results, as chosen by --format
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    /// One JSON object per line and per row, as written by write_events
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            FORMAT_TABLE => Ok(Self::Table),
+            FORMAT_NDJSON => Ok(Self::Ndjson),
+            _ => Err(anyhow!("Unknown format: {}", name)),
+        }
+    }
+
+    ///
+    /// Prints `df` to stdout as a table with its amounts and dates rendered
+    /// by `locale`, or as one `event` per row
+    ///
+    pub async fn show(&self, df: DataFrame, event: &str, locale: &Locale) -> Result<()> {
+        match self {
+            Self::Table => locale.print_batches(&df.collect().await?)?,
+            Self::Ndjson => {
+                write_events(&mut std::io::stdout().lock(), event, df).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// show of the rows `stream` yields, as from spawn_stream
+    pub async fn show_stream(
+        &self,
+        stream: SendableRecordBatchStream,
+        event: &str,
+        locale: &Locale,
+    ) -> Result<()> {
+        match self {
+            Self::Table => {
+                let batches: Vec<RecordBatch> = stream.try_collect().await?;
+                locale.print_batches(&batches)?;
+            }
+            Self::Ndjson => {
+                write_stream_events(&mut std::io::stdout().lock(), event, stream).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// Starts executing `df` on a task of its own, so that the plans of several
+/// reports run at once on their shared context while one is printed. The
+/// batches wait, up to STREAM_AHEAD_BATCHES of them, until the stream
+/// returned is read; dropping it stops the plan.
+///
+pub fn spawn_stream(df: DataFrame) -> SendableRecordBatchStream {
+    let schema = df.schema().inner().clone();
+    let mut builder = RecordBatchReceiverStream::builder(schema, STREAM_AHEAD_BATCHES);
+    let tx = builder.tx();
+    builder.spawn(async move {
+        let mut stream = df.execute_stream().await?;
+        while let Some(b) = stream.next().await {
+            if tx.send(b).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+    builder.build()
+}
+
+/// One line of `--format ndjson`: what it is, then its fields
+#[derive(Serialize)]
+pub(crate) struct Event<'a, T: Serialize> {
+    pub(crate) event: &'a str,
+    #[serde(flatten)]
+    pub(crate) fields: T,
+}
+
+/// Writes `event` with `fields` as one line of JSON
+pub(crate) fn write_event<T: Serialize>(w: &mut impl Write, event: &str, fields: T) -> Result<()> {
+    serde_json::to_writer(&mut *w, &Event { event, fields })?;
+    writeln!(w)?;
+    Ok(())
+}
+
+///
+/// Writes each row of `df` as one line of JSON, an object with `event` as
+/// its `event` key and the columns of the row as the others, nulls included
+/// so that every line of an event has the same keys, in the order of the
+/// columns. Amounts are written as numbers and dates as `YYYY-MM-DD`. Rows
+/// are written batch by batch as the plan produces them. Returns the number
+/// of rows.
+///
+pub async fn write_events(w: &mut impl Write, event: &str, df: DataFrame) -> Result<usize> {
+    write_stream_events(w, event, df.execute_stream().await?).await
+}
+
+/// write_events of the rows `stream` yields
+pub async fn write_stream_events(
+    w: &mut impl Write,
+    event: &str,
+    mut stream: SendableRecordBatchStream,
+) -> Result<usize> {
+    let mut n = 0;
+    while let Some(b) = stream.next().await.transpose()? {
+        let mut writer = WriterBuilder::new()
+            .with_explicit_nulls(true)
+            .build::<_, LineDelimited>(vec![]);
+        writer.write(&b)?;
+        writer.finish()?;
+        let lines = writer.into_inner();
+        // The objects are spliced rather than parsed, which would read the
+        // amounts as floats and sort the columns
+        let event = serde_json::to_string(event)?;
+        for line in String::from_utf8(lines)?.lines() {
+            match line.strip_prefix('{') {
+                Some("}") => writeln!(w, "{{\"event\":{}}}", event)?,
+                Some(rest) => writeln!(w, "{{\"event\":{},{}", event, rest)?,
+                None => return Err(anyhow!("Unexpected JSON row: {}", line)),
+            }
+            n += 1;
+        }
+    }
+    Ok(n)
+}
@@ -0,0 +1,382 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::core::{
+    BALANCE_SYMBOL, CLOSE_ACTION, CLOSE_SYMBOL, CRLF, OPEN_ACTION, OPEN_SYMBOL, PRICE_SEP,
+    PRICE_SYMBOL, SUBTREE_BALANCE_ACTION, SUBTREE_FLAG, TOLERANCE_SYMBOL,
+};
+use crate::parse::parse_shallow;
+use crate::roots::AccountRoots;
+use crate::state::ledgerstate::LedgerState;
+
+const POSTING_INDENT: &str = "  ";
+
+/// The comment of a statement, from the first `;` after byte `from`
+fn trailing_comment(s: &str, from: usize) -> Option<&str> {
+    s[from..].find(';').map(|n| s[from + n..].trim_end())
+}
+
+fn with_comment(line: String, comment: Option<&str>) -> String {
+    match comment {
+        Some(c) => format!("{} {}", line, c),
+        None => line,
+    }
+}
+
+/// `prefix` followed by `amount`, its number ending at `column` if it fits
+fn aligned(prefix: String, quantity: Decimal, rest: &str, column: usize) -> String {
+    let q = quantity.to_string();
+    let pad = column
+        .saturating_sub(prefix.chars().count() + q.len())
+        .max(2);
+    format!("{}{}{} {}", prefix, " ".repeat(pad), q, rest)
+}
+
+/// The rows of `a` and `b` that fmt must leave unchanged, positions aside
+fn same_statements(a: &LedgerState, b: &LedgerState) -> bool {
+    let postings = |s: &LedgerState| -> Vec<_> {
+        s.postings
+            .iter()
+            .map(|p| {
+                (
+                    p.account.clone(),
+                    p.cp_quantity,
+                    p.cp_commodity.clone(),
+                    p.tc_quantity,
+                    p.tc_commodity.clone(),
+                )
+            })
+            .collect()
+    };
+    let transactions = |s: &LedgerState| -> Vec<_> {
+        s.transactions
+            .iter()
+            .map(|t| (t.date, t.narration.clone(), t.tags.clone()))
+            .collect()
+    };
+    let verifications = |s: &LedgerState| -> Vec<_> {
+        s.verifications
+            .iter()
+            .map(|v| {
+                (
+                    v.date,
+                    v.action,
+                    v.account.clone(),
+                    v.quantity,
+                    v.commodity.clone(),
+                    v.tolerance,
+                )
+            })
+            .collect()
+    };
+    let prices = |s: &LedgerState| -> Vec<_> {
+        s.prices
+            .iter()
+            .map(|p| (p.date, p.commodity.clone(), p.price, p.currency.clone()))
+            .collect()
+    };
+    postings(a) == postings(b)
+        && transactions(a) == transactions(b)
+        && verifications(a) == verifications(b)
+        && prices(a) == prices(b)
+        && a.metadata.len() == b.metadata.len()
+        && a.informationals.len() == b.informationals.len()
+        && a.includes.len() == b.includes.len()
+}
+
+///
+/// `contents` of the ledger file `f` with transaction headers, postings and
+/// open, close, balance and price directives rewritten: single spaces between
+/// fields, postings indented by two spaces, and amounts right-aligned so
+/// their numbers end at `column`. Comments and all other lines, including
+/// metadata, are kept as written. The result is re-parsed and an error is
+/// returned if any statement would change.
+///
+pub fn format_ledger(f: &Path, contents: &str, column: usize) -> Result<String> {
+    let state = parse_shallow(f, contents, &AccountRoots::default())?;
+    let span = |start: u32, end: u32| &contents[start as usize..end as usize];
+
+    let mut lines: Vec<(u32, u32, String)> = vec![];
+    for t in state.transactions.iter() {
+        let s = span(t.start, t.end);
+        let after_narration = s
+            .match_indices('"')
+            .nth(1)
+            .map(|(n, _)| n + 1)
+            .unwrap_or(s.len());
+        let mut line = format!("{} {} \"{}\"", t.date, t.flag, t.narration);
+        if let Some(tags) = t.tags.as_ref() {
+            line = format!("{} {}", line, tags);
+        }
+        lines.push((
+            t.start,
+            t.end,
+            with_comment(line, trailing_comment(s, after_narration)),
+        ));
+    }
+    for p in state.postings.iter() {
+        let s = span(p.start, p.end);
+        let flag = p
+            .flag
+            .as_ref()
+            .map(|f| format!("{} ", f))
+            .unwrap_or_default();
+        let prefix = format!("{}{}{}", POSTING_INDENT, flag, p.account);
+        let line = match (p.cp_quantity, p.cp_commodity.as_ref()) {
+            (Some(q), Some(c)) => {
+                let mut line = aligned(prefix, q, c, column);
+                // The price is kept as written, `@` or `@@` and its sign
+                let written = s.split(';').next().unwrap_or_default();
+                if p.tc_commodity.is_some()
+                    && let Some(n) = written.find(PRICE_SEP)
+                {
+                    let price: Vec<&str> = written[n..].split_whitespace().collect();
+                    line = format!("{} {}", line, price.join(" "));
+                }
+                line
+            }
+            _ => prefix,
+        };
+        lines.push((p.start, p.end, with_comment(line, trailing_comment(s, 0))));
+    }
+    for v in state.verifications.iter() {
+        let s = span(v.start, v.end);
+        let line = match (v.action, v.quantity, v.commodity.as_ref()) {
+            (OPEN_ACTION, _, Some(c)) => format!("{} {} {} {}", v.date, OPEN_SYMBOL, v.account, c),
+            (OPEN_ACTION, _, _) => format!("{} {} {}", v.date, OPEN_SYMBOL, v.account),
+            (CLOSE_ACTION, _, _) => format!("{} {} {}", v.date, CLOSE_SYMBOL, v.account),
+            (action, Some(q), Some(c)) => {
+                let subtree = if action == SUBTREE_BALANCE_ACTION {
+                    format!("{} ", SUBTREE_FLAG)
+                } else {
+                    String::new()
+                };
+                let prefix = format!("{} {} {}{}", v.date, BALANCE_SYMBOL, subtree, v.account);
+                match v.tolerance {
+                    Some(t) => aligned(
+                        prefix,
+                        q,
+                        &format!("{} {} {}", TOLERANCE_SYMBOL, t, c),
+                        column,
+                    ),
+                    None => aligned(prefix, q, c, column),
+                }
+            }
+            _ => continue,
+        };
+        lines.push((v.start, v.end, with_comment(line, trailing_comment(s, 0))));
+    }
+    for p in state.prices.iter() {
+        let s = span(p.start, p.end);
+        let prefix = format!("{} {} {}", p.date, PRICE_SYMBOL, p.commodity);
+        let line = aligned(prefix, p.price, &p.currency, column);
+        lines.push((p.start, p.end, with_comment(line, trailing_comment(s, 0))));
+    }
+    lines.sort_by_key(|x| x.0);
+
+    let mut res = String::new();
+    let mut at = 0;
+    for (start, end, line) in lines {
+        res.push_str(&contents[at..start as usize]);
+        res.push_str(&line);
+        at = end as usize;
+    }
+    res.push_str(&contents[at..]);
+
+    if !same_statements(&state, &parse_shallow(f, &res, &AccountRoots::default())?) {
+        return Err(anyhow!(
+            "{}: formatting would change the ledger",
+            f.display()
+        ));
+    }
+    Ok(res)
+}
+
+/// The number of rows of each kind, which a rewrite must keep
+pub(crate) fn row_counts(s: &LedgerState) -> [usize; 7] {
+    [
+        s.transactions.len(),
+        s.postings.len(),
+        s.verifications.len(),
+        s.includes.len(),
+        s.informationals.len(),
+        s.metadata.len(),
+        s.prices.len(),
+    ]
+}
+
+/// The non-blank lines of `s`, sorted
+fn sorted_lines(s: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = s
+        .lines()
+        .map(|l| l.trim_end())
+        .filter(|l| !l.is_empty())
+        .collect();
+    lines.sort();
+    lines
+}
+
+///
+/// `contents` of the ledger file `f` with its paragraphs, the runs of lines
+/// between blank lines, stably sorted by the date of their first dated
+/// statement. A group of directives written together moves as one, and the
+/// undated paragraphs, such as comments heading a section, move with the
+/// paragraph below them. Those above the first dated paragraph, usually the
+/// options and includes, and those after the last stay in place. Lines end
+/// as the first of `contents` does, in `\r\n` or `\n`. Returns `contents`
+/// unchanged when already in order.
+///
+pub fn sort_ledger(f: &Path, contents: &str) -> Result<String> {
+    let state = parse_shallow(f, contents, &AccountRoots::default())?;
+    let mut dated: Vec<(u32, NaiveDate)> = state
+        .transactions
+        .iter()
+        .map(|x| (x.start, x.date))
+        .chain(state.verifications.iter().map(|x| (x.start, x.date)))
+        .chain(state.prices.iter().map(|x| (x.start, x.date)))
+        .chain(
+            state
+                .informationals
+                .iter()
+                .filter_map(|x| Some((x.start, x.date?))),
+        )
+        .collect();
+    dated.sort();
+
+    let mut paragraphs: Vec<(usize, usize)> = vec![];
+    let mut begin = None;
+    let mut at = 0;
+    for line in contents.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(b) = begin.take() {
+                paragraphs.push((b, at));
+            }
+        } else if begin.is_none() {
+            begin = Some(at);
+        }
+        at += line.len();
+    }
+    if let Some(b) = begin {
+        paragraphs.push((b, at));
+    }
+
+    // The dated paragraphs, each with the undated ones above it
+    let mut units: Vec<(NaiveDate, Vec<(usize, usize)>)> = vec![];
+    let mut pending = vec![];
+    let mut preamble_end = None;
+    for (b, e) in paragraphs {
+        let n = dated.partition_point(|(s, _)| (*s as usize) < b);
+        match dated.get(n).filter(|(s, _)| (*s as usize) < e) {
+            Some((_, d)) => {
+                if preamble_end.is_none() {
+                    preamble_end = Some(b);
+                    pending.clear();
+                }
+                pending.push((b, e));
+                units.push((*d, std::mem::take(&mut pending)));
+            }
+            None => pending.push((b, e)),
+        }
+    }
+    let Some(preamble_end) = preamble_end else {
+        return Ok(contents.to_string());
+    };
+    if units.is_sorted_by_key(|(d, _)| *d) {
+        return Ok(contents.to_string());
+    }
+    units.sort_by_key(|(d, _)| *d);
+
+    let eol = match contents.split_once('\n') {
+        Some((first, _)) if first.ends_with('\r') => CRLF,
+        _ => "\n",
+    };
+    let mut res = contents[..preamble_end].to_string();
+    let paragraph_groups = units
+        .iter()
+        .map(|(_, p)| p.as_slice())
+        .chain(std::iter::once(pending.as_slice()));
+    let mut first = true;
+    for group in paragraph_groups {
+        for (b, e) in group {
+            if !first {
+                res.push_str(eol);
+            }
+            first = false;
+            let text = contents[*b..*e].trim_end_matches(['\n', '\r']);
+            res.push_str(text);
+            res.push_str(eol);
+        }
+    }
+
+    let sorted = parse_shallow(f, &res, &AccountRoots::default())?;
+    if row_counts(&state) != row_counts(&sorted) || sorted_lines(contents) != sorted_lines(&res) {
+        return Err(anyhow!("{}: sorting would change the ledger", f.display()));
+    }
+    Ok(res)
+}
+
+/// Rewrites `f` with sort_ledger, returning whether its contents changed
+pub fn sort_file(f: &Path) -> Result<bool> {
+    let contents = fs::read_to_string(f)?;
+    let sorted = sort_ledger(f, &contents)?;
+    if sorted == contents {
+        return Ok(false);
+    }
+    fs::write(f, sorted)?;
+    Ok(true)
+}
+
+/// Rewrites `f` with format_ledger, returning whether its contents changed
+pub fn format_file(f: &Path, column: usize) -> Result<bool> {
+    let contents = fs::read_to_string(f)?;
+    let formatted = format_ledger(f, &contents, column)?;
+    if formatted == contents {
+        return Ok(false);
+    }
+    fs::write(f, formatted)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::BOM;
+
+    const UNSORTED: &str = "option \"title\" \"t\"\n\n2024-02-01 * \"later\"\n  Assets:A  2.00 CAD\n  Assets:B\n\n2024-01-01 open Assets:A\n2024-01-01 open Assets:B\n";
+
+    #[test]
+    fn sort_keeps_crlf_line_endings() {
+        let crlf = UNSORTED.replace('\n', CRLF);
+        let sorted = sort_ledger(Path::new(""), &crlf).unwrap();
+        assert_ne!(sorted, crlf);
+        assert_eq!(sorted.matches('\n').count(), sorted.matches(CRLF).count());
+        assert_eq!(
+            sorted.replace(CRLF, "\n"),
+            sort_ledger(Path::new(""), UNSORTED).unwrap()
+        );
+        assert!(sorted.find("open Assets:A") < sorted.find("later"));
+        // Sorted already, the file comes back as it is
+        assert_eq!(sort_ledger(Path::new(""), &sorted).unwrap(), sorted);
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        for contents in [
+            UNSORTED.to_string(),
+            UNSORTED.replace('\n', CRLF),
+            format!("{}{}", BOM, UNSORTED.replace('\n', CRLF)),
+        ] {
+            let once = format_ledger(Path::new(""), &contents, 50).unwrap();
+            let twice = format_ledger(Path::new(""), &once, 50).unwrap();
+            assert_eq!(once, twice);
+            assert!(once.contains("2.00 CAD"));
+            assert_eq!(once.starts_with(BOM), contents.starts_with(BOM));
+            assert_eq!(once.contains(CRLF), contents.contains(CRLF));
+        }
+    }
+}
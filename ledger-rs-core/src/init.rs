@@ -0,0 +1,131 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Result, Write},
+    path::Path,
+};
+
+use chrono::NaiveDate;
+
+use crate::core::{DATE_FORMAT, INCLUDE_SYMBOL, OPEN_SYMBOL, OPTION_SYMBOL};
+
+pub const MAIN_FILE: &str = "main.bean";
+pub const ACCOUNTS_FILE: &str = "accounts.bean";
+pub const TRANSACTIONS_FILE: &str = "transactions.bean";
+
+/// Starter chart of accounts
+#[derive(Debug)]
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub currency: &'static str,
+    pub accounts: &'static [&'static str],
+}
+
+pub const TEMPLATES: [Template; 3] = [
+    Template {
+        name: "personal-ca",
+        description: "Personal finances in Canada (TFSA, RRSP)",
+        currency: "CAD",
+        accounts: &[
+            "Assets:Bank:Chequing",
+            "Assets:Bank:Savings",
+            "Assets:Investments:TFSA",
+            "Assets:Investments:RRSP",
+            "Liabilities:CreditCard:Visa",
+            "Equity:Opening-Balances",
+            "Income:Salary",
+            "Income:Interest",
+            "Expenses:Housing:Rent",
+            "Expenses:Groceries",
+            "Expenses:Dining",
+            "Expenses:Transport",
+            "Expenses:Utilities",
+            "Expenses:Taxes:Income",
+            "Expenses:Bank:Fees",
+        ],
+    },
+    Template {
+        name: "personal-us",
+        description: "Personal finances in the US (401k, IRA)",
+        currency: "USD",
+        accounts: &[
+            "Assets:Bank:Checking",
+            "Assets:Bank:Savings",
+            "Assets:Investments:401k",
+            "Assets:Investments:IRA",
+            "Liabilities:CreditCard:Visa",
+            "Equity:Opening-Balances",
+            "Income:Salary",
+            "Income:Interest",
+            "Expenses:Housing:Rent",
+            "Expenses:Groceries",
+            "Expenses:Dining",
+            "Expenses:Transport",
+            "Expenses:Utilities",
+            "Expenses:Taxes:Federal",
+            "Expenses:Taxes:State",
+            "Expenses:Bank:Fees",
+        ],
+    },
+    Template {
+        name: "small-business",
+        description: "Small business with receivables and payables",
+        currency: "CAD",
+        accounts: &[
+            "Assets:Bank:Operating",
+            "Assets:AccountsReceivable",
+            "Liabilities:AccountsPayable",
+            "Liabilities:CreditCard",
+            "Liabilities:Taxes:Sales",
+            "Equity:Opening-Balances",
+            "Equity:Owner:Contributions",
+            "Equity:Owner:Draws",
+            "Income:Sales",
+            "Income:Services",
+            "Expenses:Cost-of-Goods",
+            "Expenses:Office",
+            "Expenses:Software",
+            "Expenses:Professional-Fees",
+            "Expenses:Bank:Fees",
+        ],
+    },
+];
+
+pub fn find_template(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
+
+pub(crate) fn create_new(path: &Path) -> Result<fs::File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+///
+/// Writes a starter ledger into `dir`: MAIN_FILE with the options and the
+/// includes, ACCOUNTS_FILE with the template's open directives dated `date`,
+/// and an empty TRANSACTIONS_FILE. Existing files are never overwritten.
+///
+pub fn write_init(dir: &Path, template: &Template, title: &str, date: NaiveDate) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut w = create_new(&dir.join(MAIN_FILE))?;
+    writeln!(w, "{} \"title\" \"{}\"", OPTION_SYMBOL, title)?;
+    writeln!(
+        w,
+        "{} \"operating_currency\" \"{}\"",
+        OPTION_SYMBOL, template.currency
+    )?;
+    writeln!(w)?;
+    writeln!(w, "{} \"{}\"", INCLUDE_SYMBOL, ACCOUNTS_FILE)?;
+    writeln!(w, "{} \"{}\"", INCLUDE_SYMBOL, TRANSACTIONS_FILE)?;
+
+    let mut w = create_new(&dir.join(ACCOUNTS_FILE))?;
+    writeln!(w, "; {}", template.description)?;
+    for a in template.accounts {
+        writeln!(w, "{} {} {}", date.format(DATE_FORMAT), OPEN_SYMBOL, a)?;
+    }
+
+    let mut w = create_new(&dir.join(TRANSACTIONS_FILE))?;
+    writeln!(w, "; Transactions")?;
+
+    Ok(())
+}
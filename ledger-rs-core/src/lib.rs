@@ -1,3 +1,19 @@
+pub mod cache;
+pub mod check;
 pub mod core;
+pub mod custom;
+pub mod events;
+pub mod fmt;
+pub mod init;
+pub mod lint;
+pub mod locale;
+pub mod mapping;
+pub mod merge;
+pub mod options;
 pub mod parse;
+pub mod rename;
+pub mod roots;
+pub mod sample;
 pub mod state;
+pub mod summary;
+pub mod visit;
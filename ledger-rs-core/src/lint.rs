@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+
+use crate::check::{CHECK_DATE_ORDER, Diagnostic, Severity, out_of_order};
+use crate::core::{BALANCE_ACTION, OPEN_ACTION};
+use crate::state::ledgerstate::LedgerState;
+
+pub const LINT_UNOPENED: &str = "unopened";
+pub const LINT_IGNORED: &str = "ignored";
+pub const LINT_TRAILING_WHITESPACE: &str = "trailing-whitespace";
+pub const LINT_STALE_BALANCE: &str = "stale-balance";
+
+fn finding(
+    rule: &str,
+    severity: Severity,
+    file_no: u32,
+    start: u32,
+    date: Option<NaiveDate>,
+    message: String,
+) -> Diagnostic {
+    Diagnostic {
+        rule: rule.to_string(),
+        severity,
+        statement_no: 0,
+        file_no,
+        start,
+        date,
+        message,
+    }
+}
+
+/// The input files of `state` ordered by file number
+fn files(state: &LedgerState) -> Vec<(u32, &PathBuf)> {
+    let mut files: Vec<(u32, &PathBuf)> = state.input_files.iter().map(|(f, n)| (*n, f)).collect();
+    files.sort();
+    files
+}
+
+/// The (start, end) byte offsets of the parsed rows of each file, sorted
+fn spans(state: &LedgerState) -> HashMap<u32, Vec<(u32, u32)>> {
+    let mut spans: HashMap<u32, Vec<(u32, u32)>> = HashMap::new();
+    let rows = state
+        .transactions
+        .iter()
+        .map(|x| (x.file_no, x.start, x.end))
+        .chain(state.postings.iter().map(|x| (x.file_no, x.start, x.end)))
+        .chain(
+            state
+                .verifications
+                .iter()
+                .map(|x| (x.file_no, x.start, x.end)),
+        )
+        .chain(state.includes.iter().map(|x| (x.file_no, x.start, x.end)))
+        .chain(
+            state
+                .informationals
+                .iter()
+                .map(|x| (x.file_no, x.start, x.end)),
+        )
+        .chain(state.metadata.iter().map(|x| (x.file_no, x.start, x.end)))
+        .chain(state.prices.iter().map(|x| (x.file_no, x.start, x.end)));
+    for (file_no, start, end) in rows {
+        spans.entry(file_no).or_default().push((start, end));
+    }
+    for v in spans.values_mut() {
+        v.sort();
+    }
+    spans
+}
+
+/// Whether a span of `spans` overlaps [from, to), `max_end` the running max of their ends
+fn covered(spans: &[(u32, u32)], max_end: &[u32], from: u32, to: u32) -> bool {
+    let n = spans.partition_point(|(s, _)| *s < to);
+    n > 0 && max_end[n - 1] > from
+}
+
+/// Lines with trailing whitespace and lines not part of any statement
+fn line_findings(state: &LedgerState) -> Result<Vec<Diagnostic>> {
+    let spans = spans(state);
+    let mut res = vec![];
+    for (file_no, f) in files(state) {
+        let contents = fs::read_to_string(f)?;
+        let file_spans = spans.get(&file_no).map(|v| v.as_slice()).unwrap_or(&[]);
+        let max_end: Vec<u32> = file_spans
+            .iter()
+            .scan(0, |m, (_, e)| {
+                *m = (*m).max(*e);
+                Some(*m)
+            })
+            .collect();
+
+        let mut start = 0;
+        for line in contents.split_inclusive('\n') {
+            let body = line.trim_end_matches(['\n', '\r']);
+            let text = body.trim_start();
+            if body.ends_with([' ', '\t']) {
+                res.push(finding(
+                    LINT_TRAILING_WHITESPACE,
+                    Severity::Warning,
+                    file_no,
+                    start,
+                    None,
+                    String::from("trailing whitespace"),
+                ));
+            }
+            // Comments and org-mode or markdown headings
+            let skipped = text.is_empty() || text.starts_with(';') || body.starts_with(['*', '#']);
+            let from = start + (body.len() - text.len()) as u32;
+            if !skipped && !covered(file_spans, &max_end, from, start + body.len() as u32) {
+                res.push(finding(
+                    LINT_IGNORED,
+                    Severity::Warning,
+                    file_no,
+                    from,
+                    None,
+                    format!("not parsed as a statement: {}", text.trim_end()),
+                ));
+            }
+            start += line.len() as u32;
+        }
+    }
+    Ok(res)
+}
+
+/// Postings and balance assertions to accounts without an open directive
+fn unopened(state: &LedgerState) -> Vec<Diagnostic> {
+    let opened: HashSet<&str> = state
+        .verifications
+        .iter()
+        .filter(|x| x.action == OPEN_ACTION)
+        .map(|x| x.account.as_str())
+        .collect();
+    let dates: HashMap<u32, NaiveDate> = state
+        .transactions
+        .iter()
+        .map(|x| (x.statement_no, x.date))
+        .collect();
+    let mut res = vec![];
+    let mut seen = HashSet::new();
+    let postings = state
+        .postings
+        .iter()
+        .map(|x| (x, dates.get(&x.transaction_no).copied()));
+    for (x, date) in postings {
+        if !opened.contains(x.account.as_str()) && seen.insert(&x.account) {
+            res.push(Diagnostic {
+                statement_no: x.statement_no,
+                ..finding(
+                    LINT_UNOPENED,
+                    Severity::Error,
+                    x.file_no,
+                    x.start,
+                    date,
+                    format!("{} is not opened", x.account),
+                )
+            });
+        }
+    }
+    for x in state.verifications.iter() {
+        if x.action == BALANCE_ACTION
+            && !opened.contains(x.account.as_str())
+            && seen.insert(&x.account)
+        {
+            res.push(Diagnostic {
+                statement_no: x.statement_no,
+                ..finding(
+                    LINT_UNOPENED,
+                    Severity::Error,
+                    x.file_no,
+                    x.start,
+                    Some(x.date),
+                    format!("{} is not opened", x.account),
+                )
+            });
+        }
+    }
+    res
+}
+
+/// Accounts whose last balance assertion is more than `days` before their last posting
+fn stale_balances(state: &LedgerState, days: i64) -> Vec<Diagnostic> {
+    let dates: HashMap<u32, NaiveDate> = state
+        .transactions
+        .iter()
+        .map(|x| (x.statement_no, x.date))
+        .collect();
+    let mut last_posting: HashMap<&str, NaiveDate> = HashMap::new();
+    for x in state.postings.iter() {
+        if let Some(d) = dates.get(&x.transaction_no) {
+            let l = last_posting.entry(x.account.as_str()).or_insert(*d);
+            *l = (*l).max(*d);
+        }
+    }
+    let mut last_balance = HashMap::new();
+    for x in state.verifications.iter() {
+        if x.action == BALANCE_ACTION {
+            let l = last_balance.entry(x.account.as_str()).or_insert(x);
+            if x.date > l.date {
+                *l = x;
+            }
+        }
+    }
+
+    let mut res = vec![];
+    for (account, x) in last_balance {
+        let Some(p) = last_posting.get(account) else {
+            continue;
+        };
+        let behind = (*p - x.date).num_days();
+        if behind > days {
+            res.push(Diagnostic {
+                statement_no: x.statement_no,
+                ..finding(
+                    LINT_STALE_BALANCE,
+                    Severity::Warning,
+                    x.file_no,
+                    x.start,
+                    Some(x.date),
+                    format!(
+                        "{} was last asserted {} days before its last posting on {}",
+                        account, behind, p
+                    ),
+                )
+            });
+        }
+    }
+    res
+}
+
+///
+/// The cheap checks of a parsed but not verified ledger, fast enough for a
+/// pre-commit hook as no DataFrame is built: postings to accounts never
+/// opened are errors; lines not parsed as any statement, transactions out
+/// of date order within a file, trailing whitespace and balance assertions
+/// more than `stale_days` behind the postings of their account are
+/// warnings. Returned in file and position order.
+///
+pub fn lint(state: &LedgerState, stale_days: i64) -> Result<Vec<Diagnostic>> {
+    let mut res = line_findings(state)?;
+    res.extend(unopened(state));
+    res.extend(out_of_order(state).into_iter().map(|x| Diagnostic {
+        statement_no: x.statement_no,
+        ..finding(
+            CHECK_DATE_ORDER,
+            Severity::Warning,
+            x.file_no,
+            x.start,
+            x.date,
+            x.message,
+        )
+    }));
+    res.extend(stale_balances(state, stale_days));
+    res.sort_by_key(|x| (x.file_no, x.start));
+    Ok(res)
+}
+
+/// Strips the trailing whitespace of the input files, returning the number of lines changed
+pub fn fix_trailing_whitespace(state: &LedgerState) -> Result<usize> {
+    let mut fixed = 0;
+    for (_, f) in files(state) {
+        let contents = fs::read_to_string(f)?;
+        let mut out = String::with_capacity(contents.len());
+        let mut n = 0;
+        for line in contents.split_inclusive('\n') {
+            let body = line.trim_end_matches(['\n', '\r']);
+            let trimmed = body.trim_end_matches([' ', '\t']);
+            if trimmed.len() < body.len() {
+                n += 1;
+            }
+            out.push_str(trimmed);
+            out.push_str(&line[body.len()..]);
+        }
+        if n > 0 {
+            fs::write(f, out)?;
+            fixed += n;
+        }
+    }
+    Ok(fixed)
+}
@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use arrow::array::{ArrayRef, AsArray, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Date32Type, Decimal128Type, DecimalType, Field, Schema};
+use arrow::util::pretty;
+use chrono::NaiveDate;
+
+use crate::core::{DATE_FORMAT, PRECISION, SCALE};
+
+pub const LOCALE_ISO: &str = "iso";
+pub const LOCALE_EN_CA: &str = "en-CA";
+pub const LOCALE_EN_US: &str = "en-US";
+pub const LOCALE_FR_CA: &str = "fr-CA";
+pub const LOCALE_DE_DE: &str = "de-DE";
+
+pub const NEGATIVE_MINUS: &str = "minus";
+pub const NEGATIVE_PARENS: &str = "parens";
+pub const NEGATIVE_TRAILING: &str = "trailing";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurrencyPosition {
+    Prefix,
+    Suffix,
+}
+
+/// How negative amounts are rendered: -123.45, (123.45) or 123.45-
+#[derive(Debug, Clone, PartialEq)]
+pub enum NegativeStyle {
+    Minus,
+    Parentheses,
+    TrailingSign,
+}
+
+impl NegativeStyle {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            NEGATIVE_MINUS => Ok(Self::Minus),
+            NEGATIVE_PARENS => Ok(Self::Parentheses),
+            NEGATIVE_TRAILING => Ok(Self::TrailingSign),
+            _ => Err(anyhow!("Unknown negative style: {}", name)),
+        }
+    }
+
+    fn apply(&self, negative: bool, s: String) -> String {
+        match (negative, self) {
+            (false, _) => s,
+            (true, Self::Minus) => format!("-{}", s),
+            (true, Self::Parentheses) => format!("({})", s),
+            (true, Self::TrailingSign) => format!("{}-", s),
+        }
+    }
+}
+
+/// Presentation settings for report output. The ledger file format itself
+/// is always written canonically (ISO dates, `.` decimals, trailing codes).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale {
+    pub date_format: String,
+    pub decimal_sep: char,
+    pub thousands_sep: Option<char>,
+    pub currency_position: CurrencyPosition,
+    pub negative_style: NegativeStyle,
+    /// The currency written as plain `$`, the other dollars being told apart
+    /// as `C$` or `US$`
+    pub local_currency: Option<String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            date_format: DATE_FORMAT.to_string(),
+            decimal_sep: '.',
+            thousands_sep: None,
+            currency_position: CurrencyPosition::Suffix,
+            negative_style: NegativeStyle::Minus,
+            local_currency: None,
+        }
+    }
+}
+
+impl Locale {
+    pub fn from_name(name: &str) -> Result<Self> {
+        let l = match name {
+            LOCALE_ISO => Self::default(),
+            // Canada writes dates all-numeric as ISO, the CSA and Government
+            // of Canada standard, as month first or day first is ambiguous
+            LOCALE_EN_CA | LOCALE_EN_US => Self {
+                date_format: String::from(match name {
+                    LOCALE_EN_CA => DATE_FORMAT,
+                    _ => "%m/%d/%Y",
+                }),
+                decimal_sep: '.',
+                thousands_sep: Some(','),
+                currency_position: CurrencyPosition::Prefix,
+                negative_style: NegativeStyle::Minus,
+                local_currency: Some(String::from(match name {
+                    LOCALE_EN_CA => "CAD",
+                    _ => "USD",
+                })),
+            },
+            LOCALE_FR_CA => Self {
+                date_format: DATE_FORMAT.to_string(),
+                decimal_sep: ',',
+                thousands_sep: Some(' '),
+                currency_position: CurrencyPosition::Suffix,
+                negative_style: NegativeStyle::Minus,
+                local_currency: None,
+            },
+            LOCALE_DE_DE => Self {
+                date_format: String::from("%d.%m.%Y"),
+                decimal_sep: ',',
+                thousands_sep: Some('.'),
+                currency_position: CurrencyPosition::Suffix,
+                negative_style: NegativeStyle::Minus,
+                local_currency: None,
+            },
+            _ => return Err(anyhow!("Unknown locale: {}", name)),
+        };
+        Ok(l)
+    }
+
+    /// The locale `name`, its negative amounts in the style `negative` if given
+    pub fn from_names(name: &str, negative: Option<&str>) -> Result<Self> {
+        let mut l = Self::from_name(name)?;
+        if let Some(n) = negative {
+            l.negative_style = NegativeStyle::from_name(n)?;
+        }
+        Ok(l)
+    }
+
+    pub fn format_date(&self, d: NaiveDate) -> String {
+        d.format(&self.date_format).to_string()
+    }
+
+    /// Formats a plain decimal string such as `-1234.50`
+    pub fn format_number(&self, s: &str) -> String {
+        let (negative, n) = self.format_unsigned(s);
+        self.negative_style.apply(negative, n)
+    }
+
+    /// Formats a Decimal128 value at the ledger's PRECISION and SCALE
+    pub fn format_decimal(&self, q: i128) -> String {
+        self.format_number(&Decimal128Type::format_decimal(
+            q,
+            PRECISION as u8,
+            SCALE as i8,
+        ))
+    }
+
+    pub fn format_amount(&self, q: i128, commodity: &str) -> String {
+        let (negative, n) = self.format_unsigned(&Decimal128Type::format_decimal(
+            q,
+            PRECISION as u8,
+            SCALE as i8,
+        ));
+        match (&self.currency_position, self.currency_symbol(commodity)) {
+            (CurrencyPosition::Prefix, Some(sym)) => {
+                self.negative_style.apply(negative, format!("{}{}", sym, n))
+            }
+            (CurrencyPosition::Prefix, None) => {
+                format!("{} {}", commodity, self.negative_style.apply(negative, n))
+            }
+            (CurrencyPosition::Suffix, _) => {
+                format!("{} {}", self.negative_style.apply(negative, n), commodity)
+            }
+        }
+    }
+
+    ///
+    /// `b` with its amounts and dates as text rendered by this locale, the
+    /// other columns as they are, to print as a table
+    ///
+    pub fn format_batch(&self, b: &RecordBatch) -> Result<RecordBatch> {
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for (field, column) in b.schema().fields().iter().zip(b.columns()) {
+            let text: Option<StringArray> = match field.data_type() {
+                DataType::Decimal128(p, s) => Some(
+                    column
+                        .as_primitive::<Decimal128Type>()
+                        .iter()
+                        .map(|q| {
+                            q.map(|q| {
+                                self.format_number(&Decimal128Type::format_decimal(q, *p, *s))
+                            })
+                        })
+                        .collect(),
+                ),
+                DataType::Date32 => Some(
+                    column
+                        .as_primitive::<Date32Type>()
+                        .iter()
+                        .map(|d| d.map(|d| self.format_date(Date32Type::to_naive_date(d))))
+                        .collect(),
+                ),
+                _ => None,
+            };
+            match text {
+                Some(t) => {
+                    fields.push(Field::new(
+                        field.name(),
+                        DataType::Utf8,
+                        field.is_nullable(),
+                    ));
+                    columns.push(Arc::new(t) as ArrayRef);
+                }
+                None => {
+                    fields.push(field.as_ref().clone());
+                    columns.push(column.clone());
+                }
+            }
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    /// Prints `batches` to stdout as one table, through format_batch
+    pub fn print_batches(&self, batches: &[RecordBatch]) -> Result<()> {
+        let formatted = batches
+            .iter()
+            .map(|b| self.format_batch(b))
+            .collect::<Result<Vec<RecordBatch>>>()?;
+        pretty::print_batches(&formatted)?;
+        Ok(())
+    }
+
+    fn format_unsigned(&self, s: &str) -> (bool, String) {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(d) => (true, d),
+            None => (false, s),
+        };
+        let (int_part, frac_part) = match digits.split_once('.') {
+            Some((i, f)) => (i, Some(f)),
+            None => (digits, None),
+        };
+
+        let mut res = String::new();
+        for (n, c) in int_part.chars().enumerate() {
+            if n > 0
+                && (int_part.len() - n) % 3 == 0
+                && let Some(t) = self.thousands_sep
+            {
+                res.push(t);
+            }
+            res.push(c);
+        }
+        if let Some(f) = frac_part {
+            res.push(self.decimal_sep);
+            res.push_str(f);
+        }
+        (negative, res)
+    }
+
+    /// The symbol `commodity` is written with, `$` only for the local currency
+    fn currency_symbol(&self, commodity: &str) -> Option<&'static str> {
+        match commodity {
+            c if self.local_currency.as_deref() == Some(c) && DOLLARS.contains(&c) => Some("$"),
+            "CAD" => Some("C$"),
+            "USD" => Some("US$"),
+            "EUR" => Some("€"),
+            "GBP" => Some("£"),
+            "JPY" => Some("¥"),
+            _ => None,
+        }
+    }
+}
+
+/// The currencies whose symbol is `$`
+const DOLLARS: [&str; 2] = ["CAD", "USD"];
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, Date32Array, Decimal128Array};
+    use arrow::util::pretty::pretty_format_batches;
+
+    use super::*;
+
+    fn batch() -> RecordBatch {
+        let account: ArrayRef = Arc::new(StringArray::from(vec!["Assets:A", "Assets:B"]));
+        let date: ArrayRef = Arc::new(Date32Array::from(vec![Some(19723), None]));
+        let total: ArrayRef = Arc::new(
+            Decimal128Array::from(vec![123456, -5])
+                .with_precision_and_scale(PRECISION as u8, SCALE as i8)
+                .unwrap(),
+        );
+        RecordBatch::try_from_iter([("account", account), ("date", date), ("total", total)])
+            .unwrap()
+    }
+
+    #[test]
+    fn iso_tables_print_as_arrow_does() {
+        let formatted = Locale::default().format_batch(&batch()).unwrap();
+        assert_eq!(
+            pretty_format_batches(&[formatted]).unwrap().to_string(),
+            pretty_format_batches(&[batch()]).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn tables_render_amounts_and_dates_by_locale() {
+        let l = Locale::from_names(LOCALE_DE_DE, Some(NEGATIVE_PARENS)).unwrap();
+        let formatted = l.format_batch(&batch()).unwrap();
+        let text = |n: usize| formatted.column(n).as_string::<i32>().clone();
+        assert_eq!(text(0).value(1), "Assets:B");
+        assert_eq!(text(1).value(0), "01.01.2024");
+        assert!(text(1).is_null(1));
+        assert_eq!(text(2).value(0), "1.234,56");
+        assert_eq!(text(2).value(1), "(0,05)");
+    }
+
+    #[test]
+    fn canadian_dates_are_iso_and_american_month_first() {
+        let d = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let ca = Locale::from_name(LOCALE_EN_CA).unwrap();
+        assert_eq!(ca.format_date(d), "2024-03-01");
+        let us = Locale::from_name(LOCALE_EN_US).unwrap();
+        assert_eq!(us.format_date(d), "03/01/2024");
+    }
+
+    #[test]
+    fn only_the_local_dollar_is_plain() {
+        let ca = Locale::from_name(LOCALE_EN_CA).unwrap();
+        assert_eq!(ca.format_amount(-123456, "CAD"), "-$1,234.56");
+        assert_eq!(ca.format_amount(500, "USD"), "US$5.00");
+        let us = Locale::from_name(LOCALE_EN_US).unwrap();
+        assert_eq!(us.format_amount(500, "USD"), "$5.00");
+        assert_eq!(us.format_amount(500, "CAD"), "C$5.00");
+        assert_eq!(us.format_amount(500, "EUR"), "€5.00");
+        assert_eq!(us.format_amount(500, "VFV"), "VFV 5.00");
+    }
+}
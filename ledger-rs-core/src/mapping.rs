@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use crate::parse::is_valid_account;
+
+pub type SymbolsMap = HashMap<String, String>;
+
+const MAPPING_COMMENT: &str = "#";
+
+///
+/// A two column `key,value` CSV map, such as the RJ symbols file
+/// (`description,commodity`) or the QFX accounts file (`acctid,account`).
+///
+/// Fields may be quoted, lines starting with `#` are comments, and a first
+/// row equal to `header` (case-insensitive) is skipped. Rows without both
+/// columns, duplicate keys and values rejected by `validate` are all reported
+/// together with their line numbers.
+///
+#[derive(Debug, Clone)]
+pub struct MappingTable {
+    pub header: [&'static str; 2],
+    pub validate: Option<fn(&str) -> bool>,
+}
+
+impl MappingTable {
+    /// `description,commodity` as used by the RJ importers
+    pub fn symbols() -> Self {
+        Self {
+            header: ["description", "commodity"],
+            validate: None,
+        }
+    }
+
+    /// `acctid,account` as used by the statement importers
+    pub fn accounts() -> Self {
+        Self {
+            header: ["acctid", "account"],
+            validate: Some(is_valid_account),
+        }
+    }
+
+    /// `commodity,country` codes of the securities, for foreign_property_df
+    pub fn countries() -> Self {
+        Self {
+            header: ["commodity", "country"],
+            validate: None,
+        }
+    }
+
+    /// `type,template` postings of the RJ activities by Tran Type
+    pub fn tran_types() -> Self {
+        Self {
+            header: ["type", "template"],
+            validate: None,
+        }
+    }
+
+    /// `client,owner` names of the RJ holdings, the owner being an account component
+    pub fn owners() -> Self {
+        Self {
+            header: ["client", "owner"],
+            validate: Some(|v| is_valid_account(&format!("Assets:{}", v))),
+        }
+    }
+
+    /// `account,canonical` spellings used when comparing ledgers
+    pub fn renames() -> Self {
+        Self {
+            header: ["account", "canonical"],
+            validate: Some(is_valid_account),
+        }
+    }
+
+    pub fn load(&self, filename: &Path) -> Result<SymbolsMap, Error> {
+        let mut map = HashMap::new();
+        let mut lines: HashMap<String, u64> = HashMap::new();
+        let mut invalid = vec![];
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b',')
+            .quoting(true)
+            .has_headers(false)
+            .flexible(true)
+            .from_path(filename)?;
+        let mut first = true;
+        for result in rdr.records() {
+            let item = result?;
+            let line = item.position().map(|p| p.line()).unwrap_or(0);
+            if item
+                .get(0)
+                .is_some_and(|k| k.trim_start().starts_with(MAPPING_COMMENT))
+            {
+                continue;
+            }
+            let (k, v) = match (item.get(0), item.get(1)) {
+                (Some(k), Some(v)) => (k.trim(), v.trim()),
+                _ => {
+                    invalid.push(format!(
+                        "line {}: expected {},{}",
+                        line, self.header[0], self.header[1]
+                    ));
+                    continue;
+                }
+            };
+            let is_header = first
+                && k.eq_ignore_ascii_case(self.header[0])
+                && v.eq_ignore_ascii_case(self.header[1]);
+            first = false;
+            if is_header {
+                continue;
+            }
+            if let Some(f) = self.validate
+                && !f(v)
+            {
+                invalid.push(format!(
+                    "line {}: invalid {} \"{}\"",
+                    line, self.header[1], v
+                ));
+                continue;
+            }
+            if let Some(prev) = lines.get(k) {
+                invalid.push(format!(
+                    "line {}: duplicate {} \"{}\" (first on line {})",
+                    line, self.header[0], k, prev
+                ));
+                continue;
+            }
+            lines.insert(k.to_string(), line);
+            map.insert(k.to_string(), v.to_string());
+        }
+        if !invalid.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, invalid.join("\n")));
+        }
+        Ok(map)
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+
+use crate::core::{ACCOUNT_SEP, TODO_ACCOUNT};
+use crate::parse::parse_shallow;
+use crate::roots::AccountRoots;
+
+/// The lines the import commands print before and between the statements
+const IMPORT_NOISE: &str = "Nothing";
+
+/// What merge_imports read and kept
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    /// The statements read from all the files
+    pub read: usize,
+    /// The statements dropped as already in another file
+    pub duplicates: usize,
+    /// The merged ledger
+    pub contents: String,
+}
+
+/// A top level statement of one of the merged files with its postings and metadata
+struct Unit {
+    file: usize,
+    date: Option<NaiveDate>,
+    /// What makes two statements the same, whatever their formatting
+    key: String,
+    /// The postings to an account still to categorize
+    todo: usize,
+    text: String,
+}
+
+///
+/// `contents` of an import output as beancount: the counters printed before
+/// the statements are dropped and the transaction numbers stripped from the
+/// headers, so `3: 2024-02-15 * "PAYROLL"` reads `2024-02-15 * "PAYROLL"`.
+/// Plain beancount files are left as they are, trailing whitespace aside.
+///
+fn clean_import(contents: &str) -> String {
+    let mut res = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let line = match line.split_once(": ") {
+            Some((n, rest)) if !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()) => rest,
+            Some((name, count))
+                if !name.is_empty()
+                    && name.bytes().all(|c| c.is_ascii_lowercase())
+                    && count.trim().parse::<usize>().is_ok() =>
+            {
+                continue;
+            }
+            _ if line == IMPORT_NOISE => continue,
+            _ => line,
+        };
+        res.push_str(line.trim_end());
+        res.push('\n');
+    }
+    res
+}
+
+/// `s` with its runs of whitespace collapsed to one space
+fn normalized(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// The statements of the file `f`, its number `file` in the merge, in file order
+fn units(f: &Path, file: usize, contents: &str) -> Result<Vec<Unit>> {
+    let state = parse_shallow(f, contents, &AccountRoots::default())?;
+    let text = |start: u32, end: u32| {
+        contents[start as usize..(end as usize).min(contents.len())]
+            .trim_end()
+            .to_string()
+    };
+    let unit = |date: Option<NaiveDate>, key: String| Unit {
+        file,
+        date,
+        key,
+        todo: 0,
+        text: String::new(),
+    };
+
+    // By start and end, the postings and metadata added below
+    let mut res: Vec<(u32, u32, Unit)> = vec![];
+    for x in state.transactions.iter() {
+        let mut amounts: Vec<String> = state
+            .postings
+            .iter()
+            .filter(|p| p.transaction_no == x.statement_no)
+            .filter_map(|p| {
+                Some(format!(
+                    "{} {}",
+                    p.cp_quantity?.normalize(),
+                    p.cp_commodity.as_ref()?
+                ))
+            })
+            .collect();
+        amounts.sort();
+        let key = format!(
+            "{} {} {}",
+            x.date,
+            normalized(&x.narration),
+            amounts.join(" ")
+        );
+        res.push((x.start, x.end, unit(Some(x.date), key)));
+    }
+    for x in state.verifications.iter() {
+        let key = format!(
+            "{} {} {} {}",
+            x.action,
+            x.date,
+            x.account,
+            x.commodity.as_deref().unwrap_or_default()
+        );
+        res.push((x.start, x.end, unit(Some(x.date), key)));
+    }
+    for x in state.prices.iter() {
+        let key = format!("{} {} {}", x.date, x.commodity, x.currency);
+        res.push((x.start, x.end, unit(Some(x.date), key)));
+    }
+    for x in state.informationals.iter() {
+        let key = normalized(&text(x.start, x.end));
+        res.push((x.start, x.end, unit(x.date, key)));
+    }
+    for x in state.includes.iter() {
+        let key = normalized(&text(x.start, x.end));
+        res.push((x.start, x.end, unit(None, key)));
+    }
+    res.sort_by_key(|(start, _, _)| *start);
+
+    // A posting or metadata belongs to the last statement starting before it
+    let todo_suffix = format!("{}{}", ACCOUNT_SEP, TODO_ACCOUNT);
+    let children = state
+        .postings
+        .iter()
+        .map(|x| (x.start, x.end, x.account.ends_with(&todo_suffix)))
+        .chain(state.metadata.iter().map(|x| (x.start, x.end, false)));
+    for (start, end, todo) in children {
+        let n = res.partition_point(|(s, _, _)| *s <= start);
+        if n > 0 {
+            let (_, e, u) = &mut res[n - 1];
+            *e = (*e).max(end);
+            u.todo += todo as usize;
+        }
+    }
+    Ok(res
+        .into_iter()
+        .map(|(start, end, u)| Unit {
+            text: text(start, end),
+            ..u
+        })
+        .collect())
+}
+
+///
+/// Merges import outputs, as printed by the import commands, and ledger
+/// files of the same account into one ledger sorted by date. A statement
+/// found in several files is kept once: the same transaction is the same
+/// day, narration and amounts whatever its accounts, a balance or price
+/// the same day, account and commodity. As repeats within a file are real,
+/// such as two identical purchases on the same day, each is kept as many
+/// times as the file with the most of them has it, taken from the file
+/// with the fewest postings left to categorize, else the last given. The
+/// options and includes head the result.
+///
+pub fn merge_imports(files: &[PathBuf]) -> Result<MergeSummary> {
+    let mut all = vec![];
+    for (n, f) in files.iter().enumerate() {
+        let contents = clean_import(&fs::read_to_string(f)?);
+        all.extend(units(f, n, &contents)?);
+    }
+
+    let mut by_key: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (n, u) in all.iter().enumerate() {
+        by_key.entry(u.key.as_str()).or_default().push(n);
+    }
+    let mut kept = vec![false; all.len()];
+    for found in by_key.values() {
+        let mut per_file: HashMap<usize, (usize, usize)> = HashMap::new();
+        for n in found {
+            let e = per_file.entry(all[*n].file).or_default();
+            e.0 += 1;
+            e.1 += all[*n].todo;
+        }
+        let best = per_file
+            .iter()
+            .max_by_key(|(file, (count, todo))| (*count, std::cmp::Reverse(*todo), **file))
+            .map(|(file, _)| *file);
+        for n in found {
+            kept[*n] = Some(all[*n].file) == best;
+        }
+    }
+
+    let mut undated = vec![];
+    let mut dated = vec![];
+    for (u, keep) in all.iter().zip(kept.iter()) {
+        match (keep, u.date) {
+            (false, _) => {}
+            (true, None) => undated.push(u),
+            (true, Some(d)) => dated.push((d, u)),
+        }
+    }
+    dated.sort_by_key(|(d, _)| *d);
+
+    let mut contents = String::new();
+    for u in undated.iter() {
+        contents.push_str(&u.text);
+        contents.push('\n');
+    }
+    let mut multiline = true;
+    for (_, u) in dated.iter() {
+        let is_multiline = u.text.contains('\n');
+        if !contents.is_empty() && (multiline || is_multiline) {
+            contents.push('\n');
+        }
+        contents.push_str(&u.text);
+        contents.push('\n');
+        multiline = is_multiline;
+    }
+
+    let merged = units(Path::new("merged"), 0, &contents)
+        .map_err(|e| anyhow!("The merged ledger does not parse: {}", e))?;
+    let kept = undated.len() + dated.len();
+    if merged.len() != kept {
+        return Err(anyhow!(
+            "The merged ledger has {} statements instead of {}",
+            merged.len(),
+            kept
+        ));
+    }
+    Ok(MergeSummary {
+        read: all.len(),
+        duplicates: all.len() - kept,
+        contents,
+    })
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::core::{
+    OPERATING_CURRENCY_OPTION, ROUNDING_ACCOUNT_OPTION, TITLE_OPTION, TOLERANCE_OPTION,
+};
+
+/// The values of the `option` directives of a ledger by name, in file order
+/// as some options such as operating_currency may be given more than once
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LedgerOptions {
+    values: BTreeMap<String, Vec<String>>,
+}
+
+impl LedgerOptions {
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.values
+            .entry(name.to_string())
+            .or_default()
+            .push(value.to_string());
+    }
+
+    /// The last value of option `name`, which overrides the earlier ones
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .get(name)
+            .and_then(|v| v.last())
+            .map(|v| v.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> &[String] {
+        self.values.get(name).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get(TITLE_OPTION)
+    }
+
+    /// The account residuals within tolerance are posted to, if not the default
+    pub fn rounding_account(&self) -> Option<&str> {
+        self.get(ROUNDING_ACCOUNT_OPTION)
+    }
+
+    ///
+    /// The default balance tolerances as (commodity, tolerance), from values
+    /// such as `CAD:0.01`, `*` standing for any commodity without its own.
+    /// Later values override earlier ones; malformed values are skipped.
+    ///
+    pub fn tolerances(&self) -> Vec<(String, Decimal)> {
+        let mut res: BTreeMap<String, Decimal> = BTreeMap::new();
+        for v in self.get_all(TOLERANCE_OPTION) {
+            if let Some((c, t)) = v.split_once(':')
+                && let Ok(t) = Decimal::from_str(t.trim())
+            {
+                res.insert(c.trim().to_string(), t);
+            }
+        }
+        res.into_iter().collect()
+    }
+
+    /// The first operating currency, what reports convert to by default
+    pub fn operating_currency(&self) -> Option<&str> {
+        self.get_all(OPERATING_CURRENCY_OPTION)
+            .first()
+            .map(|v| v.as_str())
+    }
+}
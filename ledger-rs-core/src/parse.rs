@@ -1,10 +1,17 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
 use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Error;
 use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::str;
 
+use anyhow::anyhow;
 use chrono::NaiveDate;
 use rust_decimal::Decimal;
 
@@ -17,44 +24,260 @@ use winnow::ascii::till_line_ending;
 use winnow::combinator::alt;
 use winnow::combinator::delimited;
 use winnow::combinator::eof;
+use winnow::combinator::not;
 use winnow::combinator::opt;
 use winnow::combinator::preceded;
 use winnow::combinator::separated;
 use winnow::combinator::separated_pair;
 use winnow::combinator::seq;
+use winnow::combinator::terminated;
+use winnow::error::AddContext;
+use winnow::error::ContextError;
+use winnow::error::ParserError;
+use winnow::error::StrContext;
+use winnow::error::StrContextValue;
 use winnow::stream::AsChar;
+use winnow::stream::Location;
+use winnow::stream::Stream;
 use winnow::token::literal;
 use winnow::token::take_while;
 use winnow::{LocatingSlice, Parser, Result, Stateful, Str};
 
+use crate::check::line_col;
 use crate::core::{
-    ACCOUNT_SEP, ASSETS_BASE, BALANCE_ACTION, BALANCE_SYMBOL, CLOSE_ACTION, CLOSE_SYMBOL, COST_SEP,
-    CUSTOM_ACTION, CUSTOM_SYMBOL, DATE_FORMAT, EQUITY_BASE, EVENT_ACTION, EVENT_SYMBOL,
-    EXPENSES_BASE, INCLUDE_SYMBOL, INCOME_BASE, LIABILITIES_BASE, OPEN_ACTION, OPEN_SYMBOL,
-    OPTION_ACTION, OPTION_SYMBOL, TRANSACTION_FLAG,
+    ACCOUNT_SEP, BALANCE_ACTION, BALANCE_SYMBOL, BOM, CLOSE_ACTION, CLOSE_SYMBOL, COMBINING_MARKS,
+    COMMODITY_LIST_SEP, COMMODITY_PUNCTUATION, COST_SEP, CUSTOM_ACTION, CUSTOM_SYMBOL, DATE_FORMAT,
+    EVENT_ACTION, EVENT_SYMBOL, GLOB_CHARS, INCLUDE_SYMBOL, META_SEP, NOTE_ACTION, NOTE_SYMBOL,
+    OPEN_ACTION, OPEN_SYMBOL, OPTION_ACTION, OPTION_SYMBOL, PENDING_FLAG, PRICE_SEP, PRICE_SYMBOL,
+    STREAM_BATCH_BYTES, SUBTREE_BALANCE_ACTION, SUBTREE_FLAG, TOLERANCE_SYMBOL, TRANSACTION_FLAG,
 };
-use crate::core::{HeaderParams, IncludeParams, InfoParams, PostingParams, VerificationParams};
+use crate::core::{
+    HeaderParams, IncludeParams, InfoParams, MetadataParams, PostingParams, PriceParams,
+    VerificationParams,
+};
+use crate::options::LedgerOptions;
+use crate::roots::AccountRoots;
 use crate::state::ledgerstate::LedgerState;
+use crate::visit::visit_parsed;
 
 pub type BeanInput<'b> = Stateful<LocatingSlice<Str<'b>>, &'b mut LedgerState>;
 
-pub fn parse_filename(f: PathBuf, state: &mut LedgerState) {
-    let (input, _) = get_contents(f.as_path()).unwrap();
+/// Where and why the parser stopped short of the end of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub path: PathBuf,
+    /// The byte offset in the file of where the parse failed
+    pub offset: u32,
+    /// The line and column of the offset, both from 1
+    pub line: usize,
+    pub column: usize,
+    /// What the statement failing there expected, from its parser's contexts
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.path.as_os_str().is_empty() {
+            write!(f, "{}:", self.path.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// The error `message` at `offset` of the file `path` that is `contents`
+    pub(crate) fn new(path: &Path, contents: &str, offset: u32, message: String) -> Self {
+        let (line, column) = line_col(contents, offset);
+        Self {
+            path: path.to_path_buf(),
+            offset,
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+///
+/// Parses `f`, already inserted into `state`, and its includes. A file that
+/// fails to parse is a ParseError of where in which of the files it failed.
+///
+pub fn parse_filename(f: PathBuf, state: &mut LedgerState) -> anyhow::Result<()> {
+    let (input, _) = get_contents(f.as_path()).map_err(|e| anyhow!("{}: {}", f.display(), e))?;
     let mut beaninput = new_beaninput(&input, state);
-    parse_file(&mut beaninput).unwrap();
+    parse_file(&mut beaninput).map_err(|_| parse_error(state))
+}
+
+/// The ParseError parse_file recorded in `state`, as the error of the parse
+fn parse_error(state: &LedgerState) -> anyhow::Error {
+    match state.parse_error.clone() {
+        Some(e) => e.into(),
+        None => anyhow!("parse failed"),
+    }
+}
+
+///
+/// Parses `f`, already inserted into `state`, and its includes without
+/// reading any of them whole: each file is read line by line and parsed in
+/// batches of whole statements of about STREAM_BATCH_BYTES, a batch ending
+/// before a line starting a statement. Memory is then bounded by the rows
+/// parsed rather than by the size of the files; offsets and statement
+/// numbers are those parse_filename gives.
+///
+pub fn parse_streaming(f: &Path, state: &mut LedgerState) -> anyhow::Result<()> {
+    state.streaming = true;
+    stream_file(f, state)?;
+    Ok(())
+}
+
+/// Parses the file `f`, the current one of `state`, in batches, returning its length
+fn stream_file(f: &Path, state: &mut LedgerState) -> anyhow::Result<u32> {
+    let mut reader = BufReader::new(File::open(f)?);
+    let mut batch = String::new();
+    let mut line = String::new();
+    let mut base = 0;
+    // The lines of the batches parsed, to locate an error in the file
+    let mut lines = 0;
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        let starts_statement = line.starts_with(|c: char| !c.is_whitespace());
+        if !batch.is_empty() && (n == 0 || starts_statement && batch.len() >= STREAM_BATCH_BYTES) {
+            state.set_base(base);
+            let mut input = new_beaninput(&batch, state);
+            if parse_file(&mut input).is_err() {
+                if let Some(e) = state.parse_error.as_mut()
+                    && e.path == f
+                    && e.offset >= base
+                {
+                    e.line += lines;
+                }
+                return Err(parse_error(state));
+            }
+            base += batch.len() as u32;
+            lines += batch.matches('\n').count();
+            batch.clear();
+        }
+        if n == 0 {
+            return Ok(base);
+        }
+        batch.push_str(&line);
+    }
+}
+
+///
+/// Parses `contents` as the file `f` without following its includes, which
+/// are recorded as written, starting from the account `roots` of the files
+/// read before it. Statement numbers are the byte offsets within `f`.
+///
+pub(crate) fn parse_shallow(
+    f: &Path,
+    contents: &str,
+    roots: &AccountRoots,
+) -> anyhow::Result<LedgerState> {
+    parse_shallow_raw(f, contents, roots, false)
+}
+
+/// parse_shallow keeping the raw text of the statements when `keep_raw`
+pub(crate) fn parse_shallow_raw(
+    f: &Path,
+    contents: &str,
+    roots: &AccountRoots,
+    keep_raw: bool,
+) -> anyhow::Result<LedgerState> {
+    let mut state = LedgerState::new();
+    state.shallow = true;
+    state.keep_raw = keep_raw;
+    state.roots = roots.clone();
+    state.insert(f.to_path_buf());
+    let mut input = new_beaninput(contents, &mut state);
+    parse_file(&mut input).map_err(|_| parse_error(&state))?;
+    Ok(state)
+}
+
+/// The statements of a ledger as parse_str reads them, in file order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedStatements {
+    pub transactions: Vec<HeaderParams>,
+    pub postings: Vec<PostingParams>,
+    pub verifications: Vec<VerificationParams>,
+    /// The include directives as written, none being followed
+    pub includes: Vec<IncludeParams>,
+    pub informationals: Vec<InfoParams>,
+    pub metadata: Vec<MetadataParams>,
+    pub prices: Vec<PriceParams>,
+    pub options: LedgerOptions,
+}
+
+///
+/// Parses `contents` as a ledger on its own, without reading any file or
+/// touching any state but its own, as for fuzzing the parser: includes are
+/// recorded but not followed, and malformed input is a ParseError, never a
+/// panic. Offsets are byte offsets within `contents`.
+///
+pub fn parse_str(contents: &str) -> anyhow::Result<ParsedStatements> {
+    let state = parse_shallow(Path::new(""), contents, &AccountRoots::default())?;
+    Ok(ParsedStatements {
+        transactions: state.transactions,
+        postings: state.postings,
+        verifications: state.verifications,
+        includes: state.includes,
+        informationals: state.informationals,
+        metadata: state.metadata,
+        prices: state.prices,
+        options: state.options,
+    })
+}
+
+///
+/// Parses `contents` as the file `f`, already inserted into `state`, e.g. an
+/// editor buffer not yet saved, which source_snippet then reads from. Its
+/// includes are read from disk.
+///
+pub fn parse_contents(f: &Path, contents: &str, state: &mut LedgerState) -> anyhow::Result<()> {
+    if let Some(n) = state.input_files.get(f) {
+        state
+            .sources
+            .borrow_mut()
+            .insert(*n, Rc::new(contents.to_string()));
+    }
+    let mut input = new_beaninput(contents, state);
+    parse_file(&mut input).map_err(|_| parse_error(state))
+}
+
+/// Parses `f`, already inserted into `state`, for parse_with_visitor
+pub(crate) fn parse_visited(f: &Path, state: &mut LedgerState) -> anyhow::Result<()> {
+    let (contents, _) = get_contents(f)?;
+    parse_contents(f, &contents, state)
+}
+
+/// Whether `s` is an account name the parser accepts, e.g. in an open directive
+pub fn is_valid_account(s: &str) -> bool {
+    let mut state = LedgerState::new();
+    let mut input = new_beaninput(s, &mut state);
+    (full_account, eof).parse_next(&mut input).is_ok()
+}
+
+/// Whether `s` is a commodity name the parser accepts, e.g. `VFV.TO`
+pub fn is_valid_commodity(s: &str) -> bool {
+    let mut state = LedgerState::new();
+    let mut input = new_beaninput(s, &mut state);
+    (commodity, eof).parse_next(&mut input).is_ok()
 }
 
 fn new_beaninput<'s>(s: &'s str, state: &'s mut LedgerState) -> BeanInput<'s> {
     Stateful {
         input: LocatingSlice::new(s),
-        state: state,
+        state,
     }
 }
 
 fn get_contents(f: &Path) -> Result<(String, u32), Error> {
     let mut s = String::new();
-    let mut infile = OpenOptions::new().read(true).open(f).unwrap();
-    let n = infile.read_to_string(&mut s).unwrap();
+    let mut infile = OpenOptions::new().read(true).open(f)?;
+    let n = infile.read_to_string(&mut s)?;
     Ok((s, n as u32))
 }
 
@@ -62,7 +285,15 @@ fn get_contents(f: &Path) -> Result<(String, u32), Error> {
 // Winnow Parsing
 //
 
-fn date_string<'s>(i: &mut BeanInput<'s>) -> Result<NaiveDate> {
+/// The number of the file being parsed, an error rather than a panic if none is
+fn file_no<'s>(i: &BeanInput<'s>) -> Result<u32> {
+    i.state
+        .get_file_no()
+        .ok_or_else(|| ParserError::from_input(i))
+}
+
+/// The `YYYY-MM-DD` a date is written as, whether or not it is a valid date
+fn date_text<'s>(i: &mut BeanInput<'s>) -> Result<&'s str> {
     seq!(_: take_while(4, |c: char| c.is_dec_digit()),
      _: '-',
      _: take_while(2, |c: char| c.is_dec_digit()),
@@ -70,23 +301,39 @@ fn date_string<'s>(i: &mut BeanInput<'s>) -> Result<NaiveDate> {
      _: take_while(2, |c: char| c.is_dec_digit())
     )
     .take()
-    .try_map(|x| NaiveDate::parse_from_str(x, DATE_FORMAT))
     .parse_next(i)
 }
 
+fn date_string<'s>(i: &mut BeanInput<'s>) -> Result<NaiveDate> {
+    date_text
+        .try_map(|x| NaiveDate::parse_from_str(x, DATE_FORMAT))
+        .context(StrContext::Label("date"))
+        .parse_next(i)
+}
+
+/// One of the state's account roots, as renamed by the options read so far
 fn base_account_name<'s>(i: &mut BeanInput<'s>) -> Result<&'s str> {
-    alt((
-        literal(ASSETS_BASE),
-        literal(LIABILITIES_BASE),
-        literal(EQUITY_BASE),
-        literal(INCOME_BASE),
-        literal(EXPENSES_BASE),
-    ))
-    .parse_next(i)
+    let name = account_name.parse_next(i)?;
+    if i.state.roots.contains(name) {
+        Ok(name)
+    } else {
+        Err(ParserError::from_input(i))
+    }
+}
+
+///
+/// Whether `c` may be part of an account name: a letter or digit of any
+/// script, one of the COMBINING_MARKS of a decomposed letter such as `É`
+/// written `E` and U+0301, or `-`. This is the part of the non-ASCII
+/// characters beancount allows that names accounts, so `Assets:Épargne` and
+/// `Expenses:日本` parse the same in both.
+///
+fn is_account_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || COMBINING_MARKS.iter().any(|r| r.contains(&c))
 }
 
 fn account_name<'s>(i: &mut BeanInput<'s>) -> Result<&'s str> {
-    take_while(1.., |c: char| c.is_alphanumeric() || c == '-').parse_next(i)
+    take_while(1.., is_account_char).parse_next(i)
 }
 
 fn subaccount<'s>(i: &mut BeanInput<'s>) -> Result<()> {
@@ -97,15 +344,25 @@ fn full_account<'s>(i: &mut BeanInput<'s>) -> Result<String> {
     separated_pair(base_account_name, ACCOUNT_SEP, subaccount)
         .take()
         .map(|x| x.to_string())
+        .context(StrContext::Label("account"))
         .parse_next(i)
 }
 
 fn quoted_string<'s>(i: &mut BeanInput<'s>) -> Result<&'s str> {
-    delimited('"', take_while(1.., |c| c != '"'), '"').parse_next(i)
+    // An unclosed quote fails its line rather than running into the next ones
+    delimited(
+        '"',
+        take_while(1.., |c| c != '"' && c != '\n' && c != '\r'),
+        '"'.context(StrContext::Expected(StrContextValue::CharLiteral('"'))),
+    )
+    .parse_next(i)
 }
 
 fn narration<'s>(i: &mut BeanInput<'s>) -> Result<String> {
-    quoted_string.map(|x| x.to_string()).parse_next(i)
+    quoted_string
+        .map(|x| x.to_string())
+        .context(StrContext::Label("narration"))
+        .parse_next(i)
 }
 
 fn comment<'s>(i: &mut BeanInput<'s>) -> Result<&'s str> {
@@ -136,82 +393,115 @@ fn decimal_string<'s>(i: &mut BeanInput<'s>) -> Result<Decimal> {
      _: digit1,
      _: opt(preceded('.', digit1)))
     .take()
-    .try_map(|x| Decimal::from_str_exact(x))
+    .try_map(Decimal::from_str_exact)
     .parse_next(i)
 }
 
+///
+/// Capital ASCII letters, digits and `_`, with COMMODITY_PUNCTUATION allowed
+/// within the name as beancount does, as in `VFV.TO` or `BRK-B`, but not at
+/// its end. Beancount commodities are ASCII only.
+///
 fn commodity<'s>(i: &mut BeanInput<'s>) -> Result<String> {
-    take_while(1.., |c: char| {
-        c.is_ascii_uppercase() || c.is_digit(10) || c == '_'
-    })
-    .take()
-    .map(|x: &str| x.to_string())
-    .parse_next(i)
+    let is_end = |c: char| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_';
+    (
+        take_while(1, is_end),
+        take_while(0.., move |c: char| {
+            is_end(c) || COMMODITY_PUNCTUATION.contains(c)
+        }),
+    )
+        .take()
+        .verify(|x: &str| x.ends_with(is_end))
+        .map(|x: &str| x.to_string())
+        .parse_next(i)
 }
 
-fn commodity_position<'s>(i: &mut BeanInput<'s>) -> Result<(Decimal, String)> {
-    let (_, q, _, c) = (space1, decimal_string, space1, commodity).parse_next(i)?;
-    Ok((q, c))
+/// The commodity of an amount, which its number must be followed by
+fn amount_commodity<'s>(i: &mut BeanInput<'s>) -> Result<String> {
+    preceded(space1, commodity)
+        .context(StrContext::Expected(StrContextValue::Description(
+            "commodity",
+        )))
+        .parse_next(i)
 }
 
+/// The amount of a posting, if it has one
 fn opt_commodity_position<'s>(i: &mut BeanInput<'s>) -> Result<(Option<Decimal>, Option<String>)> {
-    let r = opt(commodity_position).parse_next(i)?;
-    match r {
-        Some((q, c)) => Ok((Some(q), Some(c))),
-        None => Ok((None, None)),
-    }
+    let Some(q) = opt(preceded(space1, decimal_string)).parse_next(i)? else {
+        return Ok((None, None));
+    };
+    let c = amount_commodity
+        .context(StrContext::Label("amount"))
+        .parse_next(i)?;
+    Ok((Some(q), Some(c)))
 }
 
-fn total_cost<'s>(i: &mut BeanInput<'s>) -> Result<(Decimal, String)> {
-    let (_, _, _, q, _, c) = (
+/// A price as `@@ total` or `@ per unit` if any, with whether it is per unit
+fn opt_price<'s>(i: &mut BeanInput<'s>) -> Result<Option<(Decimal, String, bool)>> {
+    let Some(per_unit) = opt(preceded(
         space1,
-        literal(COST_SEP),
-        space1,
-        decimal_string,
-        space1,
-        commodity,
-    )
+        alt((
+            literal(COST_SEP).value(false),
+            literal(PRICE_SEP).value(true),
+        )),
+    ))
+    .parse_next(i)?
+    else {
+        return Ok(None);
+    };
+    let (_, q, c) = (space1, decimal_string, amount_commodity)
+        .context(StrContext::Label("price"))
         .parse_next(i)?;
-    Ok((q, c))
+    Ok(Some((q, c, per_unit)))
 }
 
-fn opt_total_cost<'s>(i: &mut BeanInput<'s>) -> Result<(Option<Decimal>, Option<String>)> {
-    let r = opt(total_cost).parse_next(i)?;
-    match r {
-        Some((q, c)) => Ok((Some(q), Some(c))),
-        None => Ok((None, None)),
-    }
+///
+/// The commodities an account is opened with, as `CAD,USD`, the first being
+/// the default commodity of its postings without an amount
+///
+fn commodity_list<'s>(i: &mut BeanInput<'s>) -> Result<String> {
+    separated(
+        1..,
+        commodity,
+        (space0, literal(COMMODITY_LIST_SEP), space0),
+    )
+    .map(|x: Vec<String>| x.join(COMMODITY_LIST_SEP))
+    .parse_next(i)
 }
 
 fn open_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((date, _, _, _, account, _, _), r) = (
+    let (((date, _, _, _, account, commodities, _, _), taken), r) = (
         date_string,
         space1,
         literal(OPEN_SYMBOL),
         space1,
         full_account,
+        opt(preceded(space1, commodity_list)),
         space0,
         opt(comment),
     )
+        .with_taken()
         .with_span()
         .parse_next(i)?;
     let o = VerificationParams {
-        statement_no: i.state.statement_no(r.start as u32),
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date,
         action: OPEN_ACTION,
         account,
         quantity: None,
-        commodity: None,
+        commodity: commodities,
+        tolerance: None,
+        raw: raw(i, taken),
     };
     i.state.verifications.push(o);
     Ok(())
 }
 
 fn close_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((date, _, _, _, account, _, _), r) = (
+    let (((date, _, _, _, account, _, _), taken), r) = (
         date_string,
         space1,
         literal(CLOSE_SYMBOL),
@@ -220,30 +510,77 @@ fn close_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
         space0,
         opt(comment),
     )
+        .with_taken()
         .with_span()
         .parse_next(i)?;
     let c = VerificationParams {
-        statement_no: i.state.statement_no(r.start as u32),
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date,
         action: CLOSE_ACTION,
         account,
         quantity: None,
         commodity: None,
+        tolerance: None,
+        raw: raw(i, taken),
     };
     i.state.verifications.push(c);
     Ok(())
 }
 
 fn balance_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((date, _, _, _, account, _, position, _, commodity, _, _), r) = (
+    let (((date, _, _, _, subtree, account, _, position, tolerance, _, commodity, _, _), taken), r) =
+        (
+            date_string,
+            space1,
+            literal(BALANCE_SYMBOL),
+            space1,
+            opt((literal(SUBTREE_FLAG), space1)),
+            full_account,
+            space1,
+            decimal_string,
+            opt(preceded(
+                (space1, literal(TOLERANCE_SYMBOL), space1),
+                decimal_string,
+            )),
+            space1,
+            commodity,
+            space0,
+            opt(comment),
+        )
+            .with_taken()
+            .with_span()
+            .parse_next(i)?;
+    let b = VerificationParams {
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
+        date,
+        action: if subtree.is_some() {
+            SUBTREE_BALANCE_ACTION
+        } else {
+            BALANCE_ACTION
+        },
+        account,
+        quantity: Some(position),
+        commodity: Some(commodity),
+        tolerance,
+        raw: raw(i, taken),
+    };
+    i.state.verifications.push(b);
+    Ok(())
+}
+
+fn price_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
+    let (((date, _, _, _, c, _, price, _, currency, _, _), taken), r) = (
         date_string,
         space1,
-        literal(BALANCE_SYMBOL),
+        literal(PRICE_SYMBOL),
         space1,
-        full_account,
+        commodity,
         space1,
         decimal_string,
         space1,
@@ -251,23 +588,137 @@ fn balance_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
         space0,
         opt(comment),
     )
+        .with_taken()
         .with_span()
         .parse_next(i)?;
-    let b = VerificationParams {
-        statement_no: i.state.statement_no(r.start as u32),
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+    let p = PriceParams {
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date,
-        action: BALANCE_ACTION,
-        account,
-        quantity: Some(position),
-        commodity: Some(commodity),
+        commodity: c,
+        price,
+        currency,
+        raw: raw(i, taken),
     };
-    i.state.verifications.push(b);
+    i.state.prices.push(p);
     Ok(())
 }
 
+///
+/// `path` with `$VAR` and `${VAR}` replaced by the environment variable.
+/// Variables that are not set are left as written.
+///
+fn expand_env(path: &str) -> String {
+    let mut res = String::new();
+    let mut rest = path;
+    while let Some(n) = rest.find('$') {
+        res.push_str(&rest[..n]);
+        let after = &rest[n + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(e) => (&braced[..e], e + 2),
+                None => ("", 0),
+            },
+            None => {
+                let e = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..e], e)
+            }
+        };
+        match std::env::var(name) {
+            Ok(v) if !name.is_empty() => res.push_str(&v),
+            _ => res.push_str(&rest[n..n + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// The files matching `path` under `base`, in sorted order for a glob
+fn matching_files(base: &Path, path: &Path, is_glob: bool) -> Vec<PathBuf> {
+    let f = base.join(path);
+    if !is_glob {
+        return if f.exists() { vec![f] } else { vec![] };
+    }
+    let mut matches: Vec<PathBuf> = glob::glob(&f.to_string_lossy())
+        .map(|paths| paths.filter_map(|x| x.ok()).collect())
+        .unwrap_or_default();
+    matches.sort();
+    matches
+}
+
+///
+/// The files included by `include "path"` in `current`, with the path each is
+/// recorded under. Environment variables in the path are expanded. A relative
+/// path is resolved against the including file, then against each of `roots`
+/// in turn, the first that has it winning. A path with glob characters (`*`,
+/// `?`, `[`) is expanded in sorted order, skipping files already in `loaded`.
+///
+pub(crate) fn include_files(
+    current: &Path,
+    path: &str,
+    loaded: &HashMap<PathBuf, u32>,
+    roots: &[PathBuf],
+) -> Vec<(PathBuf, String)> {
+    let expanded = expand_env(path);
+    let p = Path::new(&expanded);
+    let is_glob = expanded.contains(GLOB_CHARS);
+    let parent = current.parent().unwrap_or(Path::new(""));
+
+    let (base, in_files) = if p.is_absolute() {
+        (Path::new(""), matching_files(Path::new(""), p, is_glob))
+    } else {
+        [parent]
+            .into_iter()
+            .chain(roots.iter().map(|r| r.as_path()))
+            .map(|base| (base, matching_files(base, p, is_glob)))
+            .find(|(_, files)| !files.is_empty())
+            .unwrap_or((parent, vec![]))
+    };
+    // A missing file is still returned, to fail as it did before
+    let in_files = if in_files.is_empty() && !is_glob {
+        vec![parent.join(p)]
+    } else {
+        in_files
+    };
+
+    in_files
+        .into_iter()
+        .filter(|f| !is_glob || !loaded.contains_key(f))
+        .map(|f| {
+            let f_path = f
+                .strip_prefix(base)
+                .unwrap_or(f.as_path())
+                .to_string_lossy()
+                .to_string();
+            (f, f_path)
+        })
+        .collect()
+}
+
+/// include_files of `path` in `current` for `state`, recording it in include_globs if a glob
+pub(crate) fn state_include_files(
+    state: &mut LedgerState,
+    current: &Path,
+    path: &str,
+) -> Vec<(PathBuf, String)> {
+    if expand_env(path).contains(GLOB_CHARS) {
+        state
+            .include_globs
+            .push((current.to_path_buf(), path.to_string()));
+    }
+    include_files(current, path, &state.input_files, &state.include_path)
+}
+
+///
+/// `include "path"` parses the included files in place, with one
+/// IncludeParams per file. When parsing shallow the directive is only
+/// recorded, with the path as written, and the files are left to the caller.
+///
 fn include_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
     let ((_, _, path, _, _), r) = (
         literal(INCLUDE_SYMBOL),
@@ -278,36 +729,96 @@ fn include_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
     )
         .with_span()
         .parse_next(i)?;
-    let include_statement_no = i.state.statement_no(r.start as u32);
-    let p = Path::new(path).to_path_buf();
-    let current_p = i.state.get_current_filepath().unwrap();
-    let in_filepath = if p.is_absolute() {
-        p.clone()
-    } else {
-        let parent = current_p.parent().unwrap();
-        parent.join(p.as_path())
-    };
-    i.state.insert(in_filepath.clone());
-    let (in_contents, total_n) = get_contents(in_filepath.as_path()).unwrap();
-    let mut input = new_beaninput(&in_contents, i.state);
-    parse_file(&mut input)?;
-    i.state.finished_include(total_n);
-    let s = IncludeParams {
-        statement_no: include_statement_no,
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
-        path: path.to_string(),
-    };
-    i.state.includes.push(s);
+    let include_statement_no = i.state.statement_no(i.state.offset(r.start));
+    if i.state.shallow {
+        let s = IncludeParams {
+            statement_no: include_statement_no,
+            file_no: file_no(i)?,
+            start: i.state.offset(r.start),
+            end: i.state.offset(r.end),
+            path: path.to_string(),
+        };
+        i.state.includes.push(s);
+        return Ok(());
+    }
+
+    let current_p = i
+        .state
+        .get_current_filepath()
+        .ok_or_else(|| ParserError::from_input(i))?;
+    for (f, f_path) in state_include_files(i.state, &current_p, path) {
+        i.state.insert(f.clone());
+        let total_n = if i.state.streaming {
+            stream_file(&f, i.state).map_err(|e| include_error(i, &current_p, r.start, &f, e))?
+        } else {
+            let (in_contents, total_n) = get_contents(f.as_path())
+                .map_err(|e| include_error(i, &current_p, r.start, &f, anyhow!(e)))?;
+            let mut input = new_beaninput(&in_contents, i.state);
+            parse_file(&mut input)?;
+            total_n
+        };
+        i.state.finished_include(total_n);
+        let s = IncludeParams {
+            statement_no: include_statement_no,
+            file_no: file_no(i)?,
+            start: i.state.offset(r.start),
+            end: i.state.offset(r.end),
+            path: f_path,
+        };
+        i.state.includes.push(s);
+    }
     Ok(())
 }
 
-fn transaction_header<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((date, _, _, _, narration, tags, _, _), r) = (
+///
+/// Records that the file `f` included at `start` of `current` could not be
+/// read, unless the error is one the file recorded, for parse_file to locate
+///
+fn include_error<'s>(
+    i: &mut BeanInput<'s>,
+    current: &Path,
+    start: usize,
+    f: &Path,
+    e: anyhow::Error,
+) -> ContextError {
+    if i.state.parse_error.is_none() {
+        i.state.finished_include(0);
+        i.state.parse_error = Some(ParseError {
+            path: current.to_path_buf(),
+            offset: i.state.offset(start),
+            line: 0,
+            column: 0,
+            message: format!("{}: {}", f.display(), e),
+        });
+    }
+    ParserError::from_input(i)
+}
+
+/// `taken` as the raw text of its statement, if the state keeps it
+fn raw<'s>(i: &BeanInput<'s>, taken: &str) -> Option<String> {
+    i.state.keep_raw.then(|| taken.to_string())
+}
+
+/// `*` cleared, `!` pending or any other single capital letter
+fn transaction_flag<'s>(i: &mut BeanInput<'s>) -> Result<String> {
+    alt((
+        literal(TRANSACTION_FLAG),
+        literal(PENDING_FLAG),
+        take_while(1, |c: char| c.is_ascii_uppercase()),
+    ))
+    .map(|s: &str| s.to_string())
+    .parse_next(i)
+}
+
+///
+/// The header of a transaction, pushed by transaction_statement only once its
+/// postings are parsed so that a header without any is not left behind
+///
+fn transaction_header<'s>(i: &mut BeanInput<'s>) -> Result<HeaderParams> {
+    let ((date, _, flag, _, narration, tags, _, _), r) = (
         date_string,
         space1,
-        literal(TRANSACTION_FLAG),
+        transaction_flag,
         space1,
         narration,
         opt(opt_tag_list),
@@ -316,27 +827,29 @@ fn transaction_header<'s>(i: &mut BeanInput<'s>) -> Result<()> {
     )
         .with_span()
         .parse_next(i)?;
-    let statement_no = i.state.statement_no(r.start as u32);
+    let statement_no = i.state.statement_no(i.state.offset(r.start));
     i.state.transaction_no = statement_no;
     let h = HeaderParams {
         statement_no,
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date,
+        flag,
         narration,
         tags,
+        raw: None,
     };
-    i.state.transactions.push(h);
-    Ok(())
+    Ok(h)
 }
 
 fn posting<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((_, account, (cp_quantity, cp_commodity), (tc_quantity, tc_commodity), _, _), r) = (
+    let ((_, flag, account, (cp_quantity, cp_commodity), price, _, comment), r) = (
         literal("  "),
+        opt(terminated(transaction_flag, space1)),
         full_account,
         opt_commodity_position,
-        opt_total_cost,
+        opt_price,
         space0,
         opt(comment),
     )
@@ -344,37 +857,92 @@ fn posting<'s>(i: &mut BeanInput<'s>) -> Result<()> {
         .parse_next(i)?;
 
     let mut p = PostingParams {
-        statement_no: i.state.statement_no(r.start as u32),
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
         transaction_no: i.state.transaction_no,
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         account,
         cp_quantity,
         cp_commodity: cp_commodity.clone(),
         tc_quantity: cp_quantity,
         tc_commodity: cp_commodity,
+        per_unit: false,
+        flag,
+        comment: comment.map(|c| c.trim_end().to_string()),
     };
-    if !(tc_quantity.is_none() & tc_commodity.is_none()) {
-        p.tc_quantity = tc_quantity;
-        p.tc_commodity = tc_commodity;
+    if let Some((t, c, per_unit)) = price {
+        p.tc_quantity = Some(t);
+        p.tc_commodity = Some(c);
+        p.per_unit = per_unit;
     }
     i.state.postings.push(p);
     Ok(())
 }
 
+fn metadata_key<'s>(i: &mut BeanInput<'s>) -> Result<&'s str> {
+    (
+        take_while(1, |c: char| c.is_ascii_lowercase()),
+        take_while(0.., |c: char| c.is_alphanumeric() || c == '-' || c == '_'),
+    )
+        .take()
+        .parse_next(i)
+}
+
+fn metadata_value<'s>(i: &mut BeanInput<'s>) -> Result<String> {
+    alt((
+        quoted_string,
+        take_while(1.., |c: char| c != ';' && c != '\n' && c != '\r'),
+    ))
+    .map(|x: &str| x.trim().to_string())
+    .parse_next(i)
+}
+
+///
+/// `key: value` lines of a transaction. Metadata of the transaction and of
+/// its postings are both recorded against the transaction.
+///
+fn metadata<'s>(i: &mut BeanInput<'s>) -> Result<()> {
+    let ((_, _, key, _, _, value, _, _), r) = (
+        literal("  "),
+        space0,
+        metadata_key,
+        literal(META_SEP),
+        space1,
+        metadata_value,
+        space0,
+        opt(comment),
+    )
+        .with_span()
+        .parse_next(i)?;
+    let m = MetadataParams {
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        transaction_no: i.state.transaction_no,
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
+        key: key.to_string(),
+        value,
+    };
+    i.state.metadata.push(m);
+    Ok(())
+}
+
 fn transaction_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let (_, _, _): ((), &str, Vec<()>) = (
+    let ((mut h, _, _), taken): ((HeaderParams, &str, Vec<()>), &str) = (
         transaction_header,
         line_ending,
-        separated(1.., posting, line_ending),
+        separated(1.., alt((metadata, posting)), line_ending),
     )
+        .with_taken()
         .parse_next(i)?;
+    h.raw = raw(i, taken);
+    i.state.transactions.push(h);
     Ok(())
 }
 
 fn event_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((d, _, _, _, a, _, v, _, _), r) = (
+    let (((d, _, _, _, a, _, v, _, _), taken), r) = (
         date_string,
         space1,
         literal(EVENT_SYMBOL),
@@ -385,24 +953,26 @@ fn event_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
         space0,
         opt(comment),
     )
+        .with_taken()
         .with_span()
         .parse_next(i)?;
     let s = InfoParams {
-        statement_no: i.state.statement_no(r.start as u32),
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date: Some(d),
         action: EVENT_ACTION,
         attribute: Some(a.to_string()),
         value: v.to_string(),
+        raw: raw(i, taken),
     };
     i.state.informationals.push(s);
     Ok(())
 }
 
 fn option_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((_, _, a, _, v, _, _), r) = (
+    let (((_, _, a, _, v, _, _), taken), r) = (
         literal(OPTION_SYMBOL),
         space1,
         quoted_string,
@@ -411,40 +981,76 @@ fn option_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
         space0,
         opt(comment),
     )
+        .with_taken()
         .with_span()
         .parse_next(i)?;
     let s = InfoParams {
-        statement_no: i.state.statement_no(r.start as u32),
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date: None,
         action: OPTION_ACTION,
         attribute: Some(a.to_string()),
         value: v.to_string(),
+        raw: raw(i, taken),
     };
+    i.state.roots.set_option(a, v);
+    i.state.options.set(a, v);
     i.state.informationals.push(s);
     Ok(())
 }
 
 fn custom_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    let ((d, _, _, v), r) = (
+    let (((d, _, _, v), taken), r) = (
         date_string,
         space1,
         literal(CUSTOM_SYMBOL),
         till_line_ending,
     )
+        .with_taken()
         .with_span()
         .parse_next(i)?;
     let s = InfoParams {
-        statement_no: i.state.statement_no(r.start as u32),
-        file_no: i.state.get_file_no().unwrap(),
-        start: r.start as u32,
-        end: r.end as u32,
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
         date: Some(d),
         action: CUSTOM_ACTION,
         attribute: None,
         value: v.to_string(),
+        raw: raw(i, taken),
+    };
+    i.state.informationals.push(s);
+    Ok(())
+}
+
+fn note_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
+    let (((d, _, _, _, a, _, v, _, _), taken), r) = (
+        date_string,
+        space1,
+        literal(NOTE_SYMBOL),
+        space1,
+        full_account,
+        space1,
+        quoted_string,
+        space0,
+        opt(comment),
+    )
+        .with_taken()
+        .with_span()
+        .parse_next(i)?;
+    let s = InfoParams {
+        statement_no: i.state.statement_no(i.state.offset(r.start)),
+        file_no: file_no(i)?,
+        start: i.state.offset(r.start),
+        end: i.state.offset(r.end),
+        date: Some(d),
+        action: NOTE_ACTION,
+        attribute: Some(a),
+        value: v.to_string(),
+        raw: raw(i, taken),
     };
     i.state.informationals.push(s);
     Ok(())
@@ -460,7 +1066,27 @@ fn empty_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
     Ok(())
 }
 
+///
+/// The start of a line only a transaction or a directive with a valid date
+/// is, which fails the parse when none of them parses it
+///
+fn transaction_start<'s>(i: &mut BeanInput<'s>) -> Result<()> {
+    let date = date_text.parse_next(i)?;
+    if NaiveDate::parse_from_str(date, DATE_FORMAT).is_err() {
+        return Ok(());
+    }
+    (
+        space1,
+        transaction_flag,
+        alt((space1.void(), line_ending.void(), eof.void())),
+    )
+        .void()
+        .parse_next(i)
+}
+
+/// Any other line, such as a directive the parser does not read
 fn other_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
+    not(transaction_start).parse_next(i)?;
     till_line_ending.span().parse_next(i)?;
     Ok(())
 }
@@ -470,16 +1096,26 @@ fn active_statement<'s>(i: &mut BeanInput<'s>) -> Result<()> {
         open_statement,
         close_statement,
         balance_statement,
+        price_statement,
         include_statement,
         transaction_statement,
         event_statement,
         option_statement,
         custom_statement,
+        note_statement,
         comment_statement,
         empty_statement,
         other_statement,
     ))
     .parse_next(i)?;
+    // An included file that failed is a line of its own include directive
+    if i.state.parse_error.is_some() {
+        return Err(ParserError::from_input(i));
+    }
+    visit_parsed(i.state);
+    i.state
+        .append_columns()
+        .map_err(|_| ParserError::from_input(i))?;
     Ok(())
 }
 
@@ -487,12 +1123,205 @@ fn active_statements<'s>(i: &mut BeanInput<'s>) -> Result<Vec<()>> {
     separated(0.., active_statement, line_ending).parse_next(i)
 }
 
+///
+/// The statements of a file up to its end, after the byte order mark some
+/// Windows editors start a file with. Lines may end in `\r\n` as well as `\n`.
+///
 fn full_file<'s>(i: &mut BeanInput<'s>) -> Result<Vec<()>> {
-    let (active_statements, _) = (active_statements, eof).parse_next(i)?;
+    let (_, active_statements, _) = (opt(BOM), active_statements, eof).parse_next(i)?;
     Ok(active_statements)
 }
 
+///
+/// Parses the input to its end. A failure is recorded in the state as the
+/// ParseError of where it failed, unless an included file recorded its own,
+/// the line of the offset being that of the input, which is the whole file
+/// but when streaming.
+///
 fn parse_file<'s>(i: &mut BeanInput<'s>) -> Result<()> {
-    full_file.parse_next(i)?;
-    Ok(())
+    let contents: &'s str = *i.input;
+    let start = i.checkpoint();
+    let e = match full_file.parse_next(i) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    let stop = i.current_token_start();
+    let path = i.state.get_current_filepath().unwrap_or_default();
+    let base = i.state.offset(0);
+    match i.state.parse_error.as_mut() {
+        // An include that could not be read is located in its file
+        Some(x) if x.line == 0 && x.path == path => {
+            (x.line, x.column) = line_col(contents, x.offset - base);
+        }
+        Some(_) => {}
+        None => {
+            // The statements stop at the end of the line before the one
+            // failing, or within the failing line after a statement on it
+            let rest = &contents[stop..];
+            let line_start = match rest.strip_prefix('\n').or(rest.strip_prefix("\r\n")) {
+                Some(r) => contents.len() - r.len(),
+                None => contents[..stop].rfind('\n').map(|n| n + 1).unwrap_or(0),
+            };
+            let line_start = match line_start {
+                0 if contents.starts_with(BOM) => BOM.len_utf8(),
+                n => n,
+            };
+            i.reset(&start);
+            i.next_slice(line_start);
+            let (end, e) = statement_error(i);
+            let end = end.max(stop);
+            let (line, column) = line_col(contents, end as u32);
+            let message = e.to_string().replace('\n', ", ");
+            i.state.parse_error = Some(ParseError {
+                path,
+                offset: i.state.offset(end),
+                line,
+                column,
+                message,
+            });
+        }
+    }
+    Err(e)
+}
+
+/// A statement statement_error tries to parse a failing line as
+type Statement<'s> = fn(&mut BeanInput<'s>) -> Result<()>;
+
+///
+/// Where and why the line at `i` is no statement: the error of the statement
+/// parsing farthest into it, with the offset it fails at, or ends at when it
+/// parses but is followed by more than a comment. Includes are recorded as
+/// written rather than read again.
+///
+fn statement_error<'s>(i: &mut BeanInput<'s>) -> (usize, ContextError) {
+    let statements: [(&'static str, Statement<'s>); 12] = [
+        ("open directive", open_statement),
+        ("close directive", close_statement),
+        ("balance directive", balance_statement),
+        ("price directive", price_statement),
+        ("include directive", include_statement),
+        ("transaction", transaction_statement),
+        ("event directive", event_statement),
+        ("option directive", option_statement),
+        ("custom directive", custom_statement),
+        ("note directive", note_statement),
+        ("metadata", metadata),
+        ("posting", posting),
+    ];
+    let start = i.checkpoint();
+    let shallow = i.state.shallow;
+    i.state.shallow = true;
+    let mut farthest: Option<(usize, ContextError)> = None;
+    for (name, statement) in statements {
+        i.reset(&start);
+        let label = StrContext::Label(name);
+        let e = match statement.context(label.clone()).parse_next(i) {
+            Ok(()) => ContextError::new()
+                .add_context(
+                    i,
+                    &start,
+                    StrContext::Expected(StrContextValue::Description("end of line")),
+                )
+                .add_context(i, &start, label),
+            Err(e) => e,
+        };
+        let end = i.current_token_start();
+        if farthest.as_ref().is_none_or(|(n, _)| end > *n) {
+            farthest = Some((end, e));
+        }
+    }
+    i.state.shallow = shallow;
+    farthest.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::CRLF;
+
+    /// The ParseError of parsing `contents`
+    fn error_of(contents: &str) -> ParseError {
+        let e = parse_str(contents).unwrap_err();
+        e.downcast::<ParseError>().unwrap()
+    }
+
+    #[test]
+    fn invalid_date_fails_at_the_date() {
+        let e = error_of("2024-13-45 * \"x\"\n  Assets:A 1.00 CAD\n  Assets:B\n");
+        assert_eq!((e.offset, e.line, e.column), (0, 1, 1));
+        assert!(e.message.starts_with("invalid date"), "{}", e.message);
+    }
+
+    #[test]
+    fn unclosed_quote_fails_at_the_end_of_its_line() {
+        let e = error_of("2024-01-05 * \"unclosed\n  Assets:A 1.00 CAD\n  Assets:B\n");
+        assert_eq!((e.line, e.column), (1, 23));
+        assert_eq!(e.message, "invalid narration, expected `\"`");
+    }
+
+    #[test]
+    fn amount_without_commodity_fails_after_the_number() {
+        let contents = "2024-01-01 open Assets:A\n2024-01-05 * \"x\"\n  Assets:A 1.00\n";
+        let e = error_of(contents);
+        assert_eq!((e.line, e.column), (3, 16));
+        assert_eq!(e.offset as usize, contents.len() - 1);
+        assert_eq!(e.message, "invalid amount, expected commodity");
+        assert_eq!(e.to_string(), "3:16: invalid amount, expected commodity");
+    }
+
+    #[test]
+    fn failing_posting_after_another_is_located() {
+        let e = error_of("2024-01-05 * \"x\"\n  Assets:A 1.00 CAD\n  Assets:B 2 USD @ 3\n");
+        assert_eq!((e.line, e.column), (3, 21));
+        assert_eq!(e.message, "invalid price, expected commodity");
+    }
+
+    #[test]
+    fn statement_followed_by_text_fails_at_the_text() {
+        let e = error_of("2024-01-01 open Assets:A junk\n");
+        assert_eq!((e.line, e.column), (1, 26));
+        assert_eq!(e.message, "invalid open directive, expected end of line");
+    }
+
+    #[test]
+    fn unknown_directives_still_parse() {
+        let parsed =
+            parse_str("2024-01-01 commodity CAD\n* Heading\n2024-01-01 open Assets:A\n").unwrap();
+        assert_eq!(parsed.verifications.len(), 1);
+    }
+
+    const LEDGER: &str =
+        "2024-01-01 open Assets:A\n\n2024-01-05 * \"x\"\n  Assets:A 1.00 CAD\n  Assets:B\n";
+
+    #[test]
+    fn leading_bom_is_skipped() {
+        let plain = parse_str(LEDGER).unwrap();
+        let with_bom = parse_str(&format!("{}{}", BOM, LEDGER)).unwrap();
+        assert_eq!(with_bom.verifications.len(), 1);
+        assert_eq!(with_bom.postings.len(), 2);
+        assert_eq!(with_bom.verifications[0].account, "Assets:A");
+        // Offsets stay those of the file, the BOM included
+        let shift = BOM.len_utf8() as u32;
+        assert_eq!(
+            with_bom.verifications[0].start,
+            plain.verifications[0].start + shift
+        );
+        assert_eq!(
+            with_bom.transactions[0].start,
+            plain.transactions[0].start + shift
+        );
+    }
+
+    #[test]
+    fn crlf_lines_parse_as_lf_lines() {
+        let plain = parse_str(LEDGER).unwrap();
+        let crlf = parse_str(&LEDGER.replace('\n', CRLF)).unwrap();
+        assert_eq!(crlf.verifications.len(), plain.verifications.len());
+        assert_eq!(crlf.transactions.len(), plain.transactions.len());
+        let accounts = |s: &ParsedStatements| -> Vec<String> {
+            s.postings.iter().map(|p| p.account.clone()).collect()
+        };
+        assert_eq!(accounts(&crlf), accounts(&plain));
+        assert_eq!(crlf.postings[0].cp_quantity, plain.postings[0].cp_quantity);
+    }
 }
@@ -0,0 +1,255 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use anyhow::anyhow;
+
+use crate::core::{ACCOUNT_SEP, COMMODITY_LIST_SEP, NOTE_ACTION, OPEN_ACTION};
+use crate::fmt::row_counts;
+use crate::parse::parse_shallow;
+use crate::state::ledgerstate::LedgerState;
+
+/// What a rename changed, or would change without writing
+#[derive(Debug, Default)]
+pub struct RenameSummary {
+    /// The old and new names
+    pub renamed: Vec<(String, String)>,
+    /// The files changed with the number of replacements in each
+    pub files: Vec<(PathBuf, usize)>,
+    /// The lines changed as file, line number from 1, before and after
+    pub diff: Vec<(PathBuf, usize, String, String)>,
+}
+
+/// The contents of every input file of `state` by file number
+fn read_files(state: &LedgerState) -> Result<HashMap<u32, (PathBuf, String)>> {
+    let mut res = HashMap::new();
+    for (f, n) in state.input_files.iter() {
+        res.insert(*n, (f.clone(), fs::read_to_string(f)?));
+    }
+    Ok(res)
+}
+
+/// Whether `account` is `name` or one of its sub-accounts
+fn under(account: &str, name: &str) -> bool {
+    account
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(ACCOUNT_SEP))
+}
+
+/// The offset of the first account in `s` that is `name` or under it
+fn find_account(s: &str, name: &str) -> Option<usize> {
+    s.match_indices(name).map(|(n, _)| n).find(|n| {
+        let before = s[..*n].chars().next_back();
+        let after = s[n + name.len()..].chars().next();
+        before.is_none_or(char::is_whitespace)
+            && after.is_none_or(|c| c.is_whitespace() || c.to_string() == ACCOUNT_SEP)
+    })
+}
+
+/// The offsets in `s`, up to its comment, of the commodity `name`
+fn find_commodities(s: &str, name: &str) -> Vec<usize> {
+    let s = s.split(';').next().unwrap_or_default();
+    let part = |c: char| c.is_alphanumeric() || "'._-:".contains(c);
+    s.match_indices(name)
+        .map(|(n, _)| n)
+        .filter(|n| {
+            let before = s[..*n].chars().next_back();
+            let after = s[n + name.len()..].chars().next();
+            !before.is_some_and(part) && !after.is_some_and(part)
+        })
+        .collect()
+}
+
+///
+/// Replaces the `len` bytes at each of `edits`, by file number and offset,
+/// with `to`. Every file changed is parsed again and must have the same
+/// statements as before; only then, and when `write`, are they written.
+/// Returns the files and lines changed for RenameSummary.
+///
+#[allow(clippy::type_complexity)]
+fn apply(
+    state: &LedgerState,
+    files: &HashMap<u32, (PathBuf, String)>,
+    edits: &[(u32, usize, usize)],
+    to: &str,
+    write: bool,
+) -> Result<(Vec<(PathBuf, usize)>, Vec<(PathBuf, usize, String, String)>)> {
+    let mut by_file: HashMap<u32, BTreeSet<(usize, usize)>> = HashMap::new();
+    for (file_no, start, len) in edits {
+        by_file.entry(*file_no).or_default().insert((*start, *len));
+    }
+    let mut file_nos: Vec<&u32> = by_file.keys().collect();
+    file_nos.sort();
+
+    let mut changed = vec![];
+    let mut diff = vec![];
+    for file_no in file_nos {
+        let (f, contents) = &files[file_no];
+        let mut res = String::with_capacity(contents.len());
+        let mut at = 0;
+        for (start, len) in by_file[file_no].iter() {
+            res.push_str(&contents[at..*start]);
+            res.push_str(to);
+            at = start + len;
+        }
+        res.push_str(&contents[at..]);
+
+        let before = parse_shallow(f, contents, &state.roots)?;
+        let after = parse_shallow(f, &res, &state.roots)
+            .map_err(|e| anyhow!("Renaming would break the ledger: {}", e))?;
+        if row_counts(&before) != row_counts(&after) {
+            return Err(anyhow!("{}: renaming would change the ledger", f.display()));
+        }
+        // Renames never add or remove lines, so they pair up
+        for (n, (a, b)) in contents.lines().zip(res.lines()).enumerate() {
+            if a != b {
+                diff.push((f.clone(), n + 1, a.to_string(), b.to_string()));
+            }
+        }
+        changed.push((f.clone(), res, by_file[file_no].len()));
+    }
+
+    if write {
+        for (f, res, _) in changed.iter() {
+            fs::write(f, res)?;
+        }
+    }
+    Ok((changed.into_iter().map(|(f, _, n)| (f, n)).collect(), diff))
+}
+
+///
+/// Renames the account `old` and its sub-accounts to `new` in the postings,
+/// open, close, balance and note directives of every file of the ledger,
+/// which must have been parsed but need not be verified. Fails without
+/// writing anything when `old` is not used, when both accounts are opened,
+/// or when a file would no longer parse the same.
+///
+pub fn rename_account(
+    state: &LedgerState,
+    old: &str,
+    new: &str,
+    write: bool,
+) -> Result<RenameSummary> {
+    let root = new.split(ACCOUNT_SEP).next().unwrap_or_default();
+    if old == new || new.contains(char::is_whitespace) || !state.roots.contains(root) {
+        return Err(anyhow!("Invalid new account name: {}", new));
+    }
+    let opened = |name: &str| {
+        state
+            .verifications
+            .iter()
+            .any(|x| x.action == OPEN_ACTION && x.account == name)
+    };
+    if opened(old) && opened(new) {
+        return Err(anyhow!("{} and {} are both opened", old, new));
+    }
+
+    let files = read_files(state)?;
+    let mut accounts = BTreeSet::new();
+    let mut edits = vec![];
+    let mut push = |file_no: u32, start: u32, end: u32, account: &str| -> Result<()> {
+        if !under(account, old) {
+            return Ok(());
+        }
+        let (f, contents) = files
+            .get(&file_no)
+            .ok_or(anyhow!("Unknown file number {}", file_no))?;
+        let s = &contents[start as usize..(end as usize).min(contents.len())];
+        let n = find_account(s, old).ok_or(anyhow!(
+            "{}: {} not found in its statement",
+            f.display(),
+            account
+        ))?;
+        accounts.insert(account.to_string());
+        edits.push((file_no, start as usize + n, old.len()));
+        Ok(())
+    };
+    for x in state.postings.iter() {
+        push(x.file_no, x.start, x.end, &x.account)?;
+    }
+    for x in state.verifications.iter() {
+        push(x.file_no, x.start, x.end, &x.account)?;
+    }
+    for x in state.informationals.iter() {
+        if x.action == NOTE_ACTION
+            && let Some(a) = x.attribute.as_deref()
+        {
+            push(x.file_no, x.start, x.end, a)?;
+        }
+    }
+    if edits.is_empty() {
+        return Err(anyhow!("{} is not used in the ledger", old));
+    }
+
+    let (changed, diff) = apply(state, &files, &edits, new, write)?;
+    Ok(RenameSummary {
+        renamed: accounts
+            .into_iter()
+            .map(|a| {
+                let renamed = format!("{}{}", new, &a[old.len()..]);
+                (a, renamed)
+            })
+            .collect(),
+        files: changed,
+        diff,
+    })
+}
+
+///
+/// Renames the commodity `old` to `new` in the amounts and costs of the
+/// postings, the price directives and the balance assertions of every file
+/// of the ledger, which must have been parsed but need not be verified.
+/// Comments are left as written. Fails without writing anything when `old`
+/// is not used or when a file would no longer parse the same.
+///
+pub fn rename_commodity(
+    state: &LedgerState,
+    old: &str,
+    new: &str,
+    write: bool,
+) -> Result<RenameSummary> {
+    if old == new || new.is_empty() || new.contains(char::is_whitespace) {
+        return Err(anyhow!("Invalid new commodity name: {}", new));
+    }
+    let is_old = |c: &Option<String>| c.as_deref() == Some(old);
+
+    let files = read_files(state)?;
+    let mut edits = vec![];
+    let mut push = |file_no: u32, start: u32, end: u32| -> Result<()> {
+        let (_, contents) = files
+            .get(&file_no)
+            .ok_or(anyhow!("Unknown file number {}", file_no))?;
+        let s = &contents[start as usize..(end as usize).min(contents.len())];
+        for n in find_commodities(s, old) {
+            edits.push((file_no, start as usize + n, old.len()));
+        }
+        Ok(())
+    };
+    for x in state.postings.iter() {
+        if is_old(&x.cp_commodity) || is_old(&x.tc_commodity) {
+            push(x.file_no, x.start, x.end)?;
+        }
+    }
+    for x in state.verifications.iter() {
+        let listed = x.commodity.iter().flat_map(|c| c.split(COMMODITY_LIST_SEP));
+        if listed.clone().any(|c| c == old) {
+            push(x.file_no, x.start, x.end)?;
+        }
+    }
+    for x in state.prices.iter() {
+        if x.commodity == old || x.currency == old {
+            push(x.file_no, x.start, x.end)?;
+        }
+    }
+    if edits.is_empty() {
+        return Err(anyhow!("{} is not used in the ledger", old));
+    }
+
+    let (changed, diff) = apply(state, &files, &edits, new, write)?;
+    Ok(RenameSummary {
+        renamed: vec![(old.to_string(), new.to_string())],
+        files: changed,
+        diff,
+    })
+}
@@ -0,0 +1,71 @@
+use crate::core::{
+    ACCOUNT_SEP, ASSETS_BASE, EARNINGS_NAME, EQUITY_BASE, EXPENSES_BASE, INCOME_BASE,
+    LIABILITIES_BASE, NAME_ASSETS_OPTION, NAME_EQUITY_OPTION, NAME_EXPENSES_OPTION,
+    NAME_INCOME_OPTION, NAME_LIABILITIES_OPTION, ROUNDING_NAME,
+};
+
+/// The names of the five root accounts, English unless renamed with the
+/// `option "name_assets" "Aktiva"` family of options
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct AccountRoots {
+    pub assets: String,
+    pub liabilities: String,
+    pub equity: String,
+    pub income: String,
+    pub expenses: String,
+}
+
+impl Default for AccountRoots {
+    fn default() -> Self {
+        Self {
+            assets: ASSETS_BASE.to_string(),
+            liabilities: LIABILITIES_BASE.to_string(),
+            equity: EQUITY_BASE.to_string(),
+            income: INCOME_BASE.to_string(),
+            expenses: EXPENSES_BASE.to_string(),
+        }
+    }
+}
+
+impl AccountRoots {
+    /// Applies a `name_*` option, returning false for any other option
+    pub fn set_option(&mut self, name: &str, value: &str) -> bool {
+        let root = match name {
+            NAME_ASSETS_OPTION => &mut self.assets,
+            NAME_LIABILITIES_OPTION => &mut self.liabilities,
+            NAME_EQUITY_OPTION => &mut self.equity,
+            NAME_INCOME_OPTION => &mut self.income,
+            NAME_EXPENSES_OPTION => &mut self.expenses,
+            _ => return false,
+        };
+        *root = value.to_string();
+        true
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        [
+            &self.assets,
+            &self.liabilities,
+            &self.equity,
+            &self.income,
+            &self.expenses,
+        ]
+        .iter()
+        .any(|r| r.as_str() == name)
+    }
+
+    /// `root` followed by the account separator, to match its sub-accounts
+    pub fn prefix(root: &str) -> String {
+        format!("{}{}", root, ACCOUNT_SEP)
+    }
+
+    /// The Equity account Income and Expenses are closed into
+    pub fn earnings(&self) -> String {
+        format!("{}{}", Self::prefix(&self.equity), EARNINGS_NAME)
+    }
+
+    /// The Equity account residuals within tolerance are posted to
+    pub fn rounding(&self) -> String {
+        format!("{}{}", Self::prefix(&self.equity), ROUNDING_NAME)
+    }
+}
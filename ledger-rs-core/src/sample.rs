@@ -0,0 +1,373 @@
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+
+use crate::core::{
+    BALANCE_SYMBOL, COST_SEP, DATE_FORMAT, INCLUDE_SYMBOL, OPEN_SYMBOL, OPTION_SYMBOL,
+    PENDING_FLAG, PRICE_SYMBOL, TRANSACTION_FLAG,
+};
+use crate::init::{MAIN_FILE, create_new};
+
+const SAMPLE_START: &str = "2020-01-01";
+const SAMPLE_CURRENCY: &str = "CAD";
+const SAMPLE_CHEQUING: &str = "Assets:Bank:Chequing";
+const SAMPLE_BROKERAGE: &str = "Assets:Investments:Brokerage:Securities";
+const SAMPLE_SALARY: &str = "Income:Salary";
+const SAMPLE_OPENING: &str = "Equity:Opening-Balances";
+const SAMPLE_RENT: &str = "Expenses:Housing:Rent";
+const SAMPLE_EXPENSES: [&str; 8] = [
+    "Groceries",
+    "Dining",
+    "Utilities",
+    "Transport",
+    "Entertainment",
+    "Clothing",
+    "Health",
+    "Gifts",
+];
+const SAMPLE_PAYEES: [&str; 8] = [
+    "Loblaws",
+    "Tim Hortons",
+    "Hydro One",
+    "Presto",
+    "Cineplex",
+    "Uniqlo",
+    "Shoppers",
+    "Indigo",
+];
+const GEN_TAGS: [&str; 4] = ["#travel", "#work", "#home", "#gift"];
+/// Every how many transactions of a generated ledger an account is asserted
+const GEN_BALANCE_EVERY: usize = 25;
+const SAMPLE_SECURITIES: [(&str, i64); 3] = [("VFV", 10000), ("XEQT", 2500), ("ZAG", 1500)];
+
+/// Small deterministic generator so sample ledgers are reproducible
+struct SampleRng(u64);
+
+impl SampleRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next() % (high - low) as u64) as i64
+    }
+}
+
+fn sample_expense_accounts(accounts: usize) -> Vec<String> {
+    (0..accounts.max(1))
+        .map(|n| {
+            let base = SAMPLE_EXPENSES[n % SAMPLE_EXPENSES.len()];
+            if n < SAMPLE_EXPENSES.len() {
+                format!("Expenses:{}", base)
+            } else {
+                format!("Expenses:{}:Sub{}", base, n / SAMPLE_EXPENSES.len())
+            }
+        })
+        .collect()
+}
+
+fn write_transaction<W: Write>(
+    w: &mut W,
+    date: NaiveDate,
+    narration: &str,
+    postings: &[String],
+) -> Result<()> {
+    writeln!(
+        w,
+        "{} {} \"{}\"",
+        date.format(DATE_FORMAT),
+        TRANSACTION_FLAG,
+        narration
+    )?;
+    for p in postings {
+        writeln!(w, "  {}", p)?;
+    }
+    writeln!(w)
+}
+
+/// Writes a synthetic but realistic ledger: monthly salary and rent, day to
+/// day spending over `accounts` expense accounts, and monthly investment
+/// purchases with price directives. `transactions` is the number of
+/// spending transactions generated, and the same `seed` gives the same ledger.
+pub fn write_sample<W: Write>(
+    w: &mut W,
+    transactions: usize,
+    accounts: usize,
+    seed: u64,
+) -> Result<()> {
+    let mut rng = SampleRng(seed);
+    let start = NaiveDate::parse_from_str(SAMPLE_START, DATE_FORMAT).unwrap();
+    let expenses = sample_expense_accounts(accounts);
+
+    writeln!(w, "{} \"title\" \"Sample Ledger\"", OPTION_SYMBOL)?;
+    writeln!(
+        w,
+        "{} \"operating_currency\" \"{}\"",
+        OPTION_SYMBOL, SAMPLE_CURRENCY
+    )?;
+    writeln!(w)?;
+    let start_s = start.format(DATE_FORMAT);
+    for a in [
+        SAMPLE_CHEQUING,
+        SAMPLE_BROKERAGE,
+        SAMPLE_SALARY,
+        SAMPLE_OPENING,
+        SAMPLE_RENT,
+    ]
+    .iter()
+    .copied()
+    .chain(expenses.iter().map(|x| x.as_str()))
+    {
+        writeln!(w, "{} {} {}", start_s, OPEN_SYMBOL, a)?;
+    }
+    writeln!(w)?;
+
+    let mut chequing = Decimal::new(500000, 2);
+    write_transaction(
+        w,
+        start,
+        "Opening balance",
+        &[
+            format!("{} {} {}", SAMPLE_CHEQUING, chequing, SAMPLE_CURRENCY),
+            SAMPLE_OPENING.to_string(),
+        ],
+    )?;
+
+    let mut prices: Vec<i64> = SAMPLE_SECURITIES.iter().map(|(_, p)| *p).collect();
+    let mut month = start;
+    let mut date = start;
+    for n in 0..transactions {
+        date = start + Duration::days(n as i64);
+
+        while date >= month {
+            let salary = Decimal::new(rng.range(450000, 550000), 2);
+            chequing += salary;
+            write_transaction(
+                w,
+                month,
+                "Payroll",
+                &[
+                    format!("{} {} {}", SAMPLE_CHEQUING, salary, SAMPLE_CURRENCY),
+                    SAMPLE_SALARY.to_string(),
+                ],
+            )?;
+
+            let rent = Decimal::new(180000, 2);
+            chequing -= rent;
+            write_transaction(
+                w,
+                month,
+                "Rent",
+                &[
+                    format!("{} {} {}", SAMPLE_RENT, rent, SAMPLE_CURRENCY),
+                    SAMPLE_CHEQUING.to_string(),
+                ],
+            )?;
+
+            let s = (month.signed_duration_since(start).num_days() / 30) as usize
+                % SAMPLE_SECURITIES.len();
+            let (symbol, _) = SAMPLE_SECURITIES[s];
+            prices[s] = (prices[s] + rng.range(-prices[s] / 20, prices[s] / 20 + 1)).max(100);
+            let price = Decimal::new(prices[s], 2);
+            let units = Decimal::new(rng.range(1, 10), 0);
+            let cost = price * units;
+            chequing -= cost;
+            writeln!(
+                w,
+                "{} {} {} {} {}\n",
+                month.format(DATE_FORMAT),
+                PRICE_SYMBOL,
+                symbol,
+                price,
+                SAMPLE_CURRENCY
+            )?;
+            write_transaction(
+                w,
+                month,
+                "Monthly investment",
+                &[
+                    format!(
+                        "{} {} {} {} {} {}",
+                        SAMPLE_BROKERAGE, units, symbol, COST_SEP, cost, SAMPLE_CURRENCY
+                    ),
+                    format!("{} {} {}", SAMPLE_CHEQUING, -cost, SAMPLE_CURRENCY),
+                ],
+            )?;
+
+            month += Duration::days(30);
+        }
+
+        let e = rng.range(0, expenses.len() as i64) as usize;
+        let payee = SAMPLE_PAYEES[e % SAMPLE_PAYEES.len()];
+        let amount = Decimal::new(rng.range(300, 8000), 2);
+        chequing -= amount;
+        write_transaction(
+            w,
+            date,
+            payee,
+            &[
+                format!("{} {} {}", expenses[e], amount, SAMPLE_CURRENCY),
+                SAMPLE_CHEQUING.to_string(),
+            ],
+        )?;
+    }
+
+    writeln!(
+        w,
+        "{} {} {} {} {}",
+        (date + Duration::days(1)).format(DATE_FORMAT),
+        BALANCE_SYMBOL,
+        SAMPLE_CHEQUING,
+        chequing,
+        SAMPLE_CURRENCY
+    )?;
+
+    Ok(())
+}
+
+/// The accounts of a generated ledger: about a quarter of `accounts` assets,
+/// an eighth each liabilities and income, the rest expenses
+fn generated_accounts(accounts: usize) -> Vec<String> {
+    let accounts = accounts.max(4);
+    let assets = accounts / 4;
+    let liabilities = (accounts / 8).max(1);
+    let income = (accounts / 8).max(1);
+    let expenses = accounts - assets - liabilities - income;
+    let mut res = vec![];
+    res.extend((1..=assets).map(|n| format!("Assets:Bank:Account{}", n)));
+    res.extend((1..=liabilities).map(|n| format!("Liabilities:Card:Card{}", n)));
+    res.extend((1..=income).map(|n| format!("Income:Source{}", n)));
+    res.extend((1..=expenses).map(|n| format!("Expenses:Category{}:Sub{}", n % 8, n)));
+    res
+}
+
+///
+/// Writes a random but valid ledger into `dir` for benchmarking and fuzzing:
+/// MAIN_FILE with the options, the open directives of `accounts` accounts
+/// and the includes of `files` part files sharing `transactions` balanced
+/// transactions in date order. The transactions have two to four postings,
+/// the last one often without an amount, with some pending flags, tags,
+/// metadata and comments, and the asset accounts are asserted with balance
+/// directives as they go. The same `seed` gives the same ledger. Existing
+/// files are never overwritten. Returns the files written, MAIN_FILE first.
+///
+pub fn write_generated(
+    dir: &Path,
+    transactions: usize,
+    accounts: usize,
+    files: usize,
+    seed: u64,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut rng = SampleRng(seed);
+    let start = NaiveDate::parse_from_str(SAMPLE_START, DATE_FORMAT).unwrap();
+    let accounts = generated_accounts(accounts);
+    let files = files.max(1);
+    let parts: Vec<String> = (1..=files).map(|n| format!("part-{:02}.bean", n)).collect();
+
+    let mut written = vec![dir.join(MAIN_FILE)];
+    let mut w = BufWriter::new(create_new(&written[0])?);
+    writeln!(w, "{} \"title\" \"Generated Ledger\"", OPTION_SYMBOL)?;
+    writeln!(
+        w,
+        "{} \"operating_currency\" \"{}\"",
+        OPTION_SYMBOL, SAMPLE_CURRENCY
+    )?;
+    writeln!(w)?;
+    let start_s = start.format(DATE_FORMAT);
+    for a in accounts.iter() {
+        writeln!(w, "{} {} {} {}", start_s, OPEN_SYMBOL, a, SAMPLE_CURRENCY)?;
+    }
+    writeln!(w)?;
+    for p in parts.iter() {
+        writeln!(w, "{} \"{}\"", INCLUDE_SYMBOL, p)?;
+    }
+    w.flush()?;
+
+    let assets = accounts.iter().filter(|a| a.starts_with("Assets:")).count();
+    let mut balances = vec![Decimal::ZERO; accounts.len()];
+    let per_file = transactions.div_ceil(files).max(1);
+    let mut date = start;
+    let mut n = 0;
+    for p in parts.iter() {
+        written.push(dir.join(p));
+        let mut w = BufWriter::new(create_new(written.last().unwrap())?);
+        writeln!(w, "; {}", p)?;
+        writeln!(w)?;
+        for _ in 0..per_file.min(transactions - n) {
+            n += 1;
+            date += Duration::days(rng.range(0, 2));
+            let flag = if rng.range(0, 10) == 0 {
+                PENDING_FLAG
+            } else {
+                TRANSACTION_FLAG
+            };
+            let tags = match rng.range(0, 8) {
+                0 => format!(" {}", GEN_TAGS[rng.range(0, 4) as usize]),
+                _ => String::new(),
+            };
+            writeln!(
+                w,
+                "{} {} \"Transaction {}\"{}",
+                date.format(DATE_FORMAT),
+                flag,
+                n,
+                tags
+            )?;
+            if rng.range(0, 5) == 0 {
+                writeln!(w, "  ref: \"{}\"", n)?;
+            }
+
+            let postings = rng.range(2, 5) as usize;
+            let mut total = Decimal::ZERO;
+            for k in 0..postings {
+                let a = rng.range(0, accounts.len() as i64) as usize;
+                let amount = if k + 1 < postings {
+                    Decimal::new(rng.range(-50000, 50000), 2)
+                } else {
+                    -total
+                };
+                total += amount;
+                balances[a] += amount;
+                let comment = match rng.range(0, 10) {
+                    0 => " ; generated",
+                    _ => "",
+                };
+                if k + 1 == postings && !amount.is_zero() && rng.range(0, 2) == 0 {
+                    writeln!(w, "  {}{}", accounts[a], comment)?;
+                } else {
+                    writeln!(
+                        w,
+                        "  {} {} {}{}",
+                        accounts[a], amount, SAMPLE_CURRENCY, comment
+                    )?;
+                }
+            }
+            writeln!(w)?;
+
+            if n % GEN_BALANCE_EVERY == 0 && assets > 0 {
+                let a = rng.range(0, assets as i64) as usize;
+                writeln!(
+                    w,
+                    "{} {} {} {} {}\n",
+                    (date + Duration::days(1)).format(DATE_FORMAT),
+                    BALANCE_SYMBOL,
+                    accounts[a],
+                    balances[a],
+                    SAMPLE_CURRENCY
+                )?;
+                // Asserted as of the day after, so no later transaction may
+                // be dated before then
+                date += Duration::days(1);
+            }
+        }
+        w.flush()?;
+    }
+    Ok(written)
+}
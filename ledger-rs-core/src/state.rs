@@ -1,4 +1,33 @@
+pub mod anomaly;
+pub mod balance;
+pub mod cashflow;
+pub mod close;
 pub mod cmp;
+pub mod columns;
+pub mod contribution;
+pub mod convert;
+pub mod coverage;
+pub mod cycle;
+pub mod equity;
+pub mod errors;
+pub mod forecast;
+pub mod foreign;
+pub mod group;
+pub mod integrity;
 pub mod ledgerstate;
+pub mod names;
+pub mod numbering;
+pub mod opens;
+pub mod pnl;
+pub mod portfolio;
+pub mod register;
 pub mod report;
+pub mod runway;
+pub mod savings;
+pub mod shuffle;
+pub mod source;
+pub mod sql;
+pub mod todo;
+pub mod unrealized;
+pub mod value;
 pub mod verify;
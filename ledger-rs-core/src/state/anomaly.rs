@@ -0,0 +1,99 @@
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use datafusion::functions_aggregate::expr_fn::{avg, count, stddev};
+use datafusion::prelude::*;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_RIGHT, ANOMALY_MIN_POSTINGS, COMMODITY, DATE, FINAL_CP_COMMODITY,
+    FINAL_CP_QUANTITY, MEAN, MESSAGE, NARRATION, NUM, QUANTITY, STATEMENT_NO, STDDEV,
+};
+use crate::state::ledgerstate::LedgerState;
+
+const COMMODITY_RIGHT: &str = "commodity_right";
+const SIGN: &str = "sign";
+const SIGN_RIGHT: &str = "sign_right";
+
+impl LedgerState {
+    ///
+    /// Postings whose cp amount is more than `stddevs` standard deviations
+    /// from the mean of the postings of its account and commodity in the same
+    /// direction, so deposits are not measured against withdrawals, when
+    /// there are at least ANOMALY_MIN_POSTINGS of them, or whose size is
+    /// above `threshold` if given. A quick screen for typos and fraud after
+    /// an import; MESSAGE says which test a posting failed.
+    ///
+    pub fn anomalies_df(&self, stddevs: f64, threshold: Option<f64>) -> Result<DataFrame> {
+        let df = self.report_journal_df()?.select(vec![
+            col(DATE),
+            col(STATEMENT_NO),
+            col(NARRATION),
+            col(ACCOUNT),
+            col(FINAL_CP_COMMODITY).alias(COMMODITY),
+            col(FINAL_CP_QUANTITY).alias(QUANTITY),
+            signum(cast(col(FINAL_CP_QUANTITY), DataType::Float64)).alias(SIGN),
+        ])?;
+        let amount = || cast(col(QUANTITY), DataType::Float64);
+
+        let stats_df = df.clone().aggregate(
+            vec![
+                col(ACCOUNT).alias(ACCOUNT_RIGHT),
+                col(COMMODITY).alias(COMMODITY_RIGHT),
+                col(SIGN).alias(SIGN_RIGHT),
+            ],
+            vec![
+                count(col(QUANTITY)).alias(NUM),
+                avg(amount()).alias(MEAN),
+                stddev(amount()).alias(STDDEV),
+            ],
+        )?;
+
+        let outlier = col(NUM)
+            .gt_eq(lit(ANOMALY_MIN_POSTINGS))
+            .and(col(STDDEV).gt(lit(0.0)))
+            .and(abs(amount() - col(MEAN)).gt(lit(stddevs) * col(STDDEV)));
+        let too_large = match threshold {
+            Some(t) => abs(amount()).gt(lit(t)),
+            None => lit(false),
+        };
+
+        let df = df
+            .join(
+                stats_df,
+                JoinType::Inner,
+                &[ACCOUNT, COMMODITY, SIGN],
+                &[ACCOUNT_RIGHT, COMMODITY_RIGHT, SIGN_RIGHT],
+                None,
+            )?
+            .with_column(
+                MESSAGE,
+                when(
+                    outlier.clone(),
+                    lit(format!(
+                        "more than {} standard deviations from the mean",
+                        stddevs
+                    )),
+                )
+                .when(
+                    too_large.clone(),
+                    lit(format!("larger than {}", threshold.unwrap_or_default())),
+                )
+                .end()?,
+            )?
+            .filter(outlier.or(too_large))?
+            .select(vec![
+                col(DATE),
+                col(NARRATION),
+                col(ACCOUNT),
+                col(COMMODITY),
+                col(QUANTITY),
+                round(vec![col(MEAN), lit(2)]).alias(MEAN),
+                round(vec![col(STDDEV), lit(2)]).alias(STDDEV),
+                col(MESSAGE),
+            ])?
+            .sort(vec![
+                col(DATE).sort(true, false),
+                col(ACCOUNT).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+}
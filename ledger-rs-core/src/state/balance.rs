@@ -0,0 +1,252 @@
+use anyhow::Context;
+use anyhow::Result;
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+use rust_decimal::Decimal;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, ACTION_COL, BALANCE_ACTION, COMMODITY, DATE, FILE_NO, FINAL_CP_COMMODITY,
+    FINAL_CP_QUANTITY, POSTING_ACCOUNT, POSTING_DATE, PRECISION, PRICE_SCALE, QUANTITY, START,
+    STATEMENT_NO, SUBTREE_BALANCE_ACTION, TOLERANCE, TOTAL,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::zero_lit;
+
+const ANY_COMMODITY: &str = "*";
+
+fn tolerance_lit(t: Decimal) -> Expr {
+    let mut t = t;
+    t.rescale(PRICE_SCALE as u32);
+    lit(ScalarValue::Decimal128(
+        Some(t.mantissa()),
+        PRECISION as u8,
+        PRICE_SCALE as i8,
+    ))
+}
+
+impl LedgerState {
+    /// The account residuals within tolerance are posted to, Equity:Rounding
+    /// unless set by the rounding_account option. It needs not be opened.
+    pub fn rounding_account(&self) -> String {
+        self.options
+            .rounding_account()
+            .map(String::from)
+            .unwrap_or(self.roots.rounding())
+    }
+
+    /// The default tolerance of the commodity in column `commodity`, from the
+    /// inferred_tolerance_default options, else zero
+    pub(crate) fn default_tolerance(&self, commodity: &str) -> Result<Expr> {
+        let tolerances = self.options.tolerances();
+        let any = tolerances
+            .iter()
+            .find(|(c, _)| c == ANY_COMMODITY)
+            .map(|(_, t)| *t)
+            .unwrap_or_default();
+        let mut commodities = tolerances.iter().filter(|(c, _)| c != ANY_COMMODITY);
+        let Some((c, t)) = commodities.next() else {
+            return Ok(tolerance_lit(any));
+        };
+        let mut e = when(col(commodity).eq(lit(c.as_str())), tolerance_lit(*t));
+        for (c, t) in commodities {
+            e = e.when(col(commodity).eq(lit(c.as_str())), tolerance_lit(*t));
+        }
+        Ok(e.otherwise(tolerance_lit(any))?)
+    }
+
+    ///
+    /// Every balance assertion with the TOTAL it is checked against and its
+    /// TOLERANCE, written as `~ 0.01` after the amount or the default of its
+    /// commodity. As in beancount an assertion is checked at the beginning of
+    /// its date, against the cp amounts of its commodity posted to the
+    /// account. Subtree assertions (`balance * Account`) roll up the account
+    /// and all of its sub-accounts.
+    ///
+    fn balance_totals_df(&self) -> Result<DataFrame> {
+        let verifications_df = self
+            .verifications_df
+            .clone()
+            .context("No verifications df")?
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(BALANCE_ACTION))
+                    .or(col(ACTION_COL).eq(lit(SUBTREE_BALANCE_ACTION))),
+            )?;
+
+        let postings_df = self.journal_df()?.select(vec![
+            col(DATE).alias(POSTING_DATE),
+            col(ACCOUNT).alias(POSTING_ACCOUNT),
+            col(FINAL_CP_COMMODITY),
+            col(FINAL_CP_QUANTITY),
+        ])?;
+
+        let subtree_match = col(ACTION_COL)
+            .eq(lit(SUBTREE_BALANCE_ACTION))
+            .and(starts_with(
+                col(POSTING_ACCOUNT),
+                concat(vec![col(ACCOUNT), lit(ACCOUNT_SEP)]),
+            ));
+
+        let df = verifications_df
+            .join_on(
+                postings_df,
+                JoinType::Left,
+                vec![
+                    col(COMMODITY).eq(col(FINAL_CP_COMMODITY)),
+                    col(POSTING_DATE).lt(col(DATE)),
+                    col(POSTING_ACCOUNT).eq(col(ACCOUNT)).or(subtree_match),
+                ],
+            )?
+            .aggregate(
+                vec![
+                    col(STATEMENT_NO),
+                    col(FILE_NO),
+                    col(START),
+                    col(DATE),
+                    col(ACTION_COL),
+                    col(ACCOUNT),
+                    col(COMMODITY),
+                    col(QUANTITY),
+                    col(TOLERANCE),
+                ],
+                vec![sum(col(FINAL_CP_QUANTITY)).alias(TOTAL)],
+            )?
+            .with_column(TOTAL, coalesce(vec![col(TOTAL), zero_lit()]))?
+            .with_column(
+                TOLERANCE,
+                coalesce(vec![col(TOLERANCE), self.default_tolerance(COMMODITY)?]),
+            )?
+            .sort(vec![
+                col(DATE).sort(true, false),
+                col(STATEMENT_NO).sort(true, false),
+            ])?;
+
+        Ok(df)
+    }
+
+    /// Balance assertions that do not hold within their tolerance, see balance_totals_df
+    pub fn balance_errors_df(&self) -> Result<DataFrame> {
+        let difference = abs(col(TOTAL) - col(QUANTITY));
+        Ok(self
+            .balance_totals_df()?
+            .filter(difference.gt(col(TOLERANCE)))?)
+    }
+
+    /// Balance assertions that hold only thanks to their tolerance
+    pub fn balance_tolerated_df(&self) -> Result<DataFrame> {
+        let difference = abs(col(TOTAL) - col(QUANTITY));
+        Ok(self.balance_totals_df()?.filter(
+            col(TOTAL)
+                .not_eq(col(QUANTITY))
+                .and(difference.lt_eq(col(TOLERANCE))),
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use arrow::array::{Array, StringArray};
+    use arrow::datatypes::DataType;
+
+    use super::*;
+    use crate::check::{Balanced, VerificationRule};
+    use crate::parse::parse_contents;
+
+    async fn verified(contents: &str) -> LedgerState {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        state
+    }
+
+    /// The failing balance assertions as `account quantity`, in date order
+    async fn failing(state: &LedgerState) -> Vec<String> {
+        let df = state
+            .balance_errors_df()
+            .unwrap()
+            .select(vec![concat(vec![
+                col(ACCOUNT),
+                lit(" "),
+                cast(col(QUANTITY), DataType::Utf8),
+            ])])
+            .unwrap();
+        let mut res = vec![];
+        for b in df.collect().await.unwrap() {
+            let a = b.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+            res.extend(a.iter().flatten().map(String::from));
+        }
+        res
+    }
+
+    const INVESTMENTS: &str = "2024-01-02 * \"Buy\"\n  Assets:Inv:A 10.00 CAD\n  \
+                               Assets:Inv:B 20.00 CAD\n  Assets:Inv2 5.00 CAD\n  \
+                               Equity:Opening-Balances\n";
+
+    #[tokio::test]
+    async fn subtree_assertions_roll_up_the_sub_accounts() {
+        let contents = format!(
+            "{}2024-01-03 balance * Assets:Inv 30.00 CAD\n\
+             2024-01-03 balance * Assets:Inv2 5.00 CAD\n\
+             2024-01-03 balance Assets:Inv 30.00 CAD\n\
+             2024-01-03 balance * Assets:Inv 35.00 CAD\n",
+            INVESTMENTS
+        );
+        let state = verified(&contents).await;
+        assert_eq!(
+            failing(&state).await,
+            ["Assets:Inv 30.00", "Assets:Inv 35.00"]
+        );
+    }
+
+    #[tokio::test]
+    async fn assertions_hold_within_their_tolerance_only() {
+        let contents = format!(
+            "{}2024-01-03 balance Assets:Inv:A 10.02 ~ 0.02 CAD\n\
+             2024-01-03 balance Assets:Inv:B 20.03 ~ 0.02 CAD\n",
+            INVESTMENTS
+        );
+        let state = verified(&contents).await;
+        assert_eq!(failing(&state).await, ["Assets:Inv:B 20.03"]);
+        let tolerated = state.balance_tolerated_df().unwrap().count().await.unwrap();
+        assert_eq!(tolerated, 1);
+    }
+
+    /// The CAD amounts posted to the rounding account
+    async fn rounding_postings(state: &LedgerState) -> Vec<String> {
+        let df = state
+            .journal_df()
+            .unwrap()
+            .filter(col(ACCOUNT).eq(lit(state.rounding_account())))
+            .unwrap()
+            .select(vec![cast(col(FINAL_CP_QUANTITY), DataType::Utf8)])
+            .unwrap();
+        let mut res = vec![];
+        for b in df.collect().await.unwrap() {
+            let a = b.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+            res.extend(a.iter().flatten().map(String::from));
+        }
+        res
+    }
+
+    #[tokio::test]
+    async fn residuals_within_tolerance_are_posted_to_the_rounding_account() {
+        let contents = "option \"inferred_tolerance_default\" \"CAD:0.01\"\n\
+                        2024-01-02 * \"off by a cent\"\n  Assets:A 10.00 CAD\n  Assets:B -9.99 CAD\n\
+                        2024-01-03 * \"off by two\"\n  Assets:A 10.00 CAD\n  Assets:B -9.98 CAD\n";
+        let state = verified(contents).await;
+        assert_eq!(rounding_postings(&state).await, ["-0.01"]);
+        let unbalanced = Balanced.check(&state).unwrap().count().await.unwrap();
+        assert_eq!(unbalanced, 1);
+
+        let exact = contents.replacen("CAD:0.01", "CAD:0", 1);
+        let state = verified(&exact).await;
+        assert!(rounding_postings(&state).await.is_empty());
+        let unbalanced = Balanced.check(&state).unwrap().count().await.unwrap();
+        assert_eq!(unbalanced, 2);
+    }
+}
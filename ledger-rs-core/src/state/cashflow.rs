@@ -0,0 +1,103 @@
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, CASHFLOW_NET, COMMODITY, DATE, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY,
+    PERIOD, TOTAL,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{date_lit, period_expr, zero_lit};
+
+/// `account` cut to its first `level` names
+fn account_level(level: usize) -> Expr {
+    array_to_string(
+        array_slice(
+            string_to_array(col(ACCOUNT), lit(ACCOUNT_SEP), lit(ScalarValue::Utf8(None))),
+            lit(1_i64),
+            lit(level as i64),
+            None,
+        ),
+        lit(ACCOUNT_SEP),
+    )
+}
+
+impl LedgerState {
+    ///
+    /// Income and Expenses cp totals over [begin, end) with a column per
+    /// calendar month and a total column. Accounts are cut to their first
+    /// `depth` names and each shorter level is reported as a subtotal of the
+    /// accounts below it, followed by a CASHFLOW_NET row per commodity.
+    ///
+    pub fn cashflow_df(
+        &self,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        depth: usize,
+    ) -> Result<DataFrame> {
+        let mut months: Vec<NaiveDate> = self
+            .transactions
+            .iter()
+            .map(|t| t.date)
+            .filter(|d| begin.is_none_or(|b| *d >= b) && end.is_none_or(|e| *d < e))
+            .filter_map(|d| d.with_day(1))
+            .collect();
+        months.sort();
+        months.dedup();
+
+        let prefix = |base: &str| lit(format!("{}{}", base, ACCOUNT_SEP));
+        let df = self
+            .report_journal_df()?
+            .filter(
+                starts_with(col(ACCOUNT), prefix(&self.roots.income))
+                    .or(starts_with(col(ACCOUNT), prefix(&self.roots.expenses))),
+            )?
+            .filter(period_expr(begin, end))?
+            .select(vec![
+                cast(date_trunc(lit("month"), col(DATE)), DataType::Date32).alias(PERIOD),
+                col(ACCOUNT),
+                col(FINAL_CP_COMMODITY).alias(COMMODITY),
+                col(FINAL_CP_QUANTITY),
+            ])?;
+
+        let mut totals: Vec<Expr> = vec![];
+        for m in months.iter() {
+            let in_month =
+                when(col(PERIOD).eq(date_lit(*m)), col(FINAL_CP_QUANTITY)).otherwise(zero_lit())?;
+            totals.push(sum(in_month).alias(m.format("%Y-%m").to_string()));
+        }
+        totals.push(sum(col(FINAL_CP_QUANTITY)).alias(TOTAL));
+
+        let mut res = df.clone().aggregate(
+            vec![lit(CASHFLOW_NET).alias(ACCOUNT), col(COMMODITY)],
+            totals.clone(),
+        )?;
+        for level in 1..=depth.max(1) {
+            let level_df = df
+                .clone()
+                .filter(
+                    array_length(string_to_array(
+                        col(ACCOUNT),
+                        lit(ACCOUNT_SEP),
+                        lit(ScalarValue::Utf8(None)),
+                    ))
+                    .gt_eq(lit(level as u64)),
+                )?
+                .aggregate(
+                    vec![account_level(level).alias(ACCOUNT), col(COMMODITY)],
+                    totals.clone(),
+                )?;
+            res = level_df.union(res)?;
+        }
+
+        Ok(res.sort(vec![
+            col(ACCOUNT).eq(lit(CASHFLOW_NET)).sort(true, false),
+            col(ACCOUNT).sort(true, false),
+            col(COMMODITY).sort(true, false),
+        ])?)
+    }
+}
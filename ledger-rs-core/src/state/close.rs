@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use arrow::array::{Decimal128Array, StringArray};
+use arrow::datatypes::{Decimal128Type, DecimalType};
+use chrono::{Days, NaiveDate};
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+use futures::StreamExt;
+use itertools::izip;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, COST_SEP, DATE, ERROR_DOWNCAST, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY,
+    FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, OPEN_ACTION, OPEN_SYMBOL, PRECISION, SCALE,
+    TRANSACTION_FLAG,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::date_lit;
+
+const CLOSE_NARRATION: &str = "Close income and expenses";
+const OPENING_NARRATION: &str = "Opening balances";
+
+/// Account totals in a cp commodity and the tc commodity it was paid in
+struct Total {
+    account: String,
+    cp_commodity: String,
+    cp_quantity: i128,
+    tc_commodity: String,
+    tc_quantity: i128,
+}
+
+fn amount(q: i128) -> String {
+    Decimal128Type::format_decimal(q, PRECISION as u8, SCALE as i8)
+}
+
+/// A posting of `cp_quantity`, at the total cost `tc_quantity` when the
+/// commodities differ
+fn posting(account: &str, cp_quantity: i128, cp: &str, tc_quantity: i128, tc: &str) -> String {
+    let mut res = format!("  {}  {} {}", account, amount(cp_quantity), cp);
+    if cp != tc {
+        res.push_str(&format!(
+            " {} {} {}",
+            COST_SEP,
+            amount(tc_quantity.abs()),
+            tc
+        ));
+    }
+    res
+}
+
+async fn totals(df: DataFrame) -> Result<Vec<Total>> {
+    let df = df
+        .aggregate(
+            vec![
+                col(ACCOUNT),
+                col(FINAL_CP_COMMODITY),
+                col(FINAL_TC_COMMODITY),
+            ],
+            vec![
+                sum(col(FINAL_CP_QUANTITY)).alias(FINAL_CP_QUANTITY),
+                sum(col(FINAL_TC_QUANTITY)).alias(FINAL_TC_QUANTITY),
+            ],
+        )?
+        .sort(vec![
+            col(ACCOUNT).sort(true, false),
+            col(FINAL_CP_COMMODITY).sort(true, false),
+            col(FINAL_TC_COMMODITY).sort(true, false),
+        ])?;
+
+    let mut res = vec![];
+    let mut stream = df.execute_stream().await?;
+    while let Some(b) = stream.next().await.transpose()? {
+        let get_str = |name: &str| -> Result<&StringArray> {
+            b.column_by_name(name)
+                .context(format!("Unable to find {} col", name))?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)
+        };
+        let get_dec = |name: &str| -> Result<&Decimal128Array> {
+            b.column_by_name(name)
+                .context(format!("Unable to find {} col", name))?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context(ERROR_DOWNCAST)
+        };
+        for rec in izip!(
+            get_str(ACCOUNT)?,
+            get_str(FINAL_CP_COMMODITY)?,
+            get_dec(FINAL_CP_QUANTITY)?,
+            get_str(FINAL_TC_COMMODITY)?,
+            get_dec(FINAL_TC_QUANTITY)?
+        ) {
+            if let (Some(a), Some(cp_c), Some(cp_q), Some(tc_c), Some(tc_q)) = rec
+                && cp_q != 0
+            {
+                res.push(Total {
+                    account: a.to_string(),
+                    cp_commodity: cp_c.to_string(),
+                    cp_quantity: cp_q,
+                    tc_commodity: tc_c.to_string(),
+                    tc_quantity: tc_q,
+                });
+            }
+        }
+    }
+    Ok(res)
+}
+
+impl LedgerState {
+    ///
+    /// Bean text to close the books before `date`: a transaction on the day
+    /// before moving the Income and Expenses totals to the earnings account
+    /// of the ledger's roots, opening it if needed, to append to this ledger, and the opens and
+    /// opening balances of the Assets, Liabilities and Equity accounts as of
+    /// `date`, to start the next one. Either is empty when there is nothing
+    /// to carry over. Holdings bought at a cost keep it as an `@@` total
+    /// cost, so the opening transaction balances in the tc commodities.
+    ///
+    pub async fn close_books(&self, date: NaiveDate) -> Result<(String, String)> {
+        let prefix =
+            |base: &str| starts_with(col(ACCOUNT), lit(format!("{}{}", base, ACCOUNT_SEP)));
+        let journal_df = self.journal_df()?.filter(col(DATE).lt(date_lit(date)))?;
+        let closing_date = date - Days::new(1);
+        let earnings_account = self.roots.earnings();
+
+        let mut closing = String::new();
+        let mut earnings: BTreeMap<String, i128> = BTreeMap::new();
+        let earnings_df = journal_df
+            .clone()
+            .filter(prefix(&self.roots.income).or(prefix(&self.roots.expenses)))?;
+        let earnings_totals = totals(earnings_df).await?;
+        let earnings_opened = self
+            .verifications
+            .iter()
+            .any(|v| v.action == OPEN_ACTION && v.account == earnings_account);
+        if !earnings_totals.is_empty() && !earnings_opened {
+            closing.push_str(&format!(
+                "{} {} {}\n",
+                closing_date, OPEN_SYMBOL, earnings_account
+            ));
+        }
+        if !earnings_totals.is_empty() {
+            closing.push_str(&format!(
+                "{} {} \"{}\"\n",
+                closing_date, TRANSACTION_FLAG, CLOSE_NARRATION
+            ));
+        }
+        for t in earnings_totals {
+            closing.push_str(&posting(
+                &t.account,
+                -t.cp_quantity,
+                &t.cp_commodity,
+                -t.tc_quantity,
+                &t.tc_commodity,
+            ));
+            closing.push('\n');
+            *earnings.entry(t.tc_commodity).or_insert(0) += t.tc_quantity;
+        }
+        for (commodity, q) in earnings.iter().filter(|(_, q)| **q != 0) {
+            closing.push_str(&posting(&earnings_account, *q, commodity, *q, commodity));
+            closing.push('\n');
+        }
+
+        let is_earnings = prefix(&self.roots.income).or(prefix(&self.roots.expenses));
+        let balances_df = journal_df
+            .select(vec![
+                when(is_earnings, lit(self.roots.earnings()))
+                    .otherwise(col(ACCOUNT))?
+                    .alias(ACCOUNT),
+                col(FINAL_CP_COMMODITY),
+                col(FINAL_CP_QUANTITY),
+                col(FINAL_TC_COMMODITY),
+                col(FINAL_TC_QUANTITY),
+            ])?
+            .filter(
+                prefix(&self.roots.assets)
+                    .or(prefix(&self.roots.liabilities))
+                    .or(prefix(&self.roots.equity)),
+            )?;
+        let balances = totals(balances_df).await?;
+
+        let mut opening = String::new();
+        let mut accounts: Vec<&str> = balances.iter().map(|t| t.account.as_str()).collect();
+        accounts.dedup();
+        for a in accounts {
+            opening.push_str(&format!("{} {} {}\n", date, OPEN_SYMBOL, a));
+        }
+        if !balances.is_empty() {
+            opening.push_str(&format!(
+                "\n{} {} \"{}\"\n",
+                date, TRANSACTION_FLAG, OPENING_NARRATION
+            ));
+        }
+        for t in balances.iter() {
+            opening.push_str(&posting(
+                &t.account,
+                t.cp_quantity,
+                &t.cp_commodity,
+                t.tc_quantity,
+                &t.tc_commodity,
+            ));
+            opening.push('\n');
+        }
+
+        Ok((closing, opening))
+    }
+}
@@ -1,29 +1,137 @@
 use anyhow::Context;
 use anyhow::Result;
+use anyhow::anyhow;
 use datafusion::prelude::*;
 
+use crate::core::CMP_ACCOUNT;
 use crate::core::STATEMENT_NO;
 use crate::core::STATEMENT_NO_RIGHT;
 use crate::core::TRANSACTION_NO;
 use crate::{
     core::{
-        ACCOUNT, DATE, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY, FINAL_TC_QUANTITY,
+        ACCOUNT, DATE, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY,
+        FINAL_TC_QUANTITY, NARRATION,
     },
+    mapping::SymbolsMap,
     state::ledgerstate::LedgerState,
 };
 
-impl LedgerState {
-    pub async fn compare_postings(&mut self, b: &LedgerState) -> Result<()> {
-        let a_transactions_df = self.transactions_df.clone().context("No transactions df")?;
-        let b_transactions_df = b.transactions_df.clone().context("No transactions df")?;
+pub const CMP_KEY_DATE: &str = "date";
+pub const CMP_KEY_ACCOUNT: &str = "account";
+pub const CMP_KEY_CP: &str = "cp";
+pub const CMP_KEY_TC: &str = "tc";
+pub const CMP_KEY_NARRATION: &str = "narration";
+
+/// A posting attribute two ledgers must agree on for postings to match
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareKey {
+    Date,
+    Account,
+    CpAmount,
+    TcAmount,
+    Narration,
+}
+
+impl CompareKey {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            CMP_KEY_DATE => Ok(Self::Date),
+            CMP_KEY_ACCOUNT => Ok(Self::Account),
+            CMP_KEY_CP => Ok(Self::CpAmount),
+            CMP_KEY_TC => Ok(Self::TcAmount),
+            CMP_KEY_NARRATION => Ok(Self::Narration),
+            _ => Err(anyhow!("Unknown compare key: {}", name)),
+        }
+    }
+
+    fn columns(&self) -> Vec<&'static str> {
+        match self {
+            Self::Date => vec![DATE],
+            Self::Account => vec![CMP_ACCOUNT],
+            Self::CpAmount => vec![FINAL_CP_COMMODITY, FINAL_CP_QUANTITY],
+            Self::TcAmount => vec![FINAL_TC_COMMODITY, FINAL_TC_QUANTITY],
+            Self::Narration => vec![NARRATION],
+        }
+    }
+}
+
+///
+/// How postings are matched between two ledgers. A posting matches when all
+/// of `keys` agree; by default date, account, cp and tc amounts, with the
+/// narration ignored. Accounts are first renamed through `account_map`
+/// (variant spelling to canonical) on both sides and, with `ignore_case`,
+/// compared case-insensitively. The reported postings keep their original
+/// account names.
+///
+#[derive(Debug, Clone)]
+pub struct CompareOptions {
+    pub keys: Vec<CompareKey>,
+    pub account_map: SymbolsMap,
+    pub ignore_case: bool,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                CompareKey::Date,
+                CompareKey::Account,
+                CompareKey::CpAmount,
+                CompareKey::TcAmount,
+            ],
+            account_map: SymbolsMap::new(),
+            ignore_case: false,
+        }
+    }
+}
+
+impl CompareOptions {
+    fn account_key(&self) -> Result<Expr> {
+        let fold = |e: Expr| if self.ignore_case { lower(e) } else { e };
+        let mut renames: Vec<(&String, &String)> = self.account_map.iter().collect();
+        renames.sort();
+        let Some(((k, v), rest)) = renames.split_first() else {
+            return Ok(fold(col(ACCOUNT)));
+        };
+        let key = |k: &str| {
+            if self.ignore_case {
+                k.to_lowercase()
+            } else {
+                k.to_string()
+            }
+        };
+        let mut e = when(fold(col(ACCOUNT)).eq(lit(key(k))), lit(v.as_str()));
+        for (k, v) in rest {
+            e = e.when(fold(col(ACCOUNT)).eq(lit(key(k))), lit(v.as_str()));
+        }
+        Ok(fold(e.otherwise(col(ACCOUNT))?))
+    }
+
+    fn key_columns(&self) -> Vec<&'static str> {
+        let mut cols = vec![];
+        for k in self.keys.iter() {
+            for c in k.columns() {
+                if !cols.contains(&c) {
+                    cols.push(c);
+                }
+            }
+        }
+        cols
+    }
+}
 
-        let a_postings_df = self.postings_df.clone().context("No postings df")?;
-        let b_postings_df = b.postings_df.clone().context("No postings df")?;
+impl LedgerState {
+    fn compare_df(&self) -> Result<DataFrame> {
+        let transactions_df = self.transactions_df.clone().context("No transactions df")?;
+        let postings_df = self.postings_df.clone().context("No postings df")?;
 
-        let a_df = a_postings_df
+        let df = postings_df
             .join(
-                a_transactions_df
-                    .select(vec![col(DATE), col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT)])?,
+                transactions_df.select(vec![
+                    col(DATE),
+                    col(NARRATION),
+                    col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                ])?,
                 JoinType::Left,
                 &[TRANSACTION_NO],
                 &[STATEMENT_NO_RIGHT],
@@ -32,56 +140,49 @@ impl LedgerState {
             .select(vec![
                 col(TRANSACTION_NO),
                 col(DATE),
+                col(NARRATION),
                 col(ACCOUNT),
                 col(FINAL_CP_COMMODITY),
                 col(FINAL_CP_QUANTITY),
                 col(FINAL_TC_COMMODITY),
                 col(FINAL_TC_QUANTITY),
             ])?;
+        Ok(df)
+    }
+
+    ///
+    /// Shows the postings of this ledger, then those with no matching posting
+    /// in `b` according to `opts`.
+    ///
+    pub async fn compare_postings(&mut self, b: &LedgerState, opts: &CompareOptions) -> Result<()> {
+        let keys = opts.key_columns();
+        if keys.is_empty() {
+            return Err(anyhow!("No compare keys"));
+        }
+
+        let a_df = self
+            .compare_df()?
+            .with_column(CMP_ACCOUNT, opts.account_key()?)?;
+
+        let a_rows = a_df.clone().drop_columns(&[CMP_ACCOUNT])?.collect().await?;
+        self.locale.print_batches(&a_rows)?;
 
-        a_df.clone().show().await?;
+        let b_df = b
+            .compare_df()?
+            .with_column(CMP_ACCOUNT, opts.account_key()?)?
+            .select_columns(&keys)?;
 
-        let b_df = b_postings_df
+        let df = a_df
             .join(
-                b_transactions_df
-                    .select(vec![col(DATE), col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT)])?,
-                JoinType::Left,
-                &[TRANSACTION_NO],
-                &[STATEMENT_NO_RIGHT],
+                b_df,
+                datafusion::common::JoinType::LeftAnti,
+                &keys,
+                &keys,
                 None,
             )?
-            .select(vec![
-                col(DATE),
-                col(ACCOUNT),
-                col(FINAL_CP_COMMODITY),
-                col(FINAL_CP_QUANTITY),
-                col(FINAL_TC_COMMODITY),
-                col(FINAL_TC_QUANTITY),
-            ])?;
-
-        let df = a_df.join(
-            b_df,
-            datafusion::common::JoinType::LeftAnti,
-            &[
-                DATE,
-                ACCOUNT,
-                FINAL_CP_COMMODITY,
-                FINAL_CP_QUANTITY,
-                FINAL_TC_COMMODITY,
-                FINAL_TC_QUANTITY,
-            ],
-            &[
-                DATE,
-                ACCOUNT,
-                FINAL_CP_COMMODITY,
-                FINAL_CP_QUANTITY,
-                FINAL_TC_COMMODITY,
-                FINAL_TC_QUANTITY,
-            ],
-            None,
-        )?;
+            .drop_columns(&[CMP_ACCOUNT])?;
 
-        df.show().await?;
+        self.locale.print_batches(&df.collect().await?)?;
 
         Ok(())
     }
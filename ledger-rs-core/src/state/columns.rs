@@ -0,0 +1,102 @@
+use anyhow::Context;
+use anyhow::Result;
+use arrow::array::{ArrayBuilder, RecordBatch, StructArray};
+use arrow_convert::field::ArrowField;
+use arrow_convert::serialize::ArrowSerialize;
+
+use crate::core::{
+    HeaderParams, InfoParams, MetadataParams, PostingParams, PriceParams, VerificationParams,
+};
+use crate::state::ledgerstate::LedgerState;
+
+/// The rows of one table as Arrow arrays, appended to as they are parsed
+pub(crate) struct Column<T: ArrowSerialize> {
+    builder: T::ArrayBuilderType,
+}
+
+impl<T> Column<T>
+where
+    T: ArrowSerialize + ArrowField<Type = T>,
+{
+    fn new() -> Self {
+        Self {
+            builder: T::new_array(),
+        }
+    }
+
+    fn extend(&mut self, rows: &[T]) -> Result<()> {
+        for x in rows {
+            T::arrow_serialize(x, &mut self.builder)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// The rows appended so far followed by `rows` as a RecordBatch, leaving
+    /// the column empty. With `columnar`, `rows` is emptied too so they are
+    /// only held by the batch.
+    ///
+    pub(crate) fn batch(&mut self, rows: &mut Vec<T>, columnar: bool) -> Result<RecordBatch> {
+        self.extend(rows)?;
+        if columnar {
+            *rows = vec![];
+        }
+        let array = self.builder.finish();
+        let struct_array = array
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .context("Unable to downcast parsed rows")?;
+        Ok(struct_array.into())
+    }
+}
+
+/// The tables verify reads, as built by LedgerState::append_columns
+pub(crate) struct ParsedColumns {
+    pub(crate) transactions: Column<HeaderParams>,
+    pub(crate) postings: Column<PostingParams>,
+    pub(crate) verifications: Column<VerificationParams>,
+    pub(crate) informationals: Column<InfoParams>,
+    pub(crate) metadata: Column<MetadataParams>,
+    pub(crate) prices: Column<PriceParams>,
+}
+
+impl Default for ParsedColumns {
+    fn default() -> Self {
+        Self {
+            transactions: Column::new(),
+            postings: Column::new(),
+            verifications: Column::new(),
+            informationals: Column::new(),
+            metadata: Column::new(),
+            prices: Column::new(),
+        }
+    }
+}
+
+impl LedgerState {
+    ///
+    /// Moves the rows parsed since the last call into `columns`, when
+    /// `columnar`, so that a statement is held as parsed params only until
+    /// the parser is done with it. The includes stay, as they are not part
+    /// of the DataFrames.
+    ///
+    pub(crate) fn append_columns(&mut self) -> Result<()> {
+        if !self.columnar {
+            return Ok(());
+        }
+        let c = &mut self.columns;
+        c.transactions.extend(&self.transactions)?;
+        c.postings.extend(&self.postings)?;
+        c.verifications.extend(&self.verifications)?;
+        c.informationals.extend(&self.informationals)?;
+        c.metadata.extend(&self.metadata)?;
+        c.prices.extend(&self.prices)?;
+        self.transactions.clear();
+        self.postings.clear();
+        self.verifications.clear();
+        self.informationals.clear();
+        self.metadata.clear();
+        self.prices.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use datafusion::functions::datetime::expr_fn::date_part;
+use datafusion::functions_aggregate::expr_fn::{count, min, sum};
+use datafusion::functions_nested::expr_fn::array_has;
+use datafusion::functions_window::expr_fn::row_number;
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, COMMODITY, CONTRIBUTION_TAG, DATE, FILE_NO, FINAL_TC_COMMODITY,
+    FINAL_TC_QUANTITY, PRECISION, QUANTITY, ROOM, SCALE, START, STATEMENT_NO, TAG_SEP, TAGS,
+    TRANSACTION_NO, TRANSACTION_NO_RIGHT, YEAR,
+};
+use crate::custom::{ContributionLimit, custom_directives, read_rows};
+use crate::roots::AccountRoots;
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{running_sum, zero_lit};
+
+const ROW_NO: &str = "row_no";
+const REGISTERED: &str = "registered";
+const REGISTERED_COMMODITY: &str = "registered_commodity";
+const FIRST_YEAR: &str = "first_year";
+const LIMIT_YEAR: &str = "limit_year";
+const CONTRIBUTION_YEAR: &str = "contribution_year";
+const TAGGED: &str = "tagged";
+const INSIDE: &str = "inside";
+const HOLDINGS: &str = "holdings";
+const INFLOW: &str = "inflow";
+const CARRIED: &str = "carried";
+const LIMIT: &str = "limit";
+const CONTRIBUTED: &str = "contributed";
+
+impl LedgerState {
+    ///
+    /// contribution_room_df with the STATEMENT_NO, FILE_NO and START of the
+    /// contribution-limit directive of each year, null for the years without
+    /// one, for the ContributionLimit check to point at.
+    ///
+    pub(crate) fn contribution_years_df(&self) -> Result<DataFrame> {
+        let amount_type = DataType::Decimal128(PRECISION as u8, SCALE as i8);
+        let (limits, _) = ContributionLimit::limits(&custom_directives(self));
+
+        let latest = row_number()
+            .partition_by(vec![col(ACCOUNT), col(COMMODITY), col(YEAR)])
+            .order_by(vec![col(STATEMENT_NO).sort(false, false)])
+            .build()?;
+        let limits_df = read_rows(&limits)?
+            .with_column(ROW_NO, latest)?
+            .filter(col(ROW_NO).eq(lit(1u64)))?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(ACCOUNT),
+                col(YEAR),
+                col(COMMODITY),
+                cast(col(QUANTITY), amount_type.clone()).alias(LIMIT),
+            ])?;
+        let registered_df = limits_df.clone().aggregate(
+            vec![
+                col(ACCOUNT).alias(REGISTERED),
+                col(COMMODITY).alias(REGISTERED_COMMODITY),
+            ],
+            vec![min(col(YEAR)).alias(FIRST_YEAR)],
+        )?;
+
+        // The postings of each transaction to Assets and Liabilities, to tell
+        // money moved in from elsewhere from income earned in the account
+        let under = |root: &str| starts_with(col(ACCOUNT), lit(AccountRoots::prefix(root)));
+        let holdings_df = self.report_journal_df()?.aggregate(
+            vec![col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT)],
+            vec![
+                sum(when(
+                    under(&self.roots.assets).or(under(&self.roots.liabilities)),
+                    lit(1i64),
+                )
+                .otherwise(lit(0i64))?)
+                .alias(HOLDINGS),
+            ],
+        )?;
+
+        let inside = col(ACCOUNT).eq(col(REGISTERED)).or(starts_with(
+            col(ACCOUNT),
+            concat(vec![col(REGISTERED), lit(ACCOUNT_SEP)]),
+        ));
+        let tagged = array_has(
+            string_to_array(
+                coalesce(vec![col(TAGS), lit("")]),
+                lit(TAG_SEP),
+                lit(ScalarValue::Utf8(None)),
+            ),
+            lit(CONTRIBUTION_TAG),
+        );
+        let inflow = when(
+            col(FINAL_TC_QUANTITY).gt(zero_lit()),
+            col(FINAL_TC_QUANTITY),
+        )
+        .otherwise(zero_lit())?;
+        let contributions_df = self
+            .report_journal_df()?
+            .join_on(
+                registered_df,
+                JoinType::Inner,
+                vec![
+                    inside,
+                    col(FINAL_TC_COMMODITY).eq(col(REGISTERED_COMMODITY)),
+                ],
+            )?
+            .with_column(
+                YEAR,
+                cast(date_part(lit("year"), col(DATE)), DataType::Int32),
+            )?
+            .filter(col(YEAR).gt_eq(col(FIRST_YEAR)))?
+            .aggregate(
+                vec![
+                    col(TRANSACTION_NO),
+                    col(REGISTERED),
+                    col(REGISTERED_COMMODITY),
+                    col(YEAR),
+                    tagged.alias(TAGGED),
+                ],
+                vec![count(lit(1)).alias(INSIDE), sum(inflow).alias(INFLOW)],
+            )?
+            .join(
+                holdings_df,
+                JoinType::Inner,
+                &[TRANSACTION_NO],
+                &[TRANSACTION_NO_RIGHT],
+                None,
+            )?
+            .filter(col(TAGGED).or(col(HOLDINGS).gt(col(INSIDE))))?
+            .aggregate(
+                vec![
+                    col(REGISTERED),
+                    col(REGISTERED_COMMODITY),
+                    col(YEAR).alias(CONTRIBUTION_YEAR),
+                ],
+                vec![sum(col(INFLOW)).alias(CONTRIBUTED)],
+            )?;
+
+        let room = running_sum(
+            col(LIMIT) - col(CONTRIBUTED),
+            vec![col(ACCOUNT), col(COMMODITY)],
+            vec![col(YEAR).sort(true, false)],
+        )?;
+        let df = limits_df
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                col(ACCOUNT),
+                col(YEAR).alias(LIMIT_YEAR),
+                col(COMMODITY),
+                col(LIMIT),
+            ])?
+            .join(
+                contributions_df,
+                JoinType::Full,
+                &[ACCOUNT, COMMODITY, LIMIT_YEAR],
+                &[REGISTERED, REGISTERED_COMMODITY, CONTRIBUTION_YEAR],
+                None,
+            )?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(FILE_NO),
+                col(START),
+                coalesce(vec![col(ACCOUNT), col(REGISTERED)]).alias(ACCOUNT),
+                coalesce(vec![col(LIMIT_YEAR), col(CONTRIBUTION_YEAR)]).alias(YEAR),
+                coalesce(vec![col(COMMODITY), col(REGISTERED_COMMODITY)]).alias(COMMODITY),
+                coalesce(vec![col(LIMIT), zero_lit()]).alias(LIMIT),
+                cast(
+                    coalesce(vec![col(CONTRIBUTED), zero_lit()]),
+                    amount_type.clone(),
+                )
+                .alias(CONTRIBUTED),
+            ])?
+            .with_column(ROOM, cast(room, amount_type.clone()))?
+            .with_column(
+                CARRIED,
+                cast(col(ROOM) - col(LIMIT) + col(CONTRIBUTED), amount_type),
+            )?
+            .sort(vec![
+                col(ACCOUNT).sort(true, false),
+                col(COMMODITY).sort(true, false),
+                col(YEAR).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+
+    ///
+    /// Per registered account and commodity of its contribution-limit
+    /// directives, and per year from the first of them: the room carried
+    /// from the years before, the limit of the year, the contributions and
+    /// the room left. Contributions are the tc amounts moved into the account
+    /// or its sub-accounts from other Assets or Liabilities accounts, or
+    /// posted to it by a transaction tagged #contribution; withdrawals do
+    /// not give room back, and income earned in the account is not counted.
+    ///
+    pub fn contribution_room_df(&self) -> Result<DataFrame> {
+        let df = self.contribution_years_df()?.select(vec![
+            col(ACCOUNT),
+            col(YEAR),
+            col(COMMODITY),
+            col(CARRIED),
+            col(LIMIT),
+            col(CONTRIBUTED),
+            col(ROOM),
+        ])?;
+        Ok(df)
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use arrow::array::{Date32Array, Decimal128Array, StringArray, UInt32Array};
+use arrow::datatypes::{Date32Type, Decimal128Type, DecimalType};
+use chrono::{Duration, NaiveDate};
+use datafusion::prelude::*;
+use futures::StreamExt;
+use itertools::izip;
+
+use crate::core::{
+    ACCOUNT, ACTION_COL, BALANCE_ACTION, COMMODITY, CONVERT_HLEDGER, CONVERT_LEDGER, COST_SEP,
+    CURRENCY, DATE, ERROR_DOWNCAST, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY,
+    FINAL_TC_QUANTITY, FLAG, NARRATION, OPEN_ACTION, PENDING_FLAG, PRECISION, PRICE, QUANTITY,
+    SCALE, STATEMENT_NO, SUBTREE_BALANCE_ACTION, TAG_SEP, TAGS, TRANSACTION_FLAG, TRANSACTION_NO,
+};
+use crate::state::ledgerstate::{LedgerState, format_price};
+
+/// Entries are sorted by date, then transactions, assertions and prices
+struct Entry {
+    date: NaiveDate,
+    kind: u8,
+    no: u32,
+    text: String,
+}
+
+fn tag_comment(format: &str, tags: &str) -> String {
+    let names: Vec<&str> = tags
+        .split(TAG_SEP)
+        .map(|t| t.trim_start_matches('#'))
+        .filter(|t| !t.is_empty())
+        .collect();
+    if format == CONVERT_LEDGER {
+        format!("    ; :{}:", names.join(":"))
+    } else {
+        let hl: Vec<String> = names.iter().map(|t| format!("{}:", t)).collect();
+        format!("    ; {}", hl.join(", "))
+    }
+}
+
+impl LedgerState {
+    ///
+    /// Prints the ledger in ledger-cli (CONVERT_LEDGER) or hledger
+    /// (CONVERT_HLEDGER) journal syntax: account directives for the opens,
+    /// transactions with `@@` total costs and tags as comments, price
+    /// directives as `P`, and balance assertions as `=` postings of zero.
+    /// Beancount checks balances at the start of their date, so assertions are
+    /// dated the day before. Subtree assertions become hledger `=*`; ledger-cli
+    /// has no equivalent and they are emitted as comments.
+    ///
+    pub async fn write_convert(&self, format: &str) -> Result<()> {
+        if format != CONVERT_LEDGER && format != CONVERT_HLEDGER {
+            return Err(anyhow!("Unknown convert format: {}", format));
+        }
+        let mut entries = vec![];
+
+        let df = self.journal_df()?.sort(vec![
+            col(DATE).sort(true, false),
+            col(TRANSACTION_NO).sort(true, false),
+            col(STATEMENT_NO).sort(true, false),
+        ])?;
+        let mut stream = df.execute_stream().await?;
+        let mut current: Option<Entry> = None;
+        while let Some(b) = stream.next().await.transpose()? {
+            let get_str = |name: &str| -> Result<&StringArray> {
+                b.column_by_name(name)
+                    .context(format!("Unable to find {} col", name))?
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .context(ERROR_DOWNCAST)
+            };
+            let get_dec = |name: &str| -> Result<&Decimal128Array> {
+                b.column_by_name(name)
+                    .context(format!("Unable to find {} col", name))?
+                    .as_any()
+                    .downcast_ref::<Decimal128Array>()
+                    .context(ERROR_DOWNCAST)
+            };
+            let transaction_no = b
+                .column_by_name(TRANSACTION_NO)
+                .context("Unable to find transaction_no col")?
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let t_date = b
+                .column_by_name(DATE)
+                .context("Unable to find date col")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context(ERROR_DOWNCAST)?;
+
+            for (t_no, d, f, n, ts, a, cp_c, cp_q, tc_c, tc_q) in izip!(
+                transaction_no,
+                t_date,
+                get_str(FLAG)?,
+                get_str(NARRATION)?,
+                get_str(TAGS)?,
+                get_str(ACCOUNT)?,
+                get_str(FINAL_CP_COMMODITY)?,
+                get_dec(FINAL_CP_QUANTITY)?,
+                get_str(FINAL_TC_COMMODITY)?,
+                get_dec(FINAL_TC_QUANTITY)?
+            ) {
+                let (Some(t_no), Some(d), Some(a), Some(cp_c), Some(cp_q)) =
+                    (t_no, d, a, cp_c, cp_q)
+                else {
+                    continue;
+                };
+                if current.as_ref().is_none_or(|e| e.no != t_no) {
+                    if let Some(e) = current.take() {
+                        entries.push(e);
+                    }
+                    let date = Date32Type::to_naive_date(d);
+                    // Both only know the cleared and pending flags
+                    let flag = match f {
+                        Some(PENDING_FLAG) => PENDING_FLAG,
+                        _ => TRANSACTION_FLAG,
+                    };
+                    let mut text = format!("{} {} {}", date, flag, n.unwrap_or(""));
+                    if let Some(t) = ts {
+                        text.push('\n');
+                        text.push_str(&tag_comment(format, t));
+                    }
+                    current = Some(Entry {
+                        date,
+                        kind: 0,
+                        no: t_no,
+                        text,
+                    });
+                }
+                let q = Decimal128Type::format_decimal(cp_q, PRECISION as u8, SCALE as i8);
+                let mut line = format!("\n    {}  {} {}", a, q, cp_c);
+                if let (Some(tc_c), Some(tc_q)) = (tc_c, tc_q)
+                    && tc_c != cp_c
+                {
+                    let tq =
+                        Decimal128Type::format_decimal(tc_q.abs(), PRECISION as u8, SCALE as i8);
+                    line.push_str(&format!(" {} {} {}", COST_SEP, tq, tc_c));
+                }
+                if let Some(e) = current.as_mut() {
+                    e.text.push_str(&line);
+                }
+            }
+        }
+        if let Some(e) = current.take() {
+            entries.push(e);
+        }
+
+        let mut opens = vec![];
+        let df = self
+            .verifications_df
+            .clone()
+            .context("No verifications df")?;
+        let mut stream = df.execute_stream().await?;
+        while let Some(b) = stream.next().await.transpose()? {
+            let action = b
+                .column_by_name(ACTION_COL)
+                .context("Unable to find action col")?
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let statement_no = b
+                .column_by_name(STATEMENT_NO)
+                .context("Unable to find statement_no col")?
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let t_date = b
+                .column_by_name(DATE)
+                .context("Unable to find date col")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let account = b
+                .column_by_name(ACCOUNT)
+                .context("Unable to find account col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            let commodity = b
+                .column_by_name(COMMODITY)
+                .context("Unable to find commodity col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            let quantity = b
+                .column_by_name(QUANTITY)
+                .context("Unable to find quantity col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context(ERROR_DOWNCAST)?;
+
+            for rec in izip!(action, statement_no, t_date, account, commodity, quantity) {
+                match rec {
+                    (Some(OPEN_ACTION), _, _, Some(a), _, _) => opens.push(a.to_string()),
+                    (Some(act), Some(no), Some(d), Some(a), Some(c), Some(q))
+                        if act == BALANCE_ACTION || act == SUBTREE_BALANCE_ACTION =>
+                    {
+                        let date = Date32Type::to_naive_date(d) - Duration::days(1);
+                        let q = Decimal128Type::format_decimal(q, PRECISION as u8, SCALE as i8);
+                        let text = match (act == SUBTREE_BALANCE_ACTION, format) {
+                            (false, _) => format!(
+                                "{} Balance assertion\n    {}  0 {} = {} {}",
+                                date, a, c, q, c
+                            ),
+                            (true, CONVERT_HLEDGER) => format!(
+                                "{} Balance assertion\n    {}  0 {} =* {} {}",
+                                date, a, c, q, c
+                            ),
+                            (true, _) => {
+                                format!("; {} subtree balance {} {} {}", date, a, q, c)
+                            }
+                        };
+                        entries.push(Entry {
+                            date,
+                            kind: 1,
+                            no,
+                            text,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let df = self.prices_df.clone().context("No prices df")?;
+        let mut stream = df.execute_stream().await?;
+        while let Some(b) = stream.next().await.transpose()? {
+            let statement_no = b
+                .column_by_name(STATEMENT_NO)
+                .context("Unable to find statement_no col")?
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let t_date = b
+                .column_by_name(DATE)
+                .context("Unable to find date col")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let commodity = b
+                .column_by_name(COMMODITY)
+                .context("Unable to find commodity col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            let price = b
+                .column_by_name(PRICE)
+                .context("Unable to find price col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context(ERROR_DOWNCAST)?;
+            let currency = b
+                .column_by_name(CURRENCY)
+                .context("Unable to find currency col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            for (no, d, c, p, x) in izip!(statement_no, t_date, commodity, price, currency) {
+                if let (Some(no), Some(d), Some(c), Some(p), Some(x)) = (no, d, c, p, x) {
+                    let date = Date32Type::to_naive_date(d);
+                    let p = format_price(p);
+                    entries.push(Entry {
+                        date,
+                        kind: 2,
+                        no,
+                        text: format!("P {} {} {} {}", date, c, p, x),
+                    });
+                }
+            }
+        }
+
+        for a in opens {
+            println!("account {}", a);
+        }
+        entries.sort_by_key(|e| (e.date, e.kind, e.no));
+        for e in entries {
+            println!("\n{}", e.text);
+        }
+
+        Ok(())
+    }
+}
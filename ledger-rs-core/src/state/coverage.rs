@@ -0,0 +1,94 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use datafusion::prelude::*;
+
+use crate::core::CoverageParams;
+use crate::custom::read_rows;
+use crate::state::ledgerstate::LedgerState;
+
+/// Days from `a` to `b`
+fn days(a: NaiveDate, b: NaiveDate) -> u32 {
+    (b - a).num_days().max(0) as u32
+}
+
+impl LedgerState {
+    ///
+    /// Per commodity posted, cp or tc, other than the currencies prices are
+    /// quoted in: the first and last posting date, the number of price
+    /// points, and the stretches of more than `gap_days` between them within
+    /// those dates, counting from the first posting to the first price and
+    /// from the last price to the last posting. Market values over a gap use
+    /// a stale price, or none.
+    ///
+    pub fn commodity_coverage_df(&self, gap_days: u32) -> Result<DataFrame> {
+        let dates: HashMap<u32, NaiveDate> = self
+            .transactions
+            .iter()
+            .map(|t| (t.statement_no, t.date))
+            .collect();
+
+        let mut seen: BTreeMap<&str, (NaiveDate, NaiveDate)> = BTreeMap::new();
+        for p in self.postings.iter() {
+            let Some(d) = dates.get(&p.transaction_no) else {
+                continue;
+            };
+            for c in [p.cp_commodity.as_deref(), p.tc_commodity.as_deref()]
+                .into_iter()
+                .flatten()
+            {
+                let range = seen.entry(c).or_insert((*d, *d));
+                range.0 = range.0.min(*d);
+                range.1 = range.1.max(*d);
+            }
+        }
+
+        let mut price_dates: HashMap<&str, Vec<NaiveDate>> = HashMap::new();
+        let mut currencies: HashSet<&str> = HashSet::new();
+        for p in self.prices.iter() {
+            price_dates.entry(&p.commodity).or_default().push(p.date);
+            currencies.insert(&p.currency);
+        }
+
+        let mut rows = vec![];
+        for (commodity, (first, last)) in seen {
+            let mut points = price_dates.remove(commodity).unwrap_or_default();
+            if points.is_empty() && currencies.contains(commodity) {
+                continue;
+            }
+            points.sort();
+            points.dedup();
+            let prices = points.len() as u32;
+
+            let mut bounds = vec![first];
+            bounds.extend(points.into_iter().filter(|d| first < *d && *d < last));
+            bounds.push(last);
+            let gaps: Vec<(NaiveDate, NaiveDate)> = bounds
+                .windows(2)
+                .map(|w| (w[0], w[1]))
+                .filter(|(a, b)| days(*a, *b) > gap_days)
+                .collect();
+
+            rows.push(CoverageParams {
+                commodity: commodity.to_string(),
+                first_date: first,
+                last_date: last,
+                prices,
+                largest_gap: bounds
+                    .windows(2)
+                    .map(|w| days(w[0], w[1]))
+                    .max()
+                    .unwrap_or(0),
+                gaps: gaps.len() as u32,
+                gap_ranges: gaps
+                    .iter()
+                    .map(|(a, b)| format!("{}..{}", a, b))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            });
+        }
+
+        read_rows(&rows)
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result, anyhow};
+use arrow::array::{Date32Array, Decimal128Array, StringArray};
+use arrow::datatypes::{DataType, Date32Type};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use datafusion::prelude::*;
+use futures::StreamExt;
+use itertools::izip;
+use rust_decimal::Decimal;
+
+use crate::core::{
+    ACCOUNT, BALANCE_ACTION, CHANGE, CLOSING, COMMODITY, DATE, ERROR_DOWNCAST, FINAL_CP_COMMODITY,
+    FINAL_CP_QUANTITY, PRECISION, SCALE, StatementCycleParams,
+};
+use crate::custom::read_rows;
+use crate::state::ledgerstate::LedgerState;
+
+const START_DATE: &str = "start_date";
+const END_DATE: &str = "end_date";
+const CYCLE_POSTINGS: &str = "postings";
+const STATEMENT: &str = "statement";
+const DIFFERENCE: &str = "difference";
+const CYCLE_DIFFERENCE: &str = "cycle_difference";
+
+/// The closing date of the month of `d`, the last day when shorter than `day`
+fn month_close(d: NaiveDate, day: u32) -> Option<NaiveDate> {
+    let first = d.with_day(1)?;
+    let last = (first + Months::new(1)).pred_opt()?;
+    first.with_day(day.min(last.day()))
+}
+
+/// The closing date of the statement cycle `d` falls in
+fn cycle_close(d: NaiveDate, day: u32) -> Option<NaiveDate> {
+    let close = month_close(d, day)?;
+    if d <= close {
+        Some(close)
+    } else {
+        month_close(d.with_day(1)? + Months::new(1), day)
+    }
+}
+
+impl LedgerState {
+    ///
+    /// The postings of `account` grouped into statement cycles closing on
+    /// `closing_day` of each month, or the last day of shorter months, per
+    /// commodity: the change over the cycle, the ledger balance at its close
+    /// and the balance assertion of the statement, dated on the closing day or
+    /// the day after, with their difference. A cycle_difference other than
+    /// zero is where a discrepancy with the statements starts, as it counts
+    /// only the part of the difference the previous statement did not have.
+    ///
+    pub async fn statement_cycles_df(&self, account: &str, closing_day: u32) -> Result<DataFrame> {
+        if !(1..=31).contains(&closing_day) {
+            return Err(anyhow!("Invalid closing day: {}", closing_day));
+        }
+
+        let df = self
+            .report_journal_df()?
+            .filter(col(ACCOUNT).eq(lit(account)))?
+            .select(vec![
+                col(DATE),
+                col(FINAL_CP_COMMODITY),
+                col(FINAL_CP_QUANTITY),
+            ])?;
+        let mut postings: Vec<(NaiveDate, String, Decimal)> = vec![];
+        let mut stream = df.execute_stream().await?;
+        while let Some(b) = stream.next().await.transpose()? {
+            let dates = b
+                .column_by_name(DATE)
+                .context("Unable to find date col")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let commodities = b
+                .column_by_name(FINAL_CP_COMMODITY)
+                .context("Unable to find commodity col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            let quantities = b
+                .column_by_name(FINAL_CP_QUANTITY)
+                .context("Unable to find quantity col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context(ERROR_DOWNCAST)?;
+            for rec in izip!(dates, commodities, quantities) {
+                if let (Some(d), Some(c), Some(q)) = rec {
+                    postings.push((
+                        Date32Type::to_naive_date(d),
+                        c.to_string(),
+                        Decimal::from_i128_with_scale(q, SCALE as u32),
+                    ));
+                }
+            }
+        }
+
+        let statements: BTreeMap<(String, NaiveDate), Decimal> = self
+            .verifications
+            .iter()
+            .filter(|v| v.action == BALANCE_ACTION && v.account == account)
+            .filter_map(|v| Some(((v.commodity.clone()?, v.date), v.quantity?)))
+            .collect();
+
+        let commodities: BTreeSet<&str> = postings
+            .iter()
+            .map(|(_, c, _)| c.as_str())
+            .chain(statements.keys().map(|(c, _)| c.as_str()))
+            .collect();
+
+        let mut rows = vec![];
+        for commodity in commodities {
+            let dates: Vec<NaiveDate> = postings
+                .iter()
+                .filter(|(_, c, _)| c == commodity)
+                .map(|(d, _, _)| *d)
+                .chain(
+                    statements
+                        .keys()
+                        .filter(|(c, _)| c == commodity)
+                        .filter_map(|(_, d)| d.pred_opt()),
+                )
+                .collect();
+            let (Some(first), Some(last)) = (dates.iter().min(), dates.iter().max()) else {
+                continue;
+            };
+            let (Some(mut close), Some(last_close)) = (
+                cycle_close(*first, closing_day),
+                cycle_close(*last, closing_day),
+            ) else {
+                continue;
+            };
+
+            let mut start = month_close(close - Months::new(1), closing_day)
+                .and_then(|d| d.succ_opt())
+                .context("Invalid cycle date")?;
+            let mut closing = Decimal::ZERO;
+            let mut previous_difference = Decimal::ZERO;
+            while close <= last_close {
+                let cycle: Vec<Decimal> = postings
+                    .iter()
+                    .filter(|(d, c, _)| c == commodity && start <= *d && *d <= close)
+                    .map(|(_, _, q)| *q)
+                    .collect();
+                let change: Decimal = cycle.iter().sum();
+                closing += change;
+
+                let statement = close
+                    .succ_opt()
+                    .and_then(|d| statements.get(&(commodity.to_string(), d)))
+                    .or_else(|| statements.get(&(commodity.to_string(), close)))
+                    .copied();
+                let difference = statement.map(|s| s - closing);
+                let cycle_difference = difference.map(|d| d - previous_difference);
+                if let Some(d) = difference {
+                    previous_difference = d;
+                }
+
+                rows.push(StatementCycleParams {
+                    commodity: commodity.to_string(),
+                    start_date: start,
+                    end_date: close,
+                    postings: cycle.len() as u32,
+                    change,
+                    closing,
+                    statement,
+                    difference,
+                    cycle_difference,
+                });
+
+                start = close + Days::new(1);
+                close = cycle_close(start, closing_day).context("Invalid cycle date")?;
+            }
+        }
+
+        let amount = |name: &str| {
+            cast(
+                col(name),
+                DataType::Decimal128(PRECISION as u8, SCALE as i8),
+            )
+            .alias(name)
+        };
+        let df = read_rows(&rows)?.select(vec![
+            col(COMMODITY),
+            col(START_DATE),
+            col(END_DATE),
+            col(CYCLE_POSTINGS),
+            amount(CHANGE),
+            amount(CLOSING),
+            amount(STATEMENT),
+            amount(DIFFERENCE),
+            amount(CYCLE_DIFFERENCE),
+        ])?;
+        Ok(df)
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, CHANGE, CLOSING, COMMODITY, DATE, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY,
+    OPENING,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{date_lit, zero_lit};
+
+impl LedgerState {
+    ///
+    /// Changes in equity over [begin, end): the opening balance, movement and
+    /// closing balance of each Equity account (opening balances, contributions,
+    /// draws, ...). Income and Expenses are rolled up into the earnings
+    /// account, so its opening is the retained earnings and its change the
+    /// net income of the period. Amounts are cp amounts with the ledger's signs.
+    ///
+    pub fn equity_df(&self, begin: Option<NaiveDate>, end: Option<NaiveDate>) -> Result<DataFrame> {
+        let prefix = |base: &str| lit(format!("{}{}", base, ACCOUNT_SEP));
+        let is_earnings = starts_with(col(ACCOUNT), prefix(&self.roots.income))
+            .or(starts_with(col(ACCOUNT), prefix(&self.roots.expenses)));
+
+        let mut df = self.report_journal_df()?.select(vec![
+            col(DATE),
+            when(is_earnings, lit(self.roots.earnings()))
+                .otherwise(col(ACCOUNT))?
+                .alias(ACCOUNT),
+            col(FINAL_CP_COMMODITY).alias(COMMODITY),
+            col(FINAL_CP_QUANTITY),
+        ])?;
+        df = df.filter(starts_with(col(ACCOUNT), prefix(&self.roots.equity)))?;
+        if let Some(e) = end {
+            df = df.filter(col(DATE).lt(date_lit(e)))?;
+        }
+
+        let before = match begin {
+            Some(b) => col(DATE).lt(date_lit(b)),
+            None => lit(false),
+        };
+        let df = df
+            .aggregate(
+                vec![col(ACCOUNT), col(COMMODITY)],
+                vec![
+                    sum(when(before.clone(), col(FINAL_CP_QUANTITY)).otherwise(zero_lit())?)
+                        .alias(OPENING),
+                    sum(when(before, zero_lit()).otherwise(col(FINAL_CP_QUANTITY))?).alias(CHANGE),
+                ],
+            )?
+            .with_column(CLOSING, col(OPENING) + col(CHANGE))?
+            .sort(vec![
+                col(ACCOUNT).sort(true, false),
+                col(COMMODITY).sort(true, false),
+            ])?;
+
+        Ok(df)
+    }
+}
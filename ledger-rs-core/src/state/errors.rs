@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+use arrow::array::{Date32Array, Decimal128Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Date32Type};
+use chrono::NaiveDate;
+use datafusion::prelude::*;
+use futures::StreamExt;
+use itertools::izip;
+
+use crate::check::line_col;
+use crate::core::{
+    COMMODITY, DATE, ERROR_DOWNCAST, FILE_NO, FINAL_TC_COMMODITY, NARRATION, PRECISION, SCALE,
+    START, STATEMENT_NO, STATEMENT_NO_RIGHT, TOTALS, TRANSACTION_NO,
+};
+use crate::state::ledgerstate::LedgerState;
+
+/// A transaction that does not balance, as found by errors_report_df
+struct Unbalanced {
+    file_no: u32,
+    start: u32,
+    date: NaiveDate,
+    narration: String,
+    /// The imbalance per tc commodity, without a commodity when none could
+    /// be inferred for a posting left without an amount
+    totals: Vec<(Option<String>, Option<i128>)>,
+}
+
+impl LedgerState {
+    ///
+    /// The rows of errors_df joined back to their transactions: one per
+    /// transaction and tc commodity that does not balance, with the file,
+    /// offset, date and narration of the transaction and the imbalance as
+    /// TOTALS, in file order.
+    ///
+    pub fn errors_report_df(&self) -> Result<DataFrame> {
+        let transactions_df = self.transactions_df.clone().context("No transactions df")?;
+        let df = self
+            .errors_df
+            .clone()
+            .context("No errors df")?
+            .join(
+                transactions_df.select(vec![
+                    col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                    col(FILE_NO),
+                    col(START),
+                    col(DATE),
+                    col(NARRATION),
+                ])?,
+                JoinType::Inner,
+                &[TRANSACTION_NO],
+                &[STATEMENT_NO_RIGHT],
+                None,
+            )?
+            .select(vec![
+                col(TRANSACTION_NO),
+                col(FILE_NO),
+                col(START),
+                col(DATE),
+                col(NARRATION),
+                col(FINAL_TC_COMMODITY).alias(COMMODITY),
+                cast(
+                    col(TOTALS),
+                    DataType::Decimal128(PRECISION as u8, SCALE as i8),
+                )
+                .alias(TOTALS),
+            ])?
+            .sort(vec![
+                col(FILE_NO).sort(true, false),
+                col(START).sort(true, false),
+                col(COMMODITY).sort(true, true),
+            ])?;
+        Ok(df)
+    }
+
+    ///
+    /// Prints each transaction of errors_report_df as its file and line, its
+    /// text as written from the header to the last posting, and its
+    /// imbalance per commodity as a comment. Returns the number of
+    /// transactions printed.
+    ///
+    pub async fn write_errors(&self) -> Result<usize> {
+        let mut unbalanced: BTreeMap<u32, Unbalanced> = BTreeMap::new();
+        let mut stream = self.errors_report_df()?.execute_stream().await?;
+        while let Some(b) = stream.next().await.transpose()? {
+            let get_u32 = |name: &str| -> Result<&UInt32Array> {
+                b.column_by_name(name)
+                    .context(format!("Unable to find {} col", name))?
+                    .as_any()
+                    .downcast_ref::<UInt32Array>()
+                    .context(ERROR_DOWNCAST)
+            };
+            let get_str = |name: &str| -> Result<&StringArray> {
+                b.column_by_name(name)
+                    .context(format!("Unable to find {} col", name))?
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .context(ERROR_DOWNCAST)
+            };
+            let dates = b
+                .column_by_name(DATE)
+                .context("Unable to find date col")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context(ERROR_DOWNCAST)?;
+            let totals = b
+                .column_by_name(TOTALS)
+                .context("Unable to find totals col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context(ERROR_DOWNCAST)?;
+            let rows = izip!(
+                get_u32(TRANSACTION_NO)?,
+                get_u32(FILE_NO)?,
+                get_u32(START)?,
+                dates,
+                get_str(NARRATION)?,
+                get_str(COMMODITY)?,
+                totals
+            );
+            for (t, f, s, d, n, c, q) in rows {
+                let (Some(t), Some(f), Some(s), Some(d)) = (t, f, s, d) else {
+                    continue;
+                };
+                unbalanced
+                    .entry(t)
+                    .or_insert_with(|| Unbalanced {
+                        file_no: f,
+                        start: s,
+                        date: Date32Type::to_naive_date(d),
+                        narration: n.unwrap_or_default().to_string(),
+                        totals: vec![],
+                    })
+                    .totals
+                    .push((c.map(String::from), q));
+            }
+        }
+
+        // A transaction ends with the last of its postings
+        let mut ends: HashMap<u32, u32> = HashMap::new();
+        for x in self.transactions.iter() {
+            if unbalanced.contains_key(&x.statement_no) {
+                ends.insert(x.statement_no, x.end);
+            }
+        }
+        for x in self.postings.iter() {
+            if let Some(e) = ends.get_mut(&x.transaction_no) {
+                *e = (*e).max(x.end);
+            }
+        }
+
+        let files: HashMap<u32, _> = self.input_files.iter().map(|(f, n)| (*n, f)).collect();
+        let mut unbalanced: Vec<(u32, Unbalanced)> = unbalanced.into_iter().collect();
+        unbalanced.sort_by_key(|(_, x)| (x.file_no, x.start));
+        for (t, x) in unbalanced.iter() {
+            let f = files.get(&x.file_no).context("Unknown file number")?;
+            let (line, _) = line_col(&self.source(x.file_no)?, x.start);
+            let end = ends.get(t).copied().unwrap_or(x.start);
+            let text = self.source_snippet(x.file_no, x.start, end)?;
+
+            println!(
+                "{}:{}: {} \"{}\"",
+                f.display(),
+                line,
+                self.locale.format_date(x.date),
+                x.narration
+            );
+            println!("{}", text.trim_end());
+            for (commodity, q) in x.totals.iter() {
+                match (commodity, q) {
+                    (Some(c), Some(q)) => {
+                        println!("; does not balance by {}", self.locale.format_amount(*q, c))
+                    }
+                    _ => println!("; the commodity of a posting without an amount is unknown"),
+                }
+            }
+            println!();
+        }
+        Ok(unbalanced.len())
+    }
+}
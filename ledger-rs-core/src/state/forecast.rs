@@ -0,0 +1,119 @@
+use chrono::{Months, NaiveDate};
+
+use crate::core::{FORECAST_TAG, HeaderParams, PostingParams, RECUR_KEY, TAG_SEP};
+use crate::custom::{BUDGET_MONTHLY, BUDGET_QUARTERLY, BUDGET_YEARLY};
+use crate::state::ledgerstate::LedgerState;
+
+/// Months between occurrences of a `recur` period
+fn period_months(period: &str) -> Option<u32> {
+    match period {
+        BUDGET_MONTHLY => Some(1),
+        BUDGET_QUARTERLY => Some(3),
+        BUDGET_YEARLY => Some(12),
+        _ => None,
+    }
+}
+
+impl LedgerState {
+    ///
+    /// The occurrences before `until` of the transactions with `recur:
+    /// "monthly"`, "quarterly" or "yearly" metadata, as a ledger of their
+    /// own. A template recurs from its date on the same day of each period,
+    /// but only after the last transaction of the ledger, as everything up
+    /// to then was entered for real. Generated transactions are tagged
+    /// FORECAST_TAG.
+    ///
+    pub fn forecast(&self, until: NaiveDate) -> LedgerState {
+        let mut res = LedgerState::new();
+        let Some(last) = self.transactions.iter().map(|t| t.date).max() else {
+            return res;
+        };
+
+        let mut templates: Vec<(&HeaderParams, u32)> = self
+            .metadata
+            .iter()
+            .filter(|m| m.key == RECUR_KEY)
+            .filter_map(|m| {
+                let months = period_months(m.value.trim())?;
+                let t = self
+                    .transactions
+                    .iter()
+                    .find(|t| t.statement_no == m.transaction_no)?;
+                Some((t, months))
+            })
+            .collect();
+        templates.sort_by_key(|(t, _)| t.statement_no);
+
+        let mut statement_no = 0;
+        for (t, months) in templates {
+            let postings: Vec<&PostingParams> = self
+                .postings
+                .iter()
+                .filter(|p| p.transaction_no == t.statement_no)
+                .collect();
+            let tags = match t.tags.as_deref() {
+                Some(tags) => format!("{}{}{}", tags, TAG_SEP, FORECAST_TAG),
+                None => FORECAST_TAG.to_string(),
+            };
+            for n in 1.. {
+                let Some(date) = t.date.checked_add_months(Months::new(months * n)) else {
+                    break;
+                };
+                if date >= until {
+                    break;
+                }
+                if date <= last {
+                    continue;
+                }
+                statement_no += 1;
+                let header_no = statement_no;
+                res.transactions.push(HeaderParams {
+                    statement_no: header_no,
+                    file_no: t.file_no,
+                    start: t.start,
+                    end: t.end,
+                    date,
+                    flag: t.flag.clone(),
+                    narration: t.narration.clone(),
+                    tags: Some(tags.clone()),
+                    raw: None,
+                });
+                for p in postings.iter() {
+                    statement_no += 1;
+                    res.postings.push(PostingParams {
+                        statement_no,
+                        transaction_no: header_no,
+                        ..(*p).clone()
+                    });
+                }
+            }
+        }
+        res
+    }
+
+    /// Adds the forecast up to `until` to the ledger, numbered after all
+    /// its statements, so reports run after verify include it
+    pub fn add_forecast(&mut self, until: NaiveDate) {
+        let forecast = self.forecast(until);
+        let offset = self
+            .transactions
+            .iter()
+            .map(|t| t.statement_no)
+            .chain(self.postings.iter().map(|p| p.statement_no))
+            .chain(self.verifications.iter().map(|v| v.statement_no))
+            .chain(self.prices.iter().map(|p| p.statement_no))
+            .chain(self.informationals.iter().map(|x| x.statement_no))
+            .chain(self.metadata.iter().map(|m| m.statement_no))
+            .max()
+            .unwrap_or(0);
+        for mut t in forecast.transactions {
+            t.statement_no += offset;
+            self.transactions.push(t);
+        }
+        for mut p in forecast.postings {
+            p.statement_no += offset;
+            p.transaction_no += offset;
+            self.postings.push(p);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Context;
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use chrono::NaiveDate;
+use datafusion::functions_aggregate::min_max::max;
+use datafusion::logical_expr::conditional_expressions::CaseBuilder;
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_RIGHT, BUY_COST, BUY_UNITS, COMMODITY, COST_BASIS, CURRENCY, DATE,
+    DOMESTIC_COUNTRY, DOMESTIC_CURRENCY, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY,
+    FINAL_TC_QUANTITY, PRECISION, SCALE, UNITS,
+};
+use crate::mapping::SymbolsMap;
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{period_expr, running_sum, zero_lit};
+
+const COUNTRY: &str = "country";
+const COST: &str = "cost";
+const MAX_COST: &str = "max_cost";
+const YEAR_END_COST: &str = "year_end_cost";
+const COMMODITY_RIGHT: &str = "commodity_right";
+const CURRENCY_RIGHT: &str = "currency_right";
+
+impl LedgerState {
+    ///
+    /// The foreign property held during `year` for the T1135: per account,
+    /// commodity and cost currency of the holdings of holdings_df, the
+    /// country of the commodity in `countries` (commodity to country code),
+    /// the units and cost basis at the end of the year, and the maximum
+    /// cost basis at the end of any day of the year, the start included.
+    /// A commodity is foreign when its country is not DOMESTIC_COUNTRY or,
+    /// not being in `countries`, when it was bought in another currency
+    /// than DOMESTIC_CURRENCY. Costs are in the currency they were paid in.
+    ///
+    pub fn foreign_property_df(&self, year: i32, countries: &SymbolsMap) -> Result<DataFrame> {
+        let amount_type = DataType::Decimal128(PRECISION as u8, SCALE as i8);
+        let start = NaiveDate::from_ymd_opt(year, 1, 1).context("Invalid year")?;
+        let end = NaiveDate::from_ymd_opt(year + 1, 1, 1).context("Invalid year")?;
+
+        // The cost basis after each day of the year, as holdings_df has it
+        let group = vec![
+            col(ACCOUNT),
+            col(FINAL_CP_COMMODITY),
+            col(FINAL_TC_COMMODITY),
+        ];
+        let order = vec![col(DATE).sort(true, false)];
+        let bought = col(FINAL_CP_QUANTITY).gt_eq(zero_lit());
+        let units = running_sum(col(FINAL_CP_QUANTITY), group.clone(), order.clone())?;
+        let buy_units = running_sum(
+            when(bought.clone(), col(FINAL_CP_QUANTITY)).otherwise(zero_lit())?,
+            group.clone(),
+            order.clone(),
+        )?;
+        let buy_cost = running_sum(
+            when(bought, col(FINAL_TC_QUANTITY)).otherwise(zero_lit())?,
+            group,
+            order,
+        )?;
+        let during_df = self
+            .report_journal_df()?
+            .filter(period_expr(None, Some(end)))?
+            .filter(col(FINAL_CP_COMMODITY).not_eq(col(FINAL_TC_COMMODITY)))?
+            .window(vec![
+                units.alias(UNITS),
+                buy_units.alias(BUY_UNITS),
+                buy_cost.alias(BUY_COST),
+            ])?
+            .filter(period_expr(Some(start), None))?
+            .select(vec![
+                col(ACCOUNT),
+                col(FINAL_CP_COMMODITY).alias(COMMODITY),
+                col(FINAL_TC_COMMODITY).alias(CURRENCY),
+                when(col(BUY_UNITS).eq(zero_lit()), zero_lit())
+                    .otherwise(cast(
+                        col(UNITS) * col(BUY_COST) / col(BUY_UNITS),
+                        amount_type.clone(),
+                    ))?
+                    .alias(COST),
+            ])?;
+        let opening_df = self.holdings_df(Some(start))?.select(vec![
+            col(ACCOUNT),
+            col(COMMODITY),
+            col(CURRENCY),
+            col(COST_BASIS).alias(COST),
+        ])?;
+        let closing_df = self.holdings_df(Some(end))?.select(vec![
+            col(ACCOUNT).alias(ACCOUNT_RIGHT),
+            col(COMMODITY).alias(COMMODITY_RIGHT),
+            col(CURRENCY).alias(CURRENCY_RIGHT),
+            col(UNITS),
+            col(COST_BASIS),
+        ])?;
+
+        let country = countries
+            .iter()
+            .fold(None, |e: Option<CaseBuilder>, (c, country)| {
+                let is = col(COMMODITY).eq(lit(c.as_str()));
+                Some(match e {
+                    Some(mut e) => e.when(is, lit(country.as_str())),
+                    None => when(is, lit(country.as_str())),
+                })
+            })
+            .map(|mut e| e.otherwise(lit(ScalarValue::Utf8(None))))
+            .transpose()?
+            .unwrap_or(lit(ScalarValue::Utf8(None)));
+        let foreign = col(COUNTRY)
+            .is_not_null()
+            .and(col(COUNTRY).not_eq(lit(DOMESTIC_COUNTRY)))
+            .or(col(COUNTRY)
+                .is_null()
+                .and(col(CURRENCY).not_eq(lit(DOMESTIC_CURRENCY))));
+
+        let df = during_df
+            .union(opening_df)?
+            .aggregate(
+                vec![col(ACCOUNT), col(COMMODITY), col(CURRENCY)],
+                vec![max(col(COST)).alias(MAX_COST)],
+            )?
+            .filter(col(MAX_COST).gt(zero_lit()))?
+            .with_column(COUNTRY, country)?
+            .filter(foreign)?
+            .join(
+                closing_df,
+                JoinType::Left,
+                &[ACCOUNT, COMMODITY, CURRENCY],
+                &[ACCOUNT_RIGHT, COMMODITY_RIGHT, CURRENCY_RIGHT],
+                None,
+            )?
+            .select(vec![
+                col(ACCOUNT),
+                col(COMMODITY),
+                col(COUNTRY),
+                col(CURRENCY),
+                cast(coalesce(vec![col(UNITS), zero_lit()]), amount_type.clone()).alias(UNITS),
+                col(MAX_COST),
+                cast(coalesce(vec![col(COST_BASIS), zero_lit()]), amount_type).alias(YEAR_END_COST),
+            ])?
+            .sort(vec![
+                col(ACCOUNT).sort(true, false),
+                col(COMMODITY).sort(true, false),
+                col(CURRENCY).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use datafusion::functions_aggregate::expr_fn::{min, sum};
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+
+use crate::core::{
+    ACCOUNT, COMMODITY, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, KEY, META_PREFIX, NO_META,
+    PNL_BY_ACCOUNT, PNL_BY_TAG, TAG, TAG_SEP, TAGS, TOTAL, TRANSACTION_NO, TRANSACTION_NO_RIGHT,
+    UNTAGGED, VALUE,
+};
+use crate::state::ledgerstate::LedgerState;
+
+impl LedgerState {
+    ///
+    /// Totals the cp amounts of journal postings `df` by account (PNL_BY_ACCOUNT),
+    /// by transaction tag and account (PNL_BY_TAG) or by a metadata value and
+    /// account (`meta:<key>`). A transaction with several tags counts towards
+    /// each of them; transactions without a tag or the metadata key are
+    /// reported under UNTAGGED or NO_META.
+    ///
+    pub(crate) fn grouped_totals(&self, df: DataFrame, by: &str) -> Result<DataFrame> {
+        let (df, group) = if by == PNL_BY_ACCOUNT {
+            (df, vec![col(ACCOUNT), col(COMMODITY)])
+        } else if by == PNL_BY_TAG {
+            let df = df
+                .with_column(
+                    TAG,
+                    string_to_array(
+                        coalesce(vec![col(TAGS), lit(UNTAGGED)]),
+                        lit(TAG_SEP),
+                        lit(ScalarValue::Utf8(None)),
+                    ),
+                )?
+                .unnest_columns(&[TAG])?;
+            (df, vec![col(TAG), col(ACCOUNT), col(COMMODITY)])
+        } else if let Some(key) = by.strip_prefix(META_PREFIX) {
+            let meta_df = self
+                .metadata_df
+                .clone()
+                .context("No metadata df")?
+                .filter(col(KEY).eq(lit(key)))?
+                .aggregate(
+                    vec![col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT)],
+                    vec![min(col(VALUE)).alias(VALUE)],
+                )?;
+            let df = df
+                .join(
+                    meta_df,
+                    JoinType::Left,
+                    &[TRANSACTION_NO],
+                    &[TRANSACTION_NO_RIGHT],
+                    None,
+                )?
+                .with_column(by, coalesce(vec![col(VALUE), lit(NO_META)]))?;
+            (df, vec![ident(by), col(ACCOUNT), col(COMMODITY)])
+        } else {
+            return Err(anyhow!("Unknown grouping: {}", by));
+        };
+
+        let df = df
+            .with_column(COMMODITY, col(FINAL_CP_COMMODITY))?
+            .aggregate(
+                group.clone(),
+                vec![sum(col(FINAL_CP_QUANTITY)).alias(TOTAL)],
+            )?
+            .sort(group.into_iter().map(|e| e.sort(true, false)).collect())?;
+
+        Ok(df)
+    }
+}
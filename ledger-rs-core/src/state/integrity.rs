@@ -0,0 +1,174 @@
+use anyhow::Result;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use sha2::{Digest, Sha256};
+
+use crate::core::{HASH_PREFIX, HMAC_BLOCK_BYTES, HMAC_PREFIX, PRICE_SEP, PostingParams};
+use crate::state::ledgerstate::LedgerState;
+
+/// `x` as written in the canonical lines, empty when absent
+fn field<T: ToString>(x: &Option<T>) -> String {
+    x.as_ref().map(|x| x.to_string()).unwrap_or_default()
+}
+
+/// `q` without trailing zeros, so that `1.50` and `1.5` hash the same
+fn amount(q: &Option<Decimal>) -> String {
+    field(&q.map(|q| q.normalize()))
+}
+
+/// The price of `p` as amount writes it, marked as per unit when written `@`
+fn price(p: &PostingParams) -> String {
+    match p.per_unit {
+        true => format!("{}{}", PRICE_SEP, amount(&p.tc_quantity)),
+        false => amount(&p.tc_quantity),
+    }
+}
+
+/// HMAC-SHA256 of `message` with `key`, as in RFC 2104
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; HMAC_BLOCK_BYTES];
+    if key.len() > HMAC_BLOCK_BYTES {
+        let k = Sha256::digest(key);
+        block[..k.len()].copy_from_slice(&k);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |b: u8| block.iter().map(|x| x ^ b).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+impl LedgerState {
+    ///
+    /// The parsed statements dated from `begin` up to the day before `end`,
+    /// with the undated options and customs, as one line each, sorted. A
+    /// transaction is its header followed by its postings and metadata, also
+    /// sorted. Only what the statements say is kept: comments, formatting,
+    /// includes and the order and files of the statements are left out, so
+    /// a ledger reformatted, sorted or split across files reads the same.
+    ///
+    pub fn canonical(&self, begin: Option<NaiveDate>, end: Option<NaiveDate>) -> String {
+        let within = |d: &Option<NaiveDate>| match d {
+            Some(d) => begin.is_none_or(|b| b <= *d) && end.is_none_or(|e| *d < e),
+            None => true,
+        };
+        let mut res: Vec<String> = vec![];
+        for x in self.transactions.iter().filter(|x| within(&Some(x.date))) {
+            let mut lines: Vec<String> = self
+                .postings
+                .iter()
+                .filter(|p| p.transaction_no == x.statement_no)
+                .map(|p| {
+                    format!(
+                        "  {}\t{}\t{}\t{}\t{}\t{}",
+                        field(&p.flag),
+                        p.account,
+                        amount(&p.cp_quantity),
+                        field(&p.cp_commodity),
+                        price(p),
+                        field(&p.tc_commodity)
+                    )
+                })
+                .chain(
+                    self.metadata
+                        .iter()
+                        .filter(|m| m.transaction_no == x.statement_no)
+                        .map(|m| format!("  {}\t{}", m.key, m.value)),
+                )
+                .collect();
+            lines.sort();
+            res.push(format!(
+                "transaction\t{}\t{}\t{}\t{}\n{}",
+                x.date,
+                x.flag,
+                x.narration,
+                field(&x.tags),
+                lines.join("\n")
+            ));
+        }
+        for x in self.verifications.iter().filter(|x| within(&Some(x.date))) {
+            res.push(format!(
+                "verification\t{}\t{}\t{}\t{}\t{}\t{}",
+                x.action,
+                x.date,
+                x.account,
+                amount(&x.quantity),
+                field(&x.commodity),
+                amount(&x.tolerance)
+            ));
+        }
+        for x in self.prices.iter().filter(|x| within(&Some(x.date))) {
+            res.push(format!(
+                "price\t{}\t{}\t{}\t{}",
+                x.date,
+                x.commodity,
+                x.price.normalize(),
+                x.currency
+            ));
+        }
+        for x in self.informationals.iter().filter(|x| within(&x.date)) {
+            res.push(format!(
+                "info\t{}\t{}\t{}\t{}",
+                x.action,
+                field(&x.date),
+                field(&x.attribute),
+                x.value.split_whitespace().collect::<Vec<&str>>().join(" ")
+            ));
+        }
+        res.sort();
+        res.join("\n")
+    }
+
+    ///
+    /// The SHA-256 of canonical as `sha256:<hex>`, or with `key` its
+    /// HMAC-SHA256 as `hmac-sha256:<hex>`, a signature only the holders of
+    /// the key can make
+    ///
+    pub fn ledger_hash(
+        &self,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        key: Option<&[u8]>,
+    ) -> String {
+        let canonical = self.canonical(begin, end);
+        match key {
+            Some(k) => format!(
+                "{}:{}",
+                HMAC_PREFIX,
+                hex::encode(hmac_sha256(k, canonical.as_bytes()))
+            ),
+            None => format!(
+                "{}:{}",
+                HASH_PREFIX,
+                hex::encode(Sha256::digest(canonical.as_bytes()))
+            ),
+        }
+    }
+
+    ///
+    /// Whether the ledger still has the hash or signature `expected`, as
+    /// printed by ledger_hash for the same dates. A signature needs its key.
+    ///
+    pub fn verify_hash(
+        &self,
+        expected: &str,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        key: Option<&[u8]>,
+    ) -> Result<bool> {
+        let key = match expected.split_once(':') {
+            Some((HASH_PREFIX, _)) => None,
+            Some((HMAC_PREFIX, _)) => Some(key.ok_or(anyhow!("A signature needs its key"))?),
+            _ => return Err(anyhow!("Unknown hash: {}", expected)),
+        };
+        Ok(self.ledger_hash(begin, end, key) == expected.trim())
+    }
+}
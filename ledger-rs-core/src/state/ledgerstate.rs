@@ -1,9 +1,12 @@
-use std::{collections::HashMap, fmt, path::PathBuf, sync::atomic::AtomicU32};
+use std::{
+    cell::RefCell, collections::HashMap, fmt, path::PathBuf, rc::Rc, sync::atomic::AtomicU32,
+};
 
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
 
+use arrow::array::Array;
 use arrow::array::Date32Array;
 use arrow::array::Decimal128Array;
 use arrow::array::StringArray;
@@ -12,6 +15,7 @@ use arrow::datatypes::Date32Type;
 use arrow::datatypes::Decimal128Type;
 use arrow::datatypes::DecimalType;
 use datafusion::common::JoinType;
+use datafusion::execution::SendableRecordBatchStream;
 use datafusion::prelude::*;
 
 use futures::StreamExt;
@@ -21,41 +25,80 @@ use crate::core::ACCOUNT;
 use crate::core::ACTION_COL;
 use crate::core::CLOSE_ACTION;
 use crate::core::CLOSE_SYMBOL;
+use crate::core::COMMENT;
 use crate::core::COMMODITY;
+use crate::core::CURRENCY;
 use crate::core::DATE;
 use crate::core::ERROR_NO_POSTINGS_DF;
 use crate::core::FINAL_CP_COMMODITY;
 use crate::core::FINAL_CP_QUANTITY;
 use crate::core::FINAL_TC_COMMODITY;
 use crate::core::FINAL_TC_QUANTITY;
+use crate::core::FLAG;
 use crate::core::NARRATION;
 use crate::core::OPEN_ACTION;
 use crate::core::OPEN_SYMBOL;
+use crate::core::POSTING_FLAG;
 use crate::core::PRECISION;
+use crate::core::PRICE;
 use crate::core::QUANTITY;
 use crate::core::SCALE;
 use crate::core::STATEMENT_NO;
 use crate::core::STATEMENT_NO_RIGHT;
+use crate::core::SUBTREE_BALANCE_ACTION;
+use crate::core::SUBTREE_FLAG;
 use crate::core::TAGS;
+use crate::core::TOLERANCE;
+use crate::core::TOLERANCE_SYMBOL;
 use crate::core::TRANSACTION_NO;
 use crate::core::{
     BALANCE_ACTION, BALANCE_SYMBOL, COST_SEP, HeaderParams, IncludeParams, InfoParams,
-    PostingParams, TRANSACTION_FLAG, VerificationParams,
+    MetadataParams, PRICE_SCALE, PRICE_SEP, PRICE_SYMBOL, PostingParams, PriceParams, UNIT_PRICE,
+    VerificationParams,
 };
+use crate::core::{START, START_RIGHT};
+use crate::locale::Locale;
+use crate::options::LedgerOptions;
+use crate::parse::ParseError;
+use crate::roots::AccountRoots;
+use crate::state::columns::ParsedColumns;
+use crate::visit::StatementVisitor;
 
 pub struct LedgerState {
     pub input_files: HashMap<PathBuf, u32>,
     current_file_no: Vec<u32>,
     current_filepath: Vec<PathBuf>,
+    /// The offset in its file of the batch being parsed, per file being parsed
+    current_base: Vec<u32>,
     previous_position: HashMap<u32, u32>,
     statement_no: u32,
-    pub line_count: AtomicU32,
+    pub(crate) shallow: bool,
+    /// Whether included files are read in batches as by parse_streaming
+    pub(crate) streaming: bool,
+    /// Whether the parser keeps the text of each statement as its `raw`,
+    /// off by default as it doubles the memory used by a ledger
+    pub keep_raw: bool,
+    pub(crate) visitor: Option<Rc<RefCell<dyn StatementVisitor>>>,
+    /// Where the parse failed, recorded by the file that failed for the
+    /// files including it to report
+    pub(crate) parse_error: Option<ParseError>,
+    pub include_path: Vec<PathBuf>,
+    /// The glob includes read, as the including file and the path as
+    /// written, so the summary notices a file they newly match
+    pub(crate) include_globs: Vec<(PathBuf, String)>,
+    pub(crate) line_count: AtomicU32,
     pub transaction_no: u32,
     pub transactions: Vec<HeaderParams>,
     pub postings: Vec<PostingParams>,
     pub verifications: Vec<VerificationParams>,
     pub includes: Vec<IncludeParams>,
     pub informationals: Vec<InfoParams>,
+    pub metadata: Vec<MetadataParams>,
+    pub prices: Vec<PriceParams>,
+    /// The session the DataFrames below are registered in by verify, under
+    /// the names of the core *_TABLE constants, so reports and SQL queries
+    /// share one catalog
+    pub ctx: SessionContext,
     pub transactions_df: Option<DataFrame>,
     pub postings_df: Option<DataFrame>,
     pub errors_df: Option<DataFrame>,
@@ -63,6 +106,30 @@ pub struct LedgerState {
     pub tc_commodities_df: Option<DataFrame>,
     pub cp_commodities_df: Option<DataFrame>,
     pub verifications_df: Option<DataFrame>,
+    pub informationals_df: Option<DataFrame>,
+    pub metadata_df: Option<DataFrame>,
+    pub prices_df: Option<DataFrame>,
+    pub locale: Locale,
+    /// Only the transactions with this flag are part of report_journal_df
+    pub flag_filter: Option<String>,
+    pub roots: AccountRoots,
+    pub options: LedgerOptions,
+    /// The contents of the input files read by source_snippet, by file number
+    pub(crate) sources: RefCell<HashMap<u32, Rc<String>>>,
+    /// Whether the parser appends each statement to `columns` instead of
+    /// keeping it in the vectors above, so that verify wraps the finished
+    /// arrays without a second copy of the ledger. The statements are then
+    /// only in the DataFrames, as the vectors stay empty.
+    pub columnar: bool,
+    pub(crate) columns: ParsedColumns,
+}
+
+/// Price at PRICE_SCALE without the trailing zeros past SCALE
+pub(crate) fn format_price(p: i128) -> String {
+    let s = Decimal128Type::format_decimal(p, PRECISION as u8, PRICE_SCALE as i8);
+    let min_len = s.len() - (PRICE_SCALE - SCALE);
+    let trimmed = s.trim_end_matches('0');
+    s[..trimmed.len().max(min_len)].to_string()
 }
 
 impl fmt::Debug for LedgerState {
@@ -71,14 +138,28 @@ impl fmt::Debug for LedgerState {
     }
 }
 
+impl Default for LedgerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LedgerState {
     pub fn new() -> Self {
         Self {
             input_files: HashMap::new(),
             current_file_no: vec![],
             current_filepath: vec![],
+            current_base: vec![],
             previous_position: HashMap::new(),
             statement_no: 0,
+            shallow: false,
+            streaming: false,
+            keep_raw: false,
+            visitor: None,
+            parse_error: None,
+            include_path: vec![],
+            include_globs: vec![],
             line_count: AtomicU32::new(0),
             transaction_no: 0,
             transactions: vec![],
@@ -86,6 +167,9 @@ impl LedgerState {
             verifications: vec![],
             includes: vec![],
             informationals: vec![],
+            metadata: vec![],
+            prices: vec![],
+            ctx: SessionContext::new(),
             transactions_df: None,
             postings_df: None,
             errors_df: None,
@@ -93,6 +177,16 @@ impl LedgerState {
             tc_commodities_df: None,
             cp_commodities_df: None,
             verifications_df: None,
+            informationals_df: None,
+            metadata_df: None,
+            prices_df: None,
+            locale: Locale::default(),
+            flag_filter: None,
+            roots: AccountRoots::default(),
+            options: LedgerOptions::default(),
+            sources: RefCell::new(HashMap::new()),
+            columnar: false,
+            columns: ParsedColumns::default(),
         }
     }
 
@@ -102,6 +196,7 @@ impl LedgerState {
             self.input_files.insert(f.clone(), n as u32);
             self.current_file_no.push(n as u32);
             self.current_filepath.push(f);
+            self.current_base.push(0);
             self.previous_position.insert(n as u32, 0);
         }
     }
@@ -117,6 +212,18 @@ impl LedgerState {
         self.statement_no
     }
 
+    /// The offset `n` of the batch being parsed as an offset in its file
+    pub(crate) fn offset(&self, n: usize) -> u32 {
+        self.current_base.last().copied().unwrap_or(0) + n as u32
+    }
+
+    /// Starts a batch of the file being parsed at its offset `base`
+    pub(crate) fn set_base(&mut self, base: u32) {
+        if let Some(b) = self.current_base.last_mut() {
+            *b = base;
+        }
+    }
+
     pub fn get_current_filepath(&self) -> Option<PathBuf> {
         let current = self.current_file_no.len();
         if current == 0 {
@@ -142,8 +249,10 @@ impl LedgerState {
             .unwrap();
         self.statement_no = self.statement_no + n - *prev;
         self.previous_position
-            .insert(*&self.get_file_no().unwrap(), n);
+            .insert(self.get_file_no().unwrap(), n);
         self.current_file_no.pop();
+        self.current_filepath.pop();
+        self.current_base.pop();
     }
 
     pub async fn write_verifications(&self) -> Result<()> {
@@ -151,9 +260,15 @@ impl LedgerState {
             .verifications_df
             .clone()
             .context("No verifications df")?;
+        self.write_verifications_stream(df.execute_stream().await?)
+            .await
+    }
 
-        let mut stream = df.execute_stream().await?;
-
+    /// write_verifications of the rows of verifications_df as `stream` yields them
+    pub async fn write_verifications_stream(
+        &self,
+        mut stream: SendableRecordBatchStream,
+    ) -> Result<()> {
         while let Some(b) = stream.next().await.transpose()? {
             let action = b
                 .column_by_name(ACTION_COL)
@@ -185,12 +300,34 @@ impl LedgerState {
                 .as_any()
                 .downcast_ref::<Decimal128Array>()
                 .context("Unable to downcast decimal")?;
+            let tolerance = b
+                .column_by_name(TOLERANCE)
+                .context("Unable to find tolerance col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context("Unable to downcast decimal")?;
 
-            for rec in izip!(action, t_date, account, commodity, quantity) {
+            for (i, rec) in izip!(action, t_date, account, commodity, quantity).enumerate() {
+                // The tolerance goes after the amount, before the commodity
+                let c_tolerance = |c: &str| {
+                    if tolerance.is_null(i) {
+                        c.to_string()
+                    } else {
+                        format!(
+                            "{} {} {}",
+                            TOLERANCE_SYMBOL,
+                            format_price(tolerance.value(i)),
+                            c
+                        )
+                    }
+                };
                 match rec {
-                    (Some(OPEN_ACTION), Some(d), Some(a), None, None) => {
+                    (Some(OPEN_ACTION), Some(d), Some(a), c, None) => {
                         let actual_d = Date32Type::to_naive_date(d);
-                        println!("{} {} {}", actual_d, OPEN_SYMBOL, a);
+                        match c {
+                            Some(c) => println!("{} {} {} {}", actual_d, OPEN_SYMBOL, a, c),
+                            None => println!("{} {} {}", actual_d, OPEN_SYMBOL, a),
+                        }
                     }
                     (Some(CLOSE_ACTION), Some(d), Some(a), None, None) => {
                         let actual_d = Date32Type::to_naive_date(d);
@@ -200,7 +337,28 @@ impl LedgerState {
                         let actual_d = Date32Type::to_naive_date(d);
                         let actual_q =
                             Decimal128Type::format_decimal(q, PRECISION as u8, SCALE as i8);
-                        println!("{} {} {} {} {}", actual_d, BALANCE_SYMBOL, a, actual_q, c);
+                        println!(
+                            "{} {} {} {} {}",
+                            actual_d,
+                            BALANCE_SYMBOL,
+                            a,
+                            actual_q,
+                            c_tolerance(c)
+                        );
+                    }
+                    (Some(SUBTREE_BALANCE_ACTION), Some(d), Some(a), Some(c), Some(q)) => {
+                        let actual_d = Date32Type::to_naive_date(d);
+                        let actual_q =
+                            Decimal128Type::format_decimal(q, PRECISION as u8, SCALE as i8);
+                        println!(
+                            "{} {} {} {} {} {}",
+                            actual_d,
+                            BALANCE_SYMBOL,
+                            SUBTREE_FLAG,
+                            a,
+                            actual_q,
+                            c_tolerance(c)
+                        );
                     }
                     _ => return Err(anyhow!("Unknown action in write verfications")),
                 };
@@ -210,19 +368,77 @@ impl LedgerState {
         Ok(())
     }
 
+    pub async fn write_prices(&self) -> Result<()> {
+        let df = self.prices_df.clone().context("No prices df")?;
+
+        let mut stream = df.execute_stream().await?;
+
+        while let Some(b) = stream.next().await.transpose()? {
+            let t_date = b
+                .column_by_name(DATE)
+                .context("unable to find date column")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context("Unable to downcast date")?;
+            let commodity = b
+                .column_by_name(COMMODITY)
+                .context("unable to find commodity col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context("Unable to downcast commodity col")?;
+            let price = b
+                .column_by_name(PRICE)
+                .context("Unable to find price col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context("Unable to downcast decimal")?;
+            let currency = b
+                .column_by_name(CURRENCY)
+                .context("unable to find currency col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context("Unable to downcast currency col")?;
+
+            for (d, c, p, x) in izip!(t_date, commodity, price, currency) {
+                if let (Some(d), Some(c), Some(p), Some(x)) = (d, c, p, x) {
+                    let actual_d = Date32Type::to_naive_date(d);
+                    let actual_p = format_price(p);
+                    println!("{} {} {} {} {}", actual_d, PRICE_SYMBOL, c, actual_p, x);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn write_transactions(&self) -> Result<()> {
+        let df = self.transaction_lines_df()?;
+        self.write_transactions_stream(df.execute_stream().await?)
+            .await
+    }
+
+    ///
+    /// The postings joined to their transactions, in the order write_transactions
+    /// prints them: by date and transaction, then their postings as written,
+    /// those verify split in several commodities each in its place
+    ///
+    pub fn transaction_lines_df(&self) -> Result<DataFrame> {
         let transactions_df = self.transactions_df.clone().context("NO TRANSACTIONS DF")?;
         let postings_df = self.postings_df.clone().context(ERROR_NO_POSTINGS_DF)?;
         let df = transactions_df
             .join(
                 postings_df.select(vec![
                     col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                    col(START).alias(START_RIGHT),
                     col(TRANSACTION_NO),
                     col(ACCOUNT),
                     col(FINAL_CP_COMMODITY),
                     col(FINAL_CP_QUANTITY),
                     col(FINAL_TC_COMMODITY),
                     col(FINAL_TC_QUANTITY),
+                    col(UNIT_PRICE),
+                    col(POSTING_FLAG),
+                    col(COMMENT),
                 ])?,
                 JoinType::Left,
                 &[STATEMENT_NO],
@@ -231,12 +447,19 @@ impl LedgerState {
             )?
             .sort(vec![
                 col(DATE).sort(true, false),
+                col(STATEMENT_NO).sort(true, false),
+                col(START_RIGHT).sort(true, false),
                 col(STATEMENT_NO_RIGHT).sort(true, false),
             ])?;
+        Ok(df)
+    }
 
-        let mut stream = df.execute_stream().await?;
-
-        let mut current_transaction_no: u32 = 0;
+    /// write_transactions of the rows of transaction_lines_df as `stream` yields them
+    pub async fn write_transactions_stream(
+        &self,
+        mut stream: SendableRecordBatchStream,
+    ) -> Result<()> {
+        let mut current_transaction_no: Option<u32> = None;
 
         while let Some(b) = stream.next().await.transpose()? {
             let narration = b
@@ -257,6 +480,12 @@ impl LedgerState {
                 .as_any()
                 .downcast_ref::<Date32Array>()
                 .expect("Unable to downcast date");
+            let flag = b
+                .column_by_name(FLAG)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .expect("Unable to downcast flag");
             let account = b
                 .column_by_name(ACCOUNT)
                 .unwrap()
@@ -293,62 +522,104 @@ impl LedgerState {
                 .as_any()
                 .downcast_ref::<Decimal128Array>()
                 .expect("Unable to downcast decimal");
+            let unit_price = b
+                .column_by_name(UNIT_PRICE)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .expect("Unable to downcast decimal");
+            let posting_flag = b
+                .column_by_name(POSTING_FLAG)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .expect("Unable to downcast posting flag");
+            let comment = b
+                .column_by_name(COMMENT)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .expect("Unable to downcast comment");
 
             for rec in izip!(
                 transaction_no,
                 t_date,
+                flag,
                 narration,
                 tags,
                 account,
                 cp_commodity,
                 cp_quantity,
                 tc_commodity,
-                tc_quantity
+                tc_quantity,
+                unit_price,
+                posting_flag,
+                comment
             ) {
-                match rec {
-                    (
-                        Some(t_no),
-                        Some(d),
-                        Some(n),
-                        ts,
-                        Some(a),
-                        Some(cp_c),
-                        Some(cp_q),
-                        Some(tc_c),
-                        Some(tc_q),
-                    ) => {
-                        if current_transaction_no != t_no {
-                            println!();
-                            let actual_d = Date32Type::to_naive_date(d);
-                            match ts {
-                                Some(tag_string) => {
-                                    println!(
-                                        "{}: {} {} \"{}\" {}",
-                                        t_no, actual_d, TRANSACTION_FLAG, n, tag_string
-                                    )
-                                }
-                                None => println!(
-                                    "{}: {} {} \"{}\" ",
-                                    t_no, actual_d, TRANSACTION_FLAG, n
-                                ),
-                            }
-                            current_transaction_no = t_no;
-                        }
-                        let actual_cp_q =
-                            Decimal128Type::format_decimal(cp_q, PRECISION as u8, SCALE as i8);
-                        if cp_c == tc_c {
-                            println!("  {} {} {}", a, actual_cp_q, cp_c);
-                        } else {
-                            let actual_tc_q =
-                                Decimal128Type::format_decimal(tc_q, PRECISION as u8, SCALE as i8);
-                            println!(
-                                "  {} {} {} {} {} {}",
-                                a, actual_cp_q, cp_c, COST_SEP, actual_tc_q, tc_c
-                            );
+                let (
+                    Some(t_no),
+                    Some(d),
+                    Some(f),
+                    Some(n),
+                    ts,
+                    a,
+                    cp_c,
+                    cp_q,
+                    tc_c,
+                    tc_q,
+                    up,
+                    pf,
+                    pc,
+                ) = rec
+                else {
+                    continue;
+                };
+                if current_transaction_no != Some(t_no) {
+                    println!();
+                    let actual_d = Date32Type::to_naive_date(d);
+                    match ts {
+                        Some(tag_string) => {
+                            println!("{}: {} {} \"{}\" {}", t_no, actual_d, f, n, tag_string)
                         }
+                        None => println!("{}: {} {} \"{}\" ", t_no, actual_d, f, n),
                     }
-                    _ => println!("Nothing"),
+                    current_transaction_no = Some(t_no);
+                }
+                let Some(a) = a else {
+                    continue;
+                };
+                let pf = pf.map(|x| format!("{} ", x)).unwrap_or_default();
+                let pc = pc.map(|x| format!(" {}", x)).unwrap_or_default();
+                // A posting verify could infer no amount for is printed without one
+                let (Some(cp_c), Some(cp_q), Some(tc_c), Some(tc_q)) = (cp_c, cp_q, tc_c, tc_q)
+                else {
+                    println!("  {}{}{}", pf, a, pc);
+                    continue;
                 };
+                let actual_cp_q =
+                    Decimal128Type::format_decimal(cp_q, PRECISION as u8, SCALE as i8);
+                if let Some(up) = up {
+                    println!(
+                        "  {}{} {} {} {} {} {}{}",
+                        pf,
+                        a,
+                        actual_cp_q,
+                        cp_c,
+                        PRICE_SEP,
+                        format_price(up),
+                        tc_c,
+                        pc
+                    );
+                } else if cp_c == tc_c {
+                    println!("  {}{} {} {}{}", pf, a, actual_cp_q, cp_c, pc);
+                } else {
+                    let actual_tc_q =
+                        Decimal128Type::format_decimal(tc_q, PRECISION as u8, SCALE as i8);
+                    println!(
+                        "  {}{} {} {} {} {} {}{}",
+                        pf, a, actual_cp_q, cp_c, COST_SEP, actual_tc_q, tc_c, pc
+                    );
+                }
             }
         }
 
@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+
+use crate::core::{COMMODITY_LIST_SEP, TAG_SEP};
+use crate::state::ledgerstate::LedgerState;
+
+//
+// Sorted, distinct names read straight from the parsed statements, so they
+// are available without verify and cost no DataFrame query.
+//
+
+impl LedgerState {
+    /// Accounts opened, closed, asserted or posted to
+    pub fn account_names(&self) -> Vec<String> {
+        let mut res: BTreeSet<&str> = BTreeSet::new();
+        res.extend(self.verifications.iter().map(|v| v.account.as_str()));
+        res.extend(self.postings.iter().map(|p| p.account.as_str()));
+        res.into_iter().map(String::from).collect()
+    }
+
+    /// Commodities of postings, costs, balance assertions, opens and prices
+    pub fn commodities(&self) -> Vec<String> {
+        let mut res: BTreeSet<&str> = BTreeSet::new();
+        for p in self.postings.iter() {
+            res.extend(p.cp_commodity.as_deref());
+            res.extend(p.tc_commodity.as_deref());
+        }
+        // An open lists its commodities as `CAD,USD`
+        res.extend(
+            self.verifications
+                .iter()
+                .filter_map(|v| v.commodity.as_deref())
+                .flat_map(|c| c.split(COMMODITY_LIST_SEP)),
+        );
+        for p in self.prices.iter() {
+            res.insert(&p.commodity);
+            res.insert(&p.currency);
+        }
+        res.into_iter().map(String::from).collect()
+    }
+
+    /// Transaction narrations. A header has a single string, which serves as
+    /// the payee too.
+    pub fn payees(&self) -> Vec<String> {
+        let res: BTreeSet<&str> = self
+            .transactions
+            .iter()
+            .map(|t| t.narration.as_str())
+            .collect();
+        res.into_iter().map(String::from).collect()
+    }
+
+    /// Transaction tags, without their `#`
+    pub fn tags(&self) -> Vec<String> {
+        let res: BTreeSet<&str> = self
+            .transactions
+            .iter()
+            .filter_map(|t| t.tags.as_deref())
+            .flat_map(|t| t.split(TAG_SEP))
+            .map(|t| t.trim_start_matches('#'))
+            .filter(|t| !t.is_empty())
+            .collect();
+        res.into_iter().map(String::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::parse::parse_contents;
+
+    #[test]
+    fn commodities_of_an_open_are_listed_apart() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-01 open Assets:A CAD, USD\n\
+                        2024-01-02 balance Assets:A 0.00 EUR\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        assert_eq!(state.commodities(), ["CAD", "EUR", "USD"]);
+    }
+}
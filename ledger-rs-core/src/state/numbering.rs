@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use chrono::NaiveDate;
+
+use crate::state::ledgerstate::LedgerState;
+
+/// A statement renumber_statements orders, by its index in its rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Statement {
+    Transaction(usize),
+    Verification(usize),
+    Price(usize),
+}
+
+impl LedgerState {
+    ///
+    /// The number of the next statement or posting an importer adds, unique
+    /// within the state whichever importer or rule adds it. Numbers are
+    /// given in the order asked for, that of the rows read.
+    ///
+    pub fn next_statement_no(&self) -> u32 {
+        self.line_count.fetch_add(1, Ordering::SeqCst)
+    }
+
+    ///
+    /// Numbers the imported statements again from 0 by date, source and
+    /// row: the date of the statement, its file number, then the number
+    /// next_statement_no gave it, each transaction followed by its postings
+    /// in their order. What an import prints is then the same from run to
+    /// run and however its sources were mixed. Works on the parsed rows,
+    /// before verify.
+    ///
+    pub fn renumber_statements(&mut self) {
+        let mut order: Vec<(NaiveDate, u32, u32, Statement)> = vec![];
+        for (i, x) in self.transactions.iter().enumerate() {
+            order.push((x.date, x.file_no, x.statement_no, Statement::Transaction(i)));
+        }
+        for (i, x) in self.verifications.iter().enumerate() {
+            order.push((
+                x.date,
+                x.file_no,
+                x.statement_no,
+                Statement::Verification(i),
+            ));
+        }
+        for (i, x) in self.prices.iter().enumerate() {
+            order.push((x.date, x.file_no, x.statement_no, Statement::Price(i)));
+        }
+        order.sort();
+
+        let mut postings: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, p) in self.postings.iter().enumerate() {
+            postings.entry(p.transaction_no).or_default().push(i);
+        }
+        for v in postings.values_mut() {
+            v.sort_by_key(|i| self.postings[*i].statement_no);
+        }
+
+        let mut n = 0;
+        for (_, _, _, s) in order {
+            match s {
+                Statement::Transaction(i) => {
+                    let old = self.transactions[i].statement_no;
+                    self.transactions[i].statement_no = n;
+                    let transaction_no = n;
+                    n += 1;
+                    for j in postings.remove(&old).unwrap_or_default() {
+                        self.postings[j].statement_no = n;
+                        self.postings[j].transaction_no = transaction_no;
+                        n += 1;
+                    }
+                }
+                Statement::Verification(i) => {
+                    self.verifications[i].statement_no = n;
+                    n += 1;
+                }
+                Statement::Price(i) => {
+                    self.prices[i].statement_no = n;
+                    n += 1;
+                }
+            }
+        }
+        // Postings of no transaction keep their order, after the rest
+        let mut orphans: Vec<usize> = postings.into_values().flatten().collect();
+        orphans.sort_by_key(|i| self.postings[*i].statement_no);
+        for j in orphans {
+            self.postings[j].statement_no = n;
+            n += 1;
+        }
+        self.line_count.store(n, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        BALANCE_ACTION, HeaderParams, PostingParams, TRANSACTION_FLAG, VerificationParams,
+    };
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    /// Adds a transaction of `file_no` on `d` and its postings to `accounts`,
+    /// numbered as an importer numbers them
+    fn transaction(state: &mut LedgerState, file_no: u32, d: &str, accounts: &[&str]) {
+        let statement_no = state.next_statement_no();
+        state.transactions.push(HeaderParams {
+            statement_no,
+            file_no,
+            start: 0,
+            end: 0,
+            date: date(d),
+            flag: TRANSACTION_FLAG.to_string(),
+            narration: format!("{}:{}", file_no, d),
+            tags: None,
+            raw: None,
+        });
+        for a in accounts {
+            let posting_no = state.next_statement_no();
+            state.postings.push(PostingParams {
+                statement_no: posting_no,
+                transaction_no: statement_no,
+                file_no,
+                start: 0,
+                end: 0,
+                account: a.to_string(),
+                cp_quantity: None,
+                cp_commodity: None,
+                tc_quantity: None,
+                tc_commodity: None,
+                per_unit: false,
+                flag: None,
+                comment: None,
+            });
+        }
+    }
+
+    fn balance(state: &mut LedgerState, file_no: u32, d: &str, account: &str) {
+        let statement_no = state.next_statement_no();
+        state.verifications.push(VerificationParams {
+            statement_no,
+            file_no,
+            start: 0,
+            end: 0,
+            date: date(d),
+            action: BALANCE_ACTION,
+            account: account.to_string(),
+            quantity: None,
+            commodity: None,
+            tolerance: None,
+            raw: None,
+        });
+    }
+
+    /// The statements by number, as `number narration` or `number account`
+    fn numbered(state: &LedgerState) -> Vec<String> {
+        let mut res: Vec<(u32, String)> = state
+            .transactions
+            .iter()
+            .map(|x| (x.statement_no, x.narration.clone()))
+            .chain(state.postings.iter().map(|x| {
+                (
+                    x.statement_no,
+                    format!("{} of {}", x.account, x.transaction_no),
+                )
+            }))
+            .chain(
+                state
+                    .verifications
+                    .iter()
+                    .map(|x| (x.statement_no, format!("balance {}", x.account))),
+            )
+            .collect();
+        res.sort();
+        res.into_iter()
+            .map(|(n, x)| format!("{} {}", n, x))
+            .collect()
+    }
+
+    #[test]
+    fn next_statement_no_gives_each_number_once() {
+        let state = LedgerState::default();
+        let numbers: Vec<u32> = (0..4).map(|_| state.next_statement_no()).collect();
+        assert_eq!(numbers, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn statements_are_numbered_by_date_then_file() {
+        let mut state = LedgerState::default();
+        transaction(&mut state, 1, "2024-01-05", &["Assets:B", "Income:B"]);
+        balance(&mut state, 1, "2024-01-02", "Assets:B");
+        transaction(&mut state, 0, "2024-01-05", &["Assets:A", "Income:A"]);
+        transaction(&mut state, 0, "2024-01-01", &["Assets:A"]);
+        state.renumber_statements();
+        assert_eq!(
+            numbered(&state),
+            [
+                "0 0:2024-01-01",
+                "1 Assets:A of 0",
+                "2 balance Assets:B",
+                "3 0:2024-01-05",
+                "4 Assets:A of 3",
+                "5 Income:A of 3",
+                "6 1:2024-01-05",
+                "7 Assets:B of 6",
+                "8 Income:B of 6",
+            ]
+        );
+        // What is added after goes after
+        assert_eq!(state.next_statement_no(), 9);
+    }
+
+    #[test]
+    fn renumbering_is_the_same_whichever_order_the_rows_came_in() {
+        let mut a = LedgerState::default();
+        transaction(&mut a, 0, "2024-01-01", &["Assets:A", "Income:A"]);
+        transaction(&mut a, 1, "2024-01-01", &["Assets:B", "Income:B"]);
+        a.renumber_statements();
+
+        // Another importer numbered first, so the numbers of b start higher
+        let mut b = LedgerState::default();
+        b.line_count.store(100, Ordering::SeqCst);
+        transaction(&mut b, 1, "2024-01-01", &["Assets:B", "Income:B"]);
+        transaction(&mut b, 0, "2024-01-01", &["Assets:A", "Income:A"]);
+        b.renumber_statements();
+        assert_eq!(numbered(&a), numbered(&b));
+
+        // Renumbering again changes nothing
+        let before = numbered(&b);
+        b.renumber_statements();
+        assert_eq!(numbered(&b), before);
+    }
+
+    #[test]
+    fn postings_of_no_transaction_go_last_and_numbers_do_not_collide() {
+        let mut state = LedgerState::default();
+        transaction(&mut state, 0, "2024-01-02", &["Assets:A"]);
+        transaction(&mut state, 0, "2024-01-01", &["Assets:B"]);
+        // A posting whose transaction_no is that of no transaction
+        let mut orphan = state.postings[0].clone();
+        orphan.statement_no = state.next_statement_no();
+        orphan.transaction_no = 99;
+        orphan.account = "Assets:C".to_string();
+        state.postings.push(orphan);
+        state.renumber_statements();
+        let numbers = numbered(&state);
+        assert_eq!(numbers.last().unwrap(), "4 Assets:C of 99");
+        let mut n: Vec<&str> = numbers
+            .iter()
+            .map(|x| x.split(' ').next().unwrap())
+            .collect();
+        n.dedup();
+        assert_eq!(n, ["0", "1", "2", "3", "4"]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::NaiveDate;
+
+use crate::core::{OPEN_ACTION, VerificationParams};
+use crate::state::ledgerstate::LedgerState;
+
+impl LedgerState {
+    ///
+    /// Opens each account posted to or asserted that no open directive
+    /// opens, on the day it is first used, as the importers generate their
+    /// accounts without opening them. Works on the parsed rows, before
+    /// verify. Returns the number of accounts opened.
+    ///
+    pub fn open_used_accounts(&mut self) -> usize {
+        let opened: HashSet<&str> = self
+            .verifications
+            .iter()
+            .filter(|v| v.action == OPEN_ACTION)
+            .map(|v| v.account.as_str())
+            .collect();
+        let dates: HashMap<u32, NaiveDate> = self
+            .transactions
+            .iter()
+            .map(|x| (x.statement_no, x.date))
+            .collect();
+        let used = self
+            .postings
+            .iter()
+            .filter_map(|p| Some((p.account.as_str(), *dates.get(&p.transaction_no)?)))
+            .chain(
+                self.verifications
+                    .iter()
+                    .filter(|v| v.action != OPEN_ACTION)
+                    .map(|v| (v.account.as_str(), v.date)),
+            );
+        let mut first: BTreeMap<String, NaiveDate> = BTreeMap::new();
+        for (account, date) in used {
+            if opened.contains(account) {
+                continue;
+            }
+            let e = first.entry(account.to_string()).or_insert(date);
+            *e = (*e).min(date);
+        }
+
+        let n = first.len();
+        for (account, date) in first {
+            let statement_no = self.next_statement_no();
+            self.verifications.push(VerificationParams {
+                statement_no,
+                file_no: 0,
+                start: 0,
+                end: 0,
+                date,
+                action: OPEN_ACTION,
+                account,
+                quantity: None,
+                commodity: None,
+                tolerance: None,
+                raw: None,
+            });
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::parse::parse_contents;
+
+    #[test]
+    fn used_accounts_are_opened_on_their_first_day() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-01 open Assets:A\n\
+                        2024-01-09 * \"b\"\n  Assets:A -2.00 CAD\n  Expenses:B\n\
+                        2024-01-05 * \"a\"\n  Assets:A -1.00 CAD\n  Expenses:B\n\
+                        2024-01-03 balance Assets:C 0.00 CAD\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        assert_eq!(state.open_used_accounts(), 2);
+        let mut opens: Vec<String> = state
+            .verifications
+            .iter()
+            .filter(|v| v.action == OPEN_ACTION)
+            .map(|v| format!("{} {}", v.date, v.account))
+            .collect();
+        opens.sort();
+        assert_eq!(
+            opens,
+            [
+                "2024-01-01 Assets:A",
+                "2024-01-03 Assets:C",
+                "2024-01-05 Expenses:B"
+            ]
+        );
+        // All is open now
+        assert_eq!(state.open_used_accounts(), 0);
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use datafusion::prelude::*;
+
+use crate::core::{ACCOUNT, ACCOUNT_SEP};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::period_expr;
+
+impl LedgerState {
+    ///
+    /// Income and Expenses cp totals over [begin, end), grouped as described
+    /// in grouped_totals: by account, tag or metadata value.
+    ///
+    pub fn pnl_df(
+        &self,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        by: &str,
+    ) -> Result<DataFrame> {
+        let prefix = |base: &str| lit(format!("{}{}", base, ACCOUNT_SEP));
+        let df = self
+            .report_journal_df()?
+            .filter(
+                starts_with(col(ACCOUNT), prefix(&self.roots.income))
+                    .or(starts_with(col(ACCOUNT), prefix(&self.roots.expenses))),
+            )?
+            .filter(period_expr(begin, end))?;
+
+        self.grouped_totals(df, by)
+    }
+
+    ///
+    /// Assets, Liabilities and Equity cp totals of postings dated before `end`,
+    /// grouped as described in grouped_totals.
+    ///
+    pub fn balance_df(&self, end: Option<NaiveDate>, by: &str) -> Result<DataFrame> {
+        let prefix = |base: &str| lit(format!("{}{}", base, ACCOUNT_SEP));
+        let df = self
+            .report_journal_df()?
+            .filter(
+                starts_with(col(ACCOUNT), prefix(&self.roots.assets))
+                    .or(starts_with(col(ACCOUNT), prefix(&self.roots.liabilities)))
+                    .or(starts_with(col(ACCOUNT), prefix(&self.roots.equity))),
+            )?
+            .filter(period_expr(None, end))?;
+
+        self.grouped_totals(df, by)
+    }
+}
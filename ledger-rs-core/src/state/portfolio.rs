@@ -0,0 +1,146 @@
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use chrono::{Days, NaiveDate};
+use datafusion::prelude::*;
+
+use crate::core::{
+    ACCOUNT, AVERAGE_COST, BUY_COST, BUY_UNITS, COMMODITY, COST_BASIS, CURRENCY, MARKET_VALUE,
+    PRECISION, PRICE, PRICE_DATE, PRICE_SCALE, UNITS,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{account_matches, zero_lit};
+
+impl LedgerState {
+    ///
+    /// The portfolio at the close of `at`, by default the end of the ledger,
+    /// as in holdings_df: per account, commodity and cost currency, the
+    /// units held, the average cost of the units bought, their cost basis at
+    /// that average, the latest price on or before `at` and its date, and
+    /// their market value. With `accounts`, only the accounts
+    /// starting with one of them, e.g. the brokerage account of an old
+    /// statement to check it against.
+    ///
+    pub fn portfolio_df(&self, at: Option<NaiveDate>, accounts: &[String]) -> Result<DataFrame> {
+        let end = at.and_then(|d| d.checked_add_days(Days::new(1)));
+        let mut df = self.holdings_df(end)?;
+        if let Some(filter) = accounts
+            .iter()
+            .map(|a| account_matches(col(ACCOUNT), a))
+            .reduce(|a, b| a.or(b))
+        {
+            df = df.filter(filter)?;
+        }
+        let df = df
+            .select(vec![
+                col(ACCOUNT),
+                col(COMMODITY),
+                col(UNITS),
+                col(CURRENCY),
+                when(
+                    col(BUY_UNITS).not_eq(zero_lit()),
+                    cast(
+                        col(BUY_COST) / col(BUY_UNITS),
+                        DataType::Decimal128(PRECISION as u8, PRICE_SCALE as i8),
+                    ),
+                )
+                .end()?
+                .alias(AVERAGE_COST),
+                col(COST_BASIS),
+                col(PRICE),
+                col(PRICE_DATE),
+                col(MARKET_VALUE),
+            ])?
+            .sort(vec![
+                col(ACCOUNT).sort(true, false),
+                col(COMMODITY).sort(true, false),
+                col(CURRENCY).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use arrow::util::pretty::pretty_format_batches;
+
+    use super::*;
+    use crate::parse::parse_contents;
+
+    /// The portfolio of `contents` at `at`, as a table
+    async fn portfolio(contents: &str, at: Option<NaiveDate>) -> String {
+        portfolio_of(contents, at, &[]).await
+    }
+
+    /// The portfolio of `contents` at `at` for `accounts`, as a table
+    async fn portfolio_of(contents: &str, at: Option<NaiveDate>, accounts: &[String]) -> String {
+        let f = Path::new("portfolio.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        let batches = state
+            .portfolio_df(at, accounts)
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        pretty_format_batches(&batches).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn return_of_capital_lowers_the_average_cost() {
+        let table = portfolio(
+            "2024-01-01 open Assets:Broker\n2024-01-01 open Assets:Cash\n\
+             2024-01-02 * \"buy\"\n  Assets:Broker 10 VFV @@ 1000.00 CAD\n  Assets:Cash\n\
+             2024-03-01 * \"roc\"\n  Assets:Broker 0 VFV @@ -50.00 CAD\n  Assets:Cash 50.00 CAD\n",
+            None,
+        )
+        .await;
+        assert_eq!(
+            table,
+            "+---------------+-----------+-------+----------+--------------+------------+-------+------------+--------------+\n\
+             | account       | commodity | units | currency | average_cost | cost_basis | price | price_date | market_value |\n\
+             +---------------+-----------+-------+----------+--------------+------------+-------+------------+--------------+\n\
+             | Assets:Broker | VFV       | 10.00 | CAD      | 95.000000    | 950.00     |       |            |              |\n\
+             +---------------+-----------+-------+----------+--------------+------------+-------+------------+--------------+"
+        );
+    }
+
+    const LEDGER: &str = "2024-01-01 open Assets:Broker\n2024-01-01 open Assets:Other\n\
+                          2024-01-01 open Assets:Cash\n\
+                          2024-01-02 * \"buy\"\n  Assets:Broker 10 VFV @@ 1000.00 CAD\n  Assets:Cash\n\
+                          2024-01-03 * \"buy\"\n  Assets:Broker 10 VFV @@ 1200.00 CAD\n  Assets:Cash\n\
+                          2024-01-04 * \"buy\"\n  Assets:Other 2 XEQT @@ 60.00 CAD\n  Assets:Cash\n\
+                          2024-02-01 * \"sell\"\n  Assets:Broker -5 VFV @@ 600.00 CAD\n  Assets:Cash\n\
+                          2024-02-02 price VFV 120.00 CAD\n";
+
+    #[tokio::test]
+    async fn holdings_are_at_the_average_cost_of_the_units_bought() {
+        let table = portfolio(LEDGER, None).await;
+        assert_eq!(
+            table,
+            "+---------------+-----------+-------+----------+--------------+------------+------------+------------+--------------+\n\
+             | account       | commodity | units | currency | average_cost | cost_basis | price      | price_date | market_value |\n\
+             +---------------+-----------+-------+----------+--------------+------------+------------+------------+--------------+\n\
+             | Assets:Broker | VFV       | 15.00 | CAD      | 110.000000   | 1650.00    | 120.000000 | 2024-02-02 | 1800.00      |\n\
+             | Assets:Other  | XEQT      | 2.00  | CAD      | 30.000000    | 60.00      |            |            |              |\n\
+             +---------------+-----------+-------+----------+--------------+------------+------------+------------+--------------+"
+        );
+    }
+
+    #[tokio::test]
+    async fn holdings_are_those_at_the_close_of_the_day_of_the_accounts() {
+        let at = NaiveDate::from_ymd_opt(2024, 1, 3);
+        let table = portfolio_of(LEDGER, at, &["Assets:Broker".to_string()]).await;
+        assert_eq!(
+            table,
+            "+---------------+-----------+-------+----------+--------------+------------+-------+------------+--------------+\n\
+             | account       | commodity | units | currency | average_cost | cost_basis | price | price_date | market_value |\n\
+             +---------------+-----------+-------+----------+--------------+------------+-------+------------+--------------+\n\
+             | Assets:Broker | VFV       | 20.00 | CAD      | 110.000000   | 2200.00    |       |            |              |\n\
+             +---------------+-----------+-------+----------+--------------+------------+-------+------------+--------------+"
+        );
+    }
+}
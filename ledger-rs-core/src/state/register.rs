@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use arrow::array::Date32Array;
+use arrow::array::StringArray;
+use arrow::array::{Array, Decimal128Array};
+use arrow::datatypes::{DataType, Date32Type};
+use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
+use futures::StreamExt;
+use itertools::izip;
+
+use crate::core::{
+    ACCOUNT, ACTION_COL, ATTRIBUTE, COMMODITY, CONVERTED, CURRENCY, DATE, FINAL_CP_COMMODITY,
+    FINAL_CP_QUANTITY, NARRATION, NOTE_ACTION, NOTE_SYMBOL, PRECISION, QUANTITY, SCALE,
+    STATEMENT_NO, VALUE,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::account_matches;
+
+impl LedgerState {
+    pub fn register_df(&self, account: &str) -> Result<DataFrame> {
+        let informationals_df = self
+            .informationals_df
+            .clone()
+            .context("No informationals df")?;
+
+        let postings_df = self
+            .report_journal_df()?
+            .filter(account_matches(col(ACCOUNT), account))?
+            .select(vec![
+                col(DATE),
+                col(STATEMENT_NO),
+                col(NARRATION),
+                col(ACCOUNT),
+                col(FINAL_CP_COMMODITY).alias(COMMODITY),
+                col(FINAL_CP_QUANTITY).alias(QUANTITY),
+            ])?;
+
+        let notes_df = informationals_df
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(NOTE_ACTION))
+                    .and(account_matches(col(ATTRIBUTE), account)),
+            )?
+            .select(vec![
+                col(DATE),
+                col(STATEMENT_NO),
+                col(VALUE).alias(NARRATION),
+                col(ATTRIBUTE).alias(ACCOUNT),
+                lit(ScalarValue::Utf8(None)).alias(COMMODITY),
+                lit(ScalarValue::Decimal128(None, PRECISION as u8, SCALE as i8)).alias(QUANTITY),
+            ])?;
+
+        let df = postings_df.union(notes_df)?.sort(vec![
+            col(DATE).sort(true, false),
+            col(STATEMENT_NO).sort(true, false),
+        ])?;
+
+        Ok(df)
+    }
+
+    ///
+    /// register_df with, given a `currency`, each amount valued by value_df
+    /// and rounded to SCALE as CONVERTED in CURRENCY
+    ///
+    pub fn register_value_df(&self, account: &str, currency: Option<&str>) -> Result<DataFrame> {
+        let df = self.register_df(account)?;
+        let Some(c) = currency else {
+            return Ok(df);
+        };
+        let rounded = cast(
+            col(CONVERTED),
+            DataType::Decimal128(PRECISION as u8, SCALE as i8),
+        );
+        let df = self
+            .value_df(df, QUANTITY, None, c)?
+            .with_column(CURRENCY, lit(c))?
+            .with_column(CONVERTED, rounded)?
+            .sort(vec![
+                col(DATE).sort(true, false),
+                col(STATEMENT_NO).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+
+    ///
+    /// Prints register_value_df with a running total per commodity. With a
+    /// `currency`, each valued amount is followed by a running total of the
+    /// valued amounts; commodities without a price show `-` and are left
+    /// out of it.
+    ///
+    pub async fn write_register(&self, account: &str, currency: Option<&str>) -> Result<()> {
+        let mut stream = self
+            .register_value_df(account, currency)?
+            .execute_stream()
+            .await?;
+
+        let mut running: HashMap<String, i128> = HashMap::new();
+        let mut running_value: i128 = 0;
+
+        while let Some(b) = stream.next().await.transpose()? {
+            let t_date = b
+                .column_by_name(DATE)
+                .context("Unable to find date col")?
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .context("Unable to downcast date")?;
+            let narration = b
+                .column_by_name(NARRATION)
+                .context("Unable to find narration col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context("Unable to downcast narration")?;
+            let account = b
+                .column_by_name(ACCOUNT)
+                .context("Unable to find account col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context("Unable to downcast account")?;
+            let commodity = b
+                .column_by_name(COMMODITY)
+                .context("Unable to find commodity col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context("Unable to downcast commodity")?;
+            let quantity = b
+                .column_by_name(QUANTITY)
+                .context("Unable to find quantity col")?
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .context("Unable to downcast quantity")?;
+            let converted = match b.column_by_name(CONVERTED) {
+                Some(x) => Some(
+                    x.as_any()
+                        .downcast_ref::<Decimal128Array>()
+                        .context("Unable to downcast converted")?,
+                ),
+                None => None,
+            };
+
+            for (row, rec) in izip!(t_date, narration, account, commodity, quantity).enumerate() {
+                match rec {
+                    (Some(d), Some(n), Some(a), Some(c), Some(q)) => {
+                        let actual_d = self.locale.format_date(Date32Type::to_naive_date(d));
+                        let total = running.entry(c.to_string()).or_insert(0);
+                        *total += q;
+                        let actual_q = self.locale.format_amount(q, c);
+                        let actual_total = self.locale.format_amount(*total, c);
+                        let mut line =
+                            format!("{} \"{}\" {} {} {}", actual_d, n, a, actual_q, actual_total);
+                        if let (Some(v), Some(currency)) = (converted, currency) {
+                            if v.is_null(row) {
+                                line.push_str(" - -");
+                            } else {
+                                let r = v.value(row);
+                                running_value += r;
+                                line.push_str(&format!(
+                                    " {} {}",
+                                    self.locale.format_amount(r, currency),
+                                    self.locale.format_amount(running_value, currency)
+                                ));
+                            }
+                        }
+                        println!("{}", line);
+                    }
+                    (Some(d), Some(n), Some(a), None, None) => {
+                        let actual_d = self.locale.format_date(Date32Type::to_naive_date(d));
+                        println!("{} {} {} \"{}\"", actual_d, NOTE_SYMBOL, a, n);
+                    }
+                    _ => println!("Nothing"),
+                };
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,18 +1,159 @@
 use anyhow::Context;
 use anyhow::Result;
+use arrow::array::StringArray;
+use arrow::datatypes::Date32Type;
+use chrono::NaiveDate;
 use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::functions_aggregate::sum::sum_udaf;
+use datafusion::logical_expr::expr::WindowFunction;
+use datafusion::logical_expr::{SortExpr, WindowFunctionDefinition};
 use datafusion::prelude::*;
+use datafusion::scalar::ScalarValue;
 
 use crate::{
     core::{
-        ACCOUNT, ACCOUNT_RIGHT, ERROR_NO_ACCOUNT_DF, ERROR_NO_POSTINGS_DF, FINAL_CP_COMMODITY,
-        FINAL_CP_QUANTITY, FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, MATCH, RIGHT_QUALIFIER, TOTAL,
-        TOTALS_ACCOUNT,
+        ACCOUNT, ACCOUNT_RIGHT, ACCOUNT_SEP, ACCOUNT_WILDCARD, DATE, ERROR_DOWNCAST,
+        ERROR_NO_ACCOUNT_DF, ERROR_NO_POSTINGS_DF, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY,
+        FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, FLAG, MATCH, NARRATION, PRECISION, RIGHT_QUALIFIER,
+        SCALE, STATEMENT_NO, STATEMENT_NO_RIGHT, TAGS, TOTAL, TOTALS_ACCOUNT, TRANSACTION_NO,
     },
     state::ledgerstate::LedgerState,
 };
 
+/// Date literal comparable with the date columns
+pub(crate) fn date_lit(d: NaiveDate) -> Expr {
+    lit(ScalarValue::Date32(Some(Date32Type::from_naive_date(d))))
+}
+
+/// Zero at the ledger's PRECISION and SCALE
+pub(crate) fn zero_lit() -> Expr {
+    lit(ScalarValue::Decimal128(
+        Some(0),
+        PRECISION as u8,
+        SCALE as i8,
+    ))
+}
+
+/// The running sum of `e` over `partition_by`, in `order_by` order
+pub(crate) fn running_sum(
+    e: Expr,
+    partition_by: Vec<Expr>,
+    order_by: Vec<SortExpr>,
+) -> Result<Expr> {
+    let sum = WindowFunction::new(WindowFunctionDefinition::AggregateUDF(sum_udaf()), vec![e]);
+    Ok(Expr::WindowFunction(sum)
+        .partition_by(partition_by)
+        .order_by(order_by)
+        .build()?)
+}
+
+///
+/// Whether the account `e` is `pattern` or one of its sub-accounts, `*`
+/// standing for any text, so `Expenses:*:Fees` matches Expenses:Bank:Fees
+/// and its sub-accounts as `Assets:Bank` matches Assets:Bank:Savings but
+/// not Assets:Bank2
+///
+pub(crate) fn account_matches(e: Expr, pattern: &str) -> Expr {
+    let pattern = pattern.trim_end_matches(ACCOUNT_SEP);
+    if !pattern.contains(ACCOUNT_WILDCARD) {
+        let sub_account = format!("{}{}", pattern, ACCOUNT_SEP);
+        return e
+            .clone()
+            .eq(lit(pattern))
+            .or(starts_with(e, lit(sub_account)));
+    }
+    let mut like = String::with_capacity(pattern.len() + 2);
+    for c in pattern.chars() {
+        match c {
+            ACCOUNT_WILDCARD => like.push('%'),
+            '%' | '_' | '\\' => {
+                like.push('\\');
+                like.push(c);
+            }
+            c => like.push(c),
+        }
+    }
+    let sub_accounts = format!("{}{}%", like, ACCOUNT_SEP);
+    e.clone().like(lit(like)).or(e.like(lit(sub_accounts)))
+}
+
+/// Dates in [begin, end), either bound being optional
+pub(crate) fn period_expr(begin: Option<NaiveDate>, end: Option<NaiveDate>) -> Expr {
+    let mut e = lit(true);
+    if let Some(b) = begin {
+        e = e.and(col(DATE).gt_eq(date_lit(b)));
+    }
+    if let Some(x) = end {
+        e = e.and(col(DATE).lt(date_lit(x)));
+    }
+    e
+}
+
 impl LedgerState {
+    ///
+    /// Postings joined with the date, flag, narration and tags of their
+    /// transaction header, all of them whatever the flag_filter, as the
+    /// checks, close-books and convert need the whole ledger
+    ///
+    pub fn journal_df(&self) -> Result<DataFrame> {
+        self.flagged_journal_df(None)
+    }
+
+    ///
+    /// The journal_df of the reports: with a flag_filter, only the postings
+    /// of the transactions with that flag
+    ///
+    pub fn report_journal_df(&self) -> Result<DataFrame> {
+        self.flagged_journal_df(self.flag_filter.as_deref())
+    }
+
+    fn flagged_journal_df(&self, flag: Option<&str>) -> Result<DataFrame> {
+        let mut transactions_df = self.transactions_df.clone().context("No transactions df")?;
+        let postings_df = self.postings_df.clone().context(ERROR_NO_POSTINGS_DF)?;
+        let mut join_type = JoinType::Left;
+        if let Some(f) = flag {
+            transactions_df = transactions_df.filter(col(FLAG).eq(lit(f)))?;
+            join_type = JoinType::Inner;
+        }
+
+        let df = postings_df.join(
+            transactions_df.select(vec![
+                col(DATE),
+                col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                col(FLAG),
+                col(NARRATION),
+                col(TAGS),
+            ])?,
+            join_type,
+            &[TRANSACTION_NO],
+            &[STATEMENT_NO_RIGHT],
+            None,
+        )?;
+
+        Ok(df)
+    }
+
+    /// All account names, including parents, from accounts_df
+    pub async fn accounts(&self) -> Result<Vec<String>> {
+        let batches = self
+            .accounts_df
+            .clone()
+            .context(ERROR_NO_ACCOUNT_DF)?
+            .collect()
+            .await?;
+        let mut res = vec![];
+        for b in batches {
+            let account = b
+                .column_by_name(ACCOUNT)
+                .context("Unable to find account col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            res.extend(account.iter().flatten().map(|a| a.to_string()));
+        }
+        Ok(res)
+    }
+
     pub async fn tc_balances(&mut self) -> Result<DataFrame> {
         self.get_balances_df(FINAL_TC_COMMODITY, FINAL_TC_QUANTITY)
             .await
@@ -87,3 +228,55 @@ impl LedgerState {
         Ok(map_totals_df)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::parse::parse_contents;
+
+    #[tokio::test]
+    async fn accounts_match_themselves_and_their_sub_accounts() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-02 * \"x\"\n  Assets:Bank 1.00 CAD\n  Assets:Bank:Savings 2.00 CAD\n\
+                        \x20 Assets:Bank2 4.00 CAD\n  Expenses:Bank:Fees 1.00 CAD\n\
+                        \x20 Expenses:Bank:Fees2 1.00 CAD\n  Assets:Cash\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        let matching = |pattern: &str| {
+            let df = state
+                .journal_df()
+                .unwrap()
+                .filter(account_matches(col(ACCOUNT), pattern))
+                .unwrap()
+                .select(vec![col(ACCOUNT)])
+                .unwrap()
+                .sort(vec![col(ACCOUNT).sort(true, false)])
+                .unwrap();
+            async move {
+                let mut res = vec![];
+                for b in df.collect().await.unwrap() {
+                    let a = b.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+                    res.extend(a.iter().flatten().map(String::from));
+                }
+                res
+            }
+        };
+        assert_eq!(
+            matching("Assets:Bank").await,
+            ["Assets:Bank", "Assets:Bank:Savings"]
+        );
+        assert_eq!(
+            matching("Assets:Bank:").await,
+            matching("Assets:Bank").await
+        );
+        assert_eq!(matching("*:Fees").await, ["Expenses:Bank:Fees"]);
+        assert_eq!(
+            matching("Expenses:*").await,
+            ["Expenses:Bank:Fees", "Expenses:Bank:Fees2"]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use arrow::datatypes::DataType;
+use chrono::{Days, Months, NaiveDate};
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+
+use crate::core::{ACCOUNT, CHANGE, COMMODITY, DATE, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, TOTAL};
+use crate::roots::AccountRoots;
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::account_matches;
+use crate::state::report::{date_lit, zero_lit};
+
+const MONTHLY_OUTFLOW: &str = "monthly_outflow";
+const RUNWAY: &str = "runway_months";
+
+impl LedgerState {
+    ///
+    /// Per cp commodity, the balance of the liquid accounts, those starting
+    /// with `liquid` or else all of Assets, on the day before `end`, the
+    /// average monthly net outflow from them over the `months` before `end`,
+    /// and how many months the balance lasts at that rate, left empty when
+    /// the balance is not shrinking. `end` defaults to the day after the last
+    /// transaction, so a forecast added to the ledger is part of the rate
+    /// once `end` is after it.
+    ///
+    pub fn runway_df(
+        &self,
+        end: Option<NaiveDate>,
+        months: u32,
+        liquid: &[String],
+    ) -> Result<DataFrame> {
+        let end = match end {
+            Some(e) => e,
+            None => {
+                self.transactions
+                    .iter()
+                    .map(|t| t.date)
+                    .max()
+                    .unwrap_or_default()
+                    + Days::new(1)
+            }
+        };
+        let months = months.max(1);
+        let begin = end
+            .checked_sub_months(Months::new(months))
+            .context("Invalid runway period")?;
+
+        let liquid_filter = liquid
+            .iter()
+            .map(|a| account_matches(col(ACCOUNT), a))
+            .reduce(|a, b| a.or(b))
+            .unwrap_or(starts_with(
+                col(ACCOUNT),
+                lit(AccountRoots::prefix(&self.roots.assets)),
+            ));
+
+        let in_period = col(DATE).gt_eq(date_lit(begin));
+        let df = self
+            .report_journal_df()?
+            .filter(liquid_filter.and(col(DATE).lt(date_lit(end))))?
+            .aggregate(
+                vec![col(FINAL_CP_COMMODITY).alias(COMMODITY)],
+                vec![
+                    sum(col(FINAL_CP_QUANTITY)).alias(TOTAL),
+                    sum(when(in_period, col(FINAL_CP_QUANTITY)).otherwise(zero_lit())?)
+                        .alias(CHANGE),
+                ],
+            )?;
+
+        let outflow = -cast(col(CHANGE), DataType::Float64) / lit(months as f64);
+        let df = df
+            .select(vec![
+                col(COMMODITY),
+                col(TOTAL),
+                round(vec![outflow.clone(), lit(2)]).alias(MONTHLY_OUTFLOW),
+                when(
+                    outflow.clone().gt(lit(0.0)).and(col(TOTAL).gt(zero_lit())),
+                    round(vec![cast(col(TOTAL), DataType::Float64) / outflow, lit(1)]),
+                )
+                .end()?
+                .alias(RUNWAY),
+            ])?
+            .sort(vec![col(COMMODITY).sort(true, false)])?;
+        Ok(df)
+    }
+}
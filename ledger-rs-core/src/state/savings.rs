@@ -0,0 +1,121 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::functions::datetime::expr_fn::date_trunc;
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+
+use crate::core::{
+    ACCOUNT, COMMODITY, DATE, FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, INVESTMENTS_NAME, PERIOD,
+    TRANSACTION_NO, TRANSACTION_NO_RIGHT,
+};
+use crate::roots::AccountRoots;
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{period_expr, zero_lit};
+
+const INCOME: &str = "income";
+const EXPENSES: &str = "expenses";
+const INVESTED: &str = "invested";
+const SAVED: &str = "saved";
+const SAVINGS_RATE: &str = "savings_rate";
+const EXPENSES_RATE: &str = "expenses_rate";
+const INVESTED_RATE: &str = "invested_rate";
+
+/// `amount` as a percentage of the income, empty without income
+fn rate(amount: &str) -> Result<Expr> {
+    let income = cast(col(INCOME), DataType::Float64);
+    Ok(when(
+        income.clone().gt(lit(0.0)),
+        round(vec![
+            cast(col(amount), DataType::Float64) / income * lit(100.0),
+            lit(1),
+        ]),
+    )
+    .end()?)
+}
+
+impl LedgerState {
+    ///
+    /// Per calendar month over [begin, end) and tc commodity: the income, the
+    /// expenses, the contributions to the accounts starting with `investments`
+    /// (Assets:Investments by default) and what was saved, with the savings,
+    /// expenses and contributions as percentages of the income. Contributions
+    /// are the money moved in from the other Assets and Liabilities accounts,
+    /// so trades, dividends and fees within the investment accounts are not.
+    ///
+    pub fn savings_rate_df(
+        &self,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        investments: Option<&str>,
+    ) -> Result<DataFrame> {
+        let investments = match investments {
+            Some(x) => x.to_string(),
+            None => format!(
+                "{}{}",
+                AccountRoots::prefix(&self.roots.assets),
+                INVESTMENTS_NAME
+            ),
+        };
+        let under = |root: &str| starts_with(col(ACCOUNT), lit(AccountRoots::prefix(root)));
+        let is_investment = starts_with(col(ACCOUNT), lit(investments.as_str()));
+
+        let df = self.report_journal_df()?.filter(period_expr(begin, end))?;
+        let investment_transactions = df
+            .clone()
+            .filter(is_investment.clone())?
+            .select(vec![col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT)])?
+            .distinct()?;
+        let df = df.join(
+            investment_transactions,
+            JoinType::Left,
+            &[TRANSACTION_NO],
+            &[TRANSACTION_NO_RIGHT],
+            None,
+        )?;
+
+        let is_contribution = col(TRANSACTION_NO_RIGHT)
+            .is_not_null()
+            .and(is_investment.not())
+            .and(under(&self.roots.assets).or(under(&self.roots.liabilities)));
+        let amount = |e: Expr| -> Result<Expr> {
+            Ok(when(e, col(FINAL_TC_QUANTITY)).otherwise(zero_lit())?)
+        };
+
+        let df = df
+            .aggregate(
+                vec![
+                    cast(date_trunc(lit("month"), col(DATE)), DataType::Date32).alias(PERIOD),
+                    col(FINAL_TC_COMMODITY).alias(COMMODITY),
+                ],
+                vec![
+                    sum(-amount(under(&self.roots.income))?).alias(INCOME),
+                    sum(amount(under(&self.roots.expenses))?).alias(EXPENSES),
+                    sum(-amount(is_contribution)?).alias(INVESTED),
+                ],
+            )?
+            .filter(
+                col(INCOME)
+                    .not_eq(zero_lit())
+                    .or(col(EXPENSES).not_eq(zero_lit()))
+                    .or(col(INVESTED).not_eq(zero_lit())),
+            )?
+            .with_column(SAVED, col(INCOME) - col(EXPENSES))?
+            .select(vec![
+                col(PERIOD),
+                col(COMMODITY),
+                col(INCOME),
+                col(EXPENSES),
+                col(INVESTED),
+                col(SAVED),
+                rate(SAVED)?.alias(SAVINGS_RATE),
+                rate(EXPENSES)?.alias(EXPENSES_RATE),
+                rate(INVESTED)?.alias(INVESTED_RATE),
+            ])?
+            .sort(vec![
+                col(PERIOD).sort(true, false),
+                col(COMMODITY).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+}
@@ -0,0 +1,194 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+
+use anyhow::Result;
+use arrow::util::display::array_value_to_string;
+use datafusion::prelude::*;
+
+use crate::cache::{Cursor, ParseCache};
+use crate::core::{
+    IncludeParams, STATEMENT_NO, STATEMENT_NO_RIGHT, TRANSACTION_NO, TRANSACTION_NO_RIGHT,
+};
+use crate::state::ledgerstate::LedgerState;
+
+const NUMBERING: [&str; 4] = [
+    STATEMENT_NO,
+    STATEMENT_NO_RIGHT,
+    TRANSACTION_NO,
+    TRANSACTION_NO_RIGHT,
+];
+
+/// The rows of `df` as text, statement numbers left out, in sorted order
+async fn rows(name: &str, df: DataFrame) -> Result<Vec<String>> {
+    let mut res = vec![];
+    for b in df.collect().await? {
+        let columns: Vec<usize> = b
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| !NUMBERING.contains(&f.name().as_str()))
+            .map(|(n, _)| n)
+            .collect();
+        for r in 0..b.num_rows() {
+            let values = columns
+                .iter()
+                .map(|c| array_value_to_string(b.column(*c), r))
+                .collect::<Result<Vec<_>, _>>()?;
+            res.push(format!("{} {}", name, values.join(" ")));
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+impl LedgerState {
+    ///
+    /// Parses `f`, already inserted into the state, as if its files were read
+    /// in an order picked by `seed` rather than as they are included: each
+    /// file is parsed on its own as by ParseCache, then its statements are
+    /// numbered after those of the files before it in that order, by their
+    /// offset in it. The files keep the numbers they have in include order,
+    /// so findings compare by file and offset. Must be called before verify.
+    ///
+    pub fn parse_shuffled(&mut self, f: &Path, seed: u64) -> Result<()> {
+        let rank = |file_no: u32| {
+            let mut h = DefaultHasher::new();
+            (seed, file_no).hash(&mut h);
+            h.finish()
+        };
+        let file_no = self.input_files[f];
+        let mut files = vec![];
+        ParseCache::new(None).collect_files(f, file_no, self, &mut files)?;
+        files.sort_by_key(|x| (rank(x.file_no), x.file_no));
+
+        let mut base = 0;
+        for x in files {
+            x.rows
+                .copy_into(&mut Cursor::default(), u32::MAX, base, x.file_no, self);
+            for inc in x.includes {
+                self.includes.push(IncludeParams {
+                    statement_no: base + inc.statement_no,
+                    file_no: x.file_no,
+                    ..inc
+                });
+            }
+            self.append_columns()?;
+            base += x.length;
+        }
+        Ok(())
+    }
+
+    ///
+    /// The balances, unbalanced transactions and failed balance assertions of
+    /// a verified ledger as sorted text rows without statement numbers, which
+    /// must not change whatever order the files were parsed in.
+    ///
+    pub async fn order_independent_rows(&mut self) -> Result<Vec<String>> {
+        let mut res = vec![];
+        res.extend(rows("tc_balances", self.tc_balances().await?).await?);
+        res.extend(rows("cp_balances", self.cp_balances().await?).await?);
+        if let Some(df) = self.errors_df.clone() {
+            res.extend(rows("errors", df).await?);
+        }
+        res.extend(rows("balance_errors", self.balance_errors_df()?).await?);
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parse::parse_filename;
+
+    /// A ledger of a main file including two others, the first at its byte 0
+    fn write_ledger(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ledger-rs-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("main.bean"),
+            "include \"opens.bean\"\n\
+             2024-01-02 * \"Pay\"\n  Assets:Bank 100.00 CAD\n  Income:Salary\n\
+             include \"spend.bean\"\n\
+             2024-02-01 balance Assets:Bank 50.00 CAD\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("opens.bean"),
+            "2024-01-01 open Assets:Bank\n2024-01-01 open Income:Salary\n\
+             2024-01-01 open Expenses:Food\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("spend.bean"),
+            "2024-01-05 * \"Food\"\n  Expenses:Food 40.00 CAD\n  Assets:Bank\n\
+             2024-01-06 * \"Unbalanced\"\n  Expenses:Food 5.00 CAD\n  Assets:Bank -4.00 CAD\n",
+        )
+        .unwrap();
+        dir.join("main.bean")
+    }
+
+    async fn rows_of(state: &mut LedgerState) -> Vec<String> {
+        state.verify().await.unwrap();
+        state.order_independent_rows().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn shuffled_files_verify_the_same() {
+        let f = write_ledger("shuffle");
+        let mut state = LedgerState::new();
+        state.insert(f.clone());
+        parse_filename(f.clone(), &mut state).unwrap();
+        let expected = rows_of(&mut state).await;
+        assert!(expected.iter().any(|x| x.starts_with("errors")));
+        assert!(expected.iter().any(|x| x.starts_with("balance_errors")));
+
+        // The rows only go to the columns in columnar mode
+        for (seed, columnar) in [(1, false), (2, false), (3, false), (1, true)] {
+            let mut state = LedgerState::new();
+            state.columnar = columnar;
+            state.insert(f.clone());
+            state.parse_shuffled(&f, seed).unwrap();
+            assert_eq!(rows_of(&mut state).await, expected, "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn shuffled_statements_are_numbered_apart_across_files() {
+        let f = write_ledger("numbers");
+        let mut first_files = vec![];
+        for seed in 0..8 {
+            let mut state = LedgerState::new();
+            state.insert(f.clone());
+            state.parse_shuffled(&f, seed).unwrap();
+            let mut numbers: Vec<u32> = state
+                .transactions
+                .iter()
+                .map(|x| x.statement_no)
+                .chain(state.postings.iter().map(|x| x.statement_no))
+                .chain(state.verifications.iter().map(|x| x.statement_no))
+                .chain(state.includes.iter().map(|x| x.statement_no))
+                .collect();
+            let first = state.verifications.iter().min_by_key(|x| x.statement_no);
+            first_files.push(first.unwrap().file_no);
+            let n = numbers.len();
+            numbers.sort();
+            numbers.dedup();
+            assert_eq!(numbers.len(), n, "seed {}", seed);
+            // Each posting is of a transaction of its own file
+            for p in state.postings.iter() {
+                assert!(
+                    state
+                        .transactions
+                        .iter()
+                        .any(|x| x.statement_no == p.transaction_no && x.file_no == p.file_no)
+                );
+            }
+        }
+        first_files.dedup();
+        assert!(first_files.len() > 1, "the seeds give a single order");
+    }
+}
@@ -0,0 +1,43 @@
+use std::fs;
+use std::rc::Rc;
+
+use anyhow::Result;
+use anyhow::anyhow;
+
+use crate::state::ledgerstate::LedgerState;
+
+impl LedgerState {
+    /// The contents of input file `file_no`, read from disk the first time only
+    pub(crate) fn source(&self, file_no: u32) -> Result<Rc<String>> {
+        if let Some(s) = self.sources.borrow().get(&file_no) {
+            return Ok(s.clone());
+        }
+        let f = self
+            .input_files
+            .iter()
+            .find(|(_, n)| **n == file_no)
+            .map(|(f, _)| f)
+            .ok_or(anyhow!("Unknown file number {}", file_no))?;
+        let s = Rc::new(fs::read_to_string(f)?);
+        self.sources.borrow_mut().insert(file_no, s.clone());
+        Ok(s)
+    }
+
+    ///
+    /// The text from byte `start` to `end` of input file `file_no`, the span
+    /// of a parsed row, to show the lines a statement came from. The file is
+    /// read from disk once, unless parsed from a buffer with parse_contents.
+    /// Fails for an unknown file or a span outside it.
+    ///
+    pub fn source_snippet(&self, file_no: u32, start: u32, end: u32) -> Result<String> {
+        let s = self.source(file_no)?;
+        s.get(start as usize..end as usize)
+            .map(String::from)
+            .ok_or(anyhow!(
+                "No text at {}..{} of file number {}",
+                start,
+                end,
+                file_no
+            ))
+    }
+}
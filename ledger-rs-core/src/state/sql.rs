@@ -0,0 +1,183 @@
+use std::io::Write;
+
+use anyhow::Context;
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use arrow::util::display::array_value_to_string;
+use datafusion::functions_aggregate::expr_fn::{max, min};
+use datafusion::prelude::*;
+use futures::StreamExt;
+
+use crate::core::{
+    ACCOUNT, ACTION_COL, BALANCE_ACTION, CLOSE_ACTION, CLOSE_DATE, COMMODITY, CURRENCY, DATE,
+    ERROR_NO_POSTINGS_DF, FILE_NO, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY,
+    FINAL_TC_QUANTITY, NARRATION, OPEN_ACTION, OPEN_DATE, PRICE, QUANTITY, RAW, STATEMENT_NO,
+    SUBTREE_BALANCE_ACTION, TAGS, TRANSACTION_NO,
+};
+use crate::state::ledgerstate::LedgerState;
+
+const SQL_ID: &str = "id";
+const SQL_SUBTREE: &str = "subtree";
+
+fn sql_type(t: &DataType) -> &'static str {
+    match t {
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => "INTEGER",
+        DataType::Decimal128(_, _) | DataType::Float32 | DataType::Float64 => "NUMERIC",
+        _ => "TEXT",
+    }
+}
+
+/// `s` as an SQL string literal
+fn quoted(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Writes `df` as a CREATE TABLE and one INSERT per row
+async fn write_table(w: &mut impl Write, table: &str, df: DataFrame) -> Result<()> {
+    let schema = df.schema().as_arrow().clone();
+    let columns: Vec<String> = schema
+        .fields()
+        .iter()
+        .map(|f| format!("{} {}", f.name(), sql_type(f.data_type())))
+        .collect();
+    writeln!(w, "DROP TABLE IF EXISTS {};", table)?;
+    writeln!(w, "CREATE TABLE {} ({});", table, columns.join(", "))?;
+
+    let mut stream = df.execute_stream().await?;
+    while let Some(b) = stream.next().await.transpose()? {
+        for row in 0..b.num_rows() {
+            let mut values = vec![];
+            for (column, f) in b.columns().iter().zip(schema.fields().iter()) {
+                let v = if column.is_null(row) {
+                    "NULL".to_string()
+                } else {
+                    let s = array_value_to_string(column, row)?;
+                    match sql_type(f.data_type()) {
+                        "TEXT" => quoted(&s),
+                        _ => s,
+                    }
+                };
+                values.push(v);
+            }
+            writeln!(w, "INSERT INTO {} VALUES ({});", table, values.join(", "))?;
+        }
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
+impl LedgerState {
+    ///
+    /// Writes the verified ledger as an SQL script that creates and fills the
+    /// tables accounts, transactions, postings, balances (the balance
+    /// assertions) and prices, for `sqlite3 ledger.db < ledger.sql`. Postings
+    /// carry their final cp and tc amounts, with elided amounts filled in,
+    /// and reference their transaction by id. Transactions, balances and
+    /// prices have their text as written in raw when the parser kept it.
+    ///
+    pub async fn write_sql(&self, w: &mut impl Write) -> Result<()> {
+        let verifications_df = self
+            .verifications_df
+            .clone()
+            .context("No verifications df")?;
+
+        writeln!(w, "BEGIN TRANSACTION;")?;
+
+        let accounts_df = verifications_df
+            .clone()
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(OPEN_ACTION))
+                    .or(col(ACTION_COL).eq(lit(CLOSE_ACTION))),
+            )?
+            .aggregate(
+                vec![col(ACCOUNT)],
+                vec![
+                    min(when(col(ACTION_COL).eq(lit(OPEN_ACTION)), col(DATE)).end()?)
+                        .alias(OPEN_DATE),
+                    max(when(col(ACTION_COL).eq(lit(CLOSE_ACTION)), col(DATE)).end()?)
+                        .alias(CLOSE_DATE),
+                ],
+            )?
+            .sort(vec![col(ACCOUNT).sort(true, false)])?;
+        write_table(w, "accounts", accounts_df).await?;
+
+        let transactions_df = self
+            .transactions_df
+            .clone()
+            .context("No transactions df")?
+            .select(vec![
+                col(STATEMENT_NO).alias(SQL_ID),
+                col(FILE_NO),
+                col(DATE),
+                col(NARRATION),
+                col(TAGS),
+                col(RAW),
+            ])?
+            .sort(vec![col(SQL_ID).sort(true, false)])?;
+        write_table(w, "transactions", transactions_df).await?;
+
+        let postings_df = self
+            .postings_df
+            .clone()
+            .context(ERROR_NO_POSTINGS_DF)?
+            .select(vec![
+                col(STATEMENT_NO).alias(SQL_ID),
+                col(TRANSACTION_NO),
+                col(ACCOUNT),
+                col(FINAL_CP_QUANTITY),
+                col(FINAL_CP_COMMODITY),
+                col(FINAL_TC_QUANTITY),
+                col(FINAL_TC_COMMODITY),
+            ])?
+            .sort(vec![col(SQL_ID).sort(true, false)])?;
+        write_table(w, "postings", postings_df).await?;
+
+        let balances_df = verifications_df
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(BALANCE_ACTION))
+                    .or(col(ACTION_COL).eq(lit(SUBTREE_BALANCE_ACTION))),
+            )?
+            .select(vec![
+                col(STATEMENT_NO).alias(SQL_ID),
+                col(DATE),
+                col(ACCOUNT),
+                col(QUANTITY),
+                col(COMMODITY),
+                cast(
+                    col(ACTION_COL).eq(lit(SUBTREE_BALANCE_ACTION)),
+                    DataType::UInt8,
+                )
+                .alias(SQL_SUBTREE),
+                col(RAW),
+            ])?
+            .sort(vec![col(SQL_ID).sort(true, false)])?;
+        write_table(w, "balances", balances_df).await?;
+
+        let prices_df = self
+            .prices_df
+            .clone()
+            .context("No prices df")?
+            .select(vec![
+                col(STATEMENT_NO).alias(SQL_ID),
+                col(DATE),
+                col(COMMODITY),
+                col(PRICE),
+                col(CURRENCY),
+                col(RAW),
+            ])?
+            .sort(vec![col(SQL_ID).sort(true, false)])?;
+        write_table(w, "prices", prices_df).await?;
+
+        writeln!(w, "COMMIT;")?;
+        Ok(())
+    }
+}
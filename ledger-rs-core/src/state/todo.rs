@@ -0,0 +1,46 @@
+use anyhow::Result;
+use datafusion::prelude::*;
+
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, DATE, FINAL_CP_COMMODITY, FINAL_CP_QUANTITY, FINAL_TC_COMMODITY,
+    FINAL_TC_QUANTITY, NARRATION, TODO_ACCOUNT, TRANSACTION_NO,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::account_matches;
+
+impl LedgerState {
+    /// Postings booked to "inbox" accounts that still need reclassifying.
+    ///
+    /// `inboxes` are account prefixes; when empty, any account whose last
+    /// component is `TODO` (as generated by the importers) is an inbox.
+    pub fn todo_df(&self, inboxes: &[String]) -> Result<DataFrame> {
+        let mut todo_suffix = ACCOUNT_SEP.to_string();
+        todo_suffix.push_str(TODO_ACCOUNT);
+
+        let inbox_filter = inboxes
+            .iter()
+            .map(|a| account_matches(col(ACCOUNT), a))
+            .reduce(|a, b| a.or(b))
+            .unwrap_or(ends_with(col(ACCOUNT), lit(todo_suffix)));
+
+        let df = self
+            .report_journal_df()?
+            .filter(inbox_filter)?
+            .select(vec![
+                col(DATE),
+                col(TRANSACTION_NO),
+                col(NARRATION),
+                col(ACCOUNT),
+                col(FINAL_CP_QUANTITY),
+                col(FINAL_CP_COMMODITY),
+                col(FINAL_TC_QUANTITY),
+                col(FINAL_TC_COMMODITY),
+            ])?
+            .sort(vec![
+                col(DATE).sort(true, false),
+                col(TRANSACTION_NO).sort(true, false),
+            ])?;
+
+        Ok(df)
+    }
+}
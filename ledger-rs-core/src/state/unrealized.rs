@@ -0,0 +1,90 @@
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use chrono::NaiveDate;
+use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::prelude::*;
+
+use crate::core::{
+    ACCOUNT, BUY_COST, BUY_UNITS, COMMODITY, COST_BASIS, CURRENCY, FINAL_CP_COMMODITY,
+    FINAL_CP_QUANTITY, FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, GAIN, MARKET_VALUE, PRECISION, PRICE,
+    PRICE_COMMODITY, PRICE_CURRENCY, SCALE, UNITS,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::{period_expr, zero_lit};
+
+impl LedgerState {
+    ///
+    /// Holdings bought at a cost (`@@`) before `end`, per account, commodity
+    /// and cost currency: the units held, their cost basis at the average
+    /// cost of the units bought, lowered by the cost of postings of no units
+    /// such as a return of capital, the latest price in the cost currency with
+    /// its date, and their market value at that price, null for commodities
+    /// without a price. Rows come back unordered.
+    ///
+    pub(crate) fn holdings_df(&self, end: Option<NaiveDate>) -> Result<DataFrame> {
+        let amount_type = DataType::Decimal128(PRECISION as u8, SCALE as i8);
+        // No units at a cost adjust the cost, as a return of capital does
+        let bought = col(FINAL_CP_QUANTITY).gt_eq(zero_lit());
+
+        let df = self
+            .report_journal_df()?
+            .filter(period_expr(None, end))?
+            .filter(col(FINAL_CP_COMMODITY).not_eq(col(FINAL_TC_COMMODITY)))?
+            .aggregate(
+                vec![
+                    col(ACCOUNT),
+                    col(FINAL_CP_COMMODITY).alias(COMMODITY),
+                    col(FINAL_TC_COMMODITY).alias(CURRENCY),
+                ],
+                vec![
+                    sum(col(FINAL_CP_QUANTITY)).alias(UNITS),
+                    sum(when(bought.clone(), col(FINAL_CP_QUANTITY)).otherwise(zero_lit())?)
+                        .alias(BUY_UNITS),
+                    sum(when(bought, col(FINAL_TC_QUANTITY)).otherwise(zero_lit())?)
+                        .alias(BUY_COST),
+                ],
+            )?
+            .filter(col(UNITS).not_eq(zero_lit()))?
+            .join(
+                self.all_latest_prices_df(end)?,
+                JoinType::Left,
+                &[COMMODITY, CURRENCY],
+                &[PRICE_COMMODITY, PRICE_CURRENCY],
+                None,
+            )?
+            .with_column(
+                COST_BASIS,
+                when(col(BUY_UNITS).eq(zero_lit()), zero_lit()).otherwise(cast(
+                    col(UNITS) * col(BUY_COST) / col(BUY_UNITS),
+                    amount_type.clone(),
+                ))?,
+            )?
+            .with_column(MARKET_VALUE, cast(col(UNITS) * col(PRICE), amount_type))?;
+        Ok(df)
+    }
+
+    ///
+    /// The holdings of holdings_df with their unrealized gain, the market
+    /// value less the cost basis. Value and gain are null for commodities
+    /// without a price.
+    ///
+    pub fn unrealized_df(&self, end: Option<NaiveDate>) -> Result<DataFrame> {
+        let df = self
+            .holdings_df(end)?
+            .select(vec![
+                col(ACCOUNT),
+                col(COMMODITY),
+                col(UNITS),
+                col(CURRENCY),
+                col(COST_BASIS),
+                col(MARKET_VALUE),
+                (col(MARKET_VALUE) - col(COST_BASIS)).alias(GAIN),
+            ])?
+            .sort(vec![
+                col(ACCOUNT).sort(true, false),
+                col(COMMODITY).sort(true, false),
+                col(CURRENCY).sort(true, false),
+            ])?;
+        Ok(df)
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayBuilder, ArrayRef, Decimal128Array, Decimal128Builder, RecordBatch, StringArray,
+    StringBuilder,
+};
+use arrow::datatypes::{DataType, Field, Schema};
+use chrono::NaiveDate;
+use datafusion::functions_window::expr_fn::row_number;
+use datafusion::logical_expr::SortExpr;
+use datafusion::prelude::*;
+
+use crate::core::{
+    COMMODITY, CONVERTED, CONVERTED_SCALE, CURRENCY, DATE, ERROR_DOWNCAST, PRECISION, PRICE,
+    PRICE_COMMODITY, PRICE_CURRENCY, PRICE_DATE, ROUNDING, SCALE, STATEMENT_NO, TOTAL,
+};
+use crate::state::ledgerstate::LedgerState;
+use crate::state::report::period_expr;
+
+const ROW_NO: &str = "row_no";
+
+/// Rounds a CONVERTED_SCALE value to SCALE, half away from zero
+pub(crate) fn round_converted(v: i128) -> i128 {
+    let d = 10i128.pow((CONVERTED_SCALE - SCALE) as u32);
+    let half = if v < 0 { -d / 2 } else { d / 2 };
+    (v + half) / d
+}
+
+/// Sorts by every column but TOTAL, the labels of a grouped report
+fn label_sort(df: &DataFrame) -> Vec<SortExpr> {
+    df.schema()
+        .fields()
+        .iter()
+        .filter(|f| f.name() != TOTAL)
+        .map(|f| ident(f.name()).sort(true, false))
+        .collect()
+}
+
+impl LedgerState {
+    /// The latest price of each commodity in each currency dated before `end`, with its date
+    pub(crate) fn all_latest_prices_df(&self, end: Option<NaiveDate>) -> Result<DataFrame> {
+        let latest = row_number()
+            .partition_by(vec![col(COMMODITY), col(CURRENCY)])
+            .order_by(vec![
+                col(DATE).sort(false, false),
+                col(STATEMENT_NO).sort(false, false),
+            ])
+            .build()?;
+        let df = self
+            .prices_df
+            .clone()
+            .context("No prices df")?
+            .filter(period_expr(None, end))?
+            .with_column(ROW_NO, latest)?
+            .filter(col(ROW_NO).eq(lit(1u64)))?
+            .select(vec![
+                col(COMMODITY).alias(PRICE_COMMODITY),
+                col(CURRENCY).alias(PRICE_CURRENCY),
+                col(PRICE),
+                col(DATE).alias(PRICE_DATE),
+            ])?;
+        Ok(df)
+    }
+
+    /// The latest price in `currency` of each commodity dated before `end`
+    pub(crate) fn latest_prices_df(
+        &self,
+        end: Option<NaiveDate>,
+        currency: &str,
+    ) -> Result<DataFrame> {
+        let df = self
+            .all_latest_prices_df(end)?
+            .filter(col(PRICE_CURRENCY).eq(lit(currency)))?
+            .select(vec![col(PRICE_COMMODITY), col(PRICE)])?;
+        Ok(df)
+    }
+
+    ///
+    /// `df` with its `amount` column, in COMMODITY, also valued in `currency`
+    /// at the latest price before `end`. CONVERTED keeps the full
+    /// CONVERTED_SCALE precision of amount * price; it is null for
+    /// commodities without a price. Rows come back unordered.
+    ///
+    pub(crate) fn value_df(
+        &self,
+        df: DataFrame,
+        amount: &str,
+        end: Option<NaiveDate>,
+        currency: &str,
+    ) -> Result<DataFrame> {
+        let converted_type = DataType::Decimal128(PRECISION as u8, CONVERTED_SCALE as i8);
+        let columns: Vec<Expr> = df
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| ident(f.name()))
+            .collect();
+
+        let converted = when(
+            col(COMMODITY).eq(lit(currency)),
+            cast(ident(amount), converted_type.clone()),
+        )
+        .otherwise(cast(ident(amount) * col(PRICE), converted_type))?;
+
+        let df = df
+            .join(
+                self.latest_prices_df(end, currency)?,
+                JoinType::Left,
+                &[COMMODITY],
+                &[PRICE_COMMODITY],
+                None,
+            )?
+            .with_column(CONVERTED, converted)?
+            .select(
+                columns
+                    .into_iter()
+                    .chain([col(CONVERTED)])
+                    .collect::<Vec<_>>(),
+            )?;
+        Ok(df)
+    }
+
+    /// balance_df with each total valued in `currency` by value_df
+    pub fn balance_value_df(
+        &self,
+        end: Option<NaiveDate>,
+        by: &str,
+        currency: &str,
+    ) -> Result<DataFrame> {
+        let df = self.balance_df(end, by)?;
+        let sort = label_sort(&df);
+        Ok(self.value_df(df, TOTAL, end, currency)?.sort(sort)?)
+    }
+
+    /// pnl_df with each total valued in `currency` by value_df, as of `end`
+    pub fn pnl_value_df(
+        &self,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        by: &str,
+        currency: &str,
+    ) -> Result<DataFrame> {
+        let df = self.pnl_df(begin, end, by)?;
+        let sort = label_sort(&df);
+        Ok(self.value_df(df, TOTAL, end, currency)?.sort(sort)?)
+    }
+
+    /// balance_value_df rounded by rounded_value_df
+    pub async fn rounded_balance_value_df(
+        &self,
+        end: Option<NaiveDate>,
+        by: &str,
+        currency: &str,
+    ) -> Result<DataFrame> {
+        self.rounded_value_df(self.balance_value_df(end, by, currency)?, currency)
+            .await
+    }
+
+    /// pnl_value_df rounded by rounded_value_df
+    pub async fn rounded_pnl_value_df(
+        &self,
+        begin: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        by: &str,
+        currency: &str,
+    ) -> Result<DataFrame> {
+        self.rounded_value_df(self.pnl_value_df(begin, end, by, currency)?, currency)
+            .await
+    }
+
+    ///
+    /// The totals valued by value_df with each converted amount rounded to
+    /// SCALE only here, in CURRENCY. A ROUNDING row, there only when it is
+    /// not zero, makes up the difference to the rounded full precision
+    /// total, so the rows always sum to the TOTAL row that ends the report.
+    /// Commodities without a price have a null CONVERTED and are left out
+    /// of the total.
+    ///
+    async fn rounded_value_df(&self, df: DataFrame, currency: &str) -> Result<DataFrame> {
+        let labels: Vec<String> = df
+            .schema()
+            .fields()
+            .iter()
+            .filter(|f| f.name() != COMMODITY && f.data_type() == &DataType::Utf8)
+            .map(|f| f.name().clone())
+            .collect();
+        let mut label_builders: Vec<StringBuilder> =
+            labels.iter().map(|_| StringBuilder::new()).collect();
+        let decimal_builder =
+            || Decimal128Builder::new().with_precision_and_scale(PRECISION as u8, SCALE as i8);
+        let mut commodity_builder = StringBuilder::new();
+        let mut total_builder = decimal_builder()?;
+        let mut converted_builder = decimal_builder()?;
+
+        let mut full_total: i128 = 0;
+        let mut rounded_total: i128 = 0;
+        for b in df.collect().await? {
+            let label_columns = labels
+                .iter()
+                .map(|l| {
+                    b.column_by_name(l)
+                        .context(format!("Unable to find {} col", l))?
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .context(ERROR_DOWNCAST)
+                })
+                .collect::<Result<Vec<&StringArray>>>()?;
+            let get_dec = |name: &str| -> Result<&Decimal128Array> {
+                b.column_by_name(name)
+                    .context(format!("Unable to find {} col", name))?
+                    .as_any()
+                    .downcast_ref::<Decimal128Array>()
+                    .context(ERROR_DOWNCAST)
+            };
+            let commodity = b
+                .column_by_name(COMMODITY)
+                .context("Unable to find commodity col")?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context(ERROR_DOWNCAST)?;
+            let total = get_dec(TOTAL)?;
+            let converted = get_dec(CONVERTED)?;
+
+            for n in 0..b.num_rows() {
+                for (builder, column) in label_builders.iter_mut().zip(&label_columns) {
+                    builder.append_option(column.is_valid(n).then(|| column.value(n)));
+                }
+                commodity_builder.append_value(commodity.value(n));
+                total_builder.append_value(total.value(n));
+                if converted.is_null(n) {
+                    converted_builder.append_null();
+                } else {
+                    let v = converted.value(n);
+                    let r = round_converted(v);
+                    full_total += v;
+                    rounded_total += r;
+                    converted_builder.append_value(r);
+                }
+            }
+        }
+
+        let total = round_converted(full_total);
+        let rounding = total - rounded_total;
+        let mut footer = vec![];
+        if rounding != 0 {
+            footer.push((ROUNDING, rounding));
+        }
+        footer.push((TOTAL, total));
+        for (name, value) in footer {
+            for (n, builder) in label_builders.iter_mut().enumerate() {
+                builder.append_option((n == 0).then_some(name));
+            }
+            commodity_builder.append_null();
+            total_builder.append_null();
+            converted_builder.append_value(value);
+        }
+        let rows = converted_builder.len();
+
+        let mut fields: Vec<Field> = labels
+            .iter()
+            .map(|l| Field::new(l, DataType::Utf8, true))
+            .collect();
+        let decimal_type = DataType::Decimal128(PRECISION as u8, SCALE as i8);
+        fields.extend([
+            Field::new(COMMODITY, DataType::Utf8, true),
+            Field::new(TOTAL, decimal_type.clone(), true),
+            Field::new(CURRENCY, DataType::Utf8, false),
+            Field::new(CONVERTED, decimal_type, true),
+        ]);
+        let mut columns: Vec<ArrayRef> = label_builders
+            .iter_mut()
+            .map(|b| Arc::new(b.finish()) as ArrayRef)
+            .collect();
+        columns.extend([
+            Arc::new(commodity_builder.finish()) as ArrayRef,
+            Arc::new(total_builder.finish()),
+            Arc::new(StringArray::from(vec![currency; rows])),
+            Arc::new(converted_builder.finish()),
+        ]);
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+        Ok(self.ctx.read_batch(batch)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use arrow::util::pretty::pretty_format_batches;
+
+    use super::*;
+    use crate::core::PNL_BY_ACCOUNT;
+    use crate::parse::parse_contents;
+
+    async fn balance_value(contents: &str) -> String {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        let df = state
+            .rounded_balance_value_df(None, PNL_BY_ACCOUNT, "CAD")
+            .await
+            .unwrap();
+        pretty_format_batches(&df.collect().await.unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn rounding_row_is_there_only_when_not_zero() {
+        let opening = "2024-01-01 * \"Buy\"\n  Assets:A 1.00 XYZ\n  Assets:B 1.00 XYZ\n  \
+                       Equity:Opening-Balances\n";
+        let rounded = balance_value(&format!("{}2024-01-02 price XYZ 0.335 CAD\n", opening)).await;
+        let expected = "\
++-------------------------+-----------+-------+----------+-----------+
+| account                 | commodity | total | currency | converted |
++-------------------------+-----------+-------+----------+-----------+
+| Assets:A                | XYZ       | 1.00  | CAD      | 0.34      |
+| Assets:B                | XYZ       | 1.00  | CAD      | 0.34      |
+| Equity:Opening-Balances | XYZ       | -2.00 | CAD      | -0.67     |
+| rounding                |           |       | CAD      | -0.01     |
+| total                   |           |       | CAD      | 0.00      |
++-------------------------+-----------+-------+----------+-----------+";
+        assert_eq!(rounded, expected);
+
+        let exact = balance_value(&format!("{}2024-01-02 price XYZ 0.50 CAD\n", opening)).await;
+        let expected = "\
++-------------------------+-----------+-------+----------+-----------+
+| account                 | commodity | total | currency | converted |
++-------------------------+-----------+-------+----------+-----------+
+| Assets:A                | XYZ       | 1.00  | CAD      | 0.50      |
+| Assets:B                | XYZ       | 1.00  | CAD      | 0.50      |
+| Equity:Opening-Balances | XYZ       | -2.00 | CAD      | -1.00     |
+| total                   |           |       | CAD      | 0.00      |
++-------------------------+-----------+-------+----------+-----------+";
+        assert_eq!(exact, expected);
+    }
+}
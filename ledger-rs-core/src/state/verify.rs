@@ -1,11 +1,10 @@
-use std::sync::Arc;
-
+use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
-use arrow::array::{Array, RecordBatch, UInt64Array};
+use arrow::array::{Array, RecordBatch, UInt32Array, UInt64Array};
+use arrow::compute::max as max_array;
 use arrow::datatypes::DataType;
-use arrow_convert::serialize::TryIntoArrow;
-use datafusion::functions_aggregate::expr_fn::sum;
+use datafusion::functions_aggregate::expr_fn::{count, sum};
 use datafusion::functions_aggregate::min_max::max;
 use datafusion::functions_array::extract::array_slice;
 use datafusion::functions_window::expr_fn::row_number;
@@ -14,31 +13,43 @@ use datafusion::scalar::ScalarValue;
 
 use crate::core::ACTION_COL;
 use crate::core::COMMODITY;
+use crate::core::CURRENCY;
 use crate::core::DATE;
 use crate::core::ERROR_DOWNCAST;
 use crate::core::ERROR_NO_ACCOUNTS_FOUND;
 use crate::core::ERROR_NO_POSTINGS_DF;
+use crate::core::PRICE;
+use crate::core::PRICE_SCALE;
 use crate::core::QUANTITY;
+use crate::core::RAW;
+use crate::core::{
+    ACCOUNT, ACCOUNT_SEP, COMMENT, CP_COMMODITY, CP_QUANTITY, FILE_NO, FINAL_CP_COMMODITY,
+    FINAL_CP_QUANTITY, FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, FLAG, LENGTH, NUM, PER_UNIT,
+    POSTING_FLAG, PRECISION, SCALE, SPLIT_NO, START, STATEMENT_NO, STATEMENT_NO_RIGHT,
+    TC_COMMODITY, TC_COMMODITY_RIGHT, TC_QUANTITY, TOLERANCE, TOTALS, TRANSACTION_NO,
+    TRANSACTION_NO_RIGHT, UNIT_PRICE,
+};
+use crate::core::{ACCOUNT_RIGHT, COMMODITY_LIST_SEP, DEFAULT_COMMODITY, OPEN_ACTION};
 use crate::core::{
-    ACCOUNT, ACCOUNT_SEP, CP_COMMODITY, CP_QUANTITY, FILE_NO, FINAL_CP_COMMODITY,
-    FINAL_CP_QUANTITY, FINAL_TC_COMMODITY, FINAL_TC_QUANTITY, LENGTH, NUM, PRECISION, SCALE, START,
-    STATEMENT_NO, TC_COMMODITY, TC_COMMODITY_RIGHT, TC_QUANTITY, TOTALS, TRANSACTION_NO,
-    TRANSACTION_NO_RIGHT,
+    ACCOUNTS_TABLE, CP_COMMODITIES_TABLE, ERRORS_TABLE, INFORMATIONALS_TABLE, METADATA_TABLE,
+    POSTINGS_TABLE, PRICES_TABLE, TC_COMMODITIES_TABLE, TRANSACTIONS_TABLE, VERIFICATIONS_TABLE,
 };
 use crate::state::ledgerstate::LedgerState;
 
 impl LedgerState {
     pub async fn verify(&mut self) -> Result<()> {
-        let ctx = SessionContext::new();
-
-        let array: Arc<dyn Array> = self.verifications.try_into_arrow()?;
-        let struct_array = array
-            .as_any()
-            .downcast_ref::<arrow::array::StructArray>()
-            .unwrap();
-        let batch: RecordBatch = struct_array.try_into()?;
-        let df_verifications = ctx.read_batch(batch)?;
+        let columnar = self.columnar;
+        let batch = self
+            .columns
+            .verifications
+            .batch(&mut self.verifications, columnar)?;
+        // One past the number of every statement, split postings being numbered from it
+        let mut next_no = statement_no_after(&batch)?;
+        let df_verifications = self.ctx.read_batch(batch)?;
         let df_verifications = df_verifications.select(vec![
+            col(STATEMENT_NO),
+            col(FILE_NO),
+            col(START),
             col(DATE),
             col(ACTION_COL),
             col(ACCOUNT),
@@ -48,33 +59,124 @@ impl LedgerState {
             )
             .alias(QUANTITY),
             col(COMMODITY),
+            cast(
+                col(TOLERANCE),
+                DataType::Decimal128(PRECISION as u8, PRICE_SCALE as i8),
+            )
+            .alias(TOLERANCE),
+            col(RAW),
         ])?;
-        self.verifications_df = Some(df_verifications);
 
-        let array: Arc<dyn Array> = self.transactions.try_into_arrow()?;
-        let struct_array = array
-            .as_any()
-            .downcast_ref::<arrow::array::StructArray>()
-            .unwrap();
-        let batch: RecordBatch = struct_array.try_into()?;
-        let df_transactions = ctx.read_batch(batch)?;
-        self.transactions_df = Some(df_transactions);
-
-        let array: Arc<dyn Array> = self.postings.try_into_arrow()?;
-        let struct_array = array
-            .as_any()
-            .downcast_ref::<arrow::array::StructArray>()
-            .unwrap();
-        let batch: RecordBatch = struct_array.try_into()?;
+        // The default commodity of an account is the first of its open
+        // directive, the one an elided posting to it takes its residual in
+        let defaults_df = df_verifications
+            .clone()
+            .filter(
+                col(ACTION_COL)
+                    .eq(lit(OPEN_ACTION))
+                    .and(col(COMMODITY).is_not_null()),
+            )?
+            .aggregate(
+                vec![col(ACCOUNT)],
+                vec![
+                    max(split_part(col(COMMODITY), lit(COMMODITY_LIST_SEP), lit(1)))
+                        .alias(DEFAULT_COMMODITY),
+                ],
+            )?
+            .select(vec![
+                col(ACCOUNT).alias(ACCOUNT_RIGHT),
+                col(DEFAULT_COMMODITY),
+            ])?;
+        self.verifications_df = Some(self.register(VERIFICATIONS_TABLE, df_verifications).await?);
 
-        let df_postings = ctx.read_batch(batch)?;
+        let batch = self
+            .columns
+            .transactions
+            .batch(&mut self.transactions, columnar)?;
+        next_no = next_no.max(statement_no_after(&batch)?);
+        let df_transactions = self.ctx.read_batch(batch)?;
+        self.transactions_df = Some(self.register(TRANSACTIONS_TABLE, df_transactions).await?);
 
-        let row_number_window = row_number()
-            .partition_by(vec![col(TRANSACTION_NO)])
-            .order_by(vec![col(TC_COMMODITY).sort(true, false)])
-            .build()?;
+        let batch = self
+            .columns
+            .informationals
+            .batch(&mut self.informationals, columnar)?;
+        next_no = next_no.max(statement_no_after(&batch)?);
+        let df_informationals = self.ctx.read_batch(batch)?;
+        self.informationals_df = Some(
+            self.register(INFORMATIONALS_TABLE, df_informationals)
+                .await?,
+        );
+
+        let batch = self.columns.metadata.batch(&mut self.metadata, columnar)?;
+        next_no = next_no.max(statement_no_after(&batch)?);
+        let df_metadata = self.ctx.read_batch(batch)?;
+        self.metadata_df = Some(self.register(METADATA_TABLE, df_metadata).await?);
+
+        let batch = self.columns.prices.batch(&mut self.prices, columnar)?;
+        next_no = next_no.max(statement_no_after(&batch)?);
+        let df_prices = self.ctx.read_batch(batch)?.select(vec![
+            col(STATEMENT_NO),
+            col(FILE_NO),
+            col(START),
+            col(DATE),
+            col(COMMODITY),
+            cast(
+                col(PRICE),
+                DataType::Decimal128(PRECISION as u8, PRICE_SCALE as i8),
+            )
+            .alias(PRICE),
+            col(CURRENCY),
+            col(RAW),
+        ])?;
+        self.prices_df = Some(self.register(PRICES_TABLE, df_prices).await?);
+
+        let batch = self.columns.postings.batch(&mut self.postings, columnar)?;
+        next_no = next_no.max(statement_no_after(&batch)?);
+
+        // The weight of a posting, what it balances by: a price per unit
+        // times the units, rounded to SCALE; a total price with the sign of
+        // the units whichever sign it is written with, as in beancount, but
+        // for no units, an adjustment of their cost
+        let decimal = DataType::Decimal128(PRECISION as u8, SCALE as i8);
+        let weight = when(
+            col(PER_UNIT),
+            cast(col(CP_QUANTITY) * col(TC_QUANTITY), decimal),
+        )
+        .when(col(CP_QUANTITY).lt(lit(0)), -abs(col(TC_QUANTITY)))
+        .when(col(CP_QUANTITY).gt(lit(0)), abs(col(TC_QUANTITY)))
+        .otherwise(col(TC_QUANTITY))?;
+        let unit_price = when(col(PER_UNIT), col(TC_QUANTITY)).end()?;
+        let df_postings = self
+            .ctx
+            .read_batch(batch)?
+            .with_column(
+                UNIT_PRICE,
+                cast(
+                    unit_price,
+                    DataType::Decimal128(PRECISION as u8, PRICE_SCALE as i8),
+                ),
+            )?
+            .with_column(TC_QUANTITY, weight)?;
 
-        let df_balancing = df_postings
+        // The postings without an amount, with the default commodity of their account
+        let elided_df = df_postings
+            .clone()
+            .filter(col(TC_COMMODITY).is_null())?
+            .join(
+                defaults_df,
+                JoinType::Left,
+                &[ACCOUNT],
+                &[ACCOUNT_RIGHT],
+                None,
+            )?
+            .select(vec![
+                col(STATEMENT_NO).alias(STATEMENT_NO_RIGHT),
+                col(TRANSACTION_NO),
+                col(DEFAULT_COMMODITY),
+            ])?;
+
+        let residuals_df = df_postings
             .clone()
             .filter(col(TC_COMMODITY).is_not_null())?
             .aggregate(
@@ -84,26 +186,124 @@ impl LedgerState {
                         .alias(TOTALS),
                 ],
             )?
-            .filter(col(TOTALS).not_eq(lit(0)))?
-            .window(vec![row_number_window.alias(NUM)])?
+            .filter(col(TOTALS).not_eq(lit(0)))?;
+
+        // A posting without an amount takes the residual in the default
+        // commodity of its account, the first of them in the transaction if
+        // several share it
+        let first_window = row_number()
+            .partition_by(vec![col(TRANSACTION_NO), col(TC_COMMODITY)])
+            .order_by(vec![col(STATEMENT_NO_RIGHT).sort(true, false)])
+            .build()?;
+        let matched_df = residuals_df
+            .clone()
+            .join(
+                elided_df.clone().select(vec![
+                    col(STATEMENT_NO_RIGHT),
+                    col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT),
+                    col(DEFAULT_COMMODITY),
+                ])?,
+                JoinType::Inner,
+                &[TRANSACTION_NO, TC_COMMODITY],
+                &[TRANSACTION_NO_RIGHT, DEFAULT_COMMODITY],
+                None,
+            )?
+            .window(vec![first_window.alias(NUM)])?
+            .filter(col(NUM).eq(lit(1)))?
+            .select(vec![
+                col(STATEMENT_NO_RIGHT),
+                col(TRANSACTION_NO),
+                col(TC_COMMODITY),
+                col(TOTALS),
+            ])?;
+
+        // As in beancount, the one posting left without an amount takes the
+        // other residuals, one posting each; of several left, none can be
+        // told apart and they stay without an amount, an error
+        let unmatched_df = residuals_df.join(
+            matched_df.clone().select(vec![
+                col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT),
+                col(TC_COMMODITY).alias(TC_COMMODITY_RIGHT),
+            ])?,
+            JoinType::LeftAnti,
+            &[TRANSACTION_NO, TC_COMMODITY],
+            &[TRANSACTION_NO_RIGHT, TC_COMMODITY_RIGHT],
+            None,
+        )?;
+        let lone_df = elided_df
+            .join(
+                matched_df
+                    .clone()
+                    .select(vec![col(STATEMENT_NO_RIGHT).alias(STATEMENT_NO)])?,
+                JoinType::LeftAnti,
+                &[STATEMENT_NO_RIGHT],
+                &[STATEMENT_NO],
+                None,
+            )?
+            .aggregate(
+                vec![col(TRANSACTION_NO)],
+                vec![
+                    count(col(STATEMENT_NO_RIGHT)).alias(NUM),
+                    max(col(STATEMENT_NO_RIGHT)).alias(STATEMENT_NO_RIGHT),
+                ],
+            )?
             .filter(col(NUM).eq(lit(1)))?
-            .select(vec![col(TRANSACTION_NO), col(TC_COMMODITY), col(TOTALS)])?;
+            .select(vec![
+                col(STATEMENT_NO_RIGHT),
+                col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT),
+            ])?;
+        let absorbed_df = unmatched_df
+            .join(
+                lone_df,
+                JoinType::Inner,
+                &[TRANSACTION_NO],
+                &[TRANSACTION_NO_RIGHT],
+                None,
+            )?
+            .select(vec![
+                col(STATEMENT_NO_RIGHT),
+                col(TRANSACTION_NO),
+                col(TC_COMMODITY),
+                col(TOTALS),
+            ])?;
+        // A posting split in several commodities keeps its number for the
+        // first and numbers the others after every statement
+        let split_window = row_number()
+            .partition_by(vec![col(STATEMENT_NO_RIGHT)])
+            .order_by(vec![col(TC_COMMODITY).sort(true, false)])
+            .build()?;
+        let extra_window = row_number()
+            .order_by(vec![
+                col(STATEMENT_NO_RIGHT).sort(true, false),
+                col(TC_COMMODITY).sort(true, false),
+            ])
+            .build()?;
+        let df_balancing = matched_df
+            .union(absorbed_df)?
+            .window(vec![split_window.alias(NUM)])?
+            .window(vec![extra_window.alias(SPLIT_NO)])?
+            .with_column(
+                SPLIT_NO,
+                when(col(NUM).eq(lit(1u64)), col(STATEMENT_NO_RIGHT))
+                    .otherwise(cast(lit(next_no as u64) + col(SPLIT_NO), DataType::UInt32))?,
+            )?;
 
         let final_postings_df = df_postings
             .clone()
             .join(
-                df_balancing.clone().select(vec![
-                    col(TRANSACTION_NO).alias(TRANSACTION_NO_RIGHT),
+                df_balancing.select(vec![
+                    col(STATEMENT_NO_RIGHT),
                     col(TC_COMMODITY).alias(TC_COMMODITY_RIGHT),
                     col(TOTALS),
+                    col(SPLIT_NO),
                 ])?,
                 JoinType::Left,
-                &[TRANSACTION_NO],
-                &[TRANSACTION_NO_RIGHT],
+                &[STATEMENT_NO],
+                &[STATEMENT_NO_RIGHT],
                 None,
             )?
             .select(vec![
-                col(STATEMENT_NO),
+                coalesce(vec![col(SPLIT_NO), col(STATEMENT_NO)]).alias(STATEMENT_NO),
                 col(TRANSACTION_NO),
                 col(FILE_NO),
                 col(START),
@@ -122,7 +322,54 @@ impl LedgerState {
                     DataType::Decimal128(PRECISION as u8, SCALE as i8),
                 )
                 .alias(FINAL_TC_QUANTITY),
+                col(UNIT_PRICE),
+                col(FLAG).alias(POSTING_FLAG),
+                col(COMMENT),
+            ])?;
+
+        // A transaction off by no more than the tolerance of its commodity
+        // is balanced by a posting of the residual to the rounding account
+        let residual = cast(
+            -col(TOTALS),
+            DataType::Decimal128(PRECISION as u8, SCALE as i8),
+        );
+        let rounding_df = final_postings_df
+            .clone()
+            .filter(col(FINAL_TC_COMMODITY).is_not_null())?
+            .aggregate(
+                vec![col(TRANSACTION_NO), col(FINAL_TC_COMMODITY)],
+                vec![
+                    max(col(STATEMENT_NO)).alias(STATEMENT_NO),
+                    max(col(FILE_NO)).alias(FILE_NO),
+                    max(col(START)).alias(START),
+                    sum(col(FINAL_TC_QUANTITY)).alias(TOTALS),
+                ],
+            )?
+            .filter(
+                col(TOTALS)
+                    .not_eq(lit(0))
+                    .and(abs(col(TOTALS)).lt_eq(self.default_tolerance(FINAL_TC_COMMODITY)?)),
+            )?
+            .select(vec![
+                col(STATEMENT_NO),
+                col(TRANSACTION_NO),
+                col(FILE_NO),
+                col(START),
+                lit(self.rounding_account()).alias(ACCOUNT),
+                col(FINAL_TC_COMMODITY).alias(FINAL_CP_COMMODITY),
+                residual.clone().alias(FINAL_CP_QUANTITY),
+                col(FINAL_TC_COMMODITY),
+                residual.alias(FINAL_TC_QUANTITY),
+                lit(ScalarValue::Decimal128(
+                    None,
+                    PRECISION as u8,
+                    PRICE_SCALE as i8,
+                ))
+                .alias(UNIT_PRICE),
+                lit(ScalarValue::Utf8(None)).alias(POSTING_FLAG),
+                lit(ScalarValue::Utf8(None)).alias(COMMENT),
             ])?;
+        let final_postings_df = final_postings_df.union(rounding_df)?;
 
         let errors_df = final_postings_df
             .clone()
@@ -136,15 +383,37 @@ impl LedgerState {
                     .or(col(FINAL_TC_COMMODITY).is_null()),
             )?;
 
-        self.postings_df = Some(final_postings_df);
-        self.errors_df = Some(errors_df);
+        self.postings_df = Some(self.register(POSTINGS_TABLE, final_postings_df).await?);
+        self.errors_df = Some(self.register(ERRORS_TABLE, errors_df).await?);
 
-        self.cp_commodities_df = Some(self.get_commodities_df(FINAL_CP_COMMODITY)?);
-        self.tc_commodities_df = Some(self.get_commodities_df(FINAL_TC_COMMODITY)?);
-        self.accounts_df = Some(self.get_accounts_df().await?);
+        let df = self.get_commodities_df(FINAL_CP_COMMODITY)?;
+        self.cp_commodities_df = Some(self.register(CP_COMMODITIES_TABLE, df).await?);
+        let df = self.get_commodities_df(FINAL_TC_COMMODITY)?;
+        self.tc_commodities_df = Some(self.register(TC_COMMODITIES_TABLE, df).await?);
+        let df = self.get_accounts_df().await?;
+        self.accounts_df = Some(self.register(ACCOUNTS_TABLE, df).await?);
         Ok(())
     }
 
+    ///
+    /// Registers `df` as the table `name` of `ctx`, replacing the one of an
+    /// earlier verify, and returns a scan of it so the reports built on it
+    /// plan from the table rather than from everything verify did.
+    ///
+    async fn register(&self, name: &str, df: DataFrame) -> Result<DataFrame> {
+        self.ctx.register_table(name, df.into_view())?;
+        Ok(self.ctx.table(name).await?)
+    }
+
+    ///
+    /// The result of the SQL `query` over the tables registered by verify,
+    /// such as `SELECT account, SUM(tc_quantity_final) FROM postings GROUP
+    /// BY account`.
+    ///
+    pub async fn query_df(&self, query: &str) -> Result<DataFrame> {
+        Ok(self.ctx.sql(query).await?)
+    }
+
     pub fn get_commodities_df(&mut self, c_col: &str) -> Result<DataFrame> {
         match &self.postings_df {
             Some(df) => Ok(df.clone().select(vec![col(c_col)])?.distinct()?),
@@ -211,3 +480,120 @@ impl LedgerState {
         Ok(df)
     }
 }
+
+/// One past the largest statement number of `b`, 0 for no statements
+fn statement_no_after(b: &RecordBatch) -> Result<u32> {
+    let Some(nos) = b.column_by_name(STATEMENT_NO) else {
+        return Ok(0);
+    };
+    let nos = nos
+        .as_any()
+        .downcast_ref::<UInt32Array>()
+        .context(ERROR_DOWNCAST)?;
+    Ok(max_array(nos).map_or(0, |n| n + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use arrow::util::pretty::pretty_format_batches;
+
+    use super::*;
+    use crate::parse::parse_contents;
+
+    /// The state of `contents` once verified
+    async fn verified(contents: &str) -> LedgerState {
+        let f = Path::new("weights.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        parse_contents(f, contents, &mut state).unwrap();
+        state.verify().await.unwrap();
+        state
+    }
+
+    /// The rows of `query` as a table
+    async fn table(state: &LedgerState, query: &str) -> String {
+        let batches = state
+            .query_df(query)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        pretty_format_batches(&batches).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn postings_balance_by_their_weights() {
+        let state = verified(
+            "2024-01-01 open Assets:Cash\n\
+             2024-01-02 * \"fx\"\n  Assets:USD 3 USD @ 1.333 CAD\n  Assets:Cash -4.00 CAD\n\
+             2024-01-03 * \"sell\"\n  Assets:Broker -3 VFV @@ 400.00 CAD\n  Assets:Cash 400.00 CAD\n\
+             2024-01-04 * \"adjust\"\n  Assets:Broker 0 VFV @@ -5.00 CAD\n  Assets:Cash 5.00 CAD\n",
+        )
+        .await;
+        let postings = table(
+            &state,
+            "SELECT account, tc_quantity_final, unit_price FROM postings \
+             WHERE tc_commodity_final <> cp_commodity_final ORDER BY statement_no",
+        )
+        .await;
+        assert_eq!(
+            postings,
+            "+---------------+-------------------+------------+\n\
+             | account       | tc_quantity_final | unit_price |\n\
+             +---------------+-------------------+------------+\n\
+             | Assets:USD    | 4.00              | 1.333000   |\n\
+             | Assets:Broker | -400.00           |            |\n\
+             | Assets:Broker | -5.00             |            |\n\
+             +---------------+-------------------+------------+"
+        );
+        assert_eq!(table(&state, "SELECT * FROM errors").await, "++\n++");
+    }
+
+    #[tokio::test]
+    async fn split_postings_are_numbered_apart_and_printed_in_place() {
+        let state = verified(
+            "2024-01-01 open Assets:Cash\n\
+             2024-01-02 * \"split\"\n  Assets:Cash\n  Assets:Other -10.00 CAD\n  Assets:Other -5.00 USD\n\
+             2024-01-02 * \"two elided\"\n  Assets:Cash -10.00 CAD\n  Assets:Other\n  Assets:Cash\n",
+        )
+        .await;
+        assert_eq!(
+            table(
+                &state,
+                "SELECT COUNT(*) AS n, COUNT(DISTINCT statement_no) AS numbers FROM postings"
+            )
+            .await,
+            "+---+---------+\n\
+             | n | numbers |\n\
+             +---+---------+\n\
+             | 7 | 7       |\n\
+             +---+---------+"
+        );
+
+        let lines = state
+            .transaction_lines_df()
+            .unwrap()
+            .select_columns(&[ACCOUNT, FINAL_CP_QUANTITY, FINAL_CP_COMMODITY])
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            pretty_format_batches(&lines).unwrap().to_string(),
+            "+--------------+-------------------+--------------------+\n\
+             | account      | cp_quantity_final | cp_commodity_final |\n\
+             +--------------+-------------------+--------------------+\n\
+             | Assets:Cash  | 10.00             | CAD                |\n\
+             | Assets:Cash  | 5.00              | USD                |\n\
+             | Assets:Other | -10.00            | CAD                |\n\
+             | Assets:Other | -5.00             | USD                |\n\
+             | Assets:Cash  | -10.00            | CAD                |\n\
+             | Assets:Other |                   |                    |\n\
+             | Assets:Cash  |                   |                    |\n\
+             +--------------+-------------------+--------------------+"
+        );
+    }
+}
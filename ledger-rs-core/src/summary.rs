@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::parse::include_files;
+use crate::state::ledgerstate::LedgerState;
+
+const SUMMARY_EXTENSION: &str = "summary.json";
+
+/// An input file as it was when the summary was written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub path: PathBuf,
+    pub len: u64,
+    pub modified: u128,
+    pub hash: u64,
+}
+
+fn modified(m: &fs::Metadata) -> u128 {
+    m.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+fn hash_contents(contents: &[u8]) -> u64 {
+    let mut h = DefaultHasher::new();
+    contents.hash(&mut h);
+    h.finish()
+}
+
+impl FileStamp {
+    fn new(path: &Path) -> Result<Self> {
+        let contents = fs::read(path)?;
+        let m = fs::metadata(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            len: m.len(),
+            modified: modified(&m),
+            hash: hash_contents(&contents),
+        })
+    }
+
+    /// Unchanged if its size and time are, or failing that its contents
+    fn is_current(&self) -> bool {
+        let Ok(m) = fs::metadata(&self.path) else {
+            return false;
+        };
+        if m.len() == self.len && modified(&m) == self.modified {
+            return true;
+        }
+        m.len() == self.len
+            && fs::read(&self.path)
+                .map(|c| hash_contents(&c) == self.hash)
+                .unwrap_or(false)
+    }
+}
+
+/// A glob include as it matched when the summary was written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobStamp {
+    /// The including file
+    pub file: PathBuf,
+    /// The path as written
+    pub pattern: String,
+    pub matches: Vec<PathBuf>,
+}
+
+impl GlobStamp {
+    fn new(file: &Path, pattern: &str, include_path: &[PathBuf]) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            pattern: pattern.to_string(),
+            matches: Self::matching(file, pattern, include_path),
+        }
+    }
+
+    /// All the files the include matches now, loaded or not
+    fn matching(file: &Path, pattern: &str, include_path: &[PathBuf]) -> Vec<PathBuf> {
+        include_files(file, pattern, &HashMap::new(), include_path)
+            .into_iter()
+            .map(|(f, _)| f)
+            .collect()
+    }
+}
+
+///
+/// What cheap queries such as account completion need to know about a
+/// ledger, saved next to it as `.<name>.summary.json` so they can be answered
+/// without parsing. It is stale once any of its input files change, a glob
+/// include matches other files or the ledger is read with another include
+/// path.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerSummary {
+    pub files: Vec<FileStamp>,
+    pub include_path: Vec<PathBuf>,
+    pub globs: Vec<GlobStamp>,
+    pub accounts: Vec<String>,
+    pub commodities: Vec<String>,
+    pub first_date: Option<NaiveDate>,
+    pub last_date: Option<NaiveDate>,
+    pub transactions: usize,
+    pub postings: usize,
+    pub prices: usize,
+}
+
+impl LedgerSummary {
+    pub fn new(state: &LedgerState) -> Result<Self> {
+        let mut files: Vec<(&PathBuf, &u32)> = state.input_files.iter().collect();
+        files.sort_by_key(|(_, n)| **n);
+        let files = files
+            .into_iter()
+            .map(|(f, _)| FileStamp::new(f))
+            .collect::<Result<Vec<_>>>()?;
+
+        let globs = state
+            .include_globs
+            .iter()
+            .map(|(f, pattern)| GlobStamp::new(f, pattern, &state.include_path))
+            .collect();
+
+        let dates = state.transactions.iter().map(|t| t.date);
+
+        Ok(Self {
+            files,
+            include_path: state.include_path.clone(),
+            globs,
+            accounts: state.account_names(),
+            commodities: state.commodities(),
+            first_date: dates.clone().min(),
+            last_date: dates.max(),
+            transactions: state.transactions.len(),
+            postings: state.postings.len(),
+            prices: state.prices.len(),
+        })
+    }
+
+    /// The summary file of the ledger `f`
+    pub fn sidecar(f: &Path) -> PathBuf {
+        let name = f
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        f.with_file_name(format!(".{}.{}", name, SUMMARY_EXTENSION))
+    }
+
+    /// Writes the summary next to the ledger it was made from
+    pub fn write(&self) -> Result<()> {
+        let f = &self.files.first().context("Summary without files")?.path;
+        fs::write(Self::sidecar(f), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    ///
+    /// The summary of `f` read with `include_path`, unless it is missing,
+    /// any input file changed or a glob include matches other files
+    ///
+    pub fn load(f: &Path, include_path: &[PathBuf]) -> Option<Self> {
+        let s: Self = serde_json::from_str(&fs::read_to_string(Self::sidecar(f)).ok()?).ok()?;
+        if s.files.first().map(|x| x.path.as_path()) != Some(f)
+            || s.include_path != include_path
+            || !s.files.iter().all(|x| x.is_current())
+            || !s
+                .globs
+                .iter()
+                .all(|g| GlobStamp::matching(&g.file, &g.pattern, include_path) == g.matches)
+        {
+            return None;
+        }
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_filename;
+
+    /// The summary of the ledger `f` as parsed with `include_path`
+    fn summary_of(f: &Path, include_path: &[PathBuf]) -> LedgerSummary {
+        let mut state = LedgerState::new();
+        state.include_path = include_path.to_vec();
+        state.insert(f.to_path_buf());
+        parse_filename(f.to_path_buf(), &mut state).unwrap();
+        LedgerSummary::new(&state).unwrap()
+    }
+
+    #[test]
+    fn summary_is_stale_once_a_glob_matches_a_new_file() {
+        let dir = std::env::temp_dir().join(format!("ledger-rs-summary-{}", std::process::id()));
+        fs::create_dir_all(dir.join("inc")).unwrap();
+        let f = dir.join("top.bean");
+        fs::write(&f, "include \"inc/*.bean\"\n").unwrap();
+        fs::write(dir.join("inc/a.bean"), "2024-01-01 open Assets:A\n").unwrap();
+        let _ = fs::remove_file(dir.join("inc/c.bean"));
+
+        let summary = summary_of(&f, &[]);
+        assert_eq!(summary.accounts, ["Assets:A"]);
+        summary.write().unwrap();
+        assert_eq!(LedgerSummary::load(&f, &[]), Some(summary));
+        // Read with another include path
+        assert_eq!(LedgerSummary::load(&f, std::slice::from_ref(&dir)), None);
+
+        fs::write(dir.join("inc/c.bean"), "2024-01-01 open Assets:New2\n").unwrap();
+        assert_eq!(LedgerSummary::load(&f, &[]), None);
+        assert_eq!(summary_of(&f, &[]).accounts, ["Assets:A", "Assets:New2"]);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{cell::RefCell, mem, path::PathBuf, rc::Rc};
+
+use anyhow::Result;
+use anyhow::anyhow;
+
+use crate::core::{
+    HeaderParams, IncludeParams, InfoParams, MetadataParams, PostingParams, PriceParams,
+    VerificationParams,
+};
+use crate::parse::parse_visited;
+use crate::state::ledgerstate::LedgerState;
+
+/// A statement as produced by the parser, numbered as in parse_filename
+#[derive(Debug)]
+pub enum Statement<'a> {
+    Transaction(&'a HeaderParams, &'a [PostingParams], &'a [MetadataParams]),
+    Verification(&'a VerificationParams),
+    Price(&'a PriceParams),
+    Info(&'a InfoParams),
+    Include(&'a IncludeParams),
+}
+
+pub trait StatementVisitor {
+    fn visit(&mut self, statement: Statement);
+}
+
+///
+/// Hands the rows parsed since the last call to the visitor of `state`, if
+/// any, and drops them. Called after every statement, so at most one
+/// transaction is held at a time.
+///
+pub(crate) fn visit_parsed(state: &mut LedgerState) {
+    let Some(visitor) = state.visitor.clone() else {
+        return;
+    };
+    let mut v = visitor.borrow_mut();
+    let postings = mem::take(&mut state.postings);
+    let metadata = mem::take(&mut state.metadata);
+    for t in mem::take(&mut state.transactions).iter() {
+        v.visit(Statement::Transaction(t, &postings, &metadata));
+    }
+    for x in mem::take(&mut state.verifications).iter() {
+        v.visit(Statement::Verification(x));
+    }
+    for x in mem::take(&mut state.prices).iter() {
+        v.visit(Statement::Price(x));
+    }
+    for x in mem::take(&mut state.informationals).iter() {
+        v.visit(Statement::Info(x));
+    }
+    for x in mem::take(&mut state.includes).iter() {
+        v.visit(Statement::Include(x));
+    }
+}
+
+///
+/// Parses `f` and its includes, handing each statement to `visitor` as soon
+/// as it is parsed instead of collecting them into a LedgerState. An include
+/// is visited after the statements of the files it includes.
+///
+pub fn parse_with_visitor<V: StatementVisitor + 'static>(f: PathBuf, visitor: V) -> Result<V> {
+    let visitor = Rc::new(RefCell::new(visitor));
+    let mut state = LedgerState::new();
+    state.visitor = Some(visitor.clone());
+    state.insert(f.clone());
+    parse_visited(&f, &mut state)?;
+    drop(state);
+    Rc::try_unwrap(visitor)
+        .map(RefCell::into_inner)
+        .map_err(|_| anyhow!("Visitor still borrowed"))
+}
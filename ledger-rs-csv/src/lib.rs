@@ -1,3 +1,5 @@
+#![allow(clippy::upper_case_acronyms)]
+
 pub mod rj_common;
 pub mod rj_core;
 pub mod rj_decimal;
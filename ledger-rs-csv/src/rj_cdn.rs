@@ -1,8 +1,15 @@
-use std::{io::Error, sync::atomic::Ordering};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Error, ErrorKind},
+    str::FromStr,
+};
 
 use chrono::NaiveDate;
 use ledger_rs_core::{
-    core::{BALANCE_ACTION, HeaderParams, PostingParams, VerificationParams},
+    core::{
+        BALANCE_ACTION, HeaderParams, OPEN_ACTION, PostingParams, PriceParams, TRANSACTION_FLAG,
+        VerificationParams,
+    },
     state::ledgerstate::LedgerState,
 };
 use rust_decimal::Decimal;
@@ -10,12 +17,13 @@ use serde::Deserialize;
 
 use crate::{
     rj_common::{
-        acct_capgains, acct_cash, acct_distribution, acct_dividend, acct_fees, acct_foreigntax,
-        acct_gainloss, acct_interest, acct_securities, acct_todo,
+        acct_capgains, acct_cash, acct_cash_currency, acct_distribution, acct_dividend, acct_fees,
+        acct_foreigntax, acct_gainloss, acct_interest, acct_opening, acct_returnofcapital,
+        acct_securities, acct_todo,
     },
-    rj_core::{InterPost, Position},
+    rj_core::{ImportErrors, InterPost, Position, UNKNOWN_SEC, read_records},
     rj_decimal::{self, reverse_sign},
-    rj_symbols::{SymbolsMap, load_symbols},
+    rj_symbols::{SymbolsMap, SymbolsTable, load_owners, load_tran_types},
 };
 
 #[derive(Debug, Deserialize)]
@@ -27,7 +35,7 @@ struct TransRecord {
     #[serde(rename = "Settled")]
     settled: String,
     #[serde(rename = "Tran Types")]
-    tran_types: TranType,
+    tran_types: String,
 
     /// Description with symbol look up
     #[serde(rename = "Description")]
@@ -42,28 +50,112 @@ struct TransRecord {
     /// amount in currency (negative is decrease in cash)
     #[serde(rename = "Amount", with = "rj_decimal")]
     amount: Decimal,
+
+    /// The account of the activity in an export of several
+    #[serde(rename = "Account Number", default)]
+    account_number: Option<String>,
+
+    /// Whose account it is in an export of several, by the owners file
+    #[serde(rename = "Client Name", default)]
+    client_name: Option<String>,
+}
+
+///
+/// Whose accounts the activities are posted to: `acct` and `owner` for an
+/// export without the Account Number and Client Name columns or a row with
+/// them empty, else the account number and the owner of the client name in
+/// `owners`, so that an export of several accounts imports at once. With
+/// `currency_cash`, cash in another currency than that of the account, such
+/// as US dividends, goes to a sub-account of the cash by currency.
+///
+pub struct ActivityAccounts<'a> {
+    pub acct: &'a str,
+    pub owner: &'a str,
+    pub owners: SymbolsMap,
+    pub currency_cash: bool,
+}
+
+impl<'a> ActivityAccounts<'a> {
+    /// The owners from the `client,owner` file `owners_filepath`, else default_owners
+    pub fn new(
+        acct: &'a str,
+        owner: &'a str,
+        owners_filepath: Option<&str>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            acct,
+            owner,
+            owners: owners(owners_filepath)?,
+            currency_cash: false,
+        })
+    }
 }
 
 impl TransRecord {
+    /// The account number and owner of the activity; a client name without an owner is an error
+    fn account<'a>(&'a self, accounts: &'a ActivityAccounts) -> Result<(&'a str, &'a str), Error> {
+        let acct = match self.account_number.as_deref().map(|x| x.trim()) {
+            Some(a) if !a.is_empty() => a,
+            _ => accounts.acct,
+        };
+        let owner = match self.client_name.as_deref().map(|x| x.trim()) {
+            Some(c) if !c.is_empty() => {
+                accounts.owners.get(c).map(|o| o.as_str()).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("No owner for client name \"{}\"", c),
+                    )
+                })?
+            }
+            _ => accounts.owner,
+        };
+        Ok((acct, owner))
+    }
+
+    /// The cash account of the activity, in `currency` for an account in `account_currency`
+    fn cash_account(
+        &self,
+        accounts: &ActivityAccounts,
+        account_currency: &str,
+        currency: &str,
+    ) -> Result<String, Error> {
+        let (acct, owner) = self.account(accounts)?;
+        Ok(
+            match accounts.currency_cash && currency != account_currency {
+                true => acct_cash_currency!(owner, acct, currency),
+                false => acct_cash!(owner, acct),
+            },
+        )
+    }
+
     fn get_cash_position(&self, currency: &str) -> Position {
-        let mut amt = self.amount.clone();
+        let mut amt = self.amount;
         amt.rescale(3);
         (amt, String::from(currency))
     }
 
-    fn get_sec_position(&self, symbols: &SymbolsMap) -> Position {
-        let sec = match symbols.get(&self.description) {
-            Some(s) => s.clone().to_string(),
-            None => "UNKNOENSEC".to_string(),
-        };
-        let mut q = self.quantity.clone();
+    fn get_sec_position(&self, symbols: &SymbolsTable) -> Position {
+        let sec = symbols
+            .ticker(&self.description)
+            .unwrap_or(UNKNOWN_SEC)
+            .to_string();
+        let mut q = self.quantity;
         q.rescale(3);
         (q, sec)
     }
 
+    /// Whether `action` posts the security of the activity, by its description
+    fn needs_symbol(&self, action: Action) -> bool {
+        match action {
+            Action::Buy | Action::Sell | Action::Reinvest | Action::ReturnOfCapital => true,
+            Action::Transfer => self.quantity != Decimal::ZERO,
+            Action::Cash | Action::Skip => false,
+        }
+    }
+
     fn get_cost(&self, currency: &str) -> Position {
-        let price = self.price.clone();
-        let quantity = self.quantity.clone();
+        let price = self.price;
+        let quantity = self.quantity;
         let mut cost = price * quantity;
 
         cost.rescale(3);
@@ -74,7 +166,7 @@ impl TransRecord {
     fn transfer(
         &self,
         currency: &str,
-        symbols: &SymbolsMap,
+        symbols: &SymbolsTable,
         cash: &str,
         sec: &str,
         todo: &str,
@@ -96,7 +188,7 @@ impl TransRecord {
         res
     }
 
-    fn buy(&self, currency: &str, symbols: &SymbolsMap, cash: &str, sec: &str) -> Vec<InterPost> {
+    fn buy(&self, currency: &str, symbols: &SymbolsTable, cash: &str, sec: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
         let cash_p = self.get_cash_position(currency);
@@ -124,7 +216,7 @@ impl TransRecord {
     fn sell(
         &self,
         currency: &str,
-        symbols: &SymbolsMap,
+        symbols: &SymbolsTable,
         cash: &str,
         sec: &str,
         gl: &str,
@@ -142,7 +234,7 @@ impl TransRecord {
     fn reinvestment(
         &self,
         currency: &str,
-        symbols: &SymbolsMap,
+        symbols: &SymbolsTable,
         sec: &str,
         acct: &str,
     ) -> Vec<InterPost> {
@@ -159,218 +251,330 @@ impl TransRecord {
         res
     }
 
+    ///
+    /// The cash received as a posting of no units of the security at minus
+    /// its amount, which lowers the cost of the units held
+    ///
+    fn return_of_capital(
+        &self,
+        currency: &str,
+        symbols: &SymbolsTable,
+        cash: &str,
+        sec: &str,
+    ) -> Vec<InterPost> {
+        let mut res: Vec<InterPost> = Vec::new();
+
+        let cash_p = self.get_cash_position(currency);
+        let (_, sec_s) = self.get_sec_position(symbols);
+        let (c_q, c_s) = self.get_cash_position(currency);
+        let cost_p = (reverse_sign(&c_q), c_s);
+
+        res.push((
+            String::from(sec),
+            Some((Decimal::ZERO, sec_s)),
+            Some(cost_p),
+        ));
+        res.push((String::from(cash), Some(cash_p), None));
+        res
+    }
+
     fn store_transaction(
         &self,
-        acct: &str,
-        owner: &str,
+        accounts: &ActivityAccounts,
         currency: &str,
-        symbols: &SymbolsMap,
+        symbols: &SymbolsTable,
+        template: &TranTemplate,
         state: &mut LedgerState,
-    ) -> Result<(), Error> {
-        let cash = acct_cash!(owner, acct);
+    ) -> Result<Option<String>, Error> {
+        let (acct, owner) = self.account(accounts)?;
+        let posted = template.currency.as_deref().unwrap_or(currency);
+        let cash = self.cash_account(accounts, currency, posted)?;
         let sec = acct_securities!(owner, acct);
-        let todo = acct_todo!(owner);
-        let dividend_acct = acct_dividend!(owner);
-        let fees = acct_fees!(owner);
-
-        let capgains = acct_capgains!(owner);
-        let distribution = acct_distribution!(owner);
-        let foreigntax = acct_foreigntax!(owner);
-        let gl = acct_gainloss!(owner);
-        let interest = acct_interest!(owner);
+        let role = template.role.account(owner);
+        let currency = posted;
 
         let description = self.description.clone();
-        let bkdate = NaiveDate::parse_from_str(&self.settled, "%Y-%m-%d").unwrap();
-        let narration = format!("{}:{description}", self.tran_types);
+        let Ok(bkdate) = NaiveDate::parse_from_str(&self.settled, "%Y-%m-%d") else {
+            return Ok(Some(format!(
+                "skipped: unparseable date {:?}",
+                self.settled
+            )));
+        };
+        let narration = format!("{}:{description}", template.name);
 
-        let posts = match self.tran_types {
-            TranType::Buy => self.buy(currency, symbols, &cash, &sec),
-            TranType::CanadianCashDividend => {
-                self.cash_transaction(currency, &cash, &dividend_acct)
-            }
-            TranType::CashReceipt => self.cash_transaction(currency, &cash, &todo),
-            TranType::Distribution => self.cash_transaction(currency, &cash, &distribution),
-            TranType::ExpiringRightsAndWarrants
-            | TranType::MFNotionalDistribution
-            | TranType::MandatoryExchange
-            | TranType::Sell => self.sell(currency, symbols, &cash, &sec, &gl),
-            TranType::ForeignDividend => self.cash_transaction(currency, &cash, &dividend_acct),
-            TranType::ForeignNonResidentTax => self.cash_transaction(currency, &cash, &foreigntax),
-            TranType::ForeignTransfer => self.cash_transaction(currency, &cash, &todo),
-            TranType::GoodsAndServicesTax => self.cash_transaction(currency, &cash, &fees),
-            TranType::InternalTransfer => self.transfer(currency, symbols, &cash, &sec, &todo),
-            TranType::MutualFundDividend => self.cash_transaction(currency, &cash, &dividend_acct),
-            TranType::OtherManagedAcctFeeRegistered => {
-                self.cash_transaction(currency, &cash, &fees)
-            }
-            TranType::OtherManagedAcctFees => self.cash_transaction(currency, &cash, &fees),
-            TranType::PartnersFeeNonRegistered => self.cash_transaction(currency, &cash, &fees),
-            TranType::QuebecSalesTax => self.cash_transaction(currency, &cash, &fees),
-            TranType::RRSPContribution => self.cash_transaction(currency, &cash, &todo),
-            TranType::ReinvestmentDividend => {
-                self.reinvestment(currency, symbols, &sec, &dividend_acct)
-            }
-            TranType::SecTfrCosts => self.cash_transaction(currency, &cash, &fees),
-            TranType::SpinOffForeign => self.buy(currency, symbols, &cash, &sec),
-            TranType::SpinOffUS => self.buy(currency, symbols, &cash, &sec),
-            TranType::StockSplit => self.buy(currency, symbols, &cash, &sec),
-            TranType::USCashDividend => self.cash_transaction("USD", &cash, &dividend_acct),
-            TranType::USSourceLongTermGains => self.cash_transaction(currency, &cash, &capgains),
-            TranType::ViridanFeesNonRegistered => self.cash_transaction(currency, &cash, &fees),
-            TranType::MonthlyInterest => self.cash_transaction(currency, &cash, &interest),
-            TranType::MFReturnOfCapital => Vec::new(),
+        let posts = match template.action {
+            Action::Buy => self.buy(currency, symbols, &cash, &sec),
+            Action::Sell => self.sell(currency, symbols, &cash, &sec, &role),
+            Action::Cash => self.cash_transaction(currency, &cash, &role),
+            Action::Transfer => self.transfer(currency, symbols, &cash, &sec, &role),
+            Action::Reinvest => self.reinvestment(currency, symbols, &sec, &role),
+            Action::ReturnOfCapital => self.return_of_capital(currency, symbols, &cash, &sec),
+            Action::Skip => Vec::new(),
         };
 
-        let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
-        if posts.len() == 0 {
-            // storage
-            //     .lineerrors
-            //     .borrow_mut()
-            //     .append_lineerror(posno, 0, 0, format!("{:?}", self));
-        } else {
-            let transno = posno.clone();
-            let th = HeaderParams {
-                statement_no: transno,
-                file_no: 0u32,
-                start: 0u32,
-                end: 0u32,
-                date: bkdate,
-                narration: narration,
-                tags: None,
-            };
-            state.transactions.push(th);
-
-            posts
-                .into_iter()
-                .map(|(acct, cp, tc)| {
-                    let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
-                    let (cp_quantity, cp_commodity) = match cp {
-                        None => (None, None),
-                        Some((q, c)) => (Some(q), Some(c)),
-                    };
-                    let (tc_quantity, tc_commodity) = match tc {
-                        None => (None, None),
-                        Some((q, c)) => (Some(q), Some(c)),
-                    };
-                    PostingParams {
-                        statement_no: posno,
-                        transaction_no: transno,
-                        file_no: 0u32,
-                        start: 0u32,
-                        end: 0u32,
-                        account: acct,
-                        cp_quantity: cp_quantity,
-                        cp_commodity: cp_commodity,
-                        tc_quantity: tc_quantity,
-                        tc_commodity: tc_commodity,
-                    }
-                })
-                .for_each(|x| state.postings.push(x));
+        let posno = state.next_statement_no();
+        if posts.is_empty() {
+            return Ok(Some(format!("{} posts nothing", template.name)));
         }
+        let transno = posno;
+        let th = HeaderParams {
+            statement_no: transno,
+            file_no: 0u32,
+            start: 0u32,
+            end: 0u32,
+            date: bkdate,
+            flag: TRANSACTION_FLAG.to_string(),
+            narration,
+            tags: None,
+            raw: None,
+        };
+        state.transactions.push(th);
 
-        Ok(())
+        let postings: Vec<PostingParams> = posts
+            .into_iter()
+            .map(|(acct, cp, tc)| {
+                let posno = state.next_statement_no();
+                let (cp_quantity, cp_commodity) = match cp {
+                    None => (None, None),
+                    Some((q, c)) => (Some(q), Some(c)),
+                };
+                // Without a cost a posting weighs its amount, as parsed
+                let (tc_quantity, tc_commodity) = match tc {
+                    None => (cp_quantity, cp_commodity.clone()),
+                    Some((q, c)) => (Some(q), Some(c)),
+                };
+                PostingParams {
+                    statement_no: posno,
+                    transaction_no: transno,
+                    file_no: 0u32,
+                    start: 0u32,
+                    end: 0u32,
+                    account: acct,
+                    cp_quantity,
+                    cp_commodity,
+                    tc_quantity,
+                    tc_commodity,
+                    per_unit: false,
+                    flag: None,
+                    comment: None,
+                }
+            })
+            .collect();
+        state.postings.extend(postings);
+
+        Ok(None)
     }
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
-enum TranType {
-    #[serde(rename = "BUY")]
+/// What an activity posts, one of the posting functions of TransRecord
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
     Buy,
-    #[serde(rename = "CASH RECEIPT")]
-    CashReceipt,
-    #[serde(rename = "CDN CASH DIVIDEND")]
-    CanadianCashDividend,
-    #[serde(rename = "DISTRIBUTION")]
-    Distribution,
-    #[serde(rename = "EXPIRING RIGHTS/WARRANTS")]
-    ExpiringRightsAndWarrants,
-    #[serde(rename = "FOREIGN DIVIDEND")]
-    ForeignDividend,
-    #[serde(rename = "FOREIGN NON RESIDENT TAX")]
-    ForeignNonResidentTax,
-    #[serde(rename = "FOREIGN TRANSFER")]
-    ForeignTransfer,
-    #[serde(rename = "GOODS & SERVICES TAX")]
-    GoodsAndServicesTax,
-    #[serde(rename = "INTERNAL TRANSFER")]
-    InternalTransfer,
-    #[serde(rename = "MANDATORY EXCHANGE")]
-    MandatoryExchange,
-    #[serde(rename = "MF NOTIONAL DISTRIBUTION")]
-    MFNotionalDistribution,
-    #[serde(rename = "MUTUAL FUND DIVIDEND")]
-    MutualFundDividend,
-    #[serde(rename = "OTHER MANAGED ACCT FEES")]
-    OtherManagedAcctFees,
-    #[serde(rename = "OTHER MGD ACCT FEE RGSRTD")]
-    OtherManagedAcctFeeRegistered,
-    #[serde(rename = "PARTNERS FEE NON REG'D")]
-    PartnersFeeNonRegistered,
-    #[serde(rename = "QUEBEC SALES TAX")]
-    QuebecSalesTax,
-    #[serde(rename = "REINVESTED DIVIDEND")]
-    ReinvestmentDividend,
-    #[serde(rename = "RRSP CONTRIBUTION")]
-    RRSPContribution,
-    #[serde(rename = "SEC TFR COSTS")]
-    SecTfrCosts,
-    #[serde(rename = "SELL")]
     Sell,
-    #[serde(rename = "SPIN OFF (FOREIGN)")]
-    SpinOffForeign,
-    #[serde(rename = "SPIN OFF (US)")]
-    SpinOffUS,
-    #[serde(rename = "STOCK SPLIT")]
-    StockSplit,
-    #[serde(rename = "US CASH DIVIDEND")]
-    USCashDividend,
-    #[serde(rename = "US SOURCE LONG TERM GAINS")]
-    USSourceLongTermGains,
-    #[serde(rename = "VIRIDIAN FEES NON REGISTD")]
-    ViridanFeesNonRegistered,
-    #[serde(rename = "MONTHLY INTEREST")]
-    MonthlyInterest,
-    #[serde(rename = "MF RETURN OF CAPITAL")]
-    MFReturnOfCapital,
+    Cash,
+    Transfer,
+    Reinvest,
+    /// The cash received lowers the cost of the security
+    ReturnOfCapital,
+    /// Nothing is posted
+    Skip,
 }
 
-impl std::fmt::Display for TranType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            Self::Buy => "Buy",
-            Self::CanadianCashDividend => "CanadianCashDividend",
-            Self::CashReceipt => "CashReceipt",
-            Self::Distribution => "Distribution",
-            Self::ExpiringRightsAndWarrants => "ExpiringRightsAndWarrants",
-            Self::ForeignDividend => "ForeignDivident",
-            Self::ForeignNonResidentTax => "ForeignNonResidentTax",
-            Self::ForeignTransfer => "ForeignTransfer",
-            Self::GoodsAndServicesTax => "GoodsAndServicesTax",
-            Self::InternalTransfer => "InternalTransfer",
-            Self::MFNotionalDistribution => "MFNotionalDistribution",
-            Self::MandatoryExchange => "MandatoryExchange",
-            Self::MutualFundDividend => "MutualFundDivident",
-            Self::OtherManagedAcctFeeRegistered => "OtherManagedAcctFeeRegistered",
-            Self::OtherManagedAcctFees => "OtherManagedAcctFees",
-            Self::PartnersFeeNonRegistered => "PartnersFeeNonRegistered",
-            Self::QuebecSalesTax => "QuebecSalesTax",
-            Self::RRSPContribution => "RRSPContribution",
-            Self::ReinvestmentDividend => "ReinvestmentDividend",
-            Self::SecTfrCosts => "SecTfrCosts",
-            Self::Sell => "Sell",
-            Self::SpinOffForeign => "SpinOffForeign",
-            Self::SpinOffUS => "SpinOffUS",
-            Self::StockSplit => "StockSplit",
-            Self::USCashDividend => "USCashDividend",
-            Self::USSourceLongTermGains => "USSourceLongTermGains",
-            Self::ViridanFeesNonRegistered => "ViridanFeesNonRegistered",
-            Self::MonthlyInterest => "MonthlyInterest",
-            Self::MFReturnOfCapital => "MFReturnOfCapital",
+/// The account an activity posts against its cash or securities
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Todo,
+    Dividend,
+    Fees,
+    CapitalGains,
+    Distribution,
+    ForeignTax,
+    GainLoss,
+    Interest,
+    ReturnOfCapital,
+}
+
+impl Role {
+    fn account(&self, owner: &str) -> String {
+        match self {
+            Self::Todo => acct_todo!(owner),
+            Self::Dividend => acct_dividend!(owner),
+            Self::Fees => acct_fees!(owner),
+            Self::CapitalGains => acct_capgains!(owner),
+            Self::Distribution => acct_distribution!(owner),
+            Self::ForeignTax => acct_foreigntax!(owner),
+            Self::GainLoss => acct_gainloss!(owner),
+            Self::Interest => acct_interest!(owner),
+            Self::ReturnOfCapital => acct_returnofcapital!(owner),
+        }
+    }
+}
+
+///
+/// How an activity of one Tran Type is posted, written `action[:role[:currency]]`
+/// as in `cash:dividend:USD`: the action, `buy`, `sell`, `cash`, `transfer`,
+/// `reinvest`, `roc` or `skip`, the role of the account posted against, by default
+/// `gainloss` for a sale, `dividend` for a reinvestment and `todo`
+/// otherwise, and the currency when not that of the account. The name heads
+/// the narration of the transactions.
+///
+#[derive(Debug, Clone, PartialEq)]
+struct TranTemplate {
+    name: String,
+    action: Action,
+    role: Role,
+    currency: Option<String>,
+}
+
+impl TranTemplate {
+    fn parse(name: &str, template: &str) -> Option<Self> {
+        let mut parts = template.split(':').map(|x| x.trim());
+        let action = match parts.next()?.to_ascii_lowercase().as_str() {
+            "buy" => Action::Buy,
+            "sell" => Action::Sell,
+            "cash" => Action::Cash,
+            "transfer" => Action::Transfer,
+            "reinvest" => Action::Reinvest,
+            "roc" => Action::ReturnOfCapital,
+            "skip" => Action::Skip,
+            _ => return None,
+        };
+        let role = match parts.next().map(|x| x.to_ascii_lowercase()).as_deref() {
+            None | Some("") => match action {
+                Action::Sell => Role::GainLoss,
+                Action::Reinvest => Role::Dividend,
+                _ => Role::Todo,
+            },
+            Some("todo") => Role::Todo,
+            Some("dividend") => Role::Dividend,
+            Some("fees") => Role::Fees,
+            Some("capitalgains") => Role::CapitalGains,
+            Some("distribution") => Role::Distribution,
+            Some("foreigntax") => Role::ForeignTax,
+            Some("gainloss") => Role::GainLoss,
+            Some("interest") => Role::Interest,
+            Some("returnofcapital") => Role::ReturnOfCapital,
+            Some(_) => return None,
+        };
+        let currency = match parts.next() {
+            None | Some("") => None,
+            Some(c) => Some(c.to_string()),
         };
-        write!(f, "{}", s)
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_string(),
+            action,
+            role,
+            currency,
+        })
     }
 }
 
+/// The Tran Types known without a tran types file: type, name, template
+const DEFAULT_TRAN_TYPES: [(&str, &str, &str); 29] = [
+    ("BUY", "Buy", "buy"),
+    ("CASH RECEIPT", "CashReceipt", "cash"),
+    ("CDN CASH DIVIDEND", "CanadianCashDividend", "cash:dividend"),
+    ("DISTRIBUTION", "Distribution", "cash:distribution"),
+    (
+        "EXPIRING RIGHTS/WARRANTS",
+        "ExpiringRightsAndWarrants",
+        "sell",
+    ),
+    ("FOREIGN DIVIDEND", "ForeignDivident", "cash:dividend"),
+    (
+        "FOREIGN NON RESIDENT TAX",
+        "ForeignNonResidentTax",
+        "cash:foreigntax",
+    ),
+    ("FOREIGN TRANSFER", "ForeignTransfer", "cash"),
+    ("GOODS & SERVICES TAX", "GoodsAndServicesTax", "cash:fees"),
+    ("INTERNAL TRANSFER", "InternalTransfer", "transfer"),
+    ("MANDATORY EXCHANGE", "MandatoryExchange", "sell"),
+    ("MF NOTIONAL DISTRIBUTION", "MFNotionalDistribution", "sell"),
+    (
+        "MUTUAL FUND DIVIDEND",
+        "MutualFundDivident",
+        "cash:dividend",
+    ),
+    (
+        "OTHER MANAGED ACCT FEES",
+        "OtherManagedAcctFees",
+        "cash:fees",
+    ),
+    (
+        "OTHER MGD ACCT FEE RGSRTD",
+        "OtherManagedAcctFeeRegistered",
+        "cash:fees",
+    ),
+    (
+        "PARTNERS FEE NON REG'D",
+        "PartnersFeeNonRegistered",
+        "cash:fees",
+    ),
+    ("QUEBEC SALES TAX", "QuebecSalesTax", "cash:fees"),
+    ("REINVESTED DIVIDEND", "ReinvestmentDividend", "reinvest"),
+    ("RRSP CONTRIBUTION", "RRSPContribution", "cash"),
+    ("SEC TFR COSTS", "SecTfrCosts", "cash:fees"),
+    ("SELL", "Sell", "sell"),
+    ("SPIN OFF (FOREIGN)", "SpinOffForeign", "buy"),
+    ("SPIN OFF (US)", "SpinOffUS", "buy"),
+    ("STOCK SPLIT", "StockSplit", "buy"),
+    ("US CASH DIVIDEND", "USCashDividend", "cash:dividend:USD"),
+    (
+        "US SOURCE LONG TERM GAINS",
+        "USSourceLongTermGains",
+        "cash:capitalgains",
+    ),
+    (
+        "VIRIDIAN FEES NON REGISTD",
+        "ViridianFeesNonRegistered",
+        "cash:fees",
+    ),
+    ("MONTHLY INTEREST", "MonthlyInterest", "cash:interest"),
+    ("MF RETURN OF CAPITAL", "MFReturnOfCapital", "roc"),
+];
+
+///
+/// The templates by Tran Type: DEFAULT_TRAN_TYPES, overridden and extended
+/// by the `type,template` file `filepath` if any. A type of the file keeps
+/// the name of its default, else it is its own name. Invalid templates are
+/// reported together.
+///
+fn tran_templates(filepath: Option<&str>) -> Result<HashMap<String, TranTemplate>, Error> {
+    let mut res: HashMap<String, TranTemplate> = HashMap::new();
+    for (t, name, template) in DEFAULT_TRAN_TYPES {
+        let x = TranTemplate::parse(name, template).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid default template \"{}\"", template),
+            )
+        })?;
+        res.insert(t.to_string(), x);
+    }
+    let Some(f) = filepath else {
+        return Ok(res);
+    };
+    let mut invalid = vec![];
+    let mut rows: Vec<(String, String)> = load_tran_types(f)?.into_iter().collect();
+    rows.sort();
+    for (t, template) in rows {
+        let name = res.get(&t).map(|x| x.name.clone()).unwrap_or(t.clone());
+        match TranTemplate::parse(&name, &template) {
+            Some(x) => {
+                res.insert(t, x);
+            }
+            None => invalid.push(format!("{}: invalid template \"{}\"", t, template)),
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, invalid.join("\n")));
+    }
+    Ok(res)
+}
+
 #[derive(Debug, Deserialize)]
 struct HoldingRecord {
     #[serde(rename = "Client Name")]
@@ -393,15 +597,15 @@ struct HoldingRecord {
     #[serde(rename = "Quantity", with = "rj_decimal")]
     quantity: Decimal,
     #[serde(rename = "Price")]
-    _price: String,
+    price: String,
     #[serde(rename = "Fund")]
     fund: String,
     #[serde(rename = "Average Cost")]
     _average_cost: String,
     #[serde(rename = "Book Value", with = "rj_decimal")]
-    _book_value: Decimal,
+    book_value: Decimal,
     #[serde(rename = "Market Value")]
-    _market_value: String,
+    market_value: String,
     #[serde(rename = "Accrued Interest")]
     _accrued_interest: String,
     #[serde(rename = "G/L")]
@@ -413,27 +617,35 @@ struct HoldingRecord {
 }
 
 impl HoldingRecord {
+    fn owner<'a>(&self, owners: &'a SymbolsMap) -> Result<&'a str, Error> {
+        owners
+            .get(self.client_name.trim())
+            .map(|o| o.as_str())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("No owner for client name \"{}\"", self.client_name),
+                )
+            })
+    }
+
     fn store_balance(
         &self,
         bkdate: NaiveDate,
         currency: &str,
+        owners: &SymbolsMap,
         state: &mut LedgerState,
     ) -> Result<(), Error> {
-        let owner = match self.client_name.as_str() {
-            "ROBERT HUM" => "Stan",
-            "JESSICA DUBY" => "Jess",
-            "ROBERT/JESSICA HUM/DUBY" => "Joint",
-            _ => "UNKNOWN",
-        };
+        let owner = self.owner(owners)?;
         let acct = self.account_number.as_str();
 
         let cash = acct_cash!(owner, acct);
         let sec = acct_securities!(&owner, acct);
 
-        let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
+        let posno = state.next_statement_no();
 
         let v = if self.holding == "CASH" {
-            let cp_s = if self.fund != "" {
+            let cp_s = if !self.fund.is_empty() {
                 self.fund.clone()
             } else {
                 currency.to_string()
@@ -449,6 +661,8 @@ impl HoldingRecord {
                 account: cash,
                 quantity: Some(self.quantity),
                 commodity: Some(cp_s),
+                tolerance: None,
+                raw: None,
             }
         } else {
             let cp_s = self.symbol.clone();
@@ -462,62 +676,614 @@ impl HoldingRecord {
                 account: sec,
                 quantity: Some(self.quantity),
                 commodity: Some(cp_s),
+                tolerance: None,
+                raw: None,
             }
-            // TODO: add book value
         };
 
         state.verifications.push(v);
 
         Ok(())
     }
+
+    ///
+    /// A transaction the day before `bkdate` opening the position from the
+    /// owner's opening balances, so that the balance assertion holds: a
+    /// security at its book value as its total cost, cash at its quantity
+    ///
+    fn store_opening(
+        &self,
+        bkdate: NaiveDate,
+        currency: &str,
+        owners: &SymbolsMap,
+        state: &mut LedgerState,
+    ) -> Result<(), Error> {
+        if self.quantity.is_zero() {
+            return Ok(());
+        }
+        let owner = self.owner(owners)?;
+        let acct = self.account_number.as_str();
+        let opening = acct_opening!(owner);
+
+        let posts = if self.holding == "CASH" {
+            let cp_s = if !self.fund.is_empty() {
+                self.fund.clone()
+            } else {
+                currency.to_string()
+            };
+            vec![
+                (acct_cash!(owner, acct), (self.quantity, cp_s.clone()), None),
+                (opening, (-self.quantity, cp_s), None),
+            ]
+        } else {
+            let cost = if self.quantity.is_sign_negative() {
+                -self.book_value.abs()
+            } else {
+                self.book_value.abs()
+            };
+            vec![
+                (
+                    acct_securities!(owner, acct),
+                    (self.quantity, self.symbol.clone()),
+                    Some((cost, currency.to_string())),
+                ),
+                (opening, (-cost, currency.to_string()), None),
+            ]
+        };
+
+        let transno = state.next_statement_no();
+        state.transactions.push(HeaderParams {
+            statement_no: transno,
+            file_no: 0u32,
+            start: 0u32,
+            end: 0u32,
+            date: bkdate.pred_opt().unwrap_or(bkdate),
+            flag: TRANSACTION_FLAG.to_string(),
+            narration: format!("Opening:{}", self.symbol),
+            tags: None,
+            raw: None,
+        });
+        for (account, (q, c), tc) in posts {
+            let posno = state.next_statement_no();
+            let (tc_quantity, tc_commodity) = match tc {
+                None => (Some(q), Some(c.clone())),
+                Some((tq, tc)) => (Some(tq), Some(tc)),
+            };
+            state.postings.push(PostingParams {
+                statement_no: posno,
+                transaction_no: transno,
+                file_no: 0u32,
+                start: 0u32,
+                end: 0u32,
+                account,
+                cp_quantity: Some(q),
+                cp_commodity: Some(c),
+                tc_quantity,
+                tc_commodity,
+                per_unit: false,
+                flag: None,
+                comment: None,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Price of the holding from the Price column, or Market Value / Quantity
+    fn store_price(
+        &self,
+        bkdate: NaiveDate,
+        currency: &str,
+        state: &mut LedgerState,
+    ) -> Result<(), Error> {
+        if self.holding == "CASH" || self.quantity.is_zero() {
+            return Ok(());
+        }
+        let price = match parse_amount(&self.price) {
+            Some(p) => p,
+            None => match parse_amount(&self.market_value) {
+                Some(mv) => mv / self.quantity,
+                None => return Ok(()),
+            },
+        };
+
+        let posno = state.next_statement_no();
+        state.prices.push(PriceParams {
+            statement_no: posno,
+            file_no: 0u32,
+            start: 0u32,
+            end: 0u32,
+            date: bkdate,
+            commodity: self.symbol.clone(),
+            price,
+            currency: currency.to_string(),
+            raw: None,
+        });
+
+        Ok(())
+    }
+}
+
+fn parse_amount(s: &str) -> Option<Decimal> {
+    let s = s.replace("$", "").replace(",", "");
+    Decimal::from_str(s.trim()).ok()
 }
 
+///
+/// The transactions of the activities, posted to the account of each in
+/// `accounts` by the template of their Tran Type from tran_templates with
+/// `tran_types_filepath`, their securities by `symbols`. An activity of a
+/// type without one is posted as a transfer to TODO, named after its type
+/// as written, to categorize by hand. A security not in `symbols` is posted
+/// as its `unknown` policy says, and added to its missing descriptions. The
+/// currency sub-accounts of the cash are opened the day of their first
+/// activity. Returns those activities, those posting nothing and the rows
+/// that could not be read as import errors.
+///
 pub fn process_activites(
     filepath: &str,
-    acct: &str,
-    owner: &str,
+    accounts: &ActivityAccounts,
     currency: &str,
-    symbol_filepath: &str,
+    symbols: &mut SymbolsTable,
+    tran_types_filepath: Option<&str>,
     state: &mut LedgerState,
-) -> Result<(), Error> {
-    let symbols = load_symbols(symbol_filepath.to_string())?;
-    let mut rdr = csv::ReaderBuilder::new()
-        .delimiter(b',')
-        .quoting(true)
-        .from_path(filepath)?;
-    for result in rdr.deserialize::<TransRecord>() {
-        match result {
-            Ok(t) => {
-                t.store_transaction(acct, owner, currency, &symbols, state)?;
-            }
-            Err(e) => {
-                println!("{:?}\n", e);
+) -> Result<ImportErrors, Error> {
+    let templates = tran_templates(tran_types_filepath)?;
+    let mut sub_accounts: HashMap<String, String> = HashMap::new();
+    let errors = read_records(filepath, |t: TransRecord| {
+        let mut reasons = vec![];
+        let template = match templates.get(t.tran_types.trim()) {
+            Some(template) => template.clone(),
+            None => {
+                reasons.push(format!(
+                    "unknown Tran Type \"{}\", posted to TODO",
+                    t.tran_types
+                ));
+                TranTemplate {
+                    name: t.tran_types.trim().to_string(),
+                    action: Action::Transfer,
+                    role: Role::Todo,
+                    currency: None,
+                }
             }
+        };
+        if t.needs_symbol(template.action)
+            && let Some(reason) = symbols.resolve(&t.description)?
+        {
+            reasons.push(reason);
+        }
+        if let Some(c) = template.currency.as_deref()
+            && accounts.currency_cash
+            && c != currency
+        {
+            sub_accounts.insert(t.cash_account(accounts, currency, c)?, c.to_string());
+        }
+        if let Some(reason) = t.store_transaction(accounts, currency, symbols, &template, state)? {
+            reasons.push(reason);
+        }
+        Ok((!reasons.is_empty()).then(|| reasons.join("; ")))
+    })?;
+    if accounts.currency_cash {
+        store_opens(&sub_accounts, state);
+    }
+    Ok(errors)
+}
+
+///
+/// Opens the accounts of `sub_accounts`, each restricted to its currency, the
+/// day of the first transaction posting to it
+///
+fn store_opens(sub_accounts: &HashMap<String, String>, state: &mut LedgerState) {
+    let dates: HashMap<u32, NaiveDate> = state
+        .transactions
+        .iter()
+        .map(|x| (x.statement_no, x.date))
+        .collect();
+    let mut first: BTreeMap<String, NaiveDate> = BTreeMap::new();
+    for p in state.postings.iter() {
+        if sub_accounts.contains_key(&p.account)
+            && let Some(d) = dates.get(&p.transaction_no)
+        {
+            let e = first.entry(p.account.clone()).or_insert(*d);
+            *e = (*e).min(*d);
+        }
+    }
+    for (account, date) in first {
+        let posno = state.next_statement_no();
+        state.verifications.push(VerificationParams {
+            statement_no: posno,
+            file_no: 0u32,
+            start: 0u32,
+            end: 0u32,
+            date,
+            action: OPEN_ACTION,
+            commodity: sub_accounts.get(&account).cloned(),
+            account,
+            quantity: None,
+            tolerance: None,
+            raw: None,
+        });
+    }
+}
+
+///
+/// The activities of `filepath` whose security, as their Tran Type posts
+/// them, has a description not in `symbols`, as import errors, without
+/// posting anything
+///
+pub fn check_symbols(
+    filepath: &str,
+    symbols: &SymbolsTable,
+    tran_types_filepath: Option<&str>,
+) -> Result<ImportErrors, Error> {
+    let templates = tran_templates(tran_types_filepath)?;
+    read_records(filepath, |t: TransRecord| {
+        let action = templates
+            .get(t.tran_types.trim())
+            .map(|x| x.action)
+            .unwrap_or(Action::Transfer);
+        if t.needs_symbol(action) && symbols.ticker(&t.description).is_none() {
+            return Ok(Some(format!(
+                "description \"{}\" not in the symbols file",
+                t.description.trim()
+            )));
         }
+        Ok(None)
+    })
+}
+
+/// The owners of the client names in the `client,owner` file `owners_filepath`, else default_owners
+fn owners(owners_filepath: Option<&str>) -> Result<SymbolsMap, Error> {
+    match owners_filepath {
+        Some(f) => load_owners(f),
+        None => Ok(default_owners()),
     }
-    Ok(())
 }
 
+/// The owners of the client names of the holdings when no owners file is given
+fn default_owners() -> SymbolsMap {
+    [
+        ("ROBERT HUM", "Stan"),
+        ("JESSICA DUBY", "Jess"),
+        ("ROBERT/JESSICA HUM/DUBY", "Joint"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
+
+///
+/// Balance assertions for the holdings at `bkdate`, price directives when
+/// `prices` is set and the transactions opening the holdings at their book
+/// value when `opening` is. The owner of each holding, the component of its
+/// accounts, is that of its client name in the `client,owner` file
+/// `owners_filepath`, else in default_owners; a client name without one is
+/// an error.
+///
 pub fn compile_holdings(
     filepath: &str,
     bkdate: NaiveDate,
     currency: &str,
+    prices: bool,
+    opening: bool,
+    owners_filepath: Option<&str>,
     state: &mut LedgerState,
-) -> Result<(), Error> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .delimiter(b',')
-        .quoting(true)
-        .from_path(filepath)?;
-
-    for result in rdr.deserialize::<HoldingRecord>() {
-        match result {
-            Ok(t) => {
-                t.store_balance(bkdate, currency, state)?;
-            }
-            Err(e) => {
-                println!("{:?}\n", e);
-            }
+) -> Result<ImportErrors, Error> {
+    let owners = owners(owners_filepath)?;
+    read_records(filepath, |t: HoldingRecord| {
+        if opening {
+            t.store_opening(bkdate, currency, &owners, state)?;
+        }
+        t.store_balance(bkdate, currency, &owners, state)?;
+        if prices {
+            t.store_price(bkdate, currency, state)?;
         }
+        Ok(None)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rj_core::temp_csv;
+
+    const HEADER: &str = "Processed,Settled,Tran Types,Description,Price,Quantity,Amount\n";
+
+    /// The import of the activities `rows` to account 123 of owner OWN
+    fn import(name: &str, rows: &str) -> (ImportErrors, LedgerState) {
+        let accounts = ActivityAccounts::new("123", "OWN", None).unwrap();
+        import_to(name, &format!("{}{}", HEADER, rows), &accounts)
+    }
+
+    /// The import of the activities file `contents` to `accounts`
+    fn import_to(
+        name: &str,
+        contents: &str,
+        accounts: &ActivityAccounts,
+    ) -> (ImportErrors, LedgerState) {
+        let f = temp_csv(name, contents);
+        let mut symbols = SymbolsTable::default();
+        let mut state = LedgerState::default();
+        let errors =
+            process_activites(&f, accounts, "CAD", &mut symbols, None, &mut state).unwrap();
+        (errors, state)
+    }
+
+    #[test]
+    fn unparseable_date_is_an_import_error() {
+        let (errors, state) = import(
+            "bad-date",
+            "2024-01-02,2024-13-45,CASH RECEIPT,Deposit,0,0,100.00\n\
+             2024-01-03,2024-01-03,CASH RECEIPT,Deposit,0,0,50.00\n",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors.errors[0].line, 2);
+        assert_eq!(
+            errors.errors[0].reason,
+            "skipped: unparseable date \"2024-13-45\""
+        );
+        assert_eq!(state.transactions.len(), 1);
+        assert_eq!(state.transactions[0].date.to_string(), "2024-01-03");
+    }
+
+    #[test]
+    fn viridian_fees_are_named_as_spelled() {
+        let (errors, state) = import(
+            "viridian",
+            "2024-01-02,2024-01-02,VIRIDIAN FEES NON REGISTD,Fee,0,0,-12.50\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            state.transactions[0].narration,
+            "ViridianFeesNonRegistered:Fee"
+        );
+    }
+
+    #[test]
+    fn foreign_cash_goes_to_an_opened_currency_sub_account() {
+        let mut accounts = ActivityAccounts::new("123", "OWN", None).unwrap();
+        accounts.currency_cash = true;
+        let rows = "2024-01-02,2024-01-02,US CASH DIVIDEND,Div,0,0,5.00\n\
+                    2024-01-03,2024-01-03,CASH RECEIPT,Deposit,0,0,100.00\n";
+        let (errors, state) = import_to("currency-cash", &format!("{}{}", HEADER, rows), &accounts);
+        assert!(errors.is_empty());
+        let cash: Vec<(&str, Option<&str>)> = state
+            .postings
+            .iter()
+            .filter(|p| p.account.contains(":Cash"))
+            .map(|p| (p.account.as_str(), p.cp_commodity.as_deref()))
+            .collect();
+        assert_eq!(
+            cash,
+            [
+                ("Assets:Investments:OWN:123:Cash:USD", Some("USD")),
+                ("Assets:Investments:OWN:123:Cash", Some("CAD")),
+            ]
+        );
+        assert_eq!(state.verifications.len(), 1);
+        let open = &state.verifications[0];
+        assert_eq!(open.action, OPEN_ACTION);
+        assert_eq!(open.account, "Assets:Investments:OWN:123:Cash:USD");
+        assert_eq!(open.commodity.as_deref(), Some("USD"));
+        assert_eq!(open.date.to_string(), "2024-01-02");
+    }
+
+    #[test]
+    fn rows_of_several_accounts_post_to_their_own() {
+        let owners = temp_csv("owners", "client,owner\nJANE DOE,Jane\n");
+        let accounts = ActivityAccounts::new("123", "OWN", Some(&owners)).unwrap();
+        let contents = "Processed,Settled,Tran Types,Description,Price,Quantity,Amount,\
+                        Account Number,Client Name\n\
+                        2024-01-02,2024-01-02,CASH RECEIPT,Deposit,0,0,100.00,456,JANE DOE\n\
+                        2024-01-03,2024-01-03,CASH RECEIPT,Deposit,0,0,50.00,,\n";
+        let (errors, state) = import_to("several", contents, &accounts);
+        assert!(errors.is_empty());
+        let cash: Vec<&str> = state
+            .postings
+            .iter()
+            .filter(|p| p.account.ends_with(":Cash"))
+            .map(|p| p.account.as_str())
+            .collect();
+        assert_eq!(
+            cash,
+            [
+                "Assets:Investments:Jane:456:Cash",
+                "Assets:Investments:OWN:123:Cash"
+            ]
+        );
+
+        // A client name of no owner fails the import at its row
+        let f = temp_csv("no-owner", &contents.replace("JANE DOE", "JOHN ROE"));
+        let mut symbols = SymbolsTable::default();
+        let mut state = LedgerState::default();
+        let e =
+            process_activites(&f, &accounts, "CAD", &mut symbols, None, &mut state).unwrap_err();
+        assert!(
+            e.to_string()
+                .ends_with(":2: No owner for client name \"JOHN ROE\"")
+        );
+    }
+
+    #[test]
+    fn return_of_capital_lowers_the_cost_of_the_security() {
+        let (errors, state) = import(
+            "roc",
+            "2024-01-02,2024-01-02,MF RETURN OF CAPITAL,Big Fund,0,0,12.34\n",
+        );
+        // The security is not in the symbols file
+        assert_eq!(errors.len(), 1);
+        let postings: Vec<String> = state
+            .postings
+            .iter()
+            .map(|p| {
+                format!(
+                    "{} {:?} {:?} {:?} {:?}",
+                    p.account, p.cp_quantity, p.cp_commodity, p.tc_quantity, p.tc_commodity
+                )
+            })
+            .collect();
+        assert_eq!(
+            postings,
+            [
+                "Assets:Investments:OWN:123:Securities Some(0) Some(\"UNKNOWNSEC\") \
+                 Some(-12.340) Some(\"CAD\")",
+                "Assets:Investments:OWN:123:Cash Some(12.340) Some(\"CAD\") \
+                 Some(12.340) Some(\"CAD\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_tran_types_are_posted_to_todo() {
+        let (errors, state) = import(
+            "unknown-type",
+            "2024-01-02,2024-01-02,LOTTERY WIN,Prize,0,0,20.00\n",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors.errors[0].reason,
+            "unknown Tran Type \"LOTTERY WIN\", posted to TODO"
+        );
+        assert_eq!(state.transactions[0].narration, "LOTTERY WIN:Prize");
+        let postings: Vec<(&str, Option<Decimal>)> = state
+            .postings
+            .iter()
+            .map(|p| (p.account.as_str(), p.cp_quantity))
+            .collect();
+        assert_eq!(
+            postings,
+            [
+                (
+                    "Assets:Investments:OWN:123:Cash",
+                    Some(Decimal::new(20000, 3))
+                ),
+                ("Assets:Investments:OWN:TODO", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn tran_types_file_overrides_and_extends_the_templates() {
+        let f = temp_csv(
+            "tran-types",
+            "type,template\nBUY,skip\nLOTTERY WIN,cash:interest:USD\n",
+        );
+        let templates = tran_templates(Some(&f)).unwrap();
+        assert_eq!(templates["BUY"].name, "Buy");
+        assert_eq!(templates["BUY"].action, Action::Skip);
+        assert_eq!(
+            templates["LOTTERY WIN"],
+            TranTemplate {
+                name: "LOTTERY WIN".to_string(),
+                action: Action::Cash,
+                role: Role::Interest,
+                currency: Some("USD".to_string()),
+            }
+        );
+        assert_eq!(templates["SELL"].role, Role::GainLoss);
+
+        let f = temp_csv(
+            "tran-types-invalid",
+            "type,template\nBUY,borrow\nSELL,sell:lender\n",
+        );
+        let e = tran_templates(Some(&f)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "BUY: invalid template \"borrow\"\nSELL: invalid template \"sell:lender\""
+        );
+    }
+
+    const HOLDINGS_HEADER: &str = "Client Name,Client Id,Account Nickname,Account Number,\
+                                   Asset Category,Industry,Symbol,Holding,Quantity,Price,Fund,\
+                                   Average Cost,Book Value,Market Value,Accrued Interest,G/L,\
+                                   G/L (%),Percentage of Assets\n";
+
+    /// The holdings `rows` imported at 2024-01-31 with opening transactions
+    fn holdings(name: &str, rows: &str, owners: Option<&str>) -> Result<LedgerState, Error> {
+        let f = temp_csv(name, &format!("{}{}", HOLDINGS_HEADER, rows));
+        let bkdate = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let mut state = LedgerState::default();
+        compile_holdings(&f, bkdate, "CAD", false, true, owners, &mut state)?;
+        Ok(state)
+    }
+
+    #[test]
+    fn holdings_are_opened_at_their_book_value_the_day_before() {
+        let state = holdings(
+            "holdings-opening",
+            "ROBERT HUM,1,,456,Equity,,VFV,Vanguard,10,110.00,,100.00,1000.00,1100.00,,,,\n\
+             ROBERT HUM,1,,456,Cash,,,CASH,25.50,,,,25.50,25.50,,,,\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(state.transactions.len(), 2);
+        assert_eq!(state.transactions[0].date.to_string(), "2024-01-30");
+        assert_eq!(state.transactions[0].narration, "Opening:VFV");
+        let postings: Vec<String> = state
+            .postings
+            .iter()
+            .map(|p| {
+                let amount = format!(
+                    "{} {} {}",
+                    p.account,
+                    p.cp_quantity.unwrap(),
+                    p.cp_commodity.as_deref().unwrap()
+                );
+                match p.tc_commodity == p.cp_commodity {
+                    true => amount,
+                    false => format!(
+                        "{} @@ {} {}",
+                        amount,
+                        p.tc_quantity.unwrap(),
+                        p.tc_commodity.as_deref().unwrap()
+                    ),
+                }
+            })
+            .collect();
+        assert_eq!(
+            postings,
+            [
+                "Assets:Investments:Stan:456:Securities 10 VFV @@ 1000.00 CAD",
+                "Equity:Investments:Stan:Opening-Balances -1000.00 CAD",
+                "Assets:Investments:Stan:456:Cash 25.50 CAD",
+                "Equity:Investments:Stan:Opening-Balances -25.50 CAD",
+            ]
+        );
+        let balances: Vec<(&str, Option<Decimal>)> = state
+            .verifications
+            .iter()
+            .map(|v| (v.account.as_str(), v.quantity))
+            .collect();
+        assert_eq!(
+            balances,
+            [
+                (
+                    "Assets:Investments:Stan:456:Securities",
+                    Some(Decimal::new(10, 0))
+                ),
+                (
+                    "Assets:Investments:Stan:456:Cash",
+                    Some(Decimal::new(2550, 2))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn holdings_are_owned_by_the_owners_file() {
+        let owners = temp_csv("holdings-owners", "client,owner\nJANE DOE,Jane\n");
+        let row = "JANE DOE,1,,456,Cash,,,CASH,25.50,,,,25.50,25.50,,,,\n";
+        let state = holdings("holdings-owned", row, Some(&owners)).unwrap();
+        assert_eq!(
+            state.verifications[0].account,
+            "Assets:Investments:Jane:456:Cash"
+        );
+
+        // Not one of the default owners
+        let e = holdings("holdings-unowned", row, None).unwrap_err();
+        assert!(
+            e.to_string()
+                .ends_with(":2: No owner for client name \"JANE DOE\"")
+        );
     }
-    Ok(())
 }
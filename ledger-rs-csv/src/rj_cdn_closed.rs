@@ -1,7 +1,6 @@
 use std::{
     fs::{File, OpenOptions},
     io::{Error, Write},
-    sync::atomic::Ordering,
 };
 
 use chrono::NaiveDate;
@@ -9,13 +8,13 @@ use rust_decimal::Decimal;
 use serde::Deserialize;
 
 use ledger_rs_core::{
-    core::{HeaderParams, PostingParams},
+    core::{HeaderParams, PostingParams, TRANSACTION_FLAG},
     state::ledgerstate::LedgerState,
 };
 
 use crate::{
     rj_common::{acct_cash, acct_dividend, acct_fees, acct_gainloss, acct_securities, acct_todo},
-    rj_core::{InterPost, Position},
+    rj_core::{ImportErrors, InterPost, Position, UNKNOWN_SEC, read_records},
     rj_decimal::{self, reverse_sign},
 };
 
@@ -57,25 +56,25 @@ struct ClosedAcctTransRecord {
 
 impl ClosedAcctTransRecord {
     fn get_cash_position(&self, currency: &str) -> Position {
-        let mut amt = self.amount.clone();
+        let mut amt = self.amount;
         amt.rescale(3);
         let amt = reverse_sign(&amt);
         (amt, String::from(currency))
     }
 
     fn get_sec_position(&self) -> Position {
-        let sec = if self.symbol == "" {
-            String::from("UNKNOWNSEC")
+        let sec = if self.symbol.is_empty() {
+            String::from(UNKNOWN_SEC)
         } else {
             self.symbol.clone()
         };
-        let mut q = self.quantity.clone();
+        let mut q = self.quantity;
         q.rescale(3);
         (q, sec)
     }
 
     fn get_cost(&self, currency: &str) -> Position {
-        let mut cost = self.cost.clone();
+        let mut cost = self.cost;
         cost.rescale(3);
         cost.set_sign_positive(true);
         (cost, String::from(currency))
@@ -84,7 +83,7 @@ impl ClosedAcctTransRecord {
     fn transfer(&self, currency: &str, cash: &str, sec: &str, todo: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
-        if self.symbol == "" {
+        if self.symbol.is_empty() {
             let cash_p = self.get_cash_position(currency);
             res.push((String::from(cash), Some(cash_p), None));
         } else {
@@ -99,7 +98,7 @@ impl ClosedAcctTransRecord {
     fn buy(&self, currency: &str, cash: &str, sec: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
-        if self.symbol != "" {
+        if !self.symbol.is_empty() {
             let cash_p = self.get_cash_position(currency);
             let sec_p = self.get_sec_position();
             let cost_p = self.get_cost(currency);
@@ -142,7 +141,7 @@ impl ClosedAcctTransRecord {
     fn sell(&self, currency: &str, cash: &str, sec: &str, gl: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
-        if self.symbol != "" {
+        if !self.symbol.is_empty() {
             let cash_p = self.get_cash_position(currency);
             let sec_p = self.get_sec_position();
             res.push((String::from(sec), Some(sec_p), None));
@@ -159,7 +158,7 @@ impl ClosedAcctTransRecord {
         owner: &str,
         currency: &str,
         state: &mut LedgerState,
-    ) -> Result<(), Error> {
+    ) -> Result<Option<String>, Error> {
         let cash = acct_cash!(owner, acct);
         let sec = acct_securities!(owner, acct);
         let todo = acct_todo!(owner);
@@ -168,7 +167,12 @@ impl ClosedAcctTransRecord {
         let gl = acct_gainloss!(owner);
 
         let description = self.description.clone();
-        let bkdate = NaiveDate::parse_from_str(&self.settled, "%Y-%m-%d").unwrap();
+        let Ok(bkdate) = NaiveDate::parse_from_str(&self.settled, "%Y-%m-%d") else {
+            return Ok(Some(format!(
+                "skipped: unparseable date {:?}",
+                self.settled
+            )));
+        };
         let t_type = &self.tran_type;
         let narration = format!("{t_type} - {description}").trim().to_string();
         let symbol = self.symbol.trim().to_string();
@@ -190,26 +194,28 @@ impl ClosedAcctTransRecord {
             ClosedTranType::VFR => self.cash_transaction(currency, &cash, &fees), // Virdian Fees Registered
         };
 
-        let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
-        if posts.len() == 0 {
-            // Store errors in parsing file
+        let posno = state.next_statement_no();
+        if posts.is_empty() {
+            return Ok(Some(format!("{} posts nothing", t_type)));
         } else {
-            let transno = posno.clone();
+            let transno = posno;
             let th = HeaderParams {
                 statement_no: transno,
                 file_no: 0u32,
                 start: 0u32,
                 end: 0u32,
                 date: bkdate,
-                narration: narration,
+                flag: TRANSACTION_FLAG.to_string(),
+                narration,
                 tags: None,
+                raw: None,
             };
             state.transactions.push(th);
 
-            posts
+            let postings: Vec<PostingParams> = posts
                 .into_iter()
                 .map(|(acct, cp, tc)| {
-                    let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
+                    let posno = state.next_statement_no();
                     let (cp_quantity, cp_commodity) = match cp {
                         None => (None, None),
                         Some((q, c)) => (Some(q), Some(c)),
@@ -225,20 +231,24 @@ impl ClosedAcctTransRecord {
                         start: 0u32,
                         end: 0u32,
                         account: acct,
-                        cp_quantity: cp_quantity,
-                        cp_commodity: cp_commodity,
-                        tc_quantity: tc_quantity,
-                        tc_commodity: tc_commodity,
+                        cp_quantity,
+                        cp_commodity,
+                        tc_quantity,
+                        tc_commodity,
+                        per_unit: false,
+                        flag: None,
+                        comment: None,
                     }
                 })
-                .for_each(|x| state.postings.push(x));
+                .collect();
+            state.postings.extend(postings);
         }
 
-        if symbol != "" {
-            write!(commodity, "{}", format!("{symbol},{description}\n"))?;
+        if !symbol.is_empty() {
+            writeln!(commodity, "{symbol},{description}")?;
         }
 
-        Ok(())
+        Ok(None)
     }
 }
 
@@ -282,6 +292,7 @@ impl std::fmt::Display for ClosedTranType {
     }
 }
 
+/// The transactions of the activities, returning those skipped as import errors
 pub fn process_closed_acct_trans(
     filepath: &str,
     acct: &str,
@@ -289,25 +300,12 @@ pub fn process_closed_acct_trans(
     currency: &str,
     commodity_filepath: &str,
     state: &mut LedgerState,
-) -> Result<(), Error> {
+) -> Result<ImportErrors, Error> {
     let mut commodity_file = OpenOptions::new()
         .append(true)
         .create(true)
         .open(commodity_filepath)?;
-    let mut rdr = csv::ReaderBuilder::new()
-        .delimiter(b',')
-        .quoting(true)
-        .from_path(filepath)
-        .unwrap();
-    for result in rdr.deserialize::<ClosedAcctTransRecord>() {
-        match result {
-            Ok(t) => {
-                t.store_closed_transaction(&mut commodity_file, acct, owner, currency, state)?;
-            }
-            Err(e) => {
-                println!("{:?}\n", e);
-            }
-        }
-    }
-    Ok(())
+    read_records(filepath, |t: ClosedAcctTransRecord| {
+        t.store_closed_transaction(&mut commodity_file, acct, owner, currency, state)
+    })
 }
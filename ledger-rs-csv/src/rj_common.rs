@@ -10,6 +10,12 @@ macro_rules! acct_cash {
     };
 }
 
+macro_rules! acct_cash_currency {
+    ($owner:expr, $acct:expr, $currency:expr) => {
+        format!("Assets:Investments:{}:{}:Cash:{}", $owner, $acct, $currency)
+    };
+}
+
 macro_rules! acct_securities {
     ($owner:expr, $acct:expr) => {
         format!("Assets:Investments:{}:{}:Securities", $owner, $acct)
@@ -79,8 +85,21 @@ macro_rules! acct_interest {
     };
 }
 
+macro_rules! acct_returnofcapital {
+    ($owner:expr) => {
+        format!("Income:Investments:{}:ReturnOfCapital", $owner)
+    };
+}
+
+macro_rules! acct_opening {
+    ($owner:expr) => {
+        format!("Equity:Investments:{}:Opening-Balances", $owner)
+    };
+}
+
 pub(crate) use acct_capgains;
 pub(crate) use acct_cash;
+pub(crate) use acct_cash_currency;
 pub(crate) use acct_distribution;
 pub(crate) use acct_dividend;
 pub(crate) use acct_fees;
@@ -88,6 +107,8 @@ pub(crate) use acct_foreigntax;
 pub(crate) use acct_gainloss;
 pub(crate) use acct_interest;
 pub(crate) use acct_longtermcapgains;
+pub(crate) use acct_opening;
+pub(crate) use acct_returnofcapital;
 pub(crate) use acct_securities;
 pub(crate) use acct_shorttermcapgains;
 pub(crate) use acct_todo;
@@ -1,4 +1,93 @@
+use std::io::Error;
+
+use csv::StringRecord;
 use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
 
 pub type Position = (Decimal, String);
 pub type InterPost = (String, Option<Position>, Option<Position>);
+
+/// The commodity posted for a security the importers cannot name
+pub const UNKNOWN_SEC: &str = "UNKNOWNSEC";
+
+/// A row of a CSV import skipped or posted to TODO: its line, the row as read and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportError {
+    pub line: u64,
+    pub record: String,
+    pub reason: String,
+}
+
+/// The rows of a CSV import to look at, in file order
+#[derive(Debug, Default)]
+pub struct ImportErrors {
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportErrors {
+    pub fn push(&mut self, record: &StringRecord, reason: impl Into<String>) {
+        self.errors.push(ImportError {
+            line: record.position().map(|p| p.line()).unwrap_or(0),
+            record: record
+                .iter()
+                .map(|x| match x.contains([',', '"']) {
+                    true => format!("\"{}\"", x.replace('"', "\"\"")),
+                    false => x.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+///
+/// Reads the rows of the CSV file `filepath` as T and stores each with
+/// `store`, which returns why a row needs a look if it does. The rows that
+/// do not read as T are skipped. Returns both as import errors.
+///
+pub(crate) fn read_records<T, F>(filepath: &str, mut store: F) -> Result<ImportErrors, Error>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<Option<String>, Error>,
+{
+    let mut errors = ImportErrors::default();
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .quoting(true)
+        .flexible(true)
+        .from_path(filepath)?;
+    let headers = rdr.headers()?.clone();
+    for result in rdr.records() {
+        let record = result?;
+        match record.deserialize::<T>(Some(&headers)) {
+            Ok(t) => {
+                let reason = store(t).map_err(|e| {
+                    let line = record.position().map(|p| p.line()).unwrap_or(0);
+                    Error::new(e.kind(), format!("{}:{}: {}", filepath, line, e))
+                })?;
+                if let Some(reason) = reason {
+                    errors.push(&record, reason);
+                }
+            }
+            Err(e) => errors.push(&record, format!("skipped: {}", e)),
+        }
+    }
+    Ok(errors)
+}
+
+/// `contents` written to a file of the temp dir named after `name`, returning its path
+#[cfg(test)]
+pub(crate) fn temp_csv(name: &str, contents: &str) -> String {
+    let f = std::env::temp_dir().join(format!("ledger-rs-{}-{}.csv", name, std::process::id()));
+    std::fs::write(&f, contents).unwrap();
+    f.to_string_lossy().into_owned()
+}
@@ -15,7 +15,7 @@ where
 }
 
 pub fn reverse_sign(d: &Decimal) -> Decimal {
-    let mut res = d.clone();
+    let mut res = *d;
     if res.is_sign_positive() {
         res.set_sign_negative(true);
     } else {
@@ -1,20 +1,350 @@
-use std::{collections::HashMap, io::Error};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Error, ErrorKind},
+    path::Path,
+};
 
-pub type SymbolsMap = HashMap<String, String>;
+use ledger_rs_core::mapping::MappingTable;
+pub use ledger_rs_core::mapping::SymbolsMap;
+use ledger_rs_core::parse::is_valid_commodity;
 
-pub fn load_symbols(filename: String) -> Result<SymbolsMap, Error> {
-    let mut map = HashMap::new();
+use crate::rj_core::UNKNOWN_SEC;
+
+const SYMBOLS_COMMENT: &str = "#";
+
+pub const UNKNOWN_SYMBOLS_PLACEHOLDER: &str = "unknown";
+pub const UNKNOWN_SYMBOLS_DERIVE: &str = "derive";
+pub const UNKNOWN_SYMBOLS_FAIL: &str = "fail";
+
+/// Beancount commodities are at most this long
+const MAX_TICKER_LEN: usize = 24;
+
+/// The columns of the symbols file, in the order of a file without a header
+const SYMBOLS_COLUMNS: [&str; 4] = ["description", "ticker", "currency", "type"];
+
+/// A security of the symbols file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    /// The commodity the security is posted as
+    pub ticker: String,
+    /// The currency it trades in, if given
+    pub currency: Option<String>,
+    /// What it is, such as `ETF` or `STOCK`, if given
+    pub kind: Option<String>,
+}
+
+/// What to post for a security whose description is not in the symbols file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UnknownSymbols {
+    /// UNKNOWN_SEC
+    #[default]
+    Placeholder,
+    /// A ticker made of the description, as by derive_ticker
+    Derive,
+    /// Nothing, the import fails
+    Fail,
+}
+
+impl UnknownSymbols {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            UNKNOWN_SYMBOLS_PLACEHOLDER => Ok(Self::Placeholder),
+            UNKNOWN_SYMBOLS_DERIVE => Ok(Self::Derive),
+            UNKNOWN_SYMBOLS_FAIL => Ok(Self::Fail),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown symbols policy: {}", name),
+            )),
+        }
+    }
+}
+
+/// The securities of the symbols file by the description the RJ activities give them
+#[derive(Debug, Default)]
+pub struct SymbolsTable {
+    pub symbols: HashMap<String, Symbol>,
+    /// What the file has that was ignored, such as duplicate descriptions
+    pub warnings: Vec<String>,
+    pub unknown: UnknownSymbols,
+    /// The descriptions looked up by resolve that the file does not have,
+    /// with the ticker posted for them
+    pub missing: BTreeMap<String, String>,
+}
+
+impl SymbolsTable {
+    pub fn ticker(&self, description: &str) -> Option<&str> {
+        self.symbols
+            .get(description.trim())
+            .map(|s| s.ticker.as_str())
+    }
+
+    ///
+    /// Makes sure `description` has a ticker, adding the one `unknown` gives
+    /// it if the file has none. Returns what was posted for the description
+    /// when not in the file, an error when `unknown` is Fail.
+    ///
+    pub fn resolve(&mut self, description: &str) -> Result<Option<String>, Error> {
+        let description = description.trim();
+        if let Some(ticker) = self.missing.get(description) {
+            return Ok(Some(unknown_symbol(description, ticker)));
+        }
+        if self.ticker(description).is_some() {
+            return Ok(None);
+        }
+        let ticker = match self.unknown {
+            UnknownSymbols::Placeholder => UNKNOWN_SEC.to_string(),
+            UnknownSymbols::Derive => derive_ticker(description),
+            UnknownSymbols::Fail => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("description \"{}\" not in the symbols file", description),
+                ));
+            }
+        };
+        self.symbols.insert(
+            description.to_string(),
+            Symbol {
+                ticker: ticker.clone(),
+                currency: None,
+                kind: None,
+            },
+        );
+        self.missing.insert(description.to_string(), ticker.clone());
+        Ok(Some(unknown_symbol(description, &ticker)))
+    }
+
+    ///
+    /// Writes the missing descriptions as a symbols file to `filename`, with
+    /// their derived tickers when derived and else without, to complete and
+    /// add to the symbols file
+    ///
+    pub fn write_missing(&self, filename: &str) -> Result<(), Error> {
+        let mut wtr = csv::Writer::from_path(filename)?;
+        wtr.write_record(["description", "ticker"])?;
+        for (description, ticker) in self.missing.iter() {
+            let ticker = match self.unknown {
+                UnknownSymbols::Derive => ticker.as_str(),
+                _ => "",
+            };
+            wtr.write_record([description.as_str(), ticker])?;
+        }
+        wtr.flush()
+    }
+}
+
+/// Why an activity is posted as `ticker`
+fn unknown_symbol(description: &str, ticker: &str) -> String {
+    format!(
+        "description \"{}\" not in the symbols file, posted as {}",
+        description, ticker
+    )
+}
+
+///
+/// A commodity made of `description`: its ASCII letters and digits in capitals,
+/// each run of other characters an `_`, cut to MAX_TICKER_LEN, so that
+/// `Vanguard S&P 500` is `VANGUARD_S_P_500`. UNKNOWN_SEC if nothing is left.
+///
+pub fn derive_ticker(description: &str) -> String {
+    let mut res = String::new();
+    for c in description.chars() {
+        if c.is_ascii_alphanumeric() {
+            res.push(c.to_ascii_uppercase());
+        } else if !res.is_empty() && !res.ends_with('_') {
+            res.push('_');
+        }
+    }
+    res.truncate(MAX_TICKER_LEN);
+    let res = res.trim_end_matches('_');
+    match is_valid_commodity(res) {
+        true => res.to_string(),
+        false => UNKNOWN_SEC.to_string(),
+    }
+}
+
+///
+/// Loads the `description,ticker,currency,type` CSV file of the securities,
+/// only the first two columns being required. A first row naming the columns
+/// gives their order, `commodity` standing for `ticker` as in the older two
+/// column files; without one they are in that order. Fields may be quoted
+/// and lines starting with `#` are comments. A description given again is a
+/// warning and keeps its first row; rows without a description or a valid
+/// ticker or currency are all reported together with their line numbers.
+///
+pub fn load_symbols(filename: &str) -> Result<SymbolsTable, Error> {
+    let mut table = SymbolsTable::default();
+    let mut lines: HashMap<String, u64> = HashMap::new();
+    let mut invalid = vec![];
     let mut rdr = csv::ReaderBuilder::new()
         .delimiter(b',')
-        .quoting(false)
+        .quoting(true)
         .has_headers(false)
-        .from_path(filename)
-        .unwrap();
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(filename)?;
+    // The column of each of SYMBOLS_COLUMNS, if any
+    let mut columns: Option<[Option<usize>; 4]> = None;
     for result in rdr.records() {
         let item = result?;
-        let k = item.get(0).unwrap().to_string();
-        let v = item.get(1).unwrap().to_string();
-        map.insert(k, v);
+        let line = item.position().map(|p| p.line()).unwrap_or(0);
+        if item.get(0).is_some_and(|k| k.starts_with(SYMBOLS_COMMENT)) {
+            continue;
+        }
+        let cols = match columns {
+            Some(c) => c,
+            None => {
+                let names: Vec<String> = item.iter().map(|x| x.to_ascii_lowercase()).collect();
+                let found = SYMBOLS_COLUMNS.map(|c| {
+                    names
+                        .iter()
+                        .position(|n| n == c || (c == "ticker" && n == "commodity"))
+                });
+                if found[0].is_some() {
+                    if found[1].is_none() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("line {}: the header has no ticker column", line),
+                        ));
+                    }
+                    columns = Some(found);
+                    continue;
+                }
+                let c = [Some(0), Some(1), Some(2), Some(3)];
+                columns = Some(c);
+                c
+            }
+        };
+        let get = |n: usize| cols[n].and_then(|c| item.get(c)).filter(|x| !x.is_empty());
+        let (Some(description), Some(ticker)) = (get(0), get(1)) else {
+            invalid.push(format!("line {}: expected description,ticker", line));
+            continue;
+        };
+        if !is_valid_commodity(ticker) {
+            invalid.push(format!("line {}: invalid ticker \"{}\"", line, ticker));
+            continue;
+        }
+        let currency = get(2);
+        if let Some(c) = currency
+            && !is_valid_commodity(c)
+        {
+            invalid.push(format!("line {}: invalid currency \"{}\"", line, c));
+            continue;
+        }
+        if let Some(prev) = lines.get(description) {
+            let kept = table.ticker(description).unwrap_or_default();
+            table.warnings.push(format!(
+                "line {}: duplicate description \"{}\" (first on line {}), kept {}",
+                line, description, prev, kept
+            ));
+            continue;
+        }
+        lines.insert(description.to_string(), line);
+        table.symbols.insert(
+            description.to_string(),
+            Symbol {
+                ticker: ticker.to_string(),
+                currency: currency.map(String::from),
+                kind: get(3).map(String::from),
+            },
+        );
+    }
+    if !invalid.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, invalid.join("\n")));
+    }
+    Ok(table)
+}
+
+pub fn load_tran_types(filename: &str) -> Result<SymbolsMap, Error> {
+    MappingTable::tran_types().load(Path::new(filename))
+}
+
+pub fn load_owners(filename: &str) -> Result<SymbolsMap, Error> {
+    MappingTable::owners().load(Path::new(filename))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rj_core::temp_csv;
+
+    #[test]
+    fn headered_symbols_give_their_columns_in_any_order() {
+        let f = temp_csv(
+            "symbols-header",
+            "# securities\n\
+             Type,Commodity,Description,Currency\n\
+             ETF,VFV,\"Vanguard S&P 500, CAD\",CAD\n\
+             STOCK,AAPL,Apple,USD\n\
+             STOCK,APPL,Apple,USD\n",
+        );
+        let table = load_symbols(&f).unwrap();
+        assert_eq!(
+            table.symbols.get("Vanguard S&P 500, CAD"),
+            Some(&Symbol {
+                ticker: "VFV".to_string(),
+                currency: Some("CAD".to_string()),
+                kind: Some("ETF".to_string()),
+            })
+        );
+        assert_eq!(table.ticker("Apple"), Some("AAPL"));
+        assert_eq!(
+            table.warnings,
+            ["line 5: duplicate description \"Apple\" (first on line 4), kept AAPL"]
+        );
+    }
+
+    #[test]
+    fn symbols_without_a_header_are_description_then_ticker() {
+        let f = temp_csv("symbols-plain", "Apple,AAPL\nVanguard,VFV,CAD\n");
+        let table = load_symbols(&f).unwrap();
+        assert_eq!(table.ticker("Apple"), Some("AAPL"));
+        assert_eq!(table.symbols["Vanguard"].currency.as_deref(), Some("CAD"));
+        assert_eq!(table.symbols["Apple"].currency, None);
+
+        let f = temp_csv("symbols-invalid", "Apple,aapl\nBank\nVanguard,VFV,cad\n");
+        let e = load_symbols(&f).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 1: invalid ticker \"aapl\"\n\
+             line 2: expected description,ticker\n\
+             line 3: invalid currency \"cad\""
+        );
+    }
+
+    #[test]
+    fn derived_tickers_are_valid_commodities() {
+        assert_eq!(derive_ticker("Vanguard S&P 500"), "VANGUARD_S_P_500");
+        assert_eq!(
+            derive_ticker("iShares Core Canadian Universe Bond Index ETF"),
+            "ISHARES_CORE_CANADIAN_UN"
+        );
+        assert_eq!(derive_ticker("  & "), UNKNOWN_SEC);
+    }
+
+    #[test]
+    fn unknown_descriptions_are_posted_by_the_policy() {
+        let mut table = SymbolsTable {
+            unknown: UnknownSymbols::Derive,
+            ..Default::default()
+        };
+        let why = "description \"Big Fund\" not in the symbols file, posted as BIG_FUND";
+        assert_eq!(table.resolve(" Big Fund ").unwrap().as_deref(), Some(why));
+        assert_eq!(table.ticker("Big Fund"), Some("BIG_FUND"));
+        // Each time it is posted, but only once missing
+        assert_eq!(table.resolve("Big Fund").unwrap().as_deref(), Some(why));
+        assert_eq!(table.missing.len(), 1);
+
+        let mut table = SymbolsTable::default();
+        table.resolve("Big Fund").unwrap();
+        assert_eq!(table.ticker("Big Fund"), Some(UNKNOWN_SEC));
+
+        let mut table = SymbolsTable {
+            unknown: UnknownSymbols::Fail,
+            ..Default::default()
+        };
+        assert!(table.resolve("Big Fund").is_err());
+        assert!(table.missing.is_empty());
+
+        assert!(UnknownSymbols::from_name("guess").is_err());
     }
-    Ok(map)
 }
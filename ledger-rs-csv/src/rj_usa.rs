@@ -1,8 +1,8 @@
-use std::{io::Error, sync::atomic::Ordering};
+use std::io::Error;
 
 use chrono::NaiveDate;
 use ledger_rs_core::{
-    core::{HeaderParams, PostingParams},
+    core::{HeaderParams, PostingParams, TRANSACTION_FLAG},
     state::ledgerstate::LedgerState,
 };
 use rust_decimal::Decimal;
@@ -13,7 +13,7 @@ use crate::{
         acct_cash, acct_dividend, acct_fees, acct_foreigntax, acct_gainloss, acct_longtermcapgains,
         acct_securities, acct_shorttermcapgains, acct_todo,
     },
-    rj_core::{InterPost, Position},
+    rj_core::{ImportErrors, InterPost, Position, UNKNOWN_SEC, read_records},
     rj_decimal,
 };
 
@@ -76,14 +76,14 @@ struct USTransactionRecord {
 
 impl USTransactionRecord {
     fn get_cash_position(&self, currency: &str) -> Position {
-        let mut amt = self.amount.clone();
+        let mut amt = self.amount;
         amt.rescale(3);
         (amt, String::from(currency))
     }
 
     fn get_sec_position(&self) -> Position {
-        let mut sec = if self.symbol == "" {
-            String::from("UNKNOWNSEC")
+        let mut sec = if self.symbol.is_empty() {
+            String::from(UNKNOWN_SEC)
         } else {
             self.symbol.clone()
         };
@@ -95,7 +95,7 @@ impl USTransactionRecord {
             Err(_) => {
                 let mut new_sec = "Error".to_string();
                 new_sec.push_str(&self.quantity.clone());
-                new_sec.push_str(&" ");
+                new_sec.push(' ');
                 new_sec.push_str(&sec);
                 sec = new_sec.to_owned();
                 Decimal::from(0)
@@ -114,7 +114,7 @@ impl USTransactionRecord {
     fn transfer(&self, currency: &str, cash: &str, sec: &str, todo: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
-        if self.symbol == "" {
+        if self.symbol.is_empty() {
             let cash_p = self.get_cash_position(currency);
             res.push((String::from(cash), Some(cash_p), None));
         } else {
@@ -129,7 +129,7 @@ impl USTransactionRecord {
     fn buy(&self, currency: &str, cash: &str, sec: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
-        if self.symbol != "" {
+        if !self.symbol.is_empty() {
             let cash_p = self.get_cash_position(currency);
             let sec_p = self.get_sec_position();
             let cost_p = self.get_cost(currency);
@@ -154,7 +154,7 @@ impl USTransactionRecord {
     fn sell(&self, currency: &str, cash: &str, sec: &str, gl: &str) -> Vec<InterPost> {
         let mut res: Vec<InterPost> = Vec::new();
 
-        if self.symbol != "" {
+        if !self.symbol.is_empty() {
             let cash_p = self.get_cash_position(currency);
             let (mut sec_q, sec_s) = self.get_sec_position();
             sec_q.set_sign_negative(true);
@@ -166,13 +166,14 @@ impl USTransactionRecord {
         res
     }
 
+    #[allow(clippy::if_same_then_else)]
     fn store_us_transaction(
         &self,
         acct: &str,
         owner: &str,
         currency: &str,
         state: &mut LedgerState,
-    ) -> Result<(), Error> {
+    ) -> Result<Option<String>, Error> {
         let description = &self.description;
 
         let cash = acct_cash!(owner, acct);
@@ -187,7 +188,7 @@ impl USTransactionRecord {
         let shorttermcapgains = acct_shorttermcapgains!(owner);
         let gl = acct_gainloss!(owner);
 
-        let mut posts = if description.starts_with("ADVISORY FEES")
+        let posts = if description.starts_with("ADVISORY FEES")
             || description.starts_with("ASSET BASED FEE")
             || description.starts_with("MAINTENANCE FEE")
         {
@@ -219,91 +220,81 @@ impl USTransactionRecord {
         } else if description.starts_with("YOUR ASSET TRANSFERRED") {
             self.transfer(currency, &cash, &sec, &todo)
         } else {
-            Vec::<InterPost>::new()
+            return Ok(Some(format!(
+                "skipped: unknown description {:?}",
+                description
+            )));
         };
 
-        let bkdate = match NaiveDate::parse_from_str(&self.settled, "%m-%d-%Y") {
-            Ok(x) => x,
-            Err(_) => {
-                posts = Vec::<InterPost>::new();
-                NaiveDate::MIN
-            }
+        let Ok(bkdate) = NaiveDate::parse_from_str(&self.settled, "%m-%d-%Y") else {
+            return Ok(Some(format!(
+                "skipped: unparseable date {:?}",
+                self.settled
+            )));
         };
 
-        let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
-        if posts.len() == 0 {
-            // storage
-            //     .lineerrors
-            //     .borrow_mut()
-            //     .append_lineerror(posno, 0, 0, format!("{:?}", self));
-        } else {
-            let details = &self.details;
-            let narration = format!("{description}-{details}").trim().to_string();
-
-            let transno = posno.clone();
-            let th = HeaderParams {
-                statement_no: transno,
-                file_no: 0u32,
-                start: 0u32,
-                end: 0u32,
-                date: bkdate,
-                narration: narration,
-                tags: None,
-            };
-            state.transactions.push(th);
-
-            posts
-                .into_iter()
-                .map(|(acct, cp, tc)| {
-                    let posno = state.line_count.fetch_add(1, Ordering::SeqCst);
-                    let (cp_quantity, cp_commodity) = match cp {
-                        None => (None, None),
-                        Some((q, c)) => (Some(q), Some(c)),
-                    };
-                    let (tc_quantity, tc_commodity) = match tc {
-                        None => (None, None),
-                        Some((q, c)) => (Some(q), Some(c)),
-                    };
-                    PostingParams {
-                        statement_no: posno,
-                        transaction_no: transno,
-                        file_no: 0u32,
-                        start: 0u32,
-                        end: 0u32,
-                        account: acct,
-                        cp_quantity: cp_quantity,
-                        cp_commodity: cp_commodity,
-                        tc_quantity: tc_quantity,
-                        tc_commodity: tc_commodity,
-                    }
-                })
-                .for_each(|x| state.postings.push(x));
-        }
-
-        Ok(())
+        let posno = state.next_statement_no();
+        let details = &self.details;
+        let narration = format!("{description}-{details}").trim().to_string();
+
+        let transno = posno;
+        let th = HeaderParams {
+            statement_no: transno,
+            file_no: 0u32,
+            start: 0u32,
+            end: 0u32,
+            date: bkdate,
+            flag: TRANSACTION_FLAG.to_string(),
+            narration,
+            tags: None,
+            raw: None,
+        };
+        state.transactions.push(th);
+
+        let postings: Vec<PostingParams> = posts
+            .into_iter()
+            .map(|(acct, cp, tc)| {
+                let posno = state.next_statement_no();
+                let (cp_quantity, cp_commodity) = match cp {
+                    None => (None, None),
+                    Some((q, c)) => (Some(q), Some(c)),
+                };
+                let (tc_quantity, tc_commodity) = match tc {
+                    None => (None, None),
+                    Some((q, c)) => (Some(q), Some(c)),
+                };
+                PostingParams {
+                    statement_no: posno,
+                    transaction_no: transno,
+                    file_no: 0u32,
+                    start: 0u32,
+                    end: 0u32,
+                    account: acct,
+                    cp_quantity,
+                    cp_commodity,
+                    tc_quantity,
+                    tc_commodity,
+                    per_unit: false,
+                    flag: None,
+                    comment: None,
+                }
+            })
+            .collect();
+        state.postings.extend(postings);
+
+        Ok(None)
     }
 }
 
+/// The transactions of the activities, returning those skipped as import errors
 pub fn process_us_transaction(
     filepath: &str,
     acct: &str,
     owner: &str,
     currency: &str,
     state: &mut LedgerState,
-) -> Result<(), Error> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .delimiter(b',')
-        .quoting(true)
-        .from_path(filepath)?;
-    for result in rdr.deserialize::<USTransactionRecord>() {
-        match result {
-            Ok(t) => {
-                t.store_us_transaction(acct, owner, currency, state)?;
-            }
-            Err(e) => {
-                println!("{:?}\n", e);
-            }
-        }
-    }
-    Ok(())
+) -> Result<ImportErrors, Error> {
+    read_records(filepath, |t: USTransactionRecord| {
+        t.store_us_transaction(acct, owner, currency, state)
+    })
 }
@@ -0,0 +1,25 @@
+use std::io::{self, BufReader};
+
+use anyhow::Result;
+
+mod rpc;
+mod server;
+
+use rpc::{read_message, write_message};
+use server::Server;
+
+/// Serves the Language Server Protocol over stdin and stdout
+fn main() -> Result<()> {
+    let mut server = Server::new()?;
+    let mut stdin = BufReader::new(io::stdin().lock());
+    let mut stdout = io::stdout().lock();
+    while let Some(msg) = read_message(&mut stdin)? {
+        if msg["method"] == "exit" {
+            std::process::exit(if server.shutdown { 0 } else { 1 });
+        }
+        for out in server.handle(&msg) {
+            write_message(&mut stdout, &out)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use anyhow::anyhow;
+use serde_json::{Value, json};
+
+const CONTENT_LENGTH: &str = "Content-Length:";
+
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Reads one JSON-RPC message framed by a Content-Length header. Returns
+/// None at the end of the input.
+pub fn read_message(r: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix(CONTENT_LENGTH) {
+            length = Some(n.trim().parse::<usize>()?);
+        }
+    }
+    let length = length.ok_or(anyhow!("Message without {}", CONTENT_LENGTH))?;
+    let mut body = vec![0; length];
+    r.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message(w: &mut impl Write, v: &Value) -> Result<()> {
+    let body = serde_json::to_string(v)?;
+    write!(w, "{} {}\r\n\r\n{}", CONTENT_LENGTH, body.len(), body)?;
+    w.flush()?;
+    Ok(())
+}
+
+pub fn response(id: &Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+pub fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use anyhow::anyhow;
+use ledger_rs_core::{
+    check::{Checks, Diagnostic, Severity},
+    core::OPEN_ACTION,
+    parse::parse_contents,
+    state::ledgerstate::LedgerState,
+};
+use serde_json::{Value, json};
+use tokio::runtime::Runtime;
+
+use crate::rpc::{METHOD_NOT_FOUND, error_response, notification, response};
+
+const FILE_SCHEME: &str = "file://";
+
+// LSP constants
+const SYNC_FULL: u32 = 1;
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+const KIND_MODULE: u32 = 9;
+const KIND_CONSTANT: u32 = 21;
+
+fn path_of(uri: &str) -> Option<PathBuf> {
+    let s = uri.strip_prefix(FILE_SCHEME)?;
+    let mut bytes = vec![];
+    let mut it = s.bytes();
+    while let Some(b) = it.next() {
+        if b == b'%' {
+            let hex = [it.next()?, it.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn uri_of(f: &Path) -> String {
+    let f = f.canonicalize().unwrap_or(f.to_path_buf());
+    let mut res = String::from(FILE_SCHEME);
+    for b in f.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+    res
+}
+
+/// LSP position, with the character in UTF-16 code units, of byte `offset`
+fn position(contents: &str, offset: usize) -> Value {
+    let offset = offset.min(contents.len());
+    let before = &contents[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|n| n + 1).unwrap_or(0);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json!({"line": line, "character": character})
+}
+
+/// Byte offset of an LSP position
+fn offset(contents: &str, line: usize, character: usize) -> usize {
+    let line_start: usize = contents
+        .split_inclusive('\n')
+        .take(line)
+        .map(|l| l.len())
+        .sum();
+    let mut units = 0;
+    for (n, c) in contents[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + n;
+        }
+        units += c.len_utf16();
+    }
+    contents.len()
+}
+
+/// Range from byte `start` to the end of its line
+fn line_range(contents: &str, start: usize) -> Value {
+    let start = start.min(contents.len());
+    let end = contents[start..]
+        .find('\n')
+        .map(|n| start + n)
+        .unwrap_or(contents.len());
+    json!({"start": position(contents, start), "end": position(contents, end)})
+}
+
+fn is_account_char(c: char) -> bool {
+    c.is_alphanumeric() || c == ':' || c == '-'
+}
+
+/// What completion and go to definition need from the last parse of a file
+#[derive(Default)]
+struct Analysis {
+    accounts: Vec<String>,
+    commodities: Vec<String>,
+    opens: HashMap<String, (PathBuf, usize)>,
+}
+
+impl Analysis {
+    fn new(state: &LedgerState) -> Self {
+        let files: HashMap<u32, &PathBuf> =
+            state.input_files.iter().map(|(f, n)| (*n, f)).collect();
+        let mut opens = HashMap::new();
+        for v in state.verifications.iter() {
+            if v.action == OPEN_ACTION
+                && let Some(f) = files.get(&v.file_no)
+            {
+                opens
+                    .entry(v.account.clone())
+                    .or_insert(((*f).clone(), v.start as usize));
+            }
+        }
+        Self {
+            accounts: state.account_names(),
+            commodities: state.commodities(),
+            opens,
+        }
+    }
+}
+
+///
+/// A language server for ledger files. Every open document is parsed with
+/// its includes on open and on each change, and checked with the built-in
+/// verification rules, so diagnostics follow the unsaved buffer.
+///
+pub struct Server {
+    rt: Runtime,
+    documents: HashMap<PathBuf, String>,
+    analyses: HashMap<PathBuf, Analysis>,
+    published: HashMap<PathBuf, HashSet<PathBuf>>,
+    pub shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            rt: Runtime::new()?,
+            documents: HashMap::new(),
+            analyses: HashMap::new(),
+            published: HashMap::new(),
+            shutdown: false,
+        })
+    }
+
+    fn contents(&self, f: &Path) -> String {
+        match self.documents.get(f) {
+            Some(c) => c.clone(),
+            None => fs::read_to_string(f).unwrap_or_default(),
+        }
+    }
+
+    /// Handles a request or notification, returning the messages to send
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let params = &msg["params"];
+        let id = msg.get("id");
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "completionProvider": {"triggerCharacters": [":"]},
+                    "definitionProvider": true,
+                },
+                "serverInfo": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                return self.update(doc["uri"].as_str(), doc["text"].as_str());
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|x| x.last())
+                    .and_then(|x| x["text"].as_str());
+                return self.update(params["textDocument"]["uri"].as_str(), text);
+            }
+            "textDocument/didClose" => {
+                if let Some(f) = params["textDocument"]["uri"].as_str().and_then(path_of) {
+                    self.documents.remove(&f);
+                    self.analyses.remove(&f);
+                }
+                return vec![];
+            }
+            "textDocument/completion" => self.completion(params),
+            "textDocument/definition" => self.definition(params),
+            _ => match id {
+                Some(id) => return vec![error_response(id, METHOD_NOT_FOUND, method)],
+                None => return vec![],
+            },
+        };
+        match (id, result) {
+            (Some(id), Ok(r)) => vec![response(id, r)],
+            (Some(id), Err(e)) => vec![error_response(id, METHOD_NOT_FOUND, &e.to_string())],
+            (None, _) => vec![],
+        }
+    }
+
+    /// Re-parses and checks the document, returning its diagnostics
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>) -> Vec<Value> {
+        let (Some(f), Some(text)) = (uri.and_then(path_of), text) else {
+            return vec![];
+        };
+        self.documents.insert(f.clone(), text.to_string());
+
+        let mut state = LedgerState::new();
+        state.insert(f.clone());
+        let diagnostics = match parse_contents(&f, text, &mut state) {
+            Ok(()) => self.rt.block_on(async {
+                state.verify().await?;
+                Checks::builtin().run(&state).await
+            }),
+            Err(e) => Err(e),
+        };
+        // A parse error is shown where it failed, in whichever file
+        let diagnostics =
+            diagnostics.unwrap_or_else(|e| vec![Diagnostic::parse_failure(&state, &e)]);
+        self.analyses.insert(f.clone(), Analysis::new(&state));
+
+        let files: HashMap<u32, &PathBuf> =
+            state.input_files.iter().map(|(f, n)| (*n, f)).collect();
+        let mut by_file: HashMap<PathBuf, Vec<Value>> = HashMap::new();
+        for x in diagnostics.iter() {
+            // Findings not tied to a statement are shown at the top of the document
+            let (file, start) = match files.get(&x.file_no) {
+                Some(in_f) if x.statement_no != u32::MAX => ((*in_f).clone(), x.start as usize),
+                _ => (f.clone(), 0),
+            };
+            let contents = self.contents(&file);
+            let severity = match x.severity {
+                Severity::Error => SEVERITY_ERROR,
+                Severity::Warning => SEVERITY_WARNING,
+            };
+            by_file.entry(file).or_default().push(json!({
+                "range": line_range(&contents, start),
+                "severity": severity,
+                "source": x.rule,
+                "message": x.message,
+            }));
+        }
+
+        let files: HashSet<PathBuf> = by_file.keys().cloned().collect();
+        let previous = self.published.insert(f.clone(), files).unwrap_or_default();
+        let mut res = vec![];
+        for (file, diagnostics) in by_file.iter() {
+            res.push(publish(file, diagnostics.clone()));
+        }
+        for file in previous.iter().filter(|x| !by_file.contains_key(*x)) {
+            res.push(publish(file, vec![]));
+        }
+        res
+    }
+
+    fn document_at(&self, params: &Value) -> Result<(PathBuf, usize)> {
+        let f = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(path_of)
+            .ok_or(anyhow!("No document"))?;
+        let contents = self.documents.get(&f).ok_or(anyhow!("Document not open"))?;
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+        let at = offset(contents, line, character);
+        Ok((f, at))
+    }
+
+    fn completion(&self, params: &Value) -> Result<Value> {
+        let (f, _) = self.document_at(params)?;
+        let Some(a) = self.analyses.get(&f) else {
+            return Ok(json!([]));
+        };
+        let items: Vec<Value> = a
+            .accounts
+            .iter()
+            .map(|x| json!({"label": x, "kind": KIND_MODULE}))
+            .chain(
+                a.commodities
+                    .iter()
+                    .map(|x| json!({"label": x, "kind": KIND_CONSTANT})),
+            )
+            .collect();
+        Ok(json!(items))
+    }
+
+    /// The open directive of the account under the cursor
+    fn definition(&self, params: &Value) -> Result<Value> {
+        let (f, at) = self.document_at(params)?;
+        let contents = &self.documents[&f];
+        let start = contents[..at]
+            .rfind(|c: char| !is_account_char(c))
+            .map(|n| n + 1)
+            .unwrap_or(0);
+        let end = contents[at..]
+            .find(|c: char| !is_account_char(c))
+            .map(|n| at + n)
+            .unwrap_or(contents.len());
+        let word = &contents[start..end];
+        let open = self.analyses.get(&f).and_then(|a| a.opens.get(word));
+        match open {
+            Some((in_f, start)) => {
+                let contents = self.contents(in_f);
+                Ok(json!({"uri": uri_of(in_f), "range": line_range(&contents, *start)}))
+            }
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+fn publish(f: &Path, diagnostics: Vec<Value>) -> Value {
+    notification(
+        "textDocument/publishDiagnostics",
+        json!({"uri": uri_of(f), "diagnostics": diagnostics}),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_error_is_shown_where_it_fails() {
+        let mut server = Server::new().unwrap();
+        let text = "2024-01-01 open Assets:A\n2024-01-05 * \"x\"\n  Assets:A 1.00\n";
+        let res = server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///ledger-rs-lsp-test/a.bean", "text": text}},
+        }));
+        let diagnostics = &res[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        let x = &diagnostics[0];
+        assert_eq!(x["range"]["start"], json!({"line": 2, "character": 15}));
+        assert_eq!(x["source"], "parse");
+        assert_eq!(x["message"], "invalid amount, expected commodity");
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Result;
+use anyhow::anyhow;
+use chrono::{Datelike, Duration, NaiveDate};
+use ledger_rs_core::state::ledgerstate::LedgerState;
+use ledger_rs_qfx::{qfx::QfxImportState, symbols::load_accounts};
+use rust_decimal::Decimal;
+
+const MT940_DATE_FORMAT: &str = "%y%m%d";
+const NO_REFERENCE: &str = "NONREF";
+const BANK_REF_SEP: &str = "//";
+const MESSAGE_END: &str = "-";
+const TEXT_BLOCK_END: &str = "-}";
+
+/// Statement line (:61:) with its information to account owner (:86:)
+#[derive(Debug)]
+pub struct StatementLine {
+    pub value_date: NaiveDate,
+    /// The booking date, when the line gives one apart from the value date
+    pub entry_date: Option<NaiveDate>,
+    pub amount: Decimal,
+    pub customer_ref: String,
+    pub bank_ref: Option<String>,
+    pub information: String,
+}
+
+/// Opening, closing and available balances (:60a:, :62a:, :64:)
+#[derive(Debug)]
+pub struct Mt940Balance {
+    pub date: NaiveDate,
+    pub amount: Decimal,
+    pub currency: String,
+}
+
+/// One MT940 or MT942 message
+#[derive(Debug, Default)]
+pub struct Mt940Statement {
+    /// :20: transaction reference
+    pub reference: String,
+    /// :25: account identification
+    pub account: String,
+    pub currency: String,
+    pub opening: Option<Mt940Balance>,
+    /// :62F: closing booked balance, absent from MT942
+    pub closing: Option<Mt940Balance>,
+    pub lines: Vec<StatementLine>,
+}
+
+impl StatementLine {
+    /// The date the line is booked on, its entry date else its value date
+    pub fn date(&self) -> NaiveDate {
+        self.entry_date.unwrap_or(self.value_date)
+    }
+
+    /// Bank reference used like the QFX FITID, else the customer reference
+    pub fn fitid(&self) -> String {
+        match &self.bank_ref {
+            Some(r) => r.clone(),
+            None if self.customer_ref != NO_REFERENCE => self.customer_ref.clone(),
+            None => String::new(),
+        }
+    }
+
+    ///
+    /// Free text of :86:. Structured (German ?NN subfield) content gives the
+    /// counterparty name (?32, ?33) and the remittance (?20 to ?29) joined
+    /// with ` / `, falling back to the booking text (?00).
+    ///
+    pub fn narration(&self) -> String {
+        let info = self.information.as_str();
+        if !info.contains('?') {
+            return if info.is_empty() {
+                "PROBLEM".to_string()
+            } else {
+                info.to_string()
+            };
+        }
+        let mut booking = String::new();
+        let mut remittance = String::new();
+        let mut name = String::new();
+        for f in info.split('?').skip(1) {
+            let (code, text) = (f.get(0..2).unwrap_or(""), f.get(2..).unwrap_or(""));
+            match code {
+                "00" => booking.push_str(text),
+                "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" => {
+                    remittance.push_str(text)
+                }
+                "32" | "33" => name.push_str(text),
+                _ => {}
+            }
+        }
+        match (name.trim(), remittance.trim()) {
+            ("", "") if booking.trim().is_empty() => "PROBLEM".to_string(),
+            ("", "") => booking.trim().to_string(),
+            (n, "") => n.to_string(),
+            ("", m) => m.to_string(),
+            (n, m) => format!("{n} / {m}"),
+        }
+    }
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate> {
+    Ok(NaiveDate::parse_from_str(
+        s.get(0..6).ok_or(anyhow!("Bad date: {}", s))?,
+        MT940_DATE_FORMAT,
+    )?)
+}
+
+fn parse_amount(s: &str) -> Result<Decimal> {
+    Ok(Decimal::from_str(&s.replace(',', "."))?)
+}
+
+/// `C240229EUR70,50`
+fn parse_balance(s: &str) -> Result<Mt940Balance> {
+    let mark = s.get(0..1).ok_or(anyhow!("Bad balance: {}", s))?;
+    let date = parse_date(&s[1..])?;
+    let currency = s.get(7..10).ok_or(anyhow!("Bad balance: {}", s))?;
+    let mut amount = parse_amount(&s[10..])?;
+    if mark == "D" {
+        amount = -amount;
+    }
+    Ok(Mt940Balance {
+        date,
+        amount,
+        currency: currency.to_string(),
+    })
+}
+
+///
+/// The entry date `mmdd` of a statement line, in the year that puts it
+/// closest to `value_date`, as a December value date may be booked in
+/// January and the other way round
+///
+fn parse_entry_date(mmdd: &str, value_date: NaiveDate) -> Result<NaiveDate> {
+    let year = value_date.year();
+    [year - 1, year, year + 1]
+        .into_iter()
+        .filter_map(|y| NaiveDate::parse_from_str(&format!("{y}{mmdd}"), "%Y%m%d").ok())
+        .min_by_key(|d| (*d - value_date).num_days().abs())
+        .ok_or(anyhow!("Bad entry date: {}", mmdd))
+}
+
+/// `2402100210DR29,50NMSCNONREF//REF001`
+fn parse_statement_line(s: &str) -> Result<StatementLine> {
+    let value_date = parse_date(s)?;
+    let mut rest = &s[6..];
+    let mut entry_date = None;
+    if rest.len() >= 4 && rest[..4].chars().all(|c| c.is_ascii_digit()) {
+        entry_date = Some(parse_entry_date(&rest[..4], value_date)?);
+        rest = &rest[4..];
+    }
+    let (negative, n) = if rest.starts_with("RC") {
+        (true, 2)
+    } else if rest.starts_with("RD") {
+        (false, 2)
+    } else if rest.starts_with('D') {
+        (true, 1)
+    } else if rest.starts_with('C') {
+        (false, 1)
+    } else {
+        return Err(anyhow!("Bad debit/credit mark: {}", s));
+    };
+    rest = &rest[n..];
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        // funds code
+        rest = &rest[1..];
+    }
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == ','))
+        .unwrap_or(rest.len());
+    let mut amount = parse_amount(&rest[..end])?;
+    if negative {
+        amount = -amount;
+    }
+    // transaction type: N or F and three characters
+    let rest = rest.get(end + 4..).unwrap_or("");
+    let rest = rest.lines().next().unwrap_or("");
+    let (customer_ref, bank_ref) = match rest.split_once(BANK_REF_SEP) {
+        Some((c, b)) => (c, Some(b.trim().to_string())),
+        None => (rest, None),
+    };
+    Ok(StatementLine {
+        value_date,
+        entry_date,
+        amount,
+        customer_ref: customer_ref.trim().to_string(),
+        bank_ref: bank_ref.filter(|b| !b.is_empty()),
+        information: String::new(),
+    })
+}
+
+/// Splits a message into (tag, value) fields, joining continuation lines
+fn fields(message: &str) -> Vec<(&str, String)> {
+    let mut res: Vec<(&str, String)> = vec![];
+    for line in message.lines() {
+        let line = line.trim_end();
+        if let Some(l) = line.strip_prefix(':')
+            && let Some((tag, value)) = l.split_once(':')
+        {
+            res.push((tag, value.to_string()));
+        } else if let Some((_, value)) = res.last_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    res
+}
+
+///
+/// The messages of an MT940/MT942 file, ended by a line holding only `-` or
+/// by the `-}` closing the text block of a SWIFT envelope. A `-` starting a
+/// line of text, as in a :86: continuation, does not end the message.
+///
+fn messages(input: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut message = String::new();
+    for line in input.lines() {
+        let line = line.trim_end();
+        if line == MESSAGE_END || line.starts_with(TEXT_BLOCK_END) {
+            res.push(std::mem::take(&mut message));
+        } else {
+            message.push_str(line);
+            message.push('\n');
+        }
+    }
+    res.push(message);
+    res
+}
+
+///
+/// Parses the messages of an MT940/MT942 file, see messages; SWIFT block
+/// headers ({1:...}{4:) are ignored.
+///
+pub fn parse_mt940(input: &str) -> Result<Vec<Mt940Statement>> {
+    let mut statements = vec![];
+    for message in messages(input) {
+        let mut st = Mt940Statement::default();
+        for (tag, value) in fields(&message) {
+            match tag {
+                "20" => st.reference = value.trim().to_string(),
+                "25" => st.account = value.trim().to_string(),
+                "60F" | "60M" => {
+                    let b = parse_balance(value.trim())?;
+                    st.currency = b.currency.clone();
+                    st.opening = Some(b);
+                }
+                "62F" => {
+                    let b = parse_balance(value.trim())?;
+                    st.currency = b.currency.clone();
+                    st.closing = Some(b);
+                }
+                "34F" if st.currency.is_empty() => {
+                    st.currency = value.get(0..3).unwrap_or("").to_string();
+                }
+                "61" => st.lines.push(parse_statement_line(value.trim())?),
+                "86" => {
+                    if let Some(l) = st.lines.last_mut() {
+                        l.information = value.replace('\n', "").trim().to_string();
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !st.account.is_empty() {
+            statements.push(st);
+        }
+    }
+    Ok(statements)
+}
+
+impl Mt940Statement {
+    ///
+    /// Fills the QFX intermediates, each line on the date it is booked on.
+    /// The closing booked balance is end of day, so it becomes an assertion
+    /// on the following day.
+    ///
+    pub fn to_bk(&self, state: &mut QfxImportState) {
+        for l in self.lines.iter() {
+            state.append_transaction(
+                l.fitid(),
+                l.date(),
+                l.narration(),
+                self.account.clone(),
+                l.amount,
+                self.currency.clone(),
+            );
+        }
+        if let Some(b) = &self.closing {
+            state.append_balance(
+                b.date + Duration::days(1),
+                self.account.clone(),
+                b.amount,
+                b.currency.clone(),
+            );
+        }
+    }
+}
+
+///
+/// Imports an MT940/MT942 file into `state` with the accounts file of the
+/// QFX importer, see QfxImportState::insert_into.
+///
+pub fn parse_mt940_file(
+    filename: PathBuf,
+    symbols_f: PathBuf,
+    fitids_f: Option<&Path>,
+    state: &mut LedgerState,
+) -> Result<usize> {
+    let symbols = load_accounts(String::from(symbols_f.to_str().unwrap()))?;
+    let input = fs::read_to_string(filename)?.replace("\r\n", "\n");
+    let mut import_state = QfxImportState::new();
+    for s in parse_mt940(&input)? {
+        s.to_bk(&mut import_state);
+    }
+    import_state.insert_into(&symbols, fitids_f, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = "\
+{1:F01BANKDEFFXXXX0000000000}{2:I940BANKDEFFXXXXN}{4:
+:20:STMT001
+:25:DE89370400440532013000
+:28C:1/1
+:60F:C240228EUR100,00
+:61:2402290301D29,50NMSCNONREF//REF001
+:86:?00SEPA-LASTSCHRIFT?20Rechnung 42?21 Februar?32Stadtwerke
+-GmbH
+:61:240301C70,50NTRFCUST02
+:86:Salary
+:61:240301RC5,00NMSCNONREF//REF003
+:86:?00STORNO
+:61:240301RD1,25NMSCNONREF//REF004
+:86:
+:62F:C240301EUR134,75
+-}
+{1:F01BANKDEFFXXXX0000000000}{2:I940BANKDEFFXXXXN}{4:
+:20:STMT002
+:25:DE89370400440532013000
+:60F:C240301EUR134,75
+:61:2312310102C1,00NMSCNONREF//REF005
+:86:Late
+-";
+
+    #[test]
+    fn messages_end_on_their_own_line_only() {
+        let statements = parse_mt940(STATEMENT).unwrap();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].reference, "STMT001");
+        assert_eq!(statements[0].lines.len(), 4);
+        assert_eq!(statements[1].reference, "STMT002");
+        assert!(statements[1].closing.is_none());
+    }
+
+    #[test]
+    fn debit_credit_marks_and_their_reversals_give_the_sign() {
+        let statements = parse_mt940(STATEMENT).unwrap();
+        let amounts: Vec<String> = statements[0]
+            .lines
+            .iter()
+            .map(|l| l.amount.to_string())
+            .collect();
+        assert_eq!(amounts, ["-29.50", "70.50", "-5.00", "1.25"]);
+        let fitids: Vec<String> = statements[0].lines.iter().map(|l| l.fitid()).collect();
+        assert_eq!(fitids, ["REF001", "CUST02", "REF003", "REF004"]);
+    }
+
+    #[test]
+    fn lines_are_booked_on_their_entry_date() {
+        let statements = parse_mt940(STATEMENT).unwrap();
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let first = &statements[0].lines[0];
+        assert_eq!(first.value_date, date("2024-02-29"));
+        assert_eq!(first.date(), date("2024-03-01"));
+        assert_eq!(statements[0].lines[1].date(), date("2024-03-01"));
+        assert_eq!(statements[1].lines[0].date(), date("2024-01-02"));
+    }
+
+    #[test]
+    fn structured_information_names_the_counterparty() {
+        let statements = parse_mt940(STATEMENT).unwrap();
+        let narrations: Vec<String> = statements[0].lines.iter().map(|l| l.narration()).collect();
+        assert_eq!(
+            narrations,
+            [
+                "Stadtwerke-GmbH / Rechnung 42 Februar",
+                "Salary",
+                "STORNO",
+                "PROBLEM"
+            ]
+        );
+    }
+
+    #[test]
+    fn closing_balance_is_asserted_the_next_day() {
+        let statements = parse_mt940(STATEMENT).unwrap();
+        let mut state = QfxImportState::new();
+        statements[0].to_bk(&mut state);
+        assert_eq!(state.transactions.len(), 4);
+        assert_eq!(state.balances.len(), 1);
+        let b = &state.balances[0];
+        assert_eq!(b.date, NaiveDate::from_ymd_opt(2024, 3, 2).unwrap());
+        assert_eq!(b.quantity.to_string(), "134.75");
+        assert_eq!(b.commodity, "EUR");
+    }
+}
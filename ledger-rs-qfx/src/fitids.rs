@@ -0,0 +1,41 @@
+use std::{collections::HashSet, fs::OpenOptions, io::Error, path::Path};
+
+///
+/// FITIDs already imported, keyed by (acctid, fitid). The sidecar file is a
+/// headerless CSV of `acctid,fitid` rows that grows with each import.
+///
+pub type FitidSet = HashSet<(String, String)>;
+
+pub fn load_fitids(filename: &Path) -> Result<FitidSet, Error> {
+    let mut set = HashSet::new();
+    if !filename.exists() {
+        return Ok(set);
+    }
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .quoting(true)
+        .has_headers(false)
+        .from_path(filename)?;
+    for result in rdr.records() {
+        let item = result?;
+        if let (Some(a), Some(f)) = (item.get(0), item.get(1)) {
+            set.insert((a.to_string(), f.to_string()));
+        }
+    }
+    Ok(set)
+}
+
+pub fn save_fitids(filename: &Path, fitids: &[(String, String)]) -> Result<(), Error> {
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(filename)?;
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(file);
+    for (a, f) in fitids {
+        wtr.write_record([a, f])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
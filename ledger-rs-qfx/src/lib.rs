@@ -1,2 +1,5 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod fitids;
 pub mod qfx;
 pub mod symbols;
@@ -1,7 +1,7 @@
 use std::{
     fs::File,
     io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
     string::String,
 };
 
@@ -10,16 +10,19 @@ use chrono::NaiveDate;
 use encoding_rs::{Encoding, WINDOWS_1252};
 use encoding_rs_io::DecodeReaderBytesBuilder;
 use ledger_rs_core::{
-    core::{BALANCE_ACTION, HeaderParams, PostingParams, VerificationParams},
+    core::{BALANCE_ACTION, HeaderParams, PostingParams, TRANSACTION_FLAG, VerificationParams},
     state::ledgerstate::LedgerState,
 };
 use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer};
 
-use crate::symbols::load_accounts;
+use crate::fitids::{load_fitids, save_fitids};
+use crate::symbols::{SymbolsMap, load_accounts};
 
+/// A STMTTRN with its statement's acctid and currency
 #[derive(Debug)]
 pub struct InterTrans {
+    pub fitid: String,
     pub date: NaiveDate,
     pub narration: String,
     pub account: String,
@@ -27,6 +30,7 @@ pub struct InterTrans {
     pub commodity: String,
 }
 
+/// A LEDGERBAL with its statement's acctid and currency
 #[derive(Debug)]
 pub struct InterBalance {
     pub date: NaiveDate,
@@ -35,12 +39,19 @@ pub struct InterBalance {
     pub commodity: String,
 }
 
+/// Transactions and balances of all the statements of an OFX document
 #[derive(Debug)]
 pub struct QfxImportState {
     pub transactions: Vec<InterTrans>,
     pub balances: Vec<InterBalance>,
 }
 
+impl Default for QfxImportState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl QfxImportState {
     pub fn new() -> Self {
         Self {
@@ -49,8 +60,9 @@ impl QfxImportState {
         }
     }
 
-    fn append_transaction(
+    pub fn append_transaction(
         &mut self,
+        fitid: String,
         date: NaiveDate,
         narration: String,
         account: String,
@@ -58,6 +70,7 @@ impl QfxImportState {
         commodity: String,
     ) {
         self.transactions.push(InterTrans {
+            fitid,
             date,
             narration,
             account,
@@ -66,7 +79,128 @@ impl QfxImportState {
         });
     }
 
-    fn append_balance(
+    ///
+    /// Adds the transactions and balances to `state`, mapping account ids with
+    /// `symbols`. When `fitids_f` is given, transactions whose FITID is already
+    /// recorded there for the same account are skipped and the newly imported
+    /// FITIDs are appended to it, so overlapping statements can be imported
+    /// again without duplicates. Returns the number skipped.
+    ///
+    pub fn insert_into(
+        &self,
+        symbols: &SymbolsMap,
+        fitids_f: Option<&Path>,
+        state: &mut LedgerState,
+    ) -> Result<usize> {
+        let mut seen = match fitids_f {
+            Some(f) => load_fitids(f)?,
+            None => Default::default(),
+        };
+        let mut imported = vec![];
+        let mut skipped = 0;
+
+        self.transactions.iter().for_each(|t| {
+            if !t.fitid.is_empty() {
+                let key = (t.account.clone(), t.fitid.clone());
+                if seen.contains(&key) {
+                    skipped += 1;
+                    return;
+                }
+                seen.insert(key.clone());
+                imported.push(key);
+            }
+            let acct = match symbols.get(&t.account) {
+                Some(n) => n.clone(),
+                None => t.account.clone(),
+            };
+            let transaction_no = state.next_statement_no();
+            state.transactions.push(HeaderParams {
+                statement_no: transaction_no,
+                file_no: 0u32,
+                start: 0u32,
+                end: 0u32,
+                date: t.date,
+                flag: TRANSACTION_FLAG.to_string(),
+                narration: t.narration.clone(),
+                tags: None,
+                raw: None,
+            });
+            state.postings.push(PostingParams {
+                statement_no: state.next_statement_no(),
+                transaction_no,
+                file_no: 0u32,
+                start: 0u32,
+                end: 0u32,
+                account: acct,
+                cp_quantity: Some(t.quantity),
+                cp_commodity: Some(t.commodity.clone()),
+                tc_quantity: Some(t.quantity),
+                tc_commodity: Some(t.commodity.clone()),
+                per_unit: false,
+                flag: None,
+                comment: None,
+            });
+        });
+        self.balances.iter().for_each(|t| {
+            let acct = match symbols.get(&t.account) {
+                Some(n) => n.clone(),
+                None => t.account.clone(),
+            };
+            state.verifications.push(VerificationParams {
+                statement_no: state.next_statement_no(),
+                file_no: 0u32,
+                start: 0u32,
+                end: 0u32,
+                date: t.date,
+                action: BALANCE_ACTION,
+                account: acct,
+                quantity: Some(t.quantity),
+                commodity: Some(t.commodity.clone()),
+                tolerance: None,
+                raw: None,
+            });
+        });
+
+        if let Some(f) = fitids_f {
+            save_fitids(f, &imported)?;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Distinct account ids in the order first seen
+    pub fn accounts(&self) -> Vec<&str> {
+        let mut res: Vec<&str> = vec![];
+        for a in self
+            .transactions
+            .iter()
+            .map(|t| t.account.as_str())
+            .chain(self.balances.iter().map(|b| b.account.as_str()))
+        {
+            if !res.contains(&a) {
+                res.push(a);
+            }
+        }
+        res
+    }
+
+    pub fn account_transactions<'a>(
+        &'a self,
+        account: &'a str,
+    ) -> impl Iterator<Item = &'a InterTrans> {
+        self.transactions
+            .iter()
+            .filter(move |t| t.account == account)
+    }
+
+    pub fn account_balances<'a>(
+        &'a self,
+        account: &'a str,
+    ) -> impl Iterator<Item = &'a InterBalance> {
+        self.balances.iter().filter(move |b| b.account == account)
+    }
+
+    pub fn append_balance(
         &mut self,
         date: NaiveDate,
         account: String,
@@ -108,12 +242,27 @@ pub struct _OFXHeader {
     pub ofx_data: String,
 }
 
+///
+/// Parsed OFX document, as returned by process_qfx. Only the fields used by
+/// the importer are kept; the others are skipped when deserializing.
+///
 #[derive(Debug, Deserialize)]
 pub struct OFX {
     #[serde(rename = "signonmsgsrsv1")]
     _signonmsgsrsv1: SIGNONMSGSRSV1,
-    bankmsgsrsv1: Option<BANKMSGSRSV1>,
-    creditcardmsgsrsv1: Option<CREDITCARDMSGSRSV1>,
+    /// Bank statements
+    pub bankmsgsrsv1: Option<BANKMSGSRSV1>,
+    /// Credit card statement
+    pub creditcardmsgsrsv1: Option<CREDITCARDMSGSRSV1>,
+}
+
+/// One account's statement within an OFX document
+#[derive(Debug)]
+pub struct OfxStatement<'a> {
+    pub acctid: &'a str,
+    pub currency: &'a str,
+    pub transactions: &'a [STMTTRN],
+    pub ledgerbal: &'a LEDGERBAL,
 }
 
 impl OFX {
@@ -126,6 +275,31 @@ impl OFX {
         }
         Ok(())
     }
+
+    /// Bank statements followed by the credit card statement
+    pub fn statements(&self) -> Vec<OfxStatement<'_>> {
+        let mut res = vec![];
+        if let Some(b) = &self.bankmsgsrsv1 {
+            for x in b.stmttrnrs.iter() {
+                res.push(OfxStatement {
+                    acctid: &x.stmtrs.bankacctfrom.acctid,
+                    currency: &x.stmtrs.curdef,
+                    transactions: &x.stmtrs.banktranlist.stmtrn_list,
+                    ledgerbal: &x.stmtrs.ledgerbal,
+                });
+            }
+        }
+        if let Some(c) = &self.creditcardmsgsrsv1 {
+            let x = &c.ccstmttrnrs.ccstmtrs;
+            res.push(OfxStatement {
+                acctid: &x.ccacctfrom.acctid,
+                currency: &x.curdef,
+                transactions: &x.banktranlist.stmtrn_list,
+                ledgerbal: &x.ledgerbal,
+            });
+        }
+        res
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -156,14 +330,15 @@ struct STATUS {
     _message: Option<String>,
 }
 
+/// Bank message set: one statement response per account
 #[derive(Debug, Deserialize)]
-struct BANKMSGSRSV1 {
+pub struct BANKMSGSRSV1 {
     #[serde(rename = "stmttrnrs")]
-    stmttrnrs: Vec<STMTTRNRS>,
+    pub stmttrnrs: Vec<STMTTRNRS>,
 }
 
 impl BANKMSGSRSV1 {
-    fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
         for x in self.stmttrnrs.iter() {
             x.to_bk(state)?;
         }
@@ -172,32 +347,35 @@ impl BANKMSGSRSV1 {
 }
 
 #[derive(Debug, Deserialize)]
-struct STMTTRNRS {
+pub struct STMTTRNRS {
     #[serde(rename = "trnuid", skip)]
     _trnuid: String,
     #[serde(rename = "status")]
     _status: STATUS,
-    stmtrs: STMTRS,
+    pub stmtrs: STMTRS,
 }
 
 impl STMTTRNRS {
-    fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
         self.stmtrs.to_bk(state)
     }
 }
 
+/// Bank statement
 #[derive(Debug, Deserialize)]
-struct STMTRS {
-    curdef: String,
-    bankacctfrom: BANKACCTFROM,
-    banktranlist: BANKTRANLIST,
-    ledgerbal: LEDGERBAL,
+pub struct STMTRS {
+    /// Currency of all the amounts in the statement
+    pub curdef: String,
+    pub bankacctfrom: BANKACCTFROM,
+    pub banktranlist: BANKTRANLIST,
+    /// Closing balance
+    pub ledgerbal: LEDGERBAL,
     #[serde(rename = "availbal")]
     _availbal: AVAILBAL,
 }
 
 impl STMTRS {
-    fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
         let acctid = self.bankacctfrom.get_acctid();
         let currency = self.curdef.clone();
         self.banktranlist
@@ -209,32 +387,38 @@ impl STMTRS {
 }
 
 #[derive(Debug, Deserialize)]
-struct BANKACCTFROM {
+pub struct BANKACCTFROM {
     #[serde(rename = "bankid", skip)]
     _bankid: String,
-    acctid: String,
+    /// Account number, the key of the accounts file
+    pub acctid: String,
     #[serde(rename = "accttype", skip)]
     _accttype: String,
 }
 
 impl BANKACCTFROM {
-    fn get_acctid(&self) -> String {
-        format!("{}", self.acctid)
+    pub fn get_acctid(&self) -> String {
+        self.acctid.to_string()
     }
 }
 
 #[derive(Debug, Deserialize)]
-struct BANKTRANLIST {
+pub struct BANKTRANLIST {
     #[serde(rename = "dtstart", skip)]
     _dtstart: String,
     #[serde(rename = "dtend", skip)]
     _dtend: String,
-    #[serde(rename = "stmttrn")]
-    stmtrn_list: Vec<STMTTRN>,
+    #[serde(rename = "stmttrn", default)]
+    pub stmtrn_list: Vec<STMTTRN>,
 }
 
 impl BANKTRANLIST {
-    fn to_bk(&self, state: &mut QfxImportState, acctid: String, currency: String) -> Result<()> {
+    pub fn to_bk(
+        &self,
+        state: &mut QfxImportState,
+        acctid: String,
+        currency: String,
+    ) -> Result<()> {
         for x in self.stmtrn_list.iter() {
             x.to_bk(state, acctid.clone(), currency.clone())?;
         }
@@ -242,49 +426,69 @@ impl BANKTRANLIST {
     }
 }
 
+/// Statement transaction
 #[derive(Debug, Deserialize)]
-struct STMTTRN {
+pub struct STMTTRN {
     #[serde(rename = "trntype", skip)]
     _trntype: String,
+    /// Posting date
     #[serde(deserialize_with = "from_qfx_datetime")]
-    dtposted: NaiveDate,
+    pub dtposted: NaiveDate,
+    /// Signed amount, positive for deposits and credits
     #[serde(deserialize_with = "from_qfx_decimal")]
-    trnamt: Decimal,
-    #[serde(rename = "fitid", skip)]
-    _fitid: String,
-    name: Option<String>,
-    memo: Option<String>,
+    pub trnamt: Decimal,
+    /// Financial institution's unique id of the transaction
+    #[serde(default)]
+    pub fitid: String,
+    pub name: Option<String>,
+    pub memo: Option<String>,
 }
 
 impl STMTTRN {
-    fn to_bk(&self, state: &mut QfxImportState, acctid: String, currency: String) -> Result<()> {
-        let dt = self.dtposted.clone();
-        let amt = self.trnamt.clone();
-        let narration = match (&self.name, &self.memo) {
+    /// Name and memo joined with ` / `
+    pub fn narration(&self) -> String {
+        match (&self.name, &self.memo) {
             (Some(n), Some(m)) => {
                 format!("{n} / {m}")
             }
             (Some(n), None) => n.clone(),
             (None, Some(m)) => m.clone(),
             (None, None) => "PROBLEM".to_string(),
-        };
-        state.append_transaction(dt, narration, acctid, amt, currency);
+        }
+    }
+
+    pub fn to_bk(
+        &self,
+        state: &mut QfxImportState,
+        acctid: String,
+        currency: String,
+    ) -> Result<()> {
+        let dt = self.dtposted;
+        let amt = self.trnamt;
+        let narration = self.narration();
+        state.append_transaction(self.fitid.clone(), dt, narration, acctid, amt, currency);
         Ok(())
     }
 }
 
+/// Ledger balance of the account at `dtasof`
 #[derive(Debug, Deserialize)]
-struct LEDGERBAL {
+pub struct LEDGERBAL {
     #[serde(deserialize_with = "from_qfx_decimal")]
-    balamt: Decimal,
+    pub balamt: Decimal,
     #[serde(deserialize_with = "from_qfx_datetime")]
-    dtasof: NaiveDate,
+    pub dtasof: NaiveDate,
 }
 
 impl LEDGERBAL {
-    fn to_bk(&self, state: &mut QfxImportState, acctid: String, currency: String) -> Result<()> {
-        let dt = self.dtasof.clone();
-        let amt = self.balamt.clone();
+    pub fn to_bk(
+        &self,
+        state: &mut QfxImportState,
+        acctid: String,
+        currency: String,
+    ) -> Result<()> {
+        let dt = self.dtasof;
+        let amt = self.balamt;
         state.append_balance(dt, acctid, amt, currency);
         Ok(())
     }
@@ -298,44 +502,48 @@ struct AVAILBAL {
     _dtasof: String,
 }
 
+/// Credit card message set
 #[derive(Debug, Deserialize)]
-struct CREDITCARDMSGSRSV1 {
-    ccstmttrnrs: CCSTMTTRNRS,
+pub struct CREDITCARDMSGSRSV1 {
+    pub ccstmttrnrs: CCSTMTTRNRS,
 }
 
 impl CREDITCARDMSGSRSV1 {
-    fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
         self.ccstmttrnrs.to_bk(state)
     }
 }
 
 #[derive(Debug, Deserialize)]
-struct CCSTMTTRNRS {
+pub struct CCSTMTTRNRS {
     #[serde(rename = "trnuid", skip)]
     _trnuid: String,
     #[serde(rename = "status")]
     _status: STATUS,
-    ccstmtrs: CCSTMTRS,
+    pub ccstmtrs: CCSTMTRS,
 }
 
 impl CCSTMTTRNRS {
-    fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
         self.ccstmtrs.to_bk(state)
     }
 }
 
+/// Credit card statement
 #[derive(Debug, Deserialize)]
-struct CCSTMTRS {
-    curdef: String,
-    ccacctfrom: CCACCTFROM,
-    banktranlist: BANKTRANLIST,
-    ledgerbal: LEDGERBAL,
+pub struct CCSTMTRS {
+    /// Currency of all the amounts in the statement
+    pub curdef: String,
+    pub ccacctfrom: CCACCTFROM,
+    pub banktranlist: BANKTRANLIST,
+    /// Closing balance
+    pub ledgerbal: LEDGERBAL,
     #[serde(rename = "availbal")]
     _availbal: AVAILBAL,
 }
 
 impl CCSTMTRS {
-    fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
+    pub fn to_bk(&self, state: &mut QfxImportState) -> Result<()> {
         let acct = self.ccacctfrom.get_acctid();
         let currency = self.curdef.clone();
         self.banktranlist
@@ -347,13 +555,14 @@ impl CCSTMTRS {
 }
 
 #[derive(Debug, Deserialize)]
-struct CCACCTFROM {
-    acctid: String,
+pub struct CCACCTFROM {
+    /// Card number, the key of the accounts file
+    pub acctid: String,
 }
 
 impl CCACCTFROM {
-    fn get_acctid(&self) -> String {
-        format!("{}", self.acctid)
+    pub fn get_acctid(&self) -> String {
+        self.acctid.to_string()
     }
 }
 
@@ -379,7 +588,7 @@ pub fn get_ofx_data(filename: &PathBuf, e: Option<&'static Encoding>) -> Result<
 }
 
 pub fn process_qfx(filename: &PathBuf, encoding: Option<&'static Encoding>) -> Result<OFX> {
-    let input = get_ofx_data(&filename, encoding)?;
+    let input = get_ofx_data(filename, encoding)?;
     let sgml = sgmlish::Parser::builder()
         .lowercase_names()
         .trim_whitespace(true)
@@ -396,13 +605,17 @@ pub fn process_qfx(filename: &PathBuf, encoding: Option<&'static Encoding>) -> R
     Ok(ofx_data)
 }
 
+///
+/// Imports a QFX file into `state`, see QfxImportState::insert_into.
+///
 pub fn parse_qfx_file(
     filename: PathBuf,
     encoding: Option<String>,
     symbols_f: PathBuf,
+    fitids_f: Option<&Path>,
     state: &mut LedgerState,
-) -> Result<()> {
-    let symbols = load_accounts(String::from(symbols_f.to_str().unwrap())).unwrap();
+) -> Result<usize> {
+    let symbols = load_accounts(String::from(symbols_f.to_str().unwrap()))?;
     let e = match encoding {
         Some(e_string) => {
             if e_string == "1252" {
@@ -417,58 +630,10 @@ pub fn parse_qfx_file(
     let ofx_data = process_qfx(&filename, e)?;
     ofx_data.to_bk(&mut import_state)?;
 
-    let mut count = 1;
-    import_state.transactions.iter().for_each(|t| {
-        let acct = match symbols.get(&t.account) {
-            Some(n) => n.clone(),
-            None => t.account.clone(),
-        };
-        state.transactions.push(HeaderParams {
-            statement_no: count,
-            file_no: 0u32,
-            start: 0u32,
-            end: 0u32,
-            date: t.date,
-            narration: t.narration.clone(),
-            tags: None,
-        });
-        state.postings.push(PostingParams {
-            statement_no: count,
-            transaction_no: count,
-            file_no: 0u32,
-            start: 0u32,
-            end: 0u32,
-            account: acct,
-            cp_quantity: Some(t.quantity),
-            cp_commodity: Some(t.commodity.clone()),
-            tc_quantity: Some(t.quantity),
-            tc_commodity: Some(t.commodity.clone()),
-        });
-        count = count + 1;
-    });
-    import_state.balances.iter().for_each(|t| {
-        let acct = match symbols.get(&t.account) {
-            Some(n) => n.clone(),
-            None => t.account.clone(),
-        };
-        state.verifications.push(VerificationParams {
-            statement_no: count,
-            file_no: 0u32,
-            start: 0u32,
-            end: 0u32,
-            date: t.date,
-            action: BALANCE_ACTION,
-            account: acct,
-            quantity: Some(t.quantity),
-            commodity: Some(t.commodity.clone()),
-        });
-        count = count + 1;
-    });
-
-    Ok(())
+    import_state.insert_into(&symbols, fitids_f, state)
 }
 
-const QFX_DATE_FORMAT: &'static str = "%Y%m%d";
+const QFX_DATE_FORMAT: &str = "%Y%m%d";
 
 fn from_qfx_datetime<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
 where
@@ -1,20 +1,13 @@
-use std::{collections::HashMap, io::Error};
+use std::{io::Error, path::Path};
 
-pub type SymbolsMap = HashMap<String, String>;
+use ledger_rs_core::mapping::MappingTable;
+pub use ledger_rs_core::mapping::SymbolsMap;
 
+///
+/// Loads the `acctid,account` mapping. Every row must have both columns, a
+/// unique acctid and a well-formed target account; otherwise all the invalid
+/// rows are reported with their line numbers.
+///
 pub fn load_accounts(filename: String) -> Result<SymbolsMap, Error> {
-    let mut map = HashMap::new();
-    let mut rdr = csv::ReaderBuilder::new()
-        .delimiter(b',')
-        .quoting(true)
-        .has_headers(false)
-        .from_path(filename)
-        .unwrap();
-    for result in rdr.records() {
-        let item = result?;
-        let k = item.get(0).unwrap().to_string();
-        let v = item.get(1).unwrap().to_string();
-        map.insert(k, v);
-    }
-    Ok(map)
+    MappingTable::accounts().load(Path::new(&filename))
 }
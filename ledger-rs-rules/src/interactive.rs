@@ -0,0 +1,102 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::Result;
+use ledger_rs_core::{core::ACCOUNT_SEP, state::ledgerstate::LedgerState};
+
+use crate::rules::{Rules, categorize_with};
+
+const MAX_CANDIDATES: usize = 20;
+
+fn prompt(msg: &str) -> Option<String> {
+    print!("{}", msg);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    let n = io::stdin().lock().read_line(&mut line).ok()?;
+    if n == 0 {
+        // EOF: stop prompting
+        return None;
+    }
+    Some(line.trim().to_string())
+}
+
+///
+/// Completes a typed fragment against the known accounts: an exact name or a
+/// unique case-insensitive substring match is accepted, several matches are
+/// listed and can be picked by number, and an unknown name containing the
+/// account separator is taken as a new account.
+///
+fn choose_account(accounts: &[String]) -> Option<String> {
+    let mut candidates: Vec<&String> = vec![];
+    loop {
+        let input = prompt("  account (blank to skip): ")?;
+        if input.is_empty() {
+            return None;
+        }
+        if let Ok(n) = input.parse::<usize>()
+            && n >= 1
+            && n <= candidates.len()
+        {
+            return Some(candidates[n - 1].clone());
+        }
+        if accounts.contains(&input) {
+            return Some(input);
+        }
+
+        let fragment = input.to_lowercase();
+        candidates = accounts
+            .iter()
+            .filter(|a| a.to_lowercase().contains(&fragment))
+            .collect();
+        match candidates.len() {
+            0 if input.contains(ACCOUNT_SEP) => return Some(input),
+            0 => println!("  no matching account"),
+            1 => {
+                println!("  -> {}", candidates[0]);
+                return Some(candidates[0].clone());
+            }
+            n => {
+                for (i, a) in candidates.iter().take(MAX_CANDIDATES).enumerate() {
+                    println!("  {:>3}) {}", i + 1, a);
+                }
+                if n > MAX_CANDIDATES {
+                    println!("  ... {} more", n - MAX_CANDIDATES);
+                }
+            }
+        }
+    }
+}
+
+///
+/// Applies `rules`, then prompts on the terminal for each transaction still
+/// uncategorized. Accepted answers can be saved back to `rules_f` so the same
+/// narration is categorized automatically next time.
+///
+pub fn categorize_interactive(
+    rules: &mut Rules,
+    state: &mut LedgerState,
+    accounts: &[String],
+    rules_f: Option<&str>,
+) -> Result<usize> {
+    let mut errors = vec![];
+    let count = categorize_with(state, |narration, account, amount| {
+        if let Some(r) = rules.find(narration, account, amount) {
+            return Some(r.target.clone());
+        }
+
+        let q = amount.map(|q| q.to_string()).unwrap_or_default();
+        println!("\n{} {} \"{}\"", account, q, narration);
+        let target = choose_account(accounts)?;
+
+        let save = prompt(&format!("  save rule for \"{}\"? [y/N] ", narration))
+            .is_some_and(|x| x.eq_ignore_ascii_case("y"));
+        let f = if save { rules_f } else { None };
+        if let Err(e) = rules.push_rule(narration, account, &target, f) {
+            errors.push(e);
+        }
+        Some(target)
+    });
+    if let Some(e) = errors.pop() {
+        return Err(e);
+    }
+    Ok(count)
+}
@@ -0,0 +1,337 @@
+use std::{collections::HashMap, fs::OpenOptions, path::Path};
+
+use anyhow::Result;
+use ledger_rs_core::{core::PostingParams, state::ledgerstate::LedgerState};
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+const REGEX_DELIM: char = '/';
+const RULES_HEADER: [&str; 5] = [
+    "narration",
+    "amount_min",
+    "amount_max",
+    "account_id",
+    "target",
+];
+
+///
+/// One row of the rules file (headered CSV):
+///
+///   narration,amount_min,amount_max,account_id,target
+///   TIM HORTONS,,,,Expenses:Dining
+///   /^(UBER|LYFT)/,,,Liabilities:Visa,Expenses:Transport
+///   ,0,5,Assets:Bank:Chequing,Expenses:Fees
+///
+/// `narration` is a case-insensitive substring, or a regex when wrapped in `/`.
+/// Empty columns match anything. Amount bounds are inclusive and compared
+/// against the absolute amount of the imported posting.
+///
+#[derive(Debug, Deserialize)]
+struct RuleRecord {
+    narration: String,
+    amount_min: Option<Decimal>,
+    amount_max: Option<Decimal>,
+    account_id: String,
+    target: String,
+}
+
+#[derive(Debug)]
+pub enum NarrationMatch {
+    Any,
+    Contains(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub narration: NarrationMatch,
+    pub amount_min: Option<Decimal>,
+    pub amount_max: Option<Decimal>,
+    pub account_id: Option<String>,
+    pub target: String,
+}
+
+impl Rule {
+    pub fn matches(&self, narration: &str, account: &str, amount: Option<Decimal>) -> bool {
+        let narration_ok = match &self.narration {
+            NarrationMatch::Any => true,
+            NarrationMatch::Contains(s) => narration.to_uppercase().contains(s),
+            NarrationMatch::Regex(r) => r.is_match(narration),
+        };
+        let account_ok = match &self.account_id {
+            Some(a) => a == account,
+            None => true,
+        };
+        let amount = amount.map(|q| q.abs());
+        let min_ok = match (self.amount_min, amount) {
+            (Some(min), Some(q)) => q >= min,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let max_ok = match (self.amount_max, amount) {
+            (Some(max), Some(q)) => q <= max,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        narration_ok && account_ok && min_ok && max_ok
+    }
+}
+
+#[derive(Debug)]
+pub struct Rules {
+    pub rules: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn load(filename: &str) -> Result<Self> {
+        let mut rules = vec![];
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b',')
+            .quoting(true)
+            .has_headers(true)
+            .from_path(filename)?;
+        for result in rdr.deserialize::<RuleRecord>() {
+            let r = result?;
+            let n = r.narration.trim();
+            let narration = if n.is_empty() {
+                NarrationMatch::Any
+            } else if n.len() > 1 && n.starts_with(REGEX_DELIM) && n.ends_with(REGEX_DELIM) {
+                NarrationMatch::Regex(Regex::new(&n[1..n.len() - 1])?)
+            } else {
+                NarrationMatch::Contains(n.to_uppercase())
+            };
+            let account_id = if r.account_id.trim().is_empty() {
+                None
+            } else {
+                Some(r.account_id.trim().to_string())
+            };
+            rules.push(Rule {
+                narration,
+                amount_min: r.amount_min,
+                amount_max: r.amount_max,
+                account_id,
+                target: r.target.trim().to_string(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// First matching rule wins
+    pub fn find(&self, narration: &str, account: &str, amount: Option<Decimal>) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|r| r.matches(narration, account, amount))
+    }
+
+    /// Rewrites the counter-account of imported transactions matched by a rule.
+    /// Returns the number of transactions categorized.
+    pub fn apply(&self, state: &mut LedgerState) -> usize {
+        categorize_with(state, |narration, account, amount| {
+            self.find(narration, account, amount)
+                .map(|r| r.target.clone())
+        })
+    }
+
+    /// Appends a narration/account rule, saving it to `filename` when given
+    pub fn push_rule(
+        &mut self,
+        narration: &str,
+        account_id: &str,
+        target: &str,
+        filename: Option<&str>,
+    ) -> Result<()> {
+        if let Some(f) = filename {
+            let exists = Path::new(f).exists();
+            let file = OpenOptions::new().append(true).create(true).open(f)?;
+            let mut wtr = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(file);
+            if !exists {
+                wtr.write_record(RULES_HEADER)?;
+            }
+            wtr.write_record([narration, "", "", account_id, target])?;
+            wtr.flush()?;
+        }
+        self.rules.push(Rule {
+            narration: NarrationMatch::Contains(narration.to_uppercase()),
+            amount_min: None,
+            amount_max: None,
+            account_id: Some(account_id.to_string()),
+            target: target.to_string(),
+        });
+        Ok(())
+    }
+}
+
+///
+/// Rewrites the counter-account of imported transactions. The first posting
+/// with an amount is passed to `f` with the narration; when `f` returns an
+/// account, the single elided posting is moved to it, or one is added when
+/// the importer generated only one posting (as the QFX importer does).
+/// Returns the number of transactions categorized.
+///
+pub fn categorize_with<F>(state: &mut LedgerState, mut f: F) -> usize
+where
+    F: FnMut(&str, &str, Option<Decimal>) -> Option<String>,
+{
+    let mut by_transaction: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (n, p) in state.postings.iter().enumerate() {
+        by_transaction.entry(p.transaction_no).or_default().push(n);
+    }
+
+    let mut count = 0;
+    let mut new_postings = vec![];
+    for h in state.transactions.iter() {
+        let Some(idxs) = by_transaction.get(&h.statement_no) else {
+            continue;
+        };
+        let Some(source) = idxs
+            .iter()
+            .copied()
+            .find(|n| state.postings[*n].cp_quantity.is_some())
+        else {
+            continue;
+        };
+        let elided: Vec<usize> = idxs
+            .iter()
+            .copied()
+            .filter(|n| state.postings[*n].cp_quantity.is_none())
+            .collect();
+        if elided.len() != 1 && idxs.len() != 1 {
+            continue;
+        }
+
+        let (account, amount) = {
+            let p = &state.postings[source];
+            (p.account.clone(), p.cp_quantity)
+        };
+        let Some(target) = f(&h.narration, &account, amount) else {
+            continue;
+        };
+
+        if elided.len() == 1 {
+            state.postings[elided[0]].account = target;
+        } else {
+            new_postings.push(PostingParams {
+                statement_no: state.next_statement_no(),
+                transaction_no: h.statement_no,
+                file_no: state.postings[source].file_no,
+                start: 0u32,
+                end: 0u32,
+                account: target,
+                cp_quantity: None,
+                cp_commodity: None,
+                tc_quantity: None,
+                tc_commodity: None,
+                per_unit: false,
+                flag: None,
+                comment: None,
+            });
+        }
+        count += 1;
+    }
+    state.postings.append(&mut new_postings);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, str::FromStr};
+
+    use ledger_rs_core::parse::parse_contents;
+
+    use super::*;
+
+    fn rule(narration: NarrationMatch, min: Option<&str>, max: Option<&str>) -> Rule {
+        Rule {
+            narration,
+            amount_min: min.map(|x| Decimal::from_str(x).unwrap()),
+            amount_max: max.map(|x| Decimal::from_str(x).unwrap()),
+            account_id: None,
+            target: String::from("Expenses:Target"),
+        }
+    }
+
+    fn amount(x: &str) -> Option<Decimal> {
+        Some(Decimal::from_str(x).unwrap())
+    }
+
+    #[test]
+    fn contains_is_case_insensitive_and_regex_is_as_written() {
+        let contains = rule(NarrationMatch::Contains("TIM HORTONS".into()), None, None);
+        assert!(contains.matches("Tim Hortons #123", "Acct", None));
+        assert!(!contains.matches("Starbucks", "Acct", None));
+
+        let regex = rule(
+            NarrationMatch::Regex(Regex::new("^(UBER|LYFT)").unwrap()),
+            None,
+            None,
+        );
+        assert!(regex.matches("UBER TRIP", "Acct", None));
+        assert!(!regex.matches("uber trip", "Acct", None));
+        assert!(!regex.matches("PAID UBER", "Acct", None));
+    }
+
+    #[test]
+    fn amount_bounds_are_inclusive_on_the_absolute_amount() {
+        let r = rule(NarrationMatch::Any, Some("0"), Some("5"));
+        assert!(r.matches("Fee", "Acct", amount("5.00")));
+        assert!(r.matches("Fee", "Acct", amount("-5.00")));
+        assert!(r.matches("Fee", "Acct", amount("0")));
+        assert!(!r.matches("Fee", "Acct", amount("-5.01")));
+        assert!(!r.matches("Fee", "Acct", None));
+        assert!(rule(NarrationMatch::Any, None, None).matches("Fee", "Acct", None));
+    }
+
+    #[test]
+    fn account_filter_matches_the_account_id_exactly() {
+        let mut r = rule(NarrationMatch::Any, None, None);
+        r.account_id = Some(String::from("Assets:Bank:Chequing"));
+        assert!(r.matches("x", "Assets:Bank:Chequing", None));
+        assert!(!r.matches("x", "Assets:Bank:Chequing2", None));
+        assert!(!r.matches("x", "Assets:Bank", None));
+    }
+
+    #[test]
+    fn categorize_moves_the_elided_posting_or_adds_one() {
+        let f = Path::new("buffer.bean");
+        let mut state = LedgerState::new();
+        state.insert(f.to_path_buf());
+        let contents = "2024-01-02 * \"TIM HORTONS\"\n  Assets:Bank -2.50 CAD\n  Expenses:Todo\n\
+                        2024-01-03 * \"TIM HORTONS\"\n  Assets:Bank -3.00 CAD\n\
+                        2024-01-04 * \"Rent\"\n  Assets:Bank -900.00 CAD\n  Expenses:Todo\n\
+                        2024-01-05 * \"TIM HORTONS split\"\n  Assets:Bank -4.00 CAD\n  \
+                        Expenses:A 2.00 CAD\n  Expenses:B 2.00 CAD\n";
+        parse_contents(f, contents, &mut state).unwrap();
+        let mut seen = vec![];
+        let count = categorize_with(&mut state, |narration, account, amount| {
+            seen.push((narration.to_string(), account.to_string(), amount));
+            narration
+                .contains("TIM HORTONS")
+                .then(|| String::from("Expenses:Dining"))
+        });
+        assert_eq!(count, 2);
+        assert_eq!(
+            seen,
+            [
+                ("TIM HORTONS".into(), "Assets:Bank".into(), amount("-2.50")),
+                ("TIM HORTONS".into(), "Assets:Bank".into(), amount("-3.00")),
+                ("Rent".into(), "Assets:Bank".into(), amount("-900.00")),
+            ]
+        );
+        let accounts = |transaction_no: u32| -> Vec<&str> {
+            state
+                .postings
+                .iter()
+                .filter(|p| p.transaction_no == transaction_no)
+                .map(|p| p.account.as_str())
+                .collect()
+        };
+        let t: Vec<u32> = state.transactions.iter().map(|h| h.statement_no).collect();
+        assert_eq!(accounts(t[0]), ["Assets:Bank", "Expenses:Dining"]);
+        assert_eq!(accounts(t[1]), ["Assets:Bank", "Expenses:Dining"]);
+        assert_eq!(accounts(t[2]), ["Assets:Bank", "Expenses:Todo"]);
+        assert_eq!(accounts(t[3]), ["Assets:Bank", "Expenses:A", "Expenses:B"]);
+    }
+}
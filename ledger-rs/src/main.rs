@@ -1,34 +1,488 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{OnceLock, mpsc},
+    thread,
+    time::Duration,
+};
 
-use chrono::NaiveDate;
-use clap::{Parser, Subcommand};
+use chrono::{Local, NaiveDate};
+use clap::{Args, Parser, Subcommand};
+use notify::{RecursiveMode, Watcher};
 
-use ledger_rs_core::{parse::parse_filename, state::ledgerstate::LedgerState};
+use ledger_rs_camt::camt::parse_camt_file;
+use ledger_rs_core::{
+    cache::ParseCache,
+    check::{
+        Checks, DateRange, Diagnostic, write_diagnostics, write_diagnostics_json,
+        write_diagnostics_ndjson,
+    },
+    core::{
+        ANOMALY_STDDEVS, CASHFLOW_DEPTH, CONVERT_LEDGER, COVERAGE_GAP_DAYS, EVENT_BALANCE,
+        EVENT_ERROR, EVENT_POSTING, EVENT_TRANSACTION, FMT_COLUMN, FORMAT_TABLE, GEN_ACCOUNTS,
+        GEN_FILES, GEN_SEED, LINT_STALE_DAYS, PENDING_FLAG, PNL_BY_ACCOUNT, RUNWAY_MONTHS,
+        SAMPLE_ACCOUNTS, TRANSACTION_FLAG,
+    },
+    custom::saved_report,
+    events::{OutputFormat, spawn_stream, write_events},
+    fmt::{format_file, sort_file},
+    init::{MAIN_FILE, TEMPLATES, find_template, write_init},
+    lint::{fix_trailing_whitespace, lint},
+    locale::{LOCALE_ISO, Locale},
+    mapping::{MappingTable, SymbolsMap},
+    merge::merge_imports,
+    parse::{parse_filename, parse_streaming},
+    rename::{RenameSummary, rename_account, rename_commodity},
+    sample::{write_generated, write_sample},
+    state::{
+        cmp::{CompareKey, CompareOptions},
+        ledgerstate::LedgerState,
+    },
+    summary::LedgerSummary,
+};
 use ledger_rs_csv::{
-    rj_cdn::{compile_holdings, process_activites},
+    rj_cdn::{ActivityAccounts, check_symbols, compile_holdings, process_activites},
     rj_cdn_closed::process_closed_acct_trans,
-    rj_symbols::load_symbols,
+    rj_core::ImportErrors,
+    rj_symbols::{SymbolsTable, UNKNOWN_SYMBOLS_PLACEHOLDER, UnknownSymbols, load_symbols},
     rj_usa::process_us_transaction,
 };
+use ledger_rs_mt940::mt940::parse_mt940_file;
 use ledger_rs_qfx::qfx::parse_qfx_file;
+use ledger_rs_rules::{interactive::categorize_interactive, rules::Rules};
 
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
 struct Cli {
+    /// Directory caching parsed files, so only changed files are re-parsed
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+    /// Directory searched for includes not found next to the including file
+    #[arg(long, global = true)]
+    include_path: Vec<PathBuf>,
+    /// Earliest date accepted from imported files without a warning
+    #[arg(long, global = true)]
+    min_date: Option<NaiveDate>,
+    /// Latest date accepted from imported files without a warning
+    #[arg(long, global = true)]
+    max_date: Option<NaiveDate>,
+    /// Report only the pending (!) transactions
+    #[arg(long, global = true, conflicts_with = "cleared_only")]
+    pending_only: bool,
+    /// Report only the cleared (*) transactions
+    #[arg(long, global = true)]
+    cleared_only: bool,
+    /// Keep the text of each statement as written in the raw column
+    #[arg(long, global = true)]
+    keep_raw: bool,
+    /// Parse in batches of statements instead of reading each file whole
+    #[arg(long, global = true, conflicts_with = "cache_dir")]
+    stream: bool,
+    /// Print only the data, without the headings, counts and progress
+    #[arg(long, global = true)]
+    quiet: bool,
+    /// table, the default, or ndjson for one JSON object per line and per row or finding
+    #[arg(long, global = true)]
+    format: Option<String>,
+    /// Dates and amounts of the reports as iso, the default, en-CA, en-US, fr-CA or de-DE
+    #[arg(long, global = true)]
+    locale: Option<String>,
+    /// Negative amounts of the reports as minus, parens or trailing
+    #[arg(long, global = true)]
+    negative: Option<String>,
     #[command(subcommand)]
     command: Command,
 }
 
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
+static INCLUDE_PATH: OnceLock<Vec<PathBuf>> = OnceLock::new();
+static DATE_RANGE: OnceLock<DateRange> = OnceLock::new();
+static FLAG_FILTER: OnceLock<&str> = OnceLock::new();
+static KEEP_RAW: OnceLock<bool> = OnceLock::new();
+static STREAM: OnceLock<bool> = OnceLock::new();
+static QUIET: OnceLock<bool> = OnceLock::new();
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Prints a heading, count or progress line to stderr, unless --quiet
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if QUIET.get() != Some(&true) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+const WATCH_SETTLE: Duration = Duration::from_millis(200);
+
+#[derive(Args, Debug)]
+struct CategorizeArgs {
+    /// Rules file used to categorize imported transactions
+    #[arg(long)]
+    rules: Option<PathBuf>,
+    /// Prompt for transactions not matched by the rules
+    #[arg(long)]
+    interactive: bool,
+    /// Ledger whose accounts are offered for completion
+    #[arg(long)]
+    base: Option<PathBuf>,
+    /// Also emit open directives for the accounts, dated at their first use
+    #[arg(long)]
+    open_accounts: bool,
+    /// Skip the records dated before this day
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Skip the records dated after this day
+    #[arg(long)]
+    to: Option<NaiveDate>,
+}
+
+#[derive(Args, Debug)]
+struct ActivitiesArgs {
+    /// `type,template` file of how to post each Tran Type, beyond the defaults
+    #[arg(long)]
+    tran_types: Option<PathBuf>,
+    /// Only report the activities whose description is not in the symbols file
+    #[arg(long)]
+    check_symbols: bool,
+    /// What to post for a security not in the symbols file: unknown, derive or fail
+    #[arg(long, default_value = UNKNOWN_SYMBOLS_PLACEHOLDER)]
+    unknown_symbols: String,
+    /// File the descriptions not in the symbols file are written to, to map them
+    #[arg(long)]
+    missing_symbols: Option<PathBuf>,
+    /// `client,owner` file naming the owner of each client name, for an export of several accounts
+    #[arg(long)]
+    owners: Option<PathBuf>,
+    /// Post cash in other currencies to a sub-account of the cash by currency, as Cash:USD
+    #[arg(long)]
+    currency_cash: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// Comma separated subset of date,account,cp,tc,narration to match on
+    #[arg(long, value_delimiter = ',')]
+    keys: Vec<String>,
+    /// account,canonical file of account spellings treated as equal
+    #[arg(long)]
+    account_map: Option<PathBuf>,
+    /// Compare account names case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
     Bean {
         filepath: PathBuf,
+        /// Re-parse and reprint the reports whenever an input file changes
+        #[arg(long)]
+        watch: bool,
+        /// Also run the checks with their warnings as errors, exiting with 1 on any. The
+        /// advisory sign checks stay warnings; pad directives are not supported, so
+        /// there is no unused pad to report.
+        #[arg(long)]
+        strict: bool,
+    },
+    Register {
+        filepath: PathBuf,
+        account: String,
+        /// Also value the amounts in this currency at the latest prices
+        #[arg(long, alias = "convert")]
+        currency: Option<String>,
+    },
+    Accounts {
+        filepath: PathBuf,
+        /// List the commodities instead
+        #[arg(long)]
+        commodities: bool,
+    },
+    Check {
+        filepath: PathBuf,
+        /// Comma separated rules to skip: balanced, balance, balance-tolerance, open-close, sign, date-range, date-order, budget, contribution-limit
+        #[arg(long, value_delimiter = ',')]
+        disable: Vec<String>,
+        /// Comma separated optional rules to run: zero-amount, posting-sign, single-posting
+        #[arg(long, value_delimiter = ',')]
+        enable: Vec<String>,
+        /// Print the diagnostics as JSON
+        #[arg(long)]
+        json: bool,
+        /// Also verify with the files parsed in this many other orders, failing on any difference
+        #[arg(long, num_args = 0..=1, default_missing_value = "3")]
+        shuffle_check: Option<u64>,
+    },
+    Lint {
+        filepath: PathBuf,
+        /// Strip trailing whitespace before linting
+        #[arg(long)]
+        fix: bool,
+        /// Days a balance assertion may be behind the postings of its account
+        #[arg(long, default_value_t = LINT_STALE_DAYS)]
+        stale_days: i64,
+        /// Print the diagnostics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    Errors {
+        filepath: PathBuf,
+    },
+    Compare {
+        filepath: PathBuf,
+        b_filepath: PathBuf,
+        #[command(flatten)]
+        compare: CompareArgs,
+    },
+    Convert {
+        filepath: PathBuf,
+        /// ledger or hledger
+        #[arg(long, default_value = CONVERT_LEDGER)]
+        to: String,
+    },
+    Equity {
+        filepath: PathBuf,
+        /// First day of the period
+        #[arg(long)]
+        begin: Option<NaiveDate>,
+        /// Day after the period
+        #[arg(long)]
+        end: Option<NaiveDate>,
+    },
+    Fmt {
+        filepath: PathBuf,
+        /// Column at which amounts end
+        #[arg(long, default_value_t = FMT_COLUMN)]
+        column: usize,
+    },
+    SortFile {
+        filepath: PathBuf,
+    },
+    MergeImports {
+        /// Import outputs and ledger files of the same account, oldest first
+        #[arg(required = true)]
+        filepaths: Vec<PathBuf>,
+        /// File written with the merged history instead of printing it
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    RenameAccount {
+        filepath: PathBuf,
+        old: String,
+        new: String,
+        /// Print the lines that would change without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    RenameCommodity {
+        filepath: PathBuf,
+        old: String,
+        new: String,
+        /// Print the lines that would change without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Pnl {
+        filepath: PathBuf,
+        /// First day of the period
+        #[arg(long)]
+        begin: Option<NaiveDate>,
+        /// Day after the period
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// Group by account, tag or meta:<key>
+        #[arg(long, alias = "group-by", default_value = PNL_BY_ACCOUNT)]
+        by: String,
+        /// Also value the totals in this currency at the latest prices before --end
+        #[arg(long, alias = "convert")]
+        currency: Option<String>,
+    },
+    Cashflow {
+        filepath: PathBuf,
+        /// First day of the period
+        #[arg(long)]
+        begin: Option<NaiveDate>,
+        /// Day after the period
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// Number of account levels shown, each shorter level as a subtotal
+        #[arg(long, default_value_t = CASHFLOW_DEPTH)]
+        depth: usize,
+    },
+    Balance {
+        filepath: PathBuf,
+        /// Day after the balance date
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// Group by account, tag or meta:<key>
+        #[arg(long, alias = "group-by", default_value = PNL_BY_ACCOUNT)]
+        by: String,
+        /// Also value the balances in this currency at the latest prices
+        #[arg(long, alias = "convert")]
+        currency: Option<String>,
+        /// Include the recurring transactions forecast up to this day
+        #[arg(long)]
+        forecast_until: Option<NaiveDate>,
+    },
+    Runway {
+        filepath: PathBuf,
+        /// Day after the runway starts
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// Trailing months averaged into the burn rate
+        #[arg(long, default_value_t = RUNWAY_MONTHS)]
+        months: u32,
+        /// Liquid account prefixes, all of Assets when absent
+        #[arg(long)]
+        accounts: Vec<String>,
+        /// Include the recurring transactions forecast up to this day
+        #[arg(long)]
+        forecast_until: Option<NaiveDate>,
+    },
+    Savings {
+        filepath: PathBuf,
+        /// First day of the period
+        #[arg(long)]
+        begin: Option<NaiveDate>,
+        /// Day after the period
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// Investment account prefix, Assets:Investments when absent
+        #[arg(long)]
+        investments: Option<String>,
+    },
+    CloseBooks {
+        filepath: PathBuf,
+        /// First day of the new ledger
+        date: NaiveDate,
+        /// File to write the opening balances to, instead of stdout
+        #[arg(long)]
+        opening: Option<PathBuf>,
+    },
+    Hash {
+        filepath: PathBuf,
+        /// First day of the statements hashed, the first of the ledger when absent
+        #[arg(long)]
+        begin: Option<NaiveDate>,
+        /// Day after the statements hashed, after the last of the ledger when absent
+        #[arg(long)]
+        end: Option<NaiveDate>,
+        /// File of a secret key to sign the hash with, as HMAC-SHA256
+        #[arg(long)]
+        key: Option<PathBuf>,
+        /// Hash or signature printed before, to check the ledger still has it
+        #[arg(long)]
+        verify: Option<String>,
+    },
+    Sql {
+        filepath: PathBuf,
+        /// File to write the SQL script to, instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Query to run against the verified tables instead of writing the script
+        #[arg(long, conflicts_with = "output")]
+        query: Option<String>,
+    },
+    Forecast {
+        filepath: PathBuf,
+        /// Day after the last forecast transaction
+        #[arg(long)]
+        until: NaiveDate,
+    },
+    Cycles {
+        filepath: PathBuf,
+        account: String,
+        /// Day of the month the statements close on
+        #[arg(long)]
+        closing_day: u32,
+    },
+    Commodities {
+        filepath: PathBuf,
+        /// Days without a price reported as a gap
+        #[arg(long, default_value_t = COVERAGE_GAP_DAYS)]
+        gap_days: u32,
+    },
+    Anomalies {
+        filepath: PathBuf,
+        /// Standard deviations from an account's mean amount reported as unusual
+        #[arg(long, default_value_t = ANOMALY_STDDEVS)]
+        stddevs: f64,
+        /// Also report any posting larger than this amount
+        #[arg(long)]
+        threshold: Option<f64>,
+    },
+    Unrealized {
+        filepath: PathBuf,
+        /// Day after the valuation date
+        #[arg(long)]
+        end: Option<NaiveDate>,
+    },
+    Contributions {
+        filepath: PathBuf,
+    },
+    #[command(alias = "holdings")]
+    Portfolio {
+        filepath: PathBuf,
+        /// Valuation date, included; defaults to the end of the ledger
+        #[arg(long)]
+        at: Option<NaiveDate>,
+        /// Account prefixes to report, all holdings when empty
+        accounts: Vec<String>,
+    },
+    ForeignProperty {
+        filepath: PathBuf,
+        /// Tax year to report
+        #[arg(long)]
+        year: i32,
+        /// commodity,country file of the country of each security
+        #[arg(long)]
+        countries: Option<PathBuf>,
+    },
+    Todo {
+        filepath: PathBuf,
+        accounts: Vec<String>,
+    },
+    Report {
+        filepath: PathBuf,
+        /// Report saved in the ledger as `custom "report" <name> <command> <args>...`
+        name: String,
+    },
+    Init {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// personal-ca, personal-us or small-business; prompts when absent
+        #[arg(long)]
+        template: Option<String>,
+        #[arg(long)]
+        title: Option<String>,
+    },
+    #[command(alias = "gen")]
+    GenerateSample {
+        /// Directory to write a random ledger of several files into, instead
+        /// of the sample ledger to stdout
+        dir: Option<PathBuf>,
+        #[arg(long, default_value_t = 1000)]
+        transactions: usize,
+        /// Number of accounts, by default 8 for the sample and 16 for a ledger in DIR
+        #[arg(long)]
+        accounts: Option<usize>,
+        /// Number of files the transactions are spread over, each included by the main file
+        #[arg(long, default_value_t = GEN_FILES, requires = "dir")]
+        files: usize,
+        /// Seed of the random generator, the same seed giving the same ledger
+        #[arg(long, default_value_t = GEN_SEED)]
+        seed: u64,
     },
     RjUsa {
         filepath: PathBuf,
         acct: String,
         owner: String,
         currency: String,
+        #[command(flatten)]
+        categorize: CategorizeArgs,
     },
     RjCdnClosed {
         filepath: PathBuf,
@@ -36,6 +490,8 @@ enum Command {
         owner: String,
         currency: String,
         commodity_f: PathBuf,
+        #[command(flatten)]
+        categorize: CategorizeArgs,
     },
     RjCdnActivities {
         filepath: PathBuf,
@@ -43,11 +499,27 @@ enum Command {
         owner: String,
         currency: String,
         symbol_f: PathBuf,
+        #[command(flatten)]
+        activities: ActivitiesArgs,
+        #[command(flatten)]
+        categorize: CategorizeArgs,
     },
     RjCdnHoldings {
         filepath: PathBuf,
         bkdate_string: String,
         currency: String,
+        /// Also emit price directives from the Price/Market Value columns
+        #[arg(long)]
+        prices: bool,
+        /// Also emit transactions opening the holdings at their book value
+        #[arg(long)]
+        opening: bool,
+        /// `client,owner` file naming the owner of each client name
+        #[arg(long)]
+        owners: Option<PathBuf>,
+        /// Also emit open directives for the accounts, dated at their first use
+        #[arg(long)]
+        open_accounts: bool,
     },
     RjSymbols {
         symbol_f: PathBuf,
@@ -57,27 +529,257 @@ enum Command {
         filepath: PathBuf,
         bean_filepath: Option<PathBuf>,
         encoding: Option<String>,
+        /// File of already imported FITIDs, used to skip duplicates
+        #[arg(long)]
+        fitids: Option<PathBuf>,
+        #[command(flatten)]
+        categorize: CategorizeArgs,
+        #[command(flatten)]
+        compare: CompareArgs,
+    },
+    Mt940 {
+        symbols_f: PathBuf,
+        filepath: PathBuf,
+        bean_filepath: Option<PathBuf>,
+        /// File of already imported bank references, used to skip duplicates
+        #[arg(long)]
+        fitids: Option<PathBuf>,
+        #[command(flatten)]
+        categorize: CategorizeArgs,
+        #[command(flatten)]
+        compare: CompareArgs,
+    },
+    Camt {
+        symbols_f: PathBuf,
+        filepath: PathBuf,
+        bean_filepath: Option<PathBuf>,
+        /// File of already imported entry references, used to skip duplicates
+        #[arg(long)]
+        fitids: Option<PathBuf>,
+        #[command(flatten)]
+        categorize: CategorizeArgs,
+        #[command(flatten)]
+        compare: CompareArgs,
     },
 }
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    let cli = saved_cli(Cli::parse());
+    if let Some(d) = cli.cache_dir {
+        CACHE_DIR.set(d).unwrap();
+    }
+    INCLUDE_PATH.set(cli.include_path).unwrap();
+    let default_range = DateRange::default();
+    DATE_RANGE
+        .set(DateRange {
+            min: cli.min_date.unwrap_or(default_range.min),
+            max: cli.max_date.unwrap_or(default_range.max),
+        })
+        .unwrap();
+    if cli.pending_only {
+        FLAG_FILTER.set(PENDING_FLAG).unwrap();
+    } else if cli.cleared_only {
+        FLAG_FILTER.set(TRANSACTION_FLAG).unwrap();
+    }
+    KEEP_RAW.set(cli.keep_raw).unwrap();
+    STREAM.set(cli.stream).unwrap();
+    QUIET.set(cli.quiet).unwrap();
+    match OutputFormat::from_name(cli.format.as_deref().unwrap_or(FORMAT_TABLE)) {
+        Ok(x) => FORMAT.set(x).unwrap(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    match Locale::from_names(
+        cli.locale.as_deref().unwrap_or(LOCALE_ISO),
+        cli.negative.as_deref(),
+    ) {
+        Ok(x) => LOCALE.set(x).unwrap(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 
     match cli.command {
-        Command::Bean { filepath } => bean(filepath).await,
+        Command::Bean {
+            filepath,
+            watch,
+            strict,
+        } => {
+            if watch {
+                bean_watch(filepath, strict).await
+            } else {
+                bean(filepath, strict).await
+            }
+        }
+        Command::Register {
+            filepath,
+            account,
+            currency,
+        } => register(filepath, account.as_str(), currency).await,
+        Command::Accounts {
+            filepath,
+            commodities,
+        } => accounts(filepath, commodities).await,
+        Command::Check {
+            filepath,
+            disable,
+            enable,
+            json,
+            shuffle_check,
+        } => {
+            if let Some(n) = shuffle_check {
+                check_file_orders(filepath.clone(), n).await;
+            }
+            check(filepath, disable, enable, json).await
+        }
+        Command::Lint {
+            filepath,
+            fix,
+            stale_days,
+            json,
+        } => lint_ledger(filepath, fix, stale_days, json).await,
+        Command::Errors { filepath } => errors(filepath).await,
+        Command::Compare {
+            filepath,
+            b_filepath,
+            compare,
+        } => compare_ledgers(filepath, b_filepath, compare).await,
+        Command::Convert { filepath, to } => convert(filepath, to.as_str()).await,
+        Command::Equity {
+            filepath,
+            begin,
+            end,
+        } => equity(filepath, begin, end).await,
+        Command::Fmt { filepath, column } => fmt(filepath, column),
+        Command::SortFile { filepath } => sort(filepath),
+        Command::MergeImports { filepaths, output } => merge(filepaths, output),
+        Command::RenameAccount {
+            filepath,
+            old,
+            new,
+            dry_run,
+        } => rename(filepath, &old, &new, dry_run, rename_account).await,
+        Command::RenameCommodity {
+            filepath,
+            old,
+            new,
+            dry_run,
+        } => rename(filepath, &old, &new, dry_run, rename_commodity).await,
+        Command::Pnl {
+            filepath,
+            begin,
+            end,
+            by,
+            currency,
+        } => pnl(filepath, begin, end, by.as_str(), currency).await,
+        Command::Cashflow {
+            filepath,
+            begin,
+            end,
+            depth,
+        } => cashflow(filepath, begin, end, depth).await,
+        Command::Balance {
+            filepath,
+            end,
+            by,
+            currency,
+            forecast_until,
+        } => balance(filepath, end, by.as_str(), currency, forecast_until).await,
+        Command::Runway {
+            filepath,
+            end,
+            months,
+            accounts,
+            forecast_until,
+        } => runway(filepath, end, months, accounts, forecast_until).await,
+        Command::Savings {
+            filepath,
+            begin,
+            end,
+            investments,
+        } => savings(filepath, begin, end, investments).await,
+        Command::CloseBooks {
+            filepath,
+            date,
+            opening,
+        } => close_books(filepath, date, opening).await,
+        Command::Hash {
+            filepath,
+            begin,
+            end,
+            key,
+            verify,
+        } => hash(filepath, begin, end, key, verify),
+        Command::Sql {
+            filepath,
+            output,
+            query,
+        } => sql(filepath, output, query).await,
+        Command::Forecast { filepath, until } => forecast(filepath, until).await,
+        Command::Cycles {
+            filepath,
+            account,
+            closing_day,
+        } => cycles(filepath, account.as_str(), closing_day).await,
+        Command::Commodities { filepath, gap_days } => commodities(filepath, gap_days).await,
+        Command::Anomalies {
+            filepath,
+            stddevs,
+            threshold,
+        } => anomalies(filepath, stddevs, threshold).await,
+        Command::Unrealized { filepath, end } => unrealized(filepath, end).await,
+        Command::Contributions { filepath } => contributions(filepath).await,
+        Command::Portfolio {
+            filepath,
+            at,
+            accounts,
+        } => portfolio(filepath, at, accounts).await,
+        Command::ForeignProperty {
+            filepath,
+            year,
+            countries,
+        } => foreign_property(filepath, year, countries).await,
+        Command::Todo { filepath, accounts } => todo(filepath, accounts).await,
+        Command::Report { .. } => unreachable!("saved reports are expanded by saved_cli"),
+        Command::Init {
+            dir,
+            template,
+            title,
+        } => init(dir, template, title),
+        Command::GenerateSample {
+            dir,
+            transactions,
+            accounts,
+            files,
+            seed,
+        } => generate_sample(dir, transactions, accounts, files, seed),
         Command::RjUsa {
             filepath,
             acct,
             owner,
             currency,
-        } => rj_usa(filepath, acct.as_str(), owner.as_str(), currency.as_str()).await,
+            categorize,
+        } => {
+            rj_usa(
+                filepath,
+                acct.as_str(),
+                owner.as_str(),
+                currency.as_str(),
+                categorize,
+            )
+            .await
+        }
         Command::RjCdnClosed {
             filepath,
             acct,
             owner,
             currency,
             commodity_f,
+            categorize,
         } => {
             rj_cdn_closed(
                 filepath,
@@ -85,15 +787,26 @@ async fn main() {
                 owner.as_str(),
                 currency.as_str(),
                 commodity_f,
+                categorize,
             )
             .await
         }
+        Command::RjCdnActivities {
+            filepath,
+            symbol_f,
+            activities,
+            ..
+        } if activities.check_symbols => {
+            rj_check_symbols(filepath, symbol_f, activities.tran_types)
+        }
         Command::RjCdnActivities {
             filepath,
             acct,
             owner,
             currency,
             symbol_f,
+            activities,
+            categorize,
         } => {
             rj_cdn_activites(
                 filepath,
@@ -101,6 +814,8 @@ async fn main() {
                 owner.as_str(),
                 currency.as_str(),
                 symbol_f,
+                activities,
+                categorize,
             )
             .await
         }
@@ -108,11 +823,19 @@ async fn main() {
             filepath,
             bkdate_string,
             currency,
+            prices,
+            opening,
+            owners,
+            open_accounts,
         } => {
             rj_cdn_holdings(
                 filepath,
                 NaiveDate::from_str(&bkdate_string).unwrap(),
                 currency.as_str(),
+                prices,
+                opening,
+                owners,
+                open_accounts,
             )
             .await
         }
@@ -122,42 +845,1092 @@ async fn main() {
             filepath,
             bean_filepath,
             encoding,
-        } => read_qfx(filepath, encoding, symbols_f, bean_filepath).await,
+            fitids,
+            categorize,
+            compare,
+        } => {
+            read_qfx(
+                filepath,
+                encoding,
+                symbols_f,
+                bean_filepath,
+                fitids,
+                categorize,
+                compare,
+            )
+            .await
+        }
+        Command::Camt {
+            symbols_f,
+            filepath,
+            bean_filepath,
+            fitids,
+            categorize,
+            compare,
+        } => {
+            read_camt(
+                filepath,
+                symbols_f,
+                bean_filepath,
+                fitids,
+                categorize,
+                compare,
+            )
+            .await
+        }
+        Command::Mt940 {
+            symbols_f,
+            filepath,
+            bean_filepath,
+            fitids,
+            categorize,
+            compare,
+        } => {
+            read_mt940(
+                filepath,
+                symbols_f,
+                bean_filepath,
+                fitids,
+                categorize,
+                compare,
+            )
+            .await
+        }
     }
 }
 
-async fn bean(f: PathBuf) {
+///
+/// `cli` with a report command replaced by the command saved under its name
+/// in the ledger, run on the same ledger. The global flags given on the
+/// command line take precedence over the saved ones.
+///
+fn saved_cli(cli: Cli) -> Cli {
+    let Command::Report { filepath, name } = &cli.command else {
+        return cli;
+    };
     let mut state = LedgerState::new();
+    state.include_path = cli.include_path.clone();
+    state.insert(filepath.clone());
+    if let Err(e) = ParseCache::new(None).parse(filepath.clone(), &mut state) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let args = match saved_report(&state, name) {
+        Some(args) if args[0] != "report" => args,
+        Some(_) => {
+            eprintln!("Report {} runs another report", name);
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!("Unknown report: {}", name);
+            std::process::exit(1);
+        }
+    };
+    let mut argv = vec![env!("CARGO_PKG_NAME").to_string(), args[0].clone()];
+    argv.push(filepath.display().to_string());
+    argv.extend(args[1..].iter().cloned());
+    let saved = Cli::try_parse_from(argv).unwrap_or_else(|e| {
+        eprintln!("Invalid report {}: {}", name, args.join(" "));
+        e.exit()
+    });
+
+    Cli {
+        cache_dir: cli.cache_dir.or(saved.cache_dir),
+        include_path: [cli.include_path, saved.include_path].concat(),
+        min_date: cli.min_date.or(saved.min_date),
+        max_date: cli.max_date.or(saved.max_date),
+        pending_only: cli.pending_only || (saved.pending_only && !cli.cleared_only),
+        cleared_only: cli.cleared_only || (saved.cleared_only && !cli.pending_only),
+        keep_raw: cli.keep_raw || saved.keep_raw,
+        stream: cli.stream || saved.stream,
+        quiet: cli.quiet || saved.quiet,
+        format: cli.format.or(saved.format),
+        locale: cli.locale.or(saved.locale),
+        negative: cli.negative.or(saved.negative),
+        command: saved.command,
+    }
+}
+
+///
+/// Inserts `f` into `state`, searching the --include-path roots for its
+/// includes, with the reports limited to the transactions chosen by
+/// --pending-only or --cleared-only and rendered as --locale says.
+///
+fn insert_ledger(f: &Path, state: &mut LedgerState) {
+    state.include_path = INCLUDE_PATH.get().cloned().unwrap_or_default();
+    state.locale = report_locale();
+    state.flag_filter = FLAG_FILTER.get().map(|x| x.to_string());
+    state.keep_raw = KEEP_RAW.get().copied().unwrap_or_default();
+    state.insert(f.to_path_buf());
+}
+
+///
+/// Inserts and parses `f`, through the parse cache when --cache-dir is given.
+/// A file that fails to parse is reported where it fails, exiting with 1.
+///
+fn parse_ledger(f: PathBuf, state: &mut LedgerState) {
+    insert_ledger(&f, state);
+    let parsed = match CACHE_DIR.get() {
+        Some(d) => ParseCache::new(Some(d.clone())).parse(f, state),
+        None if STREAM.get() == Some(&true) => parse_streaming(&f, state),
+        None => parse_filename(f, state),
+    };
+    if let Err(e) = parsed {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// What --format chose
+fn output_format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// What --locale and --negative chose
+fn report_locale() -> Locale {
+    LOCALE.get().cloned().unwrap_or_default()
+}
+
+/// Prints the `title` option of the ledger above a report, to stderr
+fn print_title(state: &LedgerState) {
+    if let Some(t) = state.options.title() {
+        status!("{}\n", t);
+    }
+}
 
-    state.insert(f.clone());
-    parse_filename(f, &mut state);
+/// Verifies a ledger read by parse_ledger
+async fn verify_ledger(state: &mut LedgerState) {
     state.verify().await.unwrap();
-    println!("tc_balances\n");
-    state.tc_balances().await.unwrap().show().await.unwrap();
-    println!("cp_balances\n");
-    state.cp_balances().await.unwrap().show().await.unwrap();
+}
 
-    state.write_transactions().await.unwrap();
-    state.write_verifications().await.unwrap();
+///
+/// Refreshes the summary next to the ledger, which only bean writes so that
+/// the reports leave the ledger directory as it is
+///
+fn write_summary(state: &LedgerState) {
+    if let Err(e) = LedgerSummary::new(state).and_then(|x| x.write()) {
+        status!("Unable to write the ledger summary: {}", e);
+    }
+}
+
+async fn bean(f: PathBuf, strict: bool) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    write_summary(&state);
+    let format = output_format();
+    // The plans all start now and are printed in order as each is done
+    let tc_balances = spawn_stream(state.tc_balances().await.unwrap());
+    let cp_balances = spawn_stream(state.cp_balances().await.unwrap());
+    let balance_errors = spawn_stream(state.balance_errors_df().unwrap());
+    let [transactions, rest] = match format {
+        OutputFormat::Table => [
+            state.transaction_lines_df().unwrap(),
+            state.verifications_df.clone().unwrap(),
+        ],
+        OutputFormat::Ndjson => [
+            state.transactions_df.clone().unwrap(),
+            state.postings_df.clone().unwrap(),
+        ],
+    }
+    .map(spawn_stream);
+
+    status!("tc_balances\n");
+    format
+        .show_stream(tc_balances, EVENT_BALANCE, &state.locale)
+        .await
+        .unwrap();
+    status!("cp_balances\n");
+    format
+        .show_stream(cp_balances, EVENT_BALANCE, &state.locale)
+        .await
+        .unwrap();
+    status!("balance_errors\n");
+    format
+        .show_stream(balance_errors, EVENT_ERROR, &state.locale)
+        .await
+        .unwrap();
+
+    match format {
+        OutputFormat::Table => {
+            state.write_transactions_stream(transactions).await.unwrap();
+            state.write_verifications_stream(rest).await.unwrap();
+        }
+        OutputFormat::Ndjson => {
+            format
+                .show_stream(transactions, EVENT_TRANSACTION, &state.locale)
+                .await
+                .unwrap();
+            format
+                .show_stream(rest, EVENT_POSTING, &state.locale)
+                .await
+                .unwrap();
+        }
+    }
+
+    if strict {
+        let mut checks = Checks::builtin();
+        checks.set_strict(true);
+        let diagnostics = checks.run(&state).await.unwrap();
+        if print_diagnostics(&state, &diagnostics, false) > 0 {
+            std::process::exit(1);
+        }
+    }
+}
+
+///
+/// Prints the balances and the check diagnostics of `f`, then waits for any
+/// of its input files to change and starts over. Directories are watched
+/// rather than files so editors that save by renaming are seen, and a short
+/// pause lets a burst of events settle into one re-parse. Unchanged files are
+/// reused from the parse cache. `strict` reports the warnings as errors.
+///
+async fn bean_watch(f: PathBuf, strict: bool) {
+    let mut cache = ParseCache::new(CACHE_DIR.get().cloned());
+    loop {
+        let mut state = LedgerState::new();
+        insert_ledger(&f, &mut state);
+        status!("\n{} {}\n", Local::now().format("%H:%M:%S"), f.display());
+        match cache.parse(f.clone(), &mut state) {
+            Ok(()) => {
+                verify_ledger(&mut state).await;
+                write_summary(&state);
+                status!("cp_balances\n");
+                let df = state.cp_balances().await.unwrap();
+                output_format()
+                    .show(df, EVENT_BALANCE, &state.locale)
+                    .await
+                    .unwrap();
+                let mut checks = Checks::builtin();
+                checks.set_strict(strict);
+                let diagnostics = checks.run(&state).await.unwrap();
+                print_diagnostics(&state, &diagnostics, false);
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+
+        let files: HashSet<PathBuf> = state
+            .input_files
+            .keys()
+            .map(|x| x.canonicalize().unwrap_or(x.clone()))
+            .collect();
+        let dirs: HashSet<PathBuf> = files
+            .iter()
+            .filter_map(|x| x.parent().map(|p| p.to_path_buf()))
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).unwrap();
+        for d in dirs.iter() {
+            watcher.watch(d, RecursiveMode::NonRecursive).unwrap();
+        }
+        while let Ok(event) = rx.recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if !event.kind.is_access() && event.paths.iter().any(|p| files.contains(p)) {
+                break;
+            }
+        }
+        thread::sleep(WATCH_SETTLE);
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+async fn register(f: PathBuf, account: &str, currency: Option<String>) {
+    let mut state = LedgerState::new();
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let currency = currency.or(state.options.operating_currency().map(String::from));
+    match output_format() {
+        OutputFormat::Table => state
+            .write_register(account, currency.as_deref())
+            .await
+            .unwrap(),
+        OutputFormat::Ndjson => {
+            let df = state
+                .register_value_df(account, currency.as_deref())
+                .unwrap();
+            output_format()
+                .show(df, EVENT_POSTING, &state.locale)
+                .await
+                .unwrap()
+        }
+    }
+}
+
+/// Lists the accounts or commodities of `f`, from the summary bean wrote while it is current
+async fn accounts(f: PathBuf, commodities: bool) {
+    let include_path = INCLUDE_PATH.get().cloned().unwrap_or_default();
+    let summary = match LedgerSummary::load(&f, &include_path) {
+        Some(s) => s,
+        None => {
+            let mut state = LedgerState::new();
+            parse_ledger(f, &mut state);
+            verify_ledger(&mut state).await;
+            LedgerSummary::new(&state).unwrap()
+        }
+    };
+    let names = if commodities {
+        summary.commodities
+    } else {
+        summary.accounts
+    };
+    for x in names {
+        println!("{}", x);
+    }
+}
+
+///
+/// Runs the checks on `f` without printing any report, with the optional
+/// rules named in `enable` and without those named in `disable`. A parse
+/// error is reported as a diagnostic of the parse rule, skipping the others.
+///
+async fn check(f: PathBuf, disable: Vec<String>, enable: Vec<String>, json: bool) {
+    let mut state = LedgerState::new();
+    insert_ledger(&f, &mut state);
+    let diagnostics = match ParseCache::new(CACHE_DIR.get().cloned()).parse(f, &mut state) {
+        Ok(()) => {
+            verify_ledger(&mut state).await;
+            let mut checks = Checks::builtin();
+            for name in enable.iter() {
+                checks.enable(name.trim());
+            }
+            for name in disable.iter() {
+                checks.disable(name.trim());
+            }
+            checks.run(&state).await.unwrap()
+        }
+        Err(e) => vec![Diagnostic::parse_failure(&state, &e)],
+    };
+    let errors = print_diagnostics(&state, &diagnostics, json);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Prints `diagnostics` as --format asks, or as a JSON array with `json`; returns the number of errors
+fn print_diagnostics(state: &LedgerState, diagnostics: &[Diagnostic], json: bool) -> usize {
+    match (json, output_format()) {
+        (true, _) => write_diagnostics_json(state, diagnostics).unwrap(),
+        (false, OutputFormat::Ndjson) => write_diagnostics_ndjson(state, diagnostics).unwrap(),
+        (false, OutputFormat::Table) => write_diagnostics(state, diagnostics),
+    }
+}
+
+///
+/// Lints `f` without verifying it, for a pre-commit hook. With `fix` the
+/// trailing whitespace is stripped first and the ledger read again, so the
+/// reported lines match the rewritten files. Exits with 1 on any error.
+///
+async fn lint_ledger(f: PathBuf, fix: bool, stale_days: i64, json: bool) {
+    if fix {
+        let mut state = LedgerState::new();
+        insert_ledger(&f, &mut state);
+        // The files read before any parse error are still fixed
+        let _ = ParseCache::new(None).parse(f.clone(), &mut state);
+        let n = fix_trailing_whitespace(&state).unwrap();
+        if n > 0 {
+            status!("Stripped trailing whitespace from {} lines", n);
+        }
+    }
+
+    let mut state = LedgerState::new();
+    insert_ledger(&f, &mut state);
+    let diagnostics = match ParseCache::new(CACHE_DIR.get().cloned()).parse(f, &mut state) {
+        Ok(()) => lint(&state, stale_days).unwrap(),
+        Err(e) => vec![Diagnostic::parse_failure(&state, &e)],
+    };
+    let errors = print_diagnostics(&state, &diagnostics, json);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the transactions of `f` that do not balance as written, exiting with 1 if any
+async fn errors(f: PathBuf) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let unbalanced = match output_format() {
+        OutputFormat::Table => state.write_errors().await.unwrap(),
+        OutputFormat::Ndjson => {
+            let df = state.errors_report_df().unwrap();
+            write_events(&mut io::stdout().lock(), EVENT_ERROR, df)
+                .await
+                .unwrap()
+        }
+    };
+    if unbalanced > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// The order independent rows and diagnostics of `f`, its files ordered by `seed`
+async fn order_rows(f: PathBuf, seed: Option<u64>) -> Vec<String> {
+    let mut state = LedgerState::new();
+    match seed {
+        Some(s) => {
+            insert_ledger(&f, &mut state);
+            if let Err(e) = state.parse_shuffled(&f, s) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        None => parse_ledger(f, &mut state),
+    }
+    state.verify().await.unwrap();
+    let mut rows = state.order_independent_rows().await.unwrap();
+    for x in Checks::builtin().run(&state).await.unwrap() {
+        rows.push(format!(
+            "{} {} {} {}",
+            x.rule, x.file_no, x.start, x.message
+        ));
+    }
+    rows.sort();
+    rows
+}
+
+///
+/// Verifies `f` with its files parsed in `n` other orders and exits with an
+/// error, printing the differing rows, unless all results are the same.
+///
+async fn check_file_orders(f: PathBuf, n: u64) {
+    let expected = order_rows(f.clone(), None).await;
+    let mut same = true;
+    for seed in 1..=n {
+        let rows = order_rows(f.clone(), Some(seed)).await;
+        if rows == expected {
+            continue;
+        }
+        same = false;
+        eprintln!("shuffle {} differs:", seed);
+        for x in expected.iter().filter(|x| !rows.contains(x)) {
+            eprintln!("- {}", x);
+        }
+        for x in rows.iter().filter(|x| !expected.contains(x)) {
+            eprintln!("+ {}", x);
+        }
+    }
+    if !same {
+        std::process::exit(1);
+    }
+}
+
+async fn compare_ledgers(f: PathBuf, b: PathBuf, args: CompareArgs) {
+    let mut state = LedgerState::new();
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+
+    let mut b_state = LedgerState::new();
+    parse_ledger(b, &mut b_state);
+    verify_ledger(&mut b_state).await;
+
+    state
+        .compare_postings(&b_state, &compare_options(args))
+        .await
+        .unwrap();
+}
+
+async fn convert(f: PathBuf, to: &str) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    state.write_convert(to).await.unwrap();
+}
+
+fn fmt(f: PathBuf, column: usize) {
+    match format_file(&f, column) {
+        Ok(true) => status!("Formatted {}", f.display()),
+        Ok(false) => status!("{} is already formatted", f.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn sort(f: PathBuf) {
+    match sort_file(&f) {
+        Ok(true) => status!("Sorted {}", f.display()),
+        Ok(false) => status!("{} is already sorted", f.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Merges the import outputs `files` into `output`, or prints the result
+fn merge(files: Vec<PathBuf>, output: Option<PathBuf>) {
+    let summary = match merge_imports(&files) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match output {
+        Some(o) => {
+            fs::write(&o, &summary.contents).unwrap();
+            status!(
+                "Merged {} statements into {}, dropping {} duplicates",
+                summary.read - summary.duplicates,
+                o.display(),
+                summary.duplicates
+            );
+        }
+        None => print!("{}", summary.contents),
+    }
+}
+
+fn write_rename_summary(summary: &RenameSummary, dry_run: bool) {
+    if dry_run {
+        for (f, line, before, after) in summary.diff.iter() {
+            println!("{}:{}\n- {}\n+ {}", f.display(), line, before, after);
+        }
+    }
+    for (old, new) in summary.renamed.iter() {
+        status!("{} -> {}", old, new);
+    }
+    let verb = if dry_run { "Would change" } else { "Changed" };
+    for (f, n) in summary.files.iter() {
+        status!("{} {}: {} replacements", verb, f.display(), n);
+    }
+}
+
+///
+/// Renames the account or commodity `old` to `new` with `rename_fn` across
+/// the include tree of `f`
+///
+async fn rename<E: std::fmt::Display>(
+    f: PathBuf,
+    old: &str,
+    new: &str,
+    dry_run: bool,
+    rename_fn: fn(&LedgerState, &str, &str, bool) -> Result<RenameSummary, E>,
+) {
+    let mut state = LedgerState::new();
+    parse_ledger(f, &mut state);
+    match rename_fn(&state, old, new, !dry_run) {
+        Ok(summary) => write_rename_summary(&summary, dry_run),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn equity(f: PathBuf, begin: Option<NaiveDate>, end: Option<NaiveDate>) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let df = state.equity_df(begin, end).unwrap();
+    output_format()
+        .show(df, "equity", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn pnl(
+    f: PathBuf,
+    begin: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    by: &str,
+    currency: Option<String>,
+) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let currency = currency.or(state.options.operating_currency().map(String::from));
+    let df = match currency {
+        Some(c) => state
+            .rounded_pnl_value_df(begin, end, by, c.as_str())
+            .await
+            .unwrap(),
+        None => state.pnl_df(begin, end, by).unwrap(),
+    };
+    output_format()
+        .show(df, "pnl", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn cashflow(f: PathBuf, begin: Option<NaiveDate>, end: Option<NaiveDate>, depth: usize) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let df = state.cashflow_df(begin, end, depth).unwrap();
+    output_format()
+        .show(df, "cashflow", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn balance(
+    f: PathBuf,
+    end: Option<NaiveDate>,
+    by: &str,
+    currency: Option<String>,
+    forecast_until: Option<NaiveDate>,
+) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    match forecast_until {
+        // Not summarized, as the forecast is not part of the ledger
+        Some(until) => {
+            state.add_forecast(until);
+            state.verify().await.unwrap();
+        }
+        None => verify_ledger(&mut state).await,
+    }
+    print_title(&state);
+    let currency = currency.or(state.options.operating_currency().map(String::from));
+    let df = match currency {
+        Some(c) => state
+            .rounded_balance_value_df(end, by, c.as_str())
+            .await
+            .unwrap(),
+        None => state.balance_df(end, by).unwrap(),
+    };
+    output_format()
+        .show(df, EVENT_BALANCE, &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn runway(
+    f: PathBuf,
+    end: Option<NaiveDate>,
+    months: u32,
+    accounts: Vec<String>,
+    forecast_until: Option<NaiveDate>,
+) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    match forecast_until {
+        Some(until) => {
+            state.add_forecast(until);
+            state.verify().await.unwrap();
+        }
+        None => verify_ledger(&mut state).await,
+    }
+    print_title(&state);
+    let df = state.runway_df(end, months, &accounts).unwrap();
+    output_format()
+        .show(df, "runway", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn savings(
+    f: PathBuf,
+    begin: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    investments: Option<String>,
+) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let df = state
+        .savings_rate_df(begin, end, investments.as_deref())
+        .unwrap();
+    output_format()
+        .show(df, "savings", &state.locale)
+        .await
+        .unwrap();
 }
 
-async fn rj_usa(f: PathBuf, acct: &str, owner: &str, currency: &str) {
+async fn close_books(f: PathBuf, date: NaiveDate, opening_f: Option<PathBuf>) {
     let mut state = LedgerState::new();
 
-    process_us_transaction(f.to_str().unwrap(), acct, owner, currency, &mut state).unwrap();
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let (closing, opening) = state.close_books(date).await.unwrap();
+    println!("{}", closing);
+    match opening_f {
+        Some(o) => {
+            fs::write(&o, opening).unwrap();
+            status!("Wrote opening balances to {}", o.display());
+        }
+        None => println!("{}", opening),
+    }
+}
+
+///
+/// Prints the hash of the statements of `f` dated from `begin` to the day
+/// before `end`, signed with the contents of the file `key` if given. With
+/// `verify`, checks it against that hash instead, exiting 1 when it differs.
+///
+fn hash(
+    f: PathBuf,
+    begin: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    key: Option<PathBuf>,
+    verify: Option<String>,
+) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    let key = key.map(|k| fs::read(k).unwrap());
+    let Some(expected) = verify else {
+        println!("{}", state.ledger_hash(begin, end, key.as_deref()));
+        return;
+    };
+    match state.verify_hash(&expected, begin, end, key.as_deref()) {
+        Ok(true) => status!("The ledger is unchanged"),
+        Ok(false) => {
+            eprintln!(
+                "The ledger has changed: {}",
+                state.ledger_hash(begin, end, key.as_deref())
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn sql(f: PathBuf, output: Option<PathBuf>, query: Option<String>) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    if let Some(q) = query {
+        match state.query_df(&q).await {
+            Ok(df) => output_format()
+                .show(df, "row", &state.locale)
+                .await
+                .unwrap(),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    match output {
+        Some(o) => {
+            let mut w = BufWriter::new(fs::File::create(o).unwrap());
+            state.write_sql(&mut w).await.unwrap();
+        }
+        None => state.write_sql(&mut io::stdout().lock()).await.unwrap(),
+    }
+}
+
+async fn forecast(f: PathBuf, until: NaiveDate) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    let mut forecast = state.forecast(until);
+    forecast.verify().await.unwrap();
+    forecast.write_transactions().await.unwrap();
+}
+
+async fn cycles(f: PathBuf, account: &str, closing_day: u32) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let df = state
+        .statement_cycles_df(account, closing_day)
+        .await
+        .unwrap();
+    output_format()
+        .show(df, "cycle", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn commodities(f: PathBuf, gap_days: u32) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let df = state.commodity_coverage_df(gap_days).unwrap();
+    output_format()
+        .show(df, "commodity", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn anomalies(f: PathBuf, stddevs: f64, threshold: Option<f64>) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let df = state.anomalies_df(stddevs, threshold).unwrap();
+    output_format()
+        .show(df, "anomaly", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn unrealized(f: PathBuf, end: Option<NaiveDate>) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let df = state.unrealized_df(end).unwrap();
+    output_format()
+        .show(df, "unrealized", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn contributions(f: PathBuf) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let df = state.contribution_room_df().unwrap();
+    output_format()
+        .show(df, "contribution", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn portfolio(f: PathBuf, at: Option<NaiveDate>, accounts: Vec<String>) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let df = state.portfolio_df(at, &accounts).unwrap();
+    output_format()
+        .show(df, "holding", &state.locale)
+        .await
+        .unwrap();
+}
 
-    println!("transactions: {}", state.transactions.len());
-    println!("postings: {}", state.postings.len());
-    println!("balances: {}", state.verifications.len());
-    println!("\n");
+async fn foreign_property(f: PathBuf, year: i32, countries: Option<PathBuf>) {
+    let countries = match countries {
+        Some(c) => MappingTable::countries().load(&c).unwrap_or_else(|e| {
+            eprintln!("{}: {}", c.display(), e);
+            std::process::exit(1);
+        }),
+        None => SymbolsMap::new(),
+    };
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    print_title(&state);
+    let df = state.foreign_property_df(year, &countries).unwrap();
+    output_format()
+        .show(df, "foreign_property", &state.locale)
+        .await
+        .unwrap();
+}
+
+async fn todo(f: PathBuf, accounts: Vec<String>) {
+    let mut state = LedgerState::new();
+
+    parse_ledger(f, &mut state);
+    verify_ledger(&mut state).await;
+    let df = state.todo_df(&accounts).unwrap();
+    output_format()
+        .show(df, "todo", &state.locale)
+        .await
+        .unwrap();
+}
+
+fn prompt(msg: &str) -> String {
+    print!("{}", msg);
+    io::stdout().flush().unwrap();
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).unwrap();
+    line.trim().to_string()
+}
+
+fn init(dir: PathBuf, template: Option<String>, title: Option<String>) {
+    if dir.join(MAIN_FILE).exists() {
+        eprintln!("{} already exists", dir.join(MAIN_FILE).display());
+        return;
+    }
+    let template = match template {
+        Some(name) => match find_template(name.as_str()) {
+            Some(t) => t,
+            None => {
+                eprintln!("Unknown template: {}", name);
+                return;
+            }
+        },
+        None => {
+            for (n, t) in TEMPLATES.iter().enumerate() {
+                println!("  {}) {:<16} {}", n + 1, t.name, t.description);
+            }
+            let choice = prompt("template [1]: ");
+            let n = choice.parse::<usize>().unwrap_or(1);
+            match TEMPLATES.get(n.max(1) - 1) {
+                Some(t) => t,
+                None => {
+                    eprintln!("Unknown template: {}", choice);
+                    return;
+                }
+            }
+        }
+    };
+    let title = title.unwrap_or_else(|| {
+        let t = prompt("title [My Ledger]: ");
+        if t.is_empty() {
+            String::from("My Ledger")
+        } else {
+            t
+        }
+    });
+
+    let today = Local::now().date_naive();
+    write_init(&dir, template, title.as_str(), today).unwrap();
+    println!("{}", dir.join(MAIN_FILE).display());
+}
+
+///
+/// Writes the sample ledger to stdout, or with `dir` a random ledger of
+/// `files` files into it, printing its main file
+///
+fn generate_sample(
+    dir: Option<PathBuf>,
+    transactions: usize,
+    accounts: Option<usize>,
+    files: usize,
+    seed: u64,
+) {
+    let Some(dir) = dir else {
+        let accounts = accounts.unwrap_or(SAMPLE_ACCOUNTS);
+        write_sample(&mut io::stdout().lock(), transactions, accounts, seed).unwrap();
+        return;
+    };
+    let accounts = accounts.unwrap_or(GEN_ACCOUNTS);
+    match write_generated(&dir, transactions, accounts, files, seed) {
+        Ok(written) => println!("{}", written[0].display()),
+        Err(e) => {
+            eprintln!("{}: {}", dir.join(MAIN_FILE).display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn base_accounts(b: Option<PathBuf>) -> Vec<String> {
+    match b {
+        Some(b_path) => {
+            let mut b_state = LedgerState::new();
+            parse_ledger(b_path, &mut b_state);
+            verify_ledger(&mut b_state).await;
+            b_state.accounts().await.unwrap()
+        }
+        None => vec![],
+    }
+}
+
+/// Warns about imported statements dated outside --min-date and --max-date
+fn warn_dates(state: &LedgerState) {
+    let range = DATE_RANGE.get().copied().unwrap_or_default();
+    for x in range.outside(state) {
+        let date = x.date.map(|d| d.to_string()).unwrap_or_default();
+        eprintln!("warning: {} {}", date, x.message);
+    }
+}
+
+async fn categorize_import(args: CategorizeArgs, state: &mut LedgerState) {
+    if args.from.is_some() || args.to.is_some() {
+        let range = DateRange {
+            min: args.from.unwrap_or(NaiveDate::MIN),
+            max: args.to.unwrap_or(NaiveDate::MAX),
+        };
+        status!("out of range: {}", range.retain(state));
+    }
+    let rules_f = args.rules.as_ref().map(|f| f.to_str().unwrap());
+    let mut rules = match rules_f {
+        Some(f) if Path::new(f).exists() => Rules::load(f).unwrap(),
+        _ => Rules { rules: vec![] },
+    };
+    let n = if args.interactive {
+        let accounts = base_accounts(args.base).await;
+        categorize_interactive(&mut rules, state, &accounts, rules_f).unwrap()
+    } else {
+        rules.apply(state)
+    };
+    status!("categorized: {}", n);
+    if args.open_accounts {
+        status!("opened: {}", state.open_used_accounts());
+    }
+    state.renumber_statements();
+}
+
+///
+/// Prints what an import read to stderr: the prices for the broker
+/// exports, the rows `skipped` as already imported for the statements.
+///
+fn import_counts(state: &LedgerState, skipped: Option<usize>) {
+    status!("transactions: {}", state.transactions.len());
+    status!("postings: {}", state.postings.len());
+    status!("balances: {}", state.verifications.len());
+    match skipped {
+        Some(n) => status!("skipped: {}", n),
+        None => status!("prices: {}", state.prices.len()),
+    }
+    status!("\n");
+}
+
+///
+/// Prints the rows of the CSV file `f` an import skipped or posted to TODO
+/// to stderr, each by line with why and the row as read, then their number
+///
+fn import_errors(f: &Path, errors: &ImportErrors) {
+    if errors.is_empty() {
+        return;
+    }
+    for e in errors.errors.iter() {
+        eprintln!("{}:{}: error: {}", f.display(), e.line, e.reason);
+        eprintln!("  {}", e.record);
+    }
+    eprintln!("import errors: {}\n", errors.len());
+}
+
+async fn rj_usa(f: PathBuf, acct: &str, owner: &str, currency: &str, categorize: CategorizeArgs) {
+    let mut state = LedgerState::new();
+
+    let errors =
+        process_us_transaction(f.to_str().unwrap(), acct, owner, currency, &mut state).unwrap();
+    categorize_import(categorize, &mut state).await;
+
+    import_counts(&state, None);
+    import_errors(&f, &errors);
+    warn_dates(&state);
     state.verify().await.unwrap();
+    state.write_verifications().await.unwrap();
     state.write_transactions().await.unwrap();
+    state.write_prices().await.unwrap();
 }
 
-async fn rj_cdn_closed(f: PathBuf, acct: &str, owner: &str, currency: &str, commodity_f: PathBuf) {
+async fn rj_cdn_closed(
+    f: PathBuf,
+    acct: &str,
+    owner: &str,
+    currency: &str,
+    commodity_f: PathBuf,
+    categorize: CategorizeArgs,
+) {
     let mut state = LedgerState::new();
 
-    process_closed_acct_trans(
+    let errors = process_closed_acct_trans(
         f.to_str().unwrap(),
         acct,
         owner,
@@ -166,13 +1939,15 @@ async fn rj_cdn_closed(f: PathBuf, acct: &str, owner: &str, currency: &str, comm
         &mut state,
     )
     .unwrap();
+    categorize_import(categorize, &mut state).await;
 
-    println!("transactions: {}", state.transactions.len());
-    println!("postings: {}", state.postings.len());
-    println!("balances: {}", state.verifications.len());
-    println!("\n");
+    import_counts(&state, None);
+    import_errors(&f, &errors);
+    warn_dates(&state);
     state.verify().await.unwrap();
+    state.write_verifications().await.unwrap();
     state.write_transactions().await.unwrap();
+    state.write_prices().await.unwrap();
 }
 
 async fn rj_cdn_activites(
@@ -181,54 +1956,274 @@ async fn rj_cdn_activites(
     owner: &str,
     currency: &str,
     commodity_f: PathBuf,
+    activities: ActivitiesArgs,
+    categorize: CategorizeArgs,
 ) {
     let mut state = LedgerState::new();
+    let mut symbols = symbols_table(&commodity_f);
+    symbols.unknown = match UnknownSymbols::from_name(&activities.unknown_symbols) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let tran_types = activities.tran_types.as_deref().and_then(|t| t.to_str());
+    let owners = activities.owners.as_deref().and_then(|o| o.to_str());
+    let mut accounts = match ActivityAccounts::new(acct, owner, owners) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    accounts.currency_cash = activities.currency_cash;
 
-    process_activites(
+    let errors = match process_activites(
         f.to_str().unwrap(),
-        acct,
-        owner,
+        &accounts,
         currency,
-        commodity_f.to_str().unwrap(),
+        &mut symbols,
+        tran_types,
         &mut state,
-    )
-    .unwrap();
+    ) {
+        Ok(errors) => errors,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(m) = activities.missing_symbols
+        && let Err(e) = symbols.write_missing(m.to_str().unwrap())
+    {
+        eprintln!("{}: {}", m.display(), e);
+        std::process::exit(1);
+    }
+    categorize_import(categorize, &mut state).await;
 
-    println!("transactions: {}", state.transactions.len());
-    println!("postings: {}", state.postings.len());
-    println!("balances: {}", state.verifications.len());
-    println!("\n");
+    import_counts(&state, None);
+    import_errors(&f, &errors);
+    warn_dates(&state);
     state.verify().await.unwrap();
+    state.write_verifications().await.unwrap();
     state.write_transactions().await.unwrap();
+    state.write_prices().await.unwrap();
+}
+
+/// Reports the activities of `f` whose security is not in the symbols file, exiting 1 if any
+fn rj_check_symbols(f: PathBuf, symbol_f: PathBuf, tran_types: Option<PathBuf>) {
+    let symbols = symbols_table(&symbol_f);
+    let tran_types = tran_types.as_deref().and_then(|t| t.to_str());
+    match check_symbols(f.to_str().unwrap(), &symbols, tran_types) {
+        Ok(errors) if errors.is_empty() => {}
+        Ok(errors) => {
+            import_errors(&f, &errors);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
-async fn rj_cdn_holdings(f: PathBuf, bkdate: NaiveDate, currency: &str) {
+async fn rj_cdn_holdings(
+    f: PathBuf,
+    bkdate: NaiveDate,
+    currency: &str,
+    prices: bool,
+    opening: bool,
+    owners: Option<PathBuf>,
+    open_accounts: bool,
+) {
     let mut state = LedgerState::new();
 
-    compile_holdings(f.to_str().unwrap(), bkdate, currency, &mut state).unwrap();
+    let errors = match compile_holdings(
+        f.to_str().unwrap(),
+        bkdate,
+        currency,
+        prices,
+        opening,
+        owners.as_deref().and_then(|o| o.to_str()),
+        &mut state,
+    ) {
+        Ok(errors) => errors,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if open_accounts {
+        status!("opened: {}", state.open_used_accounts());
+    }
+    state.renumber_statements();
 
-    println!("transactions: {}", state.transactions.len());
-    println!("postings: {}", state.postings.len());
-    println!("balances: {}", state.verifications.len());
-    println!("\n");
+    import_counts(&state, None);
+    import_errors(&f, &errors);
+    warn_dates(&state);
     state.verify().await.unwrap();
     state.write_transactions().await.unwrap();
+    state.write_verifications().await.unwrap();
+    state.write_prices().await.unwrap();
+}
+
+/// The symbols file `f`, its warnings printed to stderr, else exits on its errors
+fn symbols_table(f: &Path) -> SymbolsTable {
+    let table = match load_symbols(f.to_str().unwrap()) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("{}: {}", f.display(), e);
+            std::process::exit(1);
+        }
+    };
+    for w in table.warnings.iter() {
+        eprintln!("{}: warning: {}", f.display(), w);
+    }
+    table
 }
 
 fn rj_symbols(f: PathBuf) {
-    let result = load_symbols(String::from(f.to_str().unwrap())).unwrap();
-    println!("{:?}", result);
+    let table = symbols_table(&f);
+    let mut rows: Vec<_> = table.symbols.iter().collect();
+    rows.sort_by_key(|(d, _)| d.as_str());
+    println!("description,ticker,currency,type");
+    for (d, s) in rows {
+        let d = if d.contains([',', '"']) {
+            format!("\"{}\"", d.replace('"', "\"\""))
+        } else {
+            d.to_string()
+        };
+        println!(
+            "{},{},{},{}",
+            d,
+            s.ticker,
+            s.currency.as_deref().unwrap_or_default(),
+            s.kind.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+fn compare_options(args: CompareArgs) -> CompareOptions {
+    let account_map = match args.account_map {
+        Some(f) => MappingTable::renames().load(&f).unwrap(),
+        None => SymbolsMap::new(),
+    };
+    let mut opts = CompareOptions {
+        account_map,
+        ignore_case: args.ignore_case,
+        ..Default::default()
+    };
+    if !args.keys.is_empty() {
+        opts.keys = args
+            .keys
+            .iter()
+            .map(|k| CompareKey::from_name(k.trim()).unwrap())
+            .collect();
+    }
+    opts
+}
+
+async fn read_qfx(
+    f: PathBuf,
+    e: Option<String>,
+    symbols_f: PathBuf,
+    b: Option<PathBuf>,
+    fitids: Option<PathBuf>,
+    mut categorize: CategorizeArgs,
+    compare: CompareArgs,
+) {
+    let mut state = LedgerState::new();
+
+    let skipped = match parse_qfx_file(f, e, symbols_f, fitids.as_deref(), &mut state) {
+        Ok(n) => n,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    if categorize.base.is_none() {
+        categorize.base = b.clone();
+    }
+    categorize_import(categorize, &mut state).await;
+
+    import_counts(&state, Some(skipped));
+    warn_dates(&state);
+    state.verify().await.unwrap();
+    state.write_transactions().await.unwrap();
+    state.write_verifications().await.unwrap();
+
+    if b.is_none() {
+        return;
+    }
+
+    let mut b_state = LedgerState::new();
+
+    let b_path = b.unwrap();
+
+    parse_ledger(b_path, &mut b_state);
+    verify_ledger(&mut b_state).await;
+
+    state
+        .compare_postings(&b_state, &compare_options(compare))
+        .await
+        .unwrap();
 }
 
-async fn read_qfx(f: PathBuf, e: Option<String>, symbols_f: PathBuf, b: Option<PathBuf>) {
+async fn read_camt(
+    f: PathBuf,
+    symbols_f: PathBuf,
+    b: Option<PathBuf>,
+    fitids: Option<PathBuf>,
+    categorize: CategorizeArgs,
+    compare: CompareArgs,
+) {
     let mut state = LedgerState::new();
 
-    let _ = parse_qfx_file(f, e, symbols_f, &mut state);
+    let skipped = match parse_camt_file(f, symbols_f, fitids.as_deref(), &mut state) {
+        Ok(n) => n,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    finish_statement_import(state, b, skipped, categorize, compare).await;
+}
+
+async fn read_mt940(
+    f: PathBuf,
+    symbols_f: PathBuf,
+    b: Option<PathBuf>,
+    fitids: Option<PathBuf>,
+    categorize: CategorizeArgs,
+    compare: CompareArgs,
+) {
+    let mut state = LedgerState::new();
+
+    let skipped = match parse_mt940_file(f, symbols_f, fitids.as_deref(), &mut state) {
+        Ok(n) => n,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    finish_statement_import(state, b, skipped, categorize, compare).await;
+}
+
+/// Categorizes, prints and compares against the ledger `b` an imported statement
+async fn finish_statement_import(
+    mut state: LedgerState,
+    b: Option<PathBuf>,
+    skipped: usize,
+    mut categorize: CategorizeArgs,
+    compare: CompareArgs,
+) {
+    if categorize.base.is_none() {
+        categorize.base = b.clone();
+    }
+    categorize_import(categorize, &mut state).await;
 
-    println!("transactions: {}", state.transactions.len());
-    println!("postings: {}", state.postings.len());
-    println!("balances: {}", state.verifications.len());
-    println!("\n");
+    import_counts(&state, Some(skipped));
+    warn_dates(&state);
     state.verify().await.unwrap();
     state.write_transactions().await.unwrap();
     state.write_verifications().await.unwrap();
@@ -241,9 +2236,11 @@ async fn read_qfx(f: PathBuf, e: Option<String>, symbols_f: PathBuf, b: Option<P
 
     let b_path = b.unwrap();
 
-    b_state.insert(b_path.clone());
-    parse_filename(b_path.clone(), &mut b_state);
-    b_state.verify().await.unwrap();
+    parse_ledger(b_path, &mut b_state);
+    verify_ledger(&mut b_state).await;
 
-    state.compare_postings(&b_state).await.unwrap();
+    state
+        .compare_postings(&b_state, &compare_options(compare))
+        .await
+        .unwrap();
 }